@@ -0,0 +1,122 @@
+//! Benchmark of finishing spans from many threads concurrently.
+//!
+//! Run with `cargo bench --bench finish_contention`
+//! (add `--features lock-free-queue` to compare the lock-free ring buffers with tokio channels).
+//!
+//! Spans are finished by several threads at once, both individually
+//! (with a single channel, with sharded channels, with batching and with bounded queues)
+//! and as the children of a `SharedSpan` shared by all the threads.
+//! Bounded queues discard spans while they are full, so the number of the discarded spans is
+//! reported as well.
+#![cfg_attr(rustracing_noop, allow(clippy::drop_non_drop))]
+use cf_rustracing::sampler::AllSampler;
+use cf_rustracing::span::{SharedSpan, SpanReceiver};
+use cf_rustracing::{Tracer, TracerBuilder};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+#[cfg(feature = "lock-free-queue")]
+use std::time::Duration;
+use std::time::Instant;
+
+const THREADS: usize = 8;
+const ITERATIONS: usize = 100_000;
+
+/// Drains `span_rx` in a background thread until `done` is set.
+fn drain(mut span_rx: SpanReceiver<()>, done: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while !done.load(Ordering::Relaxed) {
+            while span_rx.try_recv().is_ok() {}
+            thread::yield_now();
+        }
+    })
+}
+
+fn run<F>(name: &str, builder: TracerBuilder<AllSampler, ()>, f: F)
+where
+    F: Fn(&Tracer<AllSampler, ()>, &SharedSpan<()>) + Send + Sync + 'static,
+{
+    let (tracer, span_rx) = builder.finish();
+    let done = Arc::new(AtomicBool::new(false));
+    let drainer = drain(span_rx, Arc::clone(&done));
+    let f = Arc::new(f);
+    let parent = tracer.span("parent").start_with_state(()).into_shared();
+    let barrier = Arc::new(Barrier::new(THREADS + 1));
+    let threads = (0..THREADS)
+        .map(|_| {
+            let tracer = tracer.clone();
+            let f = Arc::clone(&f);
+            let barrier = Arc::clone(&barrier);
+            let parent = parent.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..ITERATIONS {
+                    f(&tracer, &parent);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    barrier.wait();
+    let start = Instant::now();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / (THREADS * ITERATIONS) as f64;
+    drop(parent);
+    done.store(true, Ordering::Relaxed);
+    drainer.join().unwrap();
+    println!(
+        "{:<24} {:>10.1} ns/span ({} threads, {} dropped)",
+        name,
+        nanos,
+        THREADS,
+        tracer.dropped_spans()
+    );
+}
+
+fn main() {
+    let finish = |tracer: &Tracer<AllSampler, ()>, _: &SharedSpan<()>| {
+        tracer.span("foo").start_with_state(());
+    };
+    run("single_channel", Tracer::builder(AllSampler), finish);
+    run(
+        "sharded_channels",
+        Tracer::builder(AllSampler).shards(THREADS),
+        finish,
+    );
+    run(
+        "batched",
+        Tracer::builder(AllSampler).shards(THREADS).batch_size(64),
+        finish,
+    );
+    run(
+        "bounded_channel",
+        Tracer::builder(AllSampler).channel_capacity(8192),
+        finish,
+    );
+    #[cfg(feature = "lock-free-queue")]
+    run(
+        "lock_free_queue",
+        Tracer::builder(AllSampler)
+            .channel_capacity(8192)
+            .lock_free_queue(Duration::from_millis(1)),
+        finish,
+    );
+    #[cfg(feature = "lock-free-queue")]
+    run(
+        "sharded_lock_free_queues",
+        Tracer::builder(AllSampler)
+            .shards(THREADS)
+            .channel_capacity(8192)
+            .lock_free_queue(Duration::from_millis(1)),
+        finish,
+    );
+    run(
+        "shared_span_children",
+        Tracer::builder(AllSampler),
+        |_, parent: &SharedSpan<()>| {
+            let parent = parent.clone();
+            parent.child("child", |options| options.start_with_state(()));
+        },
+    );
+}
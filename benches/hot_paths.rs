@@ -0,0 +1,254 @@
+//! Benchmarks of the hot paths of the crate.
+//!
+//! Run with `cargo bench --bench hot_paths`.
+//!
+//! The results can be saved with `-- --save-baseline FILE` and compared with
+//! a previously saved file with `-- --baseline FILE`.
+//! When comparing, this exits with a non-zero status if any benchmark got slower
+//! by more than the threshold given by `--threshold PERCENT` (default: `20`).
+//!
+//! The number of heap allocations per iteration is also reported,
+//! and this exits with a non-zero status if any of the unsampled paths allocates.
+#![cfg_attr(rustracing_noop, allow(clippy::drop_non_drop))]
+use cf_rustracing::carrier::{ExtractFromTextMap, InjectToTextMap, TextMap};
+use cf_rustracing::sampler::{AllSampler, NullSampler};
+use cf_rustracing::span::{BaggageItem, CandidateSpan, SpanContext};
+use cf_rustracing::tag::Tag;
+use cf_rustracing::Tracer;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hint::black_box;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const MEASUREMENT_TIME: Duration = Duration::from_millis(500);
+
+/// Allocator counting the allocations made by the process.
+struct CountingAllocator;
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Debug, Clone)]
+struct BenchState(u64);
+impl<'a> From<CandidateSpan<'a, BenchState>> for BenchState {
+    fn from(_: CandidateSpan<'a, BenchState>) -> Self {
+        BenchState(1)
+    }
+}
+impl<C: TextMap> InjectToTextMap<C> for BenchState {
+    fn inject_to_text_map(
+        context: &SpanContext<Self>,
+        carrier: &mut C,
+    ) -> cf_rustracing::Result<()> {
+        carrier.set_fmt("bench-id", format_args!("{}", context.state().0));
+        Ok(())
+    }
+}
+impl<C: TextMap> ExtractFromTextMap<C> for BenchState {
+    fn extract_from_text_map(carrier: &C) -> cf_rustracing::Result<Option<SpanContext<Self>>> {
+        let id = match carrier.get("bench-id").and_then(|v| v.parse().ok()) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        Ok(Some(SpanContext::new(BenchState(id), Vec::new())))
+    }
+}
+
+fn main() {
+    let mut baseline = None;
+    let mut save_baseline = None;
+    let mut threshold = 20.0;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--baseline" => baseline = args.next(),
+            "--save-baseline" => save_baseline = args.next(),
+            "--threshold" => {
+                threshold = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(threshold)
+            }
+            _ => {}
+        }
+    }
+
+    let mut results = BTreeMap::new();
+    let mut allocating_unsampled = Vec::new();
+    let mut run = |name: &str, f: &mut dyn FnMut()| {
+        let (nanos, allocations) = measure(f);
+        println!(
+            "{:<20} {:>10.1} ns/iter {:>8.2} allocs/iter",
+            name, nanos, allocations
+        );
+        results.insert(name.to_owned(), nanos);
+        if name.ends_with("_unsampled") && allocations > 0.0 {
+            allocating_unsampled.push(name.to_owned());
+        }
+    };
+
+    let (unsampled, _unsampled_rx) = Tracer::<_, BenchState>::new(NullSampler);
+    run("start_unsampled", &mut || {
+        black_box(unsampled.span("foo").tag(Tag::new("foo", "bar")).start());
+    });
+
+    let mut span = unsampled.span("foo").start();
+    run("set_tag_unsampled", &mut || {
+        span.set_tag(|| Tag::new("foo", "bar"));
+    });
+    run("log_unsampled", &mut || {
+        span.log(|log| {
+            log.std().event("foo").message("bar");
+        });
+    });
+    run("child_unsampled", &mut || {
+        black_box(span.child("bar", |options| options.start()));
+    });
+    drop(span);
+
+    let parent = SpanContext::new(BenchState(1), vec![BaggageItem::new("user", "alice")]);
+    run("child_of_unsampled", &mut || {
+        black_box(unsampled.span("foo").child_of(&parent).start());
+    });
+
+    let (tracer, mut span_rx) = Tracer::<_, BenchState>::new(AllSampler);
+    run("start_sampled", &mut || {
+        black_box(tracer.span("foo").tag(Tag::new("foo", "bar")).start());
+        while span_rx.try_recv().is_ok() {}
+    });
+
+    let mut span = tracer.span("foo").start();
+    run("set_tag", &mut || {
+        span.set_tag(|| Tag::new("foo", "bar"));
+    });
+    // The span is replaced every `LOGS_PER_SPAN` iterations, so that its logs do not grow unboundedly.
+    const LOGS_PER_SPAN: u32 = 1024;
+    let mut logs = 0;
+    run("log", &mut || {
+        if logs == LOGS_PER_SPAN {
+            span = tracer.span("foo").start();
+            while span_rx.try_recv().is_ok() {}
+            logs = 0;
+        }
+        logs += 1;
+        span.log(|log| {
+            log.std().event("foo").message("bar");
+        });
+    });
+    drop(span);
+
+    run("finish_send", &mut || {
+        let span = tracer.span("foo").start();
+        drop(span);
+        while span_rx.try_recv().is_ok() {}
+    });
+
+    // A typical span, whose tag and log buffers are recycled through the pool of the tracer.
+    run("typical_span", &mut || {
+        let mut span = tracer
+            .span("foo")
+            .tag(Tag::new("http.method", "GET"))
+            .tag(Tag::new("http.route", "/users/:id"))
+            .tag(Tag::new("http.status_code", 200))
+            .tag(Tag::new("peer.service", "users"))
+            .tag(Tag::new("span.kind", "server"))
+            .start();
+        span.set_tag(|| Tag::new("db.system", "postgresql"));
+        span.log(|log| {
+            log.std().event("cache_miss");
+        });
+        span.log(|log| {
+            log.std().event("query").message("SELECT users");
+        });
+        drop(span);
+        while span_rx.try_recv().is_ok() {}
+    });
+
+    let context = SpanContext::new(BenchState(12345), Vec::new());
+    let mut carrier = HashMap::new();
+    run("inject", &mut || {
+        context.inject_to_text_map(&mut carrier).unwrap();
+    });
+    run("extract", &mut || {
+        black_box(SpanContext::<BenchState>::extract_from_text_map(&carrier).unwrap());
+    });
+
+    if !allocating_unsampled.is_empty() {
+        eprintln!(
+            "Allocations on unsampled paths detected: {}",
+            allocating_unsampled.join(", ")
+        );
+        process::exit(1);
+    }
+    if let Some(path) = save_baseline {
+        let text = results
+            .iter()
+            .map(|(name, nanos)| format!("{} {}\n", name, nanos))
+            .collect::<String>();
+        fs::write(&path, text).unwrap_or_else(|e| panic!("cannot write {:?}: {}", path, e));
+    }
+    if let Some(path) = baseline {
+        let text =
+            fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {:?}: {}", path, e));
+        let mut regressed = false;
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let (name, old) = match (
+                fields.next(),
+                fields.next().and_then(|v| v.parse::<f64>().ok()),
+            ) {
+                (Some(name), Some(old)) => (name, old),
+                _ => continue,
+            };
+            if let Some(new) = results.get(name) {
+                let change = (new - old) / old * 100.0;
+                println!("{:<20} {:>+9.1} %", name, change);
+                regressed |= change > threshold;
+            }
+        }
+        if regressed {
+            eprintln!(
+                "Performance regression detected (threshold: {}%)",
+                threshold
+            );
+            process::exit(1);
+        }
+    }
+}
+
+/// Returns the mean time of an iteration of `f` in nanoseconds
+/// and the mean number of the allocations made by an iteration.
+fn measure(f: &mut dyn FnMut()) -> (f64, f64) {
+    for _ in 0..1000 {
+        f();
+    }
+
+    let mut iterations = 0u64;
+    let mut batch = 1u64;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    while start.elapsed() < MEASUREMENT_TIME {
+        for _ in 0..batch {
+            f();
+        }
+        iterations += batch;
+        batch *= 2;
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / iterations as f64;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    (nanos, allocations as f64 / iterations as f64)
+}
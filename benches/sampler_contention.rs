@@ -0,0 +1,77 @@
+//! Benchmark of the stateful samplers under contention.
+//!
+//! Run with `cargo bench --bench sampler_contention`.
+//!
+//! Each sampler is driven by several threads concurrently and
+//! compared with an equivalent mutex-based implementation.
+use cf_rustracing::sampler::{AdaptiveSampler, RateLimitingSampler, Sampler};
+use cf_rustracing::span::CandidateSpan;
+use cf_rustracing::Tracer;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+const THREADS: usize = 8;
+const ITERATIONS: usize = 200_000;
+
+/// A token bucket protected by a mutex.
+struct MutexRateLimitingSampler {
+    rate: f64,
+    bucket: Mutex<(f64, Instant)>,
+}
+impl<T> Sampler<T> for MutexRateLimitingSampler {
+    fn is_sampled(&self, _span: &CandidateSpan<T>) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.1).as_secs_f64() * self.rate;
+        bucket.0 = (bucket.0 + refill).min(self.rate);
+        bucket.1 = now;
+        if bucket.0 >= 1.0 {
+            bucket.0 -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn run<S>(name: &str, sampler: S)
+where
+    S: Sampler<()> + Send + Sync + 'static,
+{
+    let (tracer, _span_rx) = Tracer::new(sampler);
+    let start = Instant::now();
+    let threads = (0..THREADS)
+        .map(|_| {
+            let tracer = tracer.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERATIONS {
+                    tracer.span("foo").start_with_state(());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / (THREADS * ITERATIONS) as f64;
+    println!(
+        "{:<24} {:>10.1} ns/decision ({} threads)",
+        name, nanos, THREADS
+    );
+}
+
+fn main() {
+    run(
+        "rate_limiting",
+        RateLimitingSampler::new(100.0).expect("valid rate"),
+    );
+    run(
+        "rate_limiting_mutex",
+        MutexRateLimitingSampler {
+            rate: 100.0,
+            bucket: Mutex::new((100.0, Instant::now())),
+        },
+    );
+    run("adaptive", AdaptiveSampler::new(100.0).expect("valid rate"));
+}
@@ -0,0 +1,224 @@
+//! Attribute macros for [cf-rustracing](https://docs.rs/cf-rustracing).
+//!
+//! This crate is re-exported by `cf-rustracing` when its `macros` feature is enabled.
+#![warn(missing_docs)]
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{parse_macro_input, Expr, Ident, ItemFn, LitStr, ReturnType, Token, Type, TypeInfer};
+
+/// Traces calls of the annotated function.
+///
+/// Each call starts a span with the global tracer of `cf_rustracing::global` as a child of
+/// the active span, makes it active while the function runs, and finishes it on exit.
+/// Both synchronous and `async` functions are supported.
+/// If the function returns a `Result`, an `Err` is recorded in the span with `Span::error_log`.
+///
+/// The following arguments are accepted:
+///
+/// - `name = "..."`: the operation name of the spans (the name of the function by default).
+/// - `tags(key = value, ...)`: tags set to the spans. Keys are identifiers or string literals.
+/// - `state = Type`: the state of the spans (`cf_rustracing::context::DefaultContext` by default).
+///
+/// The error types of the functions returning `Result` have to implement `Display`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing_macros::trace; // Or `cf_rustracing::trace` with the `macros` feature.
+///
+/// #[trace(name = "db.query", tags(component = "db", "db.system" = "postgresql"))]
+/// fn query(sql: &str) -> Result<usize, std::fmt::Error> {
+///     Ok(sql.len())
+/// }
+///
+/// #[trace]
+/// async fn handle() -> usize {
+///     query("SELECT 1").unwrap_or(0)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn trace(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as TraceArgs);
+    let item = parse_macro_input!(item as ItemFn);
+    match expand(args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(args: TraceArgs, item: ItemFn) -> syn::Result<TokenStream2> {
+    if let Some(constness) = item.sig.constness {
+        return Err(syn::Error::new_spanned(
+            constness,
+            "`#[trace]` cannot be applied to `const` functions",
+        ));
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    let name = args
+        .name
+        .unwrap_or_else(|| LitStr::new(&sig.ident.to_string(), sig.ident.span()));
+    let state = args
+        .state
+        .unwrap_or_else(|| syn::parse_quote!(::cf_rustracing::context::DefaultContext));
+    let tags = args.tags.iter().map(|(key, value)| {
+        quote! { .tag(::cf_rustracing::tag::Tag::new(#key, #value)) }
+    });
+    let stmts = &block.stmts;
+    let output = match &sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => {
+            // `impl Trait` cannot be written in `let` statements, so it is left to be inferred.
+            let mut ty = (**ty).clone();
+            InferImplTraits.visit_type_mut(&mut ty);
+            ty.to_token_stream()
+        }
+    };
+    let record_error = if returns_result(&sig.output) {
+        quote! {
+            if let ::std::result::Result::Err(e) = &__trace_result {
+                __trace_span.error_log(|log| {
+                    log.message(e.to_string());
+                });
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The `if false` branch fixes the type of the wrapped body, so that `?` and `return`
+    // in the body behave as in the original function.
+    let body = if sig.asyncness.is_some() {
+        quote! {
+            let __trace_result = ::cf_rustracing::scope::SpanScope::from_handle(__trace_span.handle())
+                .enter(async move {
+                    #[allow(unreachable_code)]
+                    if false {
+                        let __trace_unreachable: #output = loop {};
+                        return __trace_unreachable;
+                    }
+                    #(#stmts)*
+                })
+                .await;
+        }
+    } else {
+        quote! {
+            let __trace_guard = __trace_span.activate();
+            #[allow(clippy::redundant_closure_call)]
+            let __trace_result = (move || {
+                #[allow(unreachable_code)]
+                if false {
+                    let __trace_unreachable: #output = loop {};
+                    return __trace_unreachable;
+                }
+                #(#stmts)*
+            })();
+            ::std::mem::drop(__trace_guard);
+        }
+    };
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #[allow(unused_mut)]
+            let mut __trace_span = ::cf_rustracing::global::global_tracer::<#state>()
+                .span(#name)
+                .child_of_active()
+                #(#tags)*
+                .start();
+            #body
+            #record_error
+            __trace_result
+        }
+    })
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::Path(path) = &**ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Result")
+}
+
+/// Replaces the `impl Trait` types with `_`.
+struct InferImplTraits;
+impl VisitMut for InferImplTraits {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::ImplTrait(impl_trait) = ty {
+            *ty = Type::Infer(TypeInfer {
+                underscore_token: Token![_](impl_trait.impl_token.span),
+            });
+        } else {
+            visit_mut::visit_type_mut(self, ty);
+        }
+    }
+}
+
+#[derive(Default)]
+struct TraceArgs {
+    name: Option<LitStr>,
+    state: Option<Type>,
+    tags: Vec<(LitStr, Expr)>,
+}
+impl Parse for TraceArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = TraceArgs::default();
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key == "name" {
+                input.parse::<Token![=]>()?;
+                args.name = Some(input.parse()?);
+            } else if key == "state" {
+                input.parse::<Token![=]>()?;
+                args.state = Some(input.parse()?);
+            } else if key == "tags" {
+                let content;
+                syn::parenthesized!(content in input);
+                let tags = Punctuated::<Tag, Token![,]>::parse_terminated(&content)?;
+                args.tags
+                    .extend(tags.into_iter().map(|tag| (tag.key, tag.value)));
+            } else {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    "expected `name`, `state` or `tags`",
+                ));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+struct Tag {
+    key: LitStr,
+    value: Expr,
+}
+impl Parse for Tag {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key = if input.peek(LitStr) {
+            input.parse()?
+        } else {
+            let key: Ident = input.parse()?;
+            LitStr::new(&key.to_string(), key.span())
+        };
+        input.parse::<Token![=]>()?;
+        let value = input.parse()?;
+        Ok(Tag { key, value })
+    }
+}
@@ -0,0 +1,123 @@
+#![cfg(not(rustracing_noop))]
+use cf_rustracing::context::DefaultContext;
+use cf_rustracing::global::set_global_tracer;
+use cf_rustracing::sampler::{AllSampler, Sampler};
+use cf_rustracing::span::{CandidateSpan, SpanReceiver};
+use cf_rustracing::tag::TagValue;
+use cf_rustracing::Tracer;
+use cf_rustracing_macros::trace;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+struct Parser;
+impl Parser {
+    #[trace(tags(component = "parser", "parser.strict" = true))]
+    fn parse(&self, s: &str) -> Result<i32, std::num::ParseIntError> {
+        let n = s.parse::<i32>()?;
+        Ok(n)
+    }
+}
+
+#[trace(name = "outer")]
+fn outer() -> i32 {
+    Parser.parse("1").unwrap_or(0) + Parser.parse("x").unwrap_or(0)
+}
+
+#[trace]
+async fn run(n: i32) -> i32 {
+    tokio::task::yield_now().await;
+    if n < 0 {
+        return 0;
+    }
+    outer() + n
+}
+
+#[trace]
+fn evens(n: u32) -> impl Iterator<Item = u32> {
+    (0..n).filter(|i| i % 2 == 0)
+}
+
+#[trace]
+fn try_evens(n: u32) -> Result<impl Iterator<Item = u32>, String> {
+    if n == 0 {
+        return Err("empty".to_owned());
+    }
+    Ok(evens(n))
+}
+
+#[derive(Debug, Clone)]
+struct CustomState;
+impl<'a> From<CandidateSpan<'a, CustomState>> for CustomState {
+    fn from(_: CandidateSpan<'a, CustomState>) -> Self {
+        CustomState
+    }
+}
+
+#[trace(state = CustomState)]
+fn custom_state() {}
+
+/// Registers the global tracer once, since it is shared by the tests.
+fn span_rx() -> MutexGuard<'static, SpanReceiver<DefaultContext>> {
+    static SPAN_RX: OnceLock<Mutex<SpanReceiver<DefaultContext>>> = OnceLock::new();
+    SPAN_RX
+        .get_or_init(|| {
+            let (tracer, span_rx) = Tracer::new(AllSampler.boxed());
+            set_global_tracer::<DefaultContext>(tracer);
+            Mutex::new(span_rx)
+        })
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+#[test]
+fn sync_functions_are_traced() {
+    let mut span_rx = span_rx();
+    assert_eq!(outer(), 1);
+    custom_state();
+
+    let ok = span_rx.try_recv().unwrap();
+    assert_eq!(ok.operation_name(), "parse");
+    assert_eq!(ok.tags().len(), 2);
+    assert_eq!(ok.tags()[1].value(), &TagValue::Boolean(true));
+    assert!(ok.logs().is_empty());
+
+    let err = span_rx.try_recv().unwrap();
+    assert_eq!(err.logs().len(), 1);
+    assert!(err.tags().iter().any(|t| t.name() == "error"));
+
+    let outer = span_rx.try_recv().unwrap();
+    assert_eq!(outer.operation_name(), "outer");
+    assert_eq!(ok.references()[0].span(), outer.context().state());
+    assert_eq!(err.references()[0].span(), outer.context().state());
+    assert!(outer.references().is_empty());
+}
+
+#[test]
+fn async_functions_are_traced() {
+    let mut span_rx = span_rx();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    assert_eq!(rt.block_on(run(-1)), 0);
+    assert_eq!(span_rx.try_recv().unwrap().operation_name(), "run");
+
+    assert_eq!(rt.block_on(run(1)), 2);
+    let mut names = Vec::new();
+    while let Ok(span) = span_rx.try_recv() {
+        names.push(span.operation_name().to_owned());
+    }
+    assert_eq!(names, ["parse", "parse", "outer", "run"]);
+}
+
+#[test]
+fn impl_trait_returns_are_traced() {
+    let mut span_rx = span_rx();
+    assert_eq!(evens(5).collect::<Vec<_>>(), [0, 2, 4]);
+    assert_eq!(span_rx.try_recv().unwrap().operation_name(), "evens");
+
+    assert_eq!(try_evens(3).unwrap().count(), 2);
+    assert!(try_evens(0).is_err());
+    let names = [span_rx.try_recv(), span_rx.try_recv(), span_rx.try_recv()]
+        .map(|span| span.unwrap().operation_name().to_owned());
+    assert_eq!(names, ["evens", "try_evens", "try_evens"]);
+    assert!(span_rx.try_recv().is_err());
+}
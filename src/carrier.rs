@@ -1,10 +1,25 @@
 //! Traits for representing carriers that propagate span contexts across process boundaries.
-use crate::span::SpanContext;
-use crate::Result;
+use crate::span::{SpanContext, TraceFlags};
+use crate::{Error, ErrorKind, Result};
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::hash::{BuildHasher, Hash};
 use std::io::{Read, Write};
 
+pub use self::b3::B3Propagator;
+pub use self::binary::{BinaryPropagator, CompactBinaryPropagator};
+pub use self::propagators::Propagators;
+pub(crate) use self::w3c::{format_baggage, parse_baggage};
+pub use self::w3c::{
+    TextMapPropagator, TraceContextPropagator, TraceContextState, TraceParent, TraceState, BAGGAGE,
+    TRACEPARENT, TRACESTATE,
+};
+
+mod b3;
+mod binary;
+mod propagators;
+mod w3c;
+
 /// This trait allows to inject `SpanContext` to `TextMap`.
 pub trait InjectToTextMap<T>: Sized
 where
@@ -33,12 +48,39 @@ pub trait TextMap {
     /// Sets the value of `key` in the map to `value`.
     fn set(&mut self, key: &str, value: &str);
 
+    /// Sets the value of `key` in the map to the formatted `value`.
+    ///
+    /// Carriers can override this to format `value` directly into their own buffers.
+    /// The default implementation formats `value` into a temporary `String` and calls `set`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::carrier::TextMap;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.set_fmt("trace-id", format_args!("{:016x}", 0xabcu64));
+    /// assert_eq!(TextMap::get(&map, "trace-id"), Some("0000000000000abc"));
+    /// ```
+    fn set_fmt(&mut self, key: &str, value: fmt::Arguments<'_>) {
+        self.set(key, &value.to_string());
+    }
+
     /// Gets the value of `key'.
     fn get(&self, key: &str) -> Option<&str>;
 }
 impl<S: BuildHasher> TextMap for HashMap<String, String, S> {
     fn set(&mut self, key: &str, value: &str) {
-        self.insert(key.to_owned(), value.to_owned());
+        self.set_fmt(key, format_args!("{}", value));
+    }
+    fn set_fmt(&mut self, key: &str, value: fmt::Arguments<'_>) {
+        match self.get_mut(key) {
+            Some(buf) => overwrite(buf, value),
+            None => {
+                self.insert(key.to_owned(), value.to_string());
+            }
+        }
     }
     fn get(&self, key: &str) -> Option<&str> {
         HashMap::get(self, key).map(|v| v.as_ref())
@@ -46,13 +88,37 @@ impl<S: BuildHasher> TextMap for HashMap<String, String, S> {
 }
 impl TextMap for BTreeMap<String, String> {
     fn set(&mut self, key: &str, value: &str) {
-        self.insert(key.to_owned(), value.to_owned());
+        self.set_fmt(key, format_args!("{}", value));
+    }
+    fn set_fmt(&mut self, key: &str, value: fmt::Arguments<'_>) {
+        match self.get_mut(key) {
+            Some(buf) => overwrite(buf, value),
+            None => {
+                self.insert(key.to_owned(), value.to_string());
+            }
+        }
     }
     fn get(&self, key: &str) -> Option<&str> {
         BTreeMap::get(self, key).map(|v| v.as_ref())
     }
 }
 
+/// Replaces the content of `buf` with `value` reusing the allocated buffer.
+fn overwrite(buf: &mut String, value: fmt::Arguments<'_>) {
+    use std::fmt::Write as _;
+    buf.clear();
+    let _ = buf.write_fmt(value);
+}
+
+/// Returns the `TraceFlags` carried by `traceparent`, which has only the sampled flag.
+pub(crate) fn trace_flags(traceparent: &TraceParent) -> TraceFlags {
+    if traceparent.is_sampled() {
+        TraceFlags::SAMPLED
+    } else {
+        TraceFlags::default()
+    }
+}
+
 /// This trait allows to inject `SpanContext` to HTTP header.
 pub trait InjectToHttpHeader<T>: Sized
 where
@@ -77,14 +143,94 @@ where
 pub trait SetHttpHeaderField {
     /// Sets the value of the field named `name` in the HTTP header to `value`.
     fn set_http_header_field(&mut self, name: &str, value: &str) -> Result<()>;
+
+    /// Sets the value of the field named `name` in the HTTP header to the formatted `value`.
+    ///
+    /// Carriers can override this to format `value` directly into their own buffers.
+    /// The default implementation formats `value` into a temporary `String`
+    /// and calls `set_http_header_field`.
+    fn set_http_header_field_fmt(&mut self, name: &str, value: fmt::Arguments<'_>) -> Result<()> {
+        track!(self.set_http_header_field(name, &value.to_string()))
+    }
 }
 impl<S: BuildHasher> SetHttpHeaderField for HashMap<String, String, S> {
     fn set_http_header_field(&mut self, name: &str, value: &str) -> Result<()> {
-        self.insert(name.to_owned(), value.to_owned());
+        self.set_http_header_field_fmt(name, format_args!("{}", value))
+    }
+    fn set_http_header_field_fmt(&mut self, name: &str, value: fmt::Arguments<'_>) -> Result<()> {
+        TextMap::set_fmt(self, name, value);
         Ok(())
     }
 }
 
+/// HTTP header carrier that writes fields into a caller-provided buffer.
+///
+/// Each field is written as a `"{name}: {value}\r\n"` line
+/// without allocating intermediate strings.
+///
+/// Names containing other characters than visible ASCII, or colons, and values containing
+/// other characters than visible ASCII, spaces and tabs are rejected with `ErrorKind::InvalidInput`,
+/// so that line breaks cannot inject other fields into the header.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::{HeaderWriter, SetHttpHeaderField};
+///
+/// let mut buf = String::from("GET / HTTP/1.1\r\n");
+/// let mut writer = HeaderWriter::new(&mut buf);
+/// writer.set_http_header_field("x-foo", "bar").unwrap();
+/// writer
+///     .set_http_header_field_fmt("x-span-id", format_args!("{:x}", 255))
+///     .unwrap();
+/// assert_eq!(buf, "GET / HTTP/1.1\r\nx-foo: bar\r\nx-span-id: ff\r\n");
+/// ```
+#[derive(Debug)]
+pub struct HeaderWriter<'a, W: 'a> {
+    buf: &'a mut W,
+}
+impl<'a, W: fmt::Write> HeaderWriter<'a, W> {
+    /// Makes a new `HeaderWriter` instance which writes fields into `buf`.
+    pub fn new(buf: &'a mut W) -> Self {
+        HeaderWriter { buf }
+    }
+}
+impl<'a, W: fmt::Write> SetHttpHeaderField for HeaderWriter<'a, W> {
+    fn set_http_header_field(&mut self, name: &str, value: &str) -> Result<()> {
+        self.set_http_header_field_fmt(name, format_args!("{}", value))
+    }
+    fn set_http_header_field_fmt(&mut self, name: &str, value: fmt::Arguments<'_>) -> Result<()> {
+        track_assert!(
+            !name.is_empty() && name.bytes().all(|b| b != b':' && b.is_ascii_graphic()),
+            ErrorKind::InvalidInput,
+            "Invalid header field name: {:?}",
+            name
+        );
+        track_assert!(
+            fmt::write(&mut FieldValueChecker, value).is_ok(),
+            ErrorKind::InvalidInput,
+            "Invalid value of header field {:?}",
+            name
+        );
+        track!(write!(self.buf, "{}: {}\r\n", name, value)
+            .map_err(|e| Error::with_source(ErrorKind::CarrierIo, e)))
+    }
+}
+
+/// Sink which fails if a formatted header field value contains disallowed characters.
+struct FieldValueChecker;
+impl fmt::Write for FieldValueChecker {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if s.bytes()
+            .all(|b| b == b'\t' || b == b' ' || b.is_ascii_graphic())
+        {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
 /// This trait allows to iterate over the fields of a HTTP header.
 pub trait IterHttpHeaderFields<'a> {
     /// Iterator for traversing HTTP header fields.
@@ -125,3 +271,51 @@ where
     /// If `carrier` contains no span context, it will return `Ok(None)`.
     fn extract_from_binary(carrier: &mut T) -> Result<Option<SpanContext<Self>>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_writer_rejects_invalid_fields() {
+        let mut buf = String::new();
+        let mut writer = HeaderWriter::new(&mut buf);
+        for (name, value) in [
+            ("x-foo", "bar\r\nx-injected: 1"),
+            ("x-foo", "bar\n"),
+            ("x-foo", "caf\u{e9}"),
+            ("x-foo", "\u{7f}"),
+            ("x-foo\r\nx-injected", "1"),
+            ("x foo", "bar"),
+            ("x-foo:", "bar"),
+            ("", "bar"),
+        ] {
+            let e = writer.set_http_header_field(name, value).unwrap_err();
+            assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        }
+        let e = writer
+            .set_http_header_field_fmt("x-foo", format_args!("{}\r\n", 1))
+            .unwrap_err();
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+
+        let mut writer = HeaderWriter::new(&mut buf);
+        writer.set_http_header_field("x-foo", "a b\tc").unwrap();
+        assert_eq!(buf, "x-foo: a b\tc\r\n");
+    }
+
+    #[test]
+    fn set_fmt_reuses_buffers() {
+        let mut map = HashMap::new();
+        map.set_fmt("foo", format_args!("{}-{}", "bar", 12345));
+        let ptr = TextMap::get(&map, "foo").unwrap().as_ptr();
+        map.set_fmt("foo", format_args!("{}", 1));
+        assert_eq!(TextMap::get(&map, "foo"), Some("1"));
+        assert_eq!(TextMap::get(&map, "foo").unwrap().as_ptr(), ptr);
+
+        let mut map = BTreeMap::new();
+        map.set("foo", "bar");
+        map.set_fmt("foo", format_args!("{:x}", 255));
+        assert_eq!(TextMap::get(&map, "foo"), Some("ff"));
+    }
+}
@@ -0,0 +1,289 @@
+//! [B3] propagation used by Zipkin.
+//!
+//! [B3]: https://github.com/openzipkin/b3-propagation
+use super::{TextMap, TextMapPropagator, TraceContextState, TraceParent};
+use crate::span::{SpanContext, TraceFlags};
+use crate::{Error, ErrorKind, Result};
+
+const B3: &str = "b3";
+const TRACE_ID: [&str; 2] = ["X-B3-TraceId", "x-b3-traceid"];
+const SPAN_ID: [&str; 2] = ["X-B3-SpanId", "x-b3-spanid"];
+const SAMPLED: [&str; 2] = ["X-B3-Sampled", "x-b3-sampled"];
+const FLAGS: [&str; 2] = ["X-B3-Flags", "x-b3-flags"];
+
+/// `TextMapPropagator` for the B3 formats.
+///
+/// Contexts are injected in either the single-header format (the `b3` field)
+/// or the multi-header format (the `X-B3-TraceId`, `X-B3-SpanId` and `X-B3-Sampled` fields),
+/// and extracted from whichever is present (the single header takes precedence).
+/// Field names of the multi-header format are also looked up in lowercase.
+///
+/// The sampled flag of the states is set if the sampling state is accept or debug.
+/// The debug sampling state is also mapped to and from `TraceFlags::DEBUG` of the contexts.
+/// Parent span identifiers are neither injected nor extracted.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::{B3Propagator, TextMapPropagator};
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::span::SpanContext;
+/// use std::collections::HashMap;
+///
+/// let mut carrier = HashMap::new();
+/// carrier.insert("b3".to_owned(), "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1".to_owned());
+/// let context: SpanContext<DefaultContext> = B3Propagator::single().extract(&carrier).unwrap().unwrap();
+/// assert_eq!(context.state().span_id(), 0xe457b5a2e4d86bd1);
+/// assert!(context.state().is_sampled());
+///
+/// let mut carrier = HashMap::new();
+/// B3Propagator::multi().inject(&context, &mut carrier).unwrap();
+/// assert_eq!(carrier["X-B3-TraceId"], "80f198ee56343ba864fe8b2a57d3eff7");
+/// assert_eq!(carrier["X-B3-Sampled"], "1");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct B3Propagator {
+    single_header: bool,
+}
+impl B3Propagator {
+    /// Makes a new `B3Propagator` instance which injects contexts in the single-header format.
+    pub fn single() -> Self {
+        B3Propagator {
+            single_header: true,
+        }
+    }
+
+    /// Makes a new `B3Propagator` instance which injects contexts in the multi-header format.
+    pub fn multi() -> Self {
+        B3Propagator {
+            single_header: false,
+        }
+    }
+}
+impl<T: TraceContextState> TextMapPropagator<T> for B3Propagator {
+    fn inject<C: TextMap>(&self, context: &SpanContext<T>, carrier: &mut C) -> Result<()> {
+        let traceparent = context.state().traceparent();
+        let debug = context.flags().is_debug();
+        let sampled = match (debug, traceparent.is_sampled()) {
+            (true, _) => "d",
+            (false, true) => "1",
+            (false, false) => "0",
+        };
+        if self.single_header {
+            carrier.set_fmt(
+                B3,
+                format_args!(
+                    "{:032x}-{:016x}-{}",
+                    traceparent.trace_id(),
+                    traceparent.span_id(),
+                    sampled
+                ),
+            );
+        } else {
+            carrier.set_fmt(TRACE_ID[0], format_args!("{:032x}", traceparent.trace_id()));
+            carrier.set_fmt(SPAN_ID[0], format_args!("{:016x}", traceparent.span_id()));
+            // Debug implies accept, so the sampling state is not sent.
+            if debug {
+                carrier.set(FLAGS[0], "1");
+            } else {
+                carrier.set(SAMPLED[0], sampled);
+            }
+        }
+        Ok(())
+    }
+
+    fn extract<C: TextMap>(&self, carrier: &C) -> Result<Option<SpanContext<T>>> {
+        let traceparent = if let Some(value) = carrier.get(B3) {
+            track!(parse_single(value))?
+        } else if let Some(trace_id) = get(carrier, TRACE_ID) {
+            let span_id = get(carrier, SPAN_ID).ok_or_else(invalid)?;
+            let debug = get(carrier, FLAGS).is_some_and(|f| f.trim() == "1");
+            let sampled = match get(carrier, SAMPLED).map(str::trim) {
+                Some("1" | "true") => true,
+                Some("0" | "false") | None => false,
+                Some(_) => return Err(invalid()),
+            };
+            let traceparent = TraceParent::new(
+                track!(parse_trace_id(trace_id))?,
+                track!(parse_span_id(span_id))?,
+                flags(sampled || debug),
+            );
+            Some((traceparent, debug))
+        } else {
+            None
+        };
+        Ok(traceparent.map(|(t, debug)| {
+            let mut flags = super::trace_flags(&t);
+            if debug {
+                flags |= TraceFlags::DEBUG;
+            }
+            SpanContext::new(T::from_trace_context(t, None), Vec::new()).with_flags(flags)
+        }))
+    }
+}
+
+fn get<'a, C: TextMap>(carrier: &'a C, names: [&str; 2]) -> Option<&'a str> {
+    names.iter().find_map(|name| carrier.get(name))
+}
+
+/// Parses a `b3` value, returning whether the sampling state is debug as well.
+///
+/// This returns `None` if the value has only the sampling state.
+fn parse_single(value: &str) -> Result<Option<(TraceParent, bool)>> {
+    let mut fields = value.trim().split('-');
+    let trace_id = fields.next().unwrap_or("");
+    let span_id = match fields.next() {
+        Some(span_id) => span_id,
+        None if matches!(trace_id, "0" | "1" | "d") => return Ok(None),
+        None => return Err(invalid()),
+    };
+    let (sampled, debug) = match fields.next() {
+        Some("1") => (true, false),
+        Some("d") => (true, true),
+        Some("0") | None => (false, false),
+        Some(_) => return Err(invalid()),
+    };
+    let parent_is_valid = fields.next().map_or(true, |p| parse_span_id(p).is_ok());
+    if !parent_is_valid || fields.next().is_some() {
+        return Err(invalid());
+    }
+    let traceparent = TraceParent::new(
+        track!(parse_trace_id(trace_id))?,
+        track!(parse_span_id(span_id))?,
+        flags(sampled),
+    );
+    Ok(Some((traceparent, debug)))
+}
+
+fn parse_trace_id(value: &str) -> Result<u128> {
+    let value = value.trim();
+    if value.len() != 16 && value.len() != 32 {
+        return Err(invalid());
+    }
+    match parse_hex(value) {
+        Some(0) | None => Err(invalid()),
+        Some(id) => Ok(id),
+    }
+}
+
+fn parse_span_id(value: &str) -> Result<u64> {
+    let value = value.trim();
+    if value.len() != 16 {
+        return Err(invalid());
+    }
+    match parse_hex(value) {
+        Some(0) | None => Err(invalid()),
+        Some(id) => Ok(id as u64),
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u128> {
+    if !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u128::from_str_radix(value, 16).ok()
+}
+
+fn flags(sampled: bool) -> u8 {
+    if sampled {
+        TraceParent::SAMPLED
+    } else {
+        0
+    }
+}
+
+fn invalid() -> Error {
+    Error::from(ErrorKind::CarrierParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DefaultContext;
+    use std::collections::HashMap;
+
+    fn extract(fields: &[(&str, &str)]) -> Result<Option<DefaultContext>> {
+        let carrier = fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        let context: Option<SpanContext<DefaultContext>> =
+            track!(B3Propagator::single().extract(&carrier))?;
+        Ok(context.map(|c| *c.state()))
+    }
+
+    #[test]
+    fn b3_headers_are_extracted() {
+        let context = track_try_unwrap!(extract(&[(
+            "b3",
+            "463ac35c9f6413ad-a2fb4a1d1a96d312-d-0020000000000001"
+        )]))
+        .unwrap();
+        assert_eq!(
+            context,
+            DefaultContext::new(0x463ac35c9f6413ad, 0xa2fb4a1d1a96d312, 1)
+        );
+
+        let context = track_try_unwrap!(extract(&[
+            ("x-b3-traceid", "463ac35c9f6413ad48485a3953bb6124"),
+            ("x-b3-spanid", "a2fb4a1d1a96d312"),
+            ("x-b3-sampled", "0"),
+        ]))
+        .unwrap();
+        assert_eq!(context.trace_id(), 0x463ac35c9f6413ad48485a3953bb6124);
+        assert!(!context.is_sampled());
+
+        assert!(track_try_unwrap!(extract(&[("b3", "0")])).is_none());
+        assert!(track_try_unwrap!(extract(&[])).is_none());
+        for invalid in [
+            "463ac35c9f6413ad",
+            "463ac35c9f6413ad-a2fb4a1d1a96d312-x",
+            "463ac35c9f6413ad-0000000000000000-1",
+            "463ac35c9f6413a-a2fb4a1d1a96d312-1",
+            "463ac35c9f6413ad-a2fb4a1d1a96d312-1-zz",
+        ] {
+            assert!(extract(&[("b3", invalid)]).is_err(), "{}", invalid);
+        }
+        assert!(extract(&[("X-B3-TraceId", "463ac35c9f6413ad")]).is_err());
+    }
+
+    #[test]
+    fn b3_headers_are_injected() {
+        let context = SpanContext::new(DefaultContext::new(0xabc, 0x12, 1), Vec::new());
+        for propagator in [B3Propagator::single(), B3Propagator::multi()] {
+            let mut carrier = HashMap::new();
+            track_try_unwrap!(propagator.inject(&context, &mut carrier));
+            let extracted: SpanContext<DefaultContext> =
+                track_try_unwrap!(propagator.extract(&carrier)).unwrap();
+            assert_eq!(extracted.state(), context.state());
+        }
+    }
+
+    #[test]
+    fn b3_debug_is_propagated() {
+        let state = DefaultContext::new(0xabc, 0x12, 1);
+        let context = SpanContext::new(state, Vec::new()).with_flags(TraceFlags::DEBUG);
+
+        let mut carrier = HashMap::new();
+        track_try_unwrap!(B3Propagator::single().inject(&context, &mut carrier));
+        assert!(carrier["b3"].ends_with("-d"));
+
+        let mut carrier = HashMap::new();
+        track_try_unwrap!(B3Propagator::multi().inject(&context, &mut carrier));
+        assert_eq!(carrier["X-B3-Flags"], "1");
+        assert!(!carrier.contains_key("X-B3-Sampled"));
+        let extracted: SpanContext<DefaultContext> =
+            track_try_unwrap!(B3Propagator::multi().extract(&carrier)).unwrap();
+        assert_eq!(extracted.state(), &state);
+        assert_eq!(extracted.flags(), TraceFlags::SAMPLED | TraceFlags::DEBUG);
+
+        carrier.clear();
+        carrier.insert(
+            "b3".to_owned(),
+            "463ac35c9f6413ad-a2fb4a1d1a96d312-1".to_owned(),
+        );
+        let extracted: SpanContext<DefaultContext> =
+            track_try_unwrap!(B3Propagator::single().extract(&carrier)).unwrap();
+        assert_eq!(extracted.flags(), TraceFlags::SAMPLED);
+    }
+}
@@ -0,0 +1,194 @@
+//! Compact binary encoding of span contexts.
+use super::{TraceContextState, TraceParent};
+use crate::span::{BaggageItem, SpanContext};
+use crate::{Error, ErrorKind, Result};
+use std::io::{self, Read, Write};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// The maximum number of the baggage items extracted from a binary carrier.
+const MAX_BAGGAGE_ITEMS: u32 = 180;
+
+/// The maximum length of a name or value of a baggage item in a binary carrier.
+const MAX_BAGGAGE_LEN: u32 = 8192;
+
+/// Propagator which injects span contexts into and extracts them from binary streams.
+pub trait BinaryPropagator<T> {
+    /// Injects `context` to `carrier`.
+    fn inject<W: Write>(&self, context: &SpanContext<T>, carrier: &mut W) -> Result<()>;
+
+    /// Extracts a context from `carrier`.
+    ///
+    /// If `carrier` is empty, it will return `Ok(None)`.
+    fn extract<R: Read>(&self, carrier: &mut R) -> Result<Option<SpanContext<T>>>;
+}
+
+/// `BinaryPropagator` using a compact fixed layout.
+///
+/// Contexts are written in the following layout (integers are big-endian):
+/// version (`0u8`, or `1u8` if the context has a deadline), trace id (`u128`),
+/// span id (`u64`), flags (`u8`), the deadline in milliseconds since the Unix epoch
+/// (`u64`, only in version `1`), the number of baggage items (`u32`), and for each item,
+/// the length (`u32`) and bytes of its name and value.
+/// A context without baggage items nor a deadline takes 30 bytes.
+///
+/// The `tracestate` of states is not propagated.
+/// Extraction rejects more than 180 baggage items and names or values longer than 8192 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::{BinaryPropagator, CompactBinaryPropagator};
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::span::SpanContext;
+///
+/// let context = SpanContext::new(DefaultContext::new(1, 2, 1), Vec::new());
+/// let mut buf = Vec::new();
+/// CompactBinaryPropagator.inject(&context, &mut buf).unwrap();
+/// assert_eq!(buf.len(), 30);
+///
+/// let extracted: SpanContext<DefaultContext> =
+///     CompactBinaryPropagator.extract(&mut &buf[..]).unwrap().unwrap();
+/// assert_eq!(extracted.state(), context.state());
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompactBinaryPropagator;
+impl<T: TraceContextState> BinaryPropagator<T> for CompactBinaryPropagator {
+    fn inject<W: Write>(&self, context: &SpanContext<T>, carrier: &mut W) -> Result<()> {
+        let traceparent = context.state().traceparent();
+        let write = |carrier: &mut W, bytes: &[u8]| {
+            carrier
+                .write_all(bytes)
+                .map_err(|e| Error::with_source(ErrorKind::CarrierIo, e))
+        };
+        let deadline = context.deadline();
+        track!(write(carrier, &[u8::from(deadline.is_some())]))?;
+        track!(write(carrier, &traceparent.trace_id().to_be_bytes()))?;
+        track!(write(carrier, &traceparent.span_id().to_be_bytes()))?;
+        track!(write(carrier, &[traceparent.flags()]))?;
+        if let Some(deadline) = deadline {
+            let millis = deadline
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64);
+            track!(write(carrier, &millis.to_be_bytes()))?;
+        }
+        let items = context.baggage_items();
+        track!(write(carrier, &(items.len() as u32).to_be_bytes()))?;
+        for item in items {
+            for s in [item.name(), item.value()] {
+                track!(write(carrier, &(s.len() as u32).to_be_bytes()))?;
+                track!(write(carrier, s.as_bytes()))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn extract<R: Read>(&self, carrier: &mut R) -> Result<Option<SpanContext<T>>> {
+        let mut version = [0; 1];
+        match carrier.read(&mut version) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(Error::with_source(ErrorKind::CarrierIo, e)),
+        }
+        if version[0] > 1 {
+            return Err(invalid());
+        }
+
+        let trace_id = u128::from_be_bytes(track!(read_array(carrier))?);
+        let span_id = u64::from_be_bytes(track!(read_array(carrier))?);
+        let [flags] = track!(read_array(carrier))?;
+        let deadline = if version[0] == 1 {
+            let millis = u64::from_be_bytes(track!(read_array(carrier))?);
+            let deadline = UNIX_EPOCH.checked_add(Duration::from_millis(millis));
+            Some(deadline.ok_or_else(invalid)?)
+        } else {
+            None
+        };
+        let count = u32::from_be_bytes(track!(read_array(carrier))?);
+        if trace_id == 0 || span_id == 0 || count > MAX_BAGGAGE_ITEMS {
+            return Err(invalid());
+        }
+        let mut items = Vec::new();
+        for _ in 0..count {
+            let name = track!(read_string(carrier))?;
+            let value = track!(read_string(carrier))?;
+            items.push(BaggageItem::new(&name, &value));
+        }
+        let traceparent = TraceParent::new(trace_id, span_id, flags);
+        let context = SpanContext::new(T::from_trace_context(traceparent, None), items)
+            .with_flags(super::trace_flags(&traceparent));
+        Ok(Some(match deadline {
+            Some(deadline) => context.with_deadline(deadline),
+            None => context,
+        }))
+    }
+}
+
+fn read_array<R: Read, const N: usize>(carrier: &mut R) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    carrier.read_exact(&mut buf).map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            invalid()
+        } else {
+            Error::with_source(ErrorKind::CarrierIo, e)
+        }
+    })?;
+    Ok(buf)
+}
+
+fn read_string<R: Read>(carrier: &mut R) -> Result<String> {
+    let len = u32::from_be_bytes(track!(read_array(carrier))?);
+    if len > MAX_BAGGAGE_LEN {
+        return Err(invalid());
+    }
+    let mut buf = Vec::new();
+    let read = carrier
+        .take(u64::from(len))
+        .read_to_end(&mut buf)
+        .map_err(|e| Error::with_source(ErrorKind::CarrierIo, e))?;
+    if read != len as usize {
+        return Err(invalid());
+    }
+    String::from_utf8(buf).map_err(|_| invalid())
+}
+
+fn invalid() -> Error {
+    Error::from(ErrorKind::CarrierParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DefaultContext;
+
+    #[test]
+    fn malformed_binary_contexts_are_rejected() {
+        let context = SpanContext::new(
+            DefaultContext::new(1, 2, 1),
+            vec![BaggageItem::new("foo", "bar")],
+        );
+        let mut buf = Vec::new();
+        track_try_unwrap!(CompactBinaryPropagator.inject(&context, &mut buf));
+
+        let extract = |bytes: &[u8]| -> Result<Option<SpanContext<DefaultContext>>> {
+            CompactBinaryPropagator.extract(&mut &bytes[..])
+        };
+        assert!(track_try_unwrap!(extract(&buf)).is_some());
+        assert!(track_try_unwrap!(extract(&[])).is_none());
+        for len in 1..buf.len() {
+            let e = extract(&buf[..len]).err().unwrap();
+            assert_eq!(*e.kind(), ErrorKind::CarrierParse);
+        }
+
+        buf[0] = 2;
+        assert!(extract(&buf).is_err());
+
+        // Out-of-range deadlines must not panic, whatever the range of `SystemTime` is.
+        let mut buf = vec![1];
+        buf.extend_from_slice(&1u128.to_be_bytes());
+        buf.extend_from_slice(&2u64.to_be_bytes());
+        buf.push(1);
+        buf.extend_from_slice(&u64::MAX.to_be_bytes());
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        let _ = extract(&buf);
+    }
+}
@@ -0,0 +1,171 @@
+//! Registry of propagators used together.
+use super::{TextMap, TextMapPropagator};
+use crate::span::SpanContext;
+use crate::Result;
+use std::fmt;
+
+/// `TextMapPropagator` combining an ordered list of propagators.
+///
+/// Injection writes the fields of every configured propagator.
+/// Extraction tries the propagators in order and returns the first context found;
+/// errors are returned only if no propagator finds a context.
+///
+/// This allows services bridging different tracing ecosystems
+/// to accept and emit several header formats at once.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::{B3Propagator, Propagators, TextMapPropagator, TraceContextPropagator};
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::span::SpanContext;
+/// use std::collections::HashMap;
+///
+/// let propagators = Propagators::new()
+///     .propagator(TraceContextPropagator)
+///     .propagator(B3Propagator::single());
+///
+/// let mut carrier = HashMap::new();
+/// carrier.insert("b3".to_owned(), "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1".to_owned());
+/// let context: SpanContext<DefaultContext> = propagators.extract(&carrier).unwrap().unwrap();
+///
+/// let mut carrier = HashMap::new();
+/// propagators.inject(&context, &mut carrier).unwrap();
+/// assert!(carrier.contains_key("traceparent"));
+/// assert!(carrier.contains_key("b3"));
+/// ```
+pub struct Propagators<T> {
+    propagators: Vec<Box<dyn DynTextMapPropagator<T> + Send + Sync>>,
+}
+impl<T> Propagators<T> {
+    /// Makes a new `Propagators` instance without propagators.
+    pub fn new() -> Self {
+        Propagators {
+            propagators: Vec::new(),
+        }
+    }
+
+    /// Appends `propagator` to the list of the propagators.
+    pub fn propagator<P>(mut self, propagator: P) -> Self
+    where
+        P: TextMapPropagator<T> + Send + Sync + 'static,
+    {
+        self.propagators.push(Box::new(propagator));
+        self
+    }
+
+    /// Returns the number of the propagators.
+    pub fn len(&self) -> usize {
+        self.propagators.len()
+    }
+
+    /// Returns `true` if there are no propagators.
+    pub fn is_empty(&self) -> bool {
+        self.propagators.is_empty()
+    }
+}
+impl<T> Default for Propagators<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T> fmt::Debug for Propagators<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Propagators")
+            .field("len", &self.propagators.len())
+            .finish_non_exhaustive()
+    }
+}
+impl<T> TextMapPropagator<T> for Propagators<T> {
+    fn inject<C: TextMap>(&self, context: &SpanContext<T>, carrier: &mut C) -> Result<()> {
+        for propagator in &self.propagators {
+            track!(propagator.inject_dyn(context, carrier))?;
+        }
+        Ok(())
+    }
+
+    fn extract<C: TextMap>(&self, carrier: &C) -> Result<Option<SpanContext<T>>> {
+        let mut error = None;
+        for propagator in &self.propagators {
+            match propagator.extract_dyn(carrier) {
+                Ok(Some(context)) => return Ok(Some(context)),
+                Ok(None) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) => Err(track!(e)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Object-safe version of `TextMapPropagator`.
+trait DynTextMapPropagator<T> {
+    fn inject_dyn(&self, context: &SpanContext<T>, carrier: &mut dyn TextMap) -> Result<()>;
+    fn extract_dyn(&self, carrier: &dyn TextMap) -> Result<Option<SpanContext<T>>>;
+}
+impl<T, P: TextMapPropagator<T>> DynTextMapPropagator<T> for P {
+    fn inject_dyn(&self, context: &SpanContext<T>, carrier: &mut dyn TextMap) -> Result<()> {
+        track!(self.inject(context, &mut DynTextMap(carrier)))
+    }
+
+    fn extract_dyn(&self, carrier: &dyn TextMap) -> Result<Option<SpanContext<T>>> {
+        track!(self.extract(&DynTextMapRef(carrier)))
+    }
+}
+
+struct DynTextMap<'a>(&'a mut dyn TextMap);
+impl TextMap for DynTextMap<'_> {
+    fn set(&mut self, key: &str, value: &str) {
+        self.0.set(key, value);
+    }
+    fn set_fmt(&mut self, key: &str, value: fmt::Arguments<'_>) {
+        self.0.set_fmt(key, value);
+    }
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)
+    }
+}
+
+/// Read-only carrier passed to extraction, which never sets values.
+struct DynTextMapRef<'a>(&'a dyn TextMap);
+impl TextMap for DynTextMapRef<'_> {
+    fn set(&mut self, _key: &str, _value: &str) {
+        unreachable!("Extraction does not modify carriers");
+    }
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::carrier::{B3Propagator, TraceContextPropagator};
+    use crate::context::DefaultContext;
+    use std::collections::HashMap;
+
+    #[test]
+    fn propagators_are_tried_in_order() {
+        let propagators = Propagators::<DefaultContext>::new()
+            .propagator(TraceContextPropagator)
+            .propagator(B3Propagator::multi());
+        let context = SpanContext::new(DefaultContext::new(0xabc, 0x12, 1), Vec::new());
+        let mut carrier = HashMap::new();
+        track_try_unwrap!(propagators.inject(&context, &mut carrier));
+        assert_eq!(carrier["X-B3-SpanId"], "0000000000000012");
+
+        // A malformed field of one format does not hide the others.
+        carrier.insert("traceparent".to_owned(), "invalid".to_owned());
+        let extracted = track_try_unwrap!(propagators.extract(&carrier)).unwrap();
+        assert_eq!(extracted.state(), context.state());
+
+        carrier.remove("X-B3-TraceId");
+        assert!(propagators.extract(&carrier).is_err());
+        carrier.clear();
+        assert!(track_try_unwrap!(propagators.extract(&carrier)).is_none());
+    }
+}
@@ -0,0 +1,552 @@
+//! [W3C Trace Context] and [W3C Baggage] propagation.
+//!
+//! [W3C Trace Context]: https://www.w3.org/TR/trace-context/
+//! [W3C Baggage]: https://www.w3.org/TR/baggage/
+use super::TextMap;
+use crate::span::{BaggageItem, SpanContext};
+use crate::{Error, ErrorKind, Result};
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+
+/// The name of the field carrying the trace and span identifiers.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// The name of the field carrying the vendor-specific trace state.
+pub const TRACESTATE: &str = "tracestate";
+
+/// The name of the field carrying the baggage items.
+pub const BAGGAGE: &str = "baggage";
+
+/// The identifiers and flags carried by a `traceparent` field.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::TraceParent;
+///
+/// let value = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+/// let traceparent: TraceParent = value.parse().unwrap();
+/// assert_eq!(traceparent.trace_id(), 0x0af7651916cd43dd8448eb211c80319c);
+/// assert_eq!(traceparent.span_id(), 0xb7ad6b7169203331);
+/// assert!(traceparent.is_sampled());
+/// assert_eq!(traceparent.to_string(), value);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceParent {
+    trace_id: u128,
+    span_id: u64,
+    flags: u8,
+}
+impl TraceParent {
+    /// The flag indicating that the trace is sampled.
+    pub const SAMPLED: u8 = 0x01;
+
+    /// Makes a new `TraceParent` instance.
+    pub fn new(trace_id: u128, span_id: u64, flags: u8) -> Self {
+        TraceParent {
+            trace_id,
+            span_id,
+            flags,
+        }
+    }
+
+    /// Returns the identifier of the trace.
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// Returns the identifier of the parent span.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Returns the trace flags.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns `true` if the sampled flag is set.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & Self::SAMPLED != 0
+    }
+}
+impl FromStr for TraceParent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut fields = s.trim().split('-');
+        let mut next = |len: usize| {
+            let field = fields
+                .next()
+                .filter(|f| f.len() == len)
+                .ok_or_else(invalid)?;
+            if field.bytes().all(|b| b.is_ascii_hexdigit()) {
+                Ok(field)
+            } else {
+                Err(invalid())
+            }
+        };
+        let version = next(2)?;
+        let trace_id = u128::from_str_radix(next(32)?, 16).map_err(|_| invalid())?;
+        let span_id = u64::from_str_radix(next(16)?, 16).map_err(|_| invalid())?;
+        let flags = u8::from_str_radix(next(2)?, 16).map_err(|_| invalid())?;
+        // Later versions may append fields.
+        let rest_is_valid = version != "00" || fields.next().is_none();
+        if version == "ff" || !rest_is_valid || trace_id == 0 || span_id == 0 {
+            return Err(invalid());
+        }
+        Ok(TraceParent::new(trace_id, span_id, flags))
+    }
+}
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+/// Vendor-specific entries carried by a `tracestate` field.
+///
+/// Entries are kept in order, with the most recently updated one first,
+/// so that a service in the middle of a trace can forward them unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::TraceState;
+///
+/// let mut state: TraceState = "rojo=00f067aa0ba902b7,congo=t61rcWkgMzE".parse().unwrap();
+/// assert_eq!(state.get("congo"), Some("t61rcWkgMzE"));
+///
+/// state.insert("congo", "ucfJifl5GOE").unwrap();
+/// assert_eq!(state.to_string(), "congo=ucfJifl5GOE,rojo=00f067aa0ba902b7");
+/// assert!(state.insert("Invalid Key", "x").is_err());
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TraceState {
+    entries: Vec<(String, String)>,
+}
+impl TraceState {
+    /// The maximum number of entries.
+    pub const MAX_ENTRIES: usize = 32;
+
+    /// Makes a new empty `TraceState` instance.
+    pub const fn new() -> Self {
+        TraceState {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the value of the entry having the key `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Inserts or updates an entry and moves it to the front.
+    ///
+    /// If the number of entries exceeds `MAX_ENTRIES`, the last entry is removed.
+    ///
+    /// # Errors
+    ///
+    /// If `key` or `value` is not valid in the W3C Trace Context format,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+        if !is_valid_key(key) || !is_valid_value(value) {
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        self.remove(key);
+        self.entries.insert(0, (key.to_owned(), value.to_owned()));
+        self.entries.truncate(Self::MAX_ENTRIES);
+        Ok(())
+    }
+
+    /// Removes the entry having the key `key` and returns its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        Some(self.entries.remove(i).1)
+    }
+
+    /// Returns an iterator over the keys and values of the entries.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the number of the entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+impl FromStr for TraceState {
+    type Err = Error;
+
+    /// Parses a `tracestate` value.
+    ///
+    /// Values having invalid or duplicate entries, or more than `MAX_ENTRIES` entries,
+    /// are rejected as a whole.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut entries = Vec::<(String, String)>::new();
+        for member in s.split(',') {
+            let member = member.trim_matches(|c| c == ' ' || c == '\t');
+            if member.is_empty() {
+                continue;
+            }
+            let (key, value) = member.split_once('=').ok_or_else(invalid)?;
+            if !is_valid_key(key) || !is_valid_value(value) || entries.iter().any(|e| e.0 == key) {
+                return Err(invalid());
+            }
+            entries.push((key.to_owned(), value.to_owned()));
+        }
+        if entries.len() > Self::MAX_ENTRIES {
+            return Err(invalid());
+        }
+        Ok(TraceState { entries })
+    }
+}
+impl fmt::Display for TraceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (key, value)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let bytes = key.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= 256
+        && (bytes[0].is_ascii_lowercase() || bytes[0].is_ascii_digit())
+        && bytes
+            .iter()
+            .all(|&b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"_-*/@".contains(&b))
+}
+
+fn is_valid_value(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= 256
+        && bytes.last() != Some(&b' ')
+        && bytes
+            .iter()
+            .all(|&b| (0x20..=0x7e).contains(&b) && b != b',' && b != b'=')
+}
+
+/// Span context state which can be propagated in the W3C Trace Context format.
+pub trait TraceContextState: Sized {
+    /// Returns the `traceparent` identifying the span of this state.
+    fn traceparent(&self) -> TraceParent;
+
+    /// Returns the `tracestate` value of this state.
+    ///
+    /// This is propagated only if the `TraceState` of the span context is empty.
+    ///
+    /// The default implementation returns `None`.
+    fn tracestate(&self) -> Option<&str> {
+        None
+    }
+
+    /// Makes a state from the propagated `traceparent` and `tracestate`.
+    fn from_trace_context(traceparent: TraceParent, tracestate: Option<&str>) -> Self;
+}
+
+/// Propagator which injects span contexts into and extracts them from text maps.
+pub trait TextMapPropagator<T> {
+    /// Injects `context` to `carrier`.
+    fn inject<C: TextMap>(&self, context: &SpanContext<T>, carrier: &mut C) -> Result<()>;
+
+    /// Extracts a context from `carrier`.
+    ///
+    /// If `carrier` contains no span context, it will return `Ok(None)`.
+    fn extract<C: TextMap>(&self, carrier: &C) -> Result<Option<SpanContext<T>>>;
+}
+
+/// `TextMapPropagator` for the W3C Trace Context (`traceparent` and `tracestate` fields)
+/// and W3C Baggage (`baggage` field) formats.
+///
+/// Span context states implementing `TraceContextState` can use this
+/// to implement their text map codecs.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::carrier::{
+///     ExtractFromTextMap, InjectToTextMap, TextMap, TextMapPropagator, TraceContextPropagator,
+///     TraceContextState, TraceParent,
+/// };
+/// use cf_rustracing::span::SpanContext;
+/// use cf_rustracing::Result;
+/// use std::collections::HashMap;
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// struct MyState {
+///     trace_id: u128,
+///     span_id: u64,
+/// }
+/// impl TraceContextState for MyState {
+///     fn traceparent(&self) -> TraceParent {
+///         TraceParent::new(self.trace_id, self.span_id, TraceParent::SAMPLED)
+///     }
+///     fn from_trace_context(traceparent: TraceParent, _tracestate: Option<&str>) -> Self {
+///         MyState { trace_id: traceparent.trace_id(), span_id: traceparent.span_id() }
+///     }
+/// }
+/// impl<C: TextMap> InjectToTextMap<C> for MyState {
+///     fn inject_to_text_map(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+///         TraceContextPropagator.inject(context, carrier)
+///     }
+/// }
+/// impl<C: TextMap> ExtractFromTextMap<C> for MyState {
+///     fn extract_from_text_map(carrier: &C) -> Result<Option<SpanContext<Self>>> {
+///         TraceContextPropagator.extract(carrier)
+///     }
+/// }
+///
+/// let context = SpanContext::new(MyState { trace_id: 1, span_id: 2 }, Vec::new());
+/// let mut carrier = HashMap::new();
+/// context.inject_to_text_map(&mut carrier).unwrap();
+/// assert_eq!(
+///     carrier["traceparent"],
+///     "00-00000000000000000000000000000001-0000000000000002-01"
+/// );
+///
+/// let extracted = SpanContext::<MyState>::extract_from_text_map(&carrier).unwrap();
+/// assert_eq!(extracted.unwrap().state(), context.state());
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TraceContextPropagator;
+impl<T: TraceContextState> TextMapPropagator<T> for TraceContextPropagator {
+    fn inject<C: TextMap>(&self, context: &SpanContext<T>, carrier: &mut C) -> Result<()> {
+        let state = context.state();
+        carrier.set_fmt(TRACEPARENT, format_args!("{}", state.traceparent()));
+        if !context.trace_state().is_empty() {
+            carrier.set_fmt(TRACESTATE, format_args!("{}", context.trace_state()));
+        } else if let Some(tracestate) = state.tracestate().filter(|s| !s.is_empty()) {
+            carrier.set(TRACESTATE, tracestate);
+        }
+        if !context.baggage_items().is_empty() {
+            carrier.set(BAGGAGE, &format_baggage(context.baggage_items()));
+        }
+        Ok(())
+    }
+
+    fn extract<C: TextMap>(&self, carrier: &C) -> Result<Option<SpanContext<T>>> {
+        let traceparent = match carrier.get(TRACEPARENT) {
+            Some(value) => track!(value.parse::<TraceParent>())?,
+            None => return Ok(None),
+        };
+        let tracestate = carrier
+            .get(TRACESTATE)
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+        let flags = super::trace_flags(&traceparent);
+        let state = T::from_trace_context(traceparent, tracestate);
+        let items = match carrier.get(BAGGAGE) {
+            Some(value) => track!(parse_baggage(value))?,
+            None => Vec::new(),
+        };
+        let trace_state = tracestate.and_then(|s| s.parse().ok()).unwrap_or_default();
+        Ok(Some(
+            SpanContext::new(state, items)
+                .with_trace_state(trace_state)
+                .with_flags(flags),
+        ))
+    }
+}
+
+/// Formats baggage items as a `baggage` value, percent-encoding their names and values.
+pub(crate) fn format_baggage(items: &[BaggageItem]) -> String {
+    let mut value = String::new();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            value.push(',');
+        }
+        percent_encode(&mut value, item.name());
+        value.push('=');
+        percent_encode(&mut value, item.value());
+    }
+    value
+}
+
+/// Parses a `baggage` value.
+///
+/// Properties of the members (following `;`) are ignored.
+pub(crate) fn parse_baggage(value: &str) -> Result<Vec<BaggageItem>> {
+    let mut items = Vec::new();
+    for member in value.split(',') {
+        let member = member.split(';').next().unwrap_or("").trim();
+        if member.is_empty() {
+            continue;
+        }
+        let (name, value) = member.split_once('=').ok_or_else(invalid)?;
+        let name = track!(percent_decode(name.trim()))?;
+        let value = track!(percent_decode(value.trim()))?;
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        items.push(BaggageItem::new(&name, &value));
+    }
+    Ok(items)
+}
+
+fn percent_encode(buf: &mut String, s: &str) {
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"!#$&'*+-.^_`|~".contains(&b) {
+            buf.push(char::from(b));
+        } else {
+            let _ = write!(buf, "%{:02X}", b);
+        }
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail.get(..2).ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+fn invalid() -> Error {
+    Error::from(ErrorKind::CarrierParse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq)]
+    struct State(TraceParent, Option<String>);
+    impl TraceContextState for State {
+        fn traceparent(&self) -> TraceParent {
+            self.0
+        }
+
+        fn tracestate(&self) -> Option<&str> {
+            self.1.as_deref()
+        }
+
+        fn from_trace_context(traceparent: TraceParent, tracestate: Option<&str>) -> Self {
+            State(traceparent, tracestate.map(str::to_owned))
+        }
+    }
+
+    #[test]
+    fn traceparent_works() {
+        let traceparent = TraceParent::new(0xabc, 0x12, TraceParent::SAMPLED);
+        let value = traceparent.to_string();
+        assert_eq!(
+            value,
+            "00-00000000000000000000000000000abc-0000000000000012-01"
+        );
+        assert_eq!(track_try_unwrap!(value.parse::<TraceParent>()), traceparent);
+
+        for invalid in [
+            "",
+            "00-00000000000000000000000000000abc-0000000000000012-01-00",
+            "00-00000000000000000000000000000000-0000000000000012-01",
+            "00-00000000000000000000000000000abc-0000000000000000-01",
+            "ff-00000000000000000000000000000abc-0000000000000012-01",
+            "00-0000000000000000000000000000+abc-0000000000000012-01",
+        ] {
+            assert!(invalid.parse::<TraceParent>().is_err());
+        }
+        assert!(
+            "01-00000000000000000000000000000abc-0000000000000012-01-future"
+                .parse::<TraceParent>()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn tracestate_is_propagated() {
+        let state = State(TraceParent::new(1, 2, 0), Some("vendor=value".to_owned()));
+        let context = SpanContext::new(state, vec![BaggageItem::new("a", "b")]);
+        let mut carrier = HashMap::new();
+        track_try_unwrap!(TraceContextPropagator.inject(&context, &mut carrier));
+        assert_eq!(carrier[TRACESTATE], "vendor=value");
+        let extracted: SpanContext<State> =
+            track_try_unwrap!(TraceContextPropagator.extract(&carrier)).unwrap();
+        assert_eq!(extracted.trace_state().get("vendor"), Some("value"));
+
+        // The entries of the context take precedence over those of the state.
+        let mut context = extracted;
+        track_try_unwrap!(context.trace_state_mut().insert("other", "1"));
+        track_try_unwrap!(TraceContextPropagator.inject(&context, &mut carrier));
+        assert_eq!(carrier[TRACESTATE], "other=1,vendor=value");
+        assert_eq!(carrier[BAGGAGE], "a=b");
+
+        let extracted: SpanContext<State> =
+            track_try_unwrap!(TraceContextPropagator.extract(&carrier)).unwrap();
+        assert_eq!(extracted.state().0, context.state().0);
+        assert_eq!(extracted.baggage_items()[0].value(), "b");
+
+        carrier.clear();
+        let extracted: Option<SpanContext<State>> =
+            track_try_unwrap!(TraceContextPropagator.extract(&carrier));
+        assert!(extracted.is_none());
+    }
+
+    #[test]
+    fn invalid_tracestate_is_rejected() {
+        assert!("a=1, b=2,,".parse::<TraceState>().is_ok());
+        for invalid in ["a", "a=", "A=1", "a=1,a=2", "a=x,y", "_a=1"] {
+            assert!(invalid.parse::<TraceState>().is_err(), "{}", invalid);
+        }
+        let many = (0..33).map(|i| format!("k{}=v", i)).collect::<Vec<_>>();
+        assert!(many.join(",").parse::<TraceState>().is_err());
+
+        let mut state = TraceState::new();
+        for i in 0..33 {
+            track_try_unwrap!(state.insert(&format!("k{}", i), "v"));
+        }
+        assert_eq!(state.len(), TraceState::MAX_ENTRIES);
+        assert_eq!(state.entries().next(), Some(("k32", "v")));
+        assert!(state.get("k0").is_none());
+        assert_eq!(state.remove("k32"), Some("v".to_owned()));
+    }
+
+    #[test]
+    fn baggage_is_percent_encoded() {
+        let items = vec![
+            BaggageItem::new("a b", "1,2=3"),
+            BaggageItem::new("c", "日本"),
+        ];
+        let value = format_baggage(&items);
+        assert_eq!(value, "a%20b=1%2C2%3D3,c=%E6%97%A5%E6%9C%AC");
+
+        let parsed = track_try_unwrap!(parse_baggage(&format!("{};prop=1, ,", value)));
+        let pairs = |items: &[BaggageItem]| {
+            items
+                .iter()
+                .map(|x| (x.name().to_owned(), x.value().to_owned()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pairs(&parsed), pairs(&items));
+        assert!(parse_baggage("a=%E").is_err());
+        assert!(parse_baggage("a").is_err());
+    }
+}
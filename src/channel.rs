@@ -0,0 +1,1458 @@
+use crate::clock::Clock;
+use crate::handoff::Handoff;
+use crate::id::IdGenerator;
+use crate::metrics::{Counters, TracerMetrics};
+use crate::pool::SpanPool;
+use crate::queue::{self, QueueRx, QueueTx};
+use crate::reporter::{SharedReporter, SpanReporter};
+#[cfg(feature = "lock-free-queue")]
+use crate::ring::{self, RingRx, RingTx};
+use crate::sampler::{FinishSampler, SharedFinishSampler};
+use crate::span::{BaggageRestrictions, FinishedSpan, OverflowPolicy, SpanLimits, TryRecvError};
+use crate::tag::SharedTags;
+use crate::tracker::SpanTracker;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::future::poll_fn;
+#[cfg(feature = "lock-free-queue")]
+use std::future::Future;
+use std::mem;
+#[cfg(feature = "lock-free-queue")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, TryLockError};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio")]
+use tokio::sync::mpsc;
+
+/// The capacity of the lock-free queues used if the capacity of the channel is not specified.
+#[cfg(feature = "lock-free-queue")]
+const DEFAULT_RING_CAPACITY: usize = 8192;
+
+/// Options of the channel between spans and the `SpanReceiver`.
+#[derive(Debug, Clone)]
+pub(crate) struct ChannelOptions {
+    pub batch_size: usize,
+    pub batch_delay: Duration,
+    pub shards: usize,
+    pub capacity: Option<usize>,
+    pub overflow_policy: OverflowPolicy,
+    #[cfg(feature = "lock-free-queue")]
+    pub ring_poll_interval: Option<Duration>,
+    pub faults: Option<Arc<Faults>>,
+}
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        ChannelOptions {
+            shards: 1,
+            capacity: None,
+            overflow_policy: OverflowPolicy::DropNewest,
+            batch_size: 1,
+            batch_delay: Duration::from_millis(10),
+            #[cfg(feature = "lock-free-queue")]
+            ring_poll_interval: None,
+            faults: None,
+        }
+    }
+}
+
+/// State shared by the spans of a tracer, set up by `TracerBuilder`.
+pub(crate) struct SenderOptions<T> {
+    pub clock: Arc<dyn Clock>,
+    pub id_generator: Arc<dyn IdGenerator>,
+    pub tracker: Option<SpanTracker>,
+    pub finish_sampler: Option<SharedFinishSampler<T>>,
+    pub finish_observers: FinishObservers<T>,
+    pub processors: SpanProcessors<T>,
+    pub default_tags: Option<SharedTags>,
+    pub limits: SpanLimits,
+    pub baggage_restrictions: Option<BaggageRestrictions>,
+    pub inherited_tags: Vec<Cow<'static, str>>,
+}
+impl<T> std::fmt::Debug for SenderOptions<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SenderOptions")
+            .field("tracker", &self.tracker)
+            .field("finish_sampler", &self.finish_sampler)
+            .field("finish_observers", &self.finish_observers)
+            .field("processors", &self.processors)
+            .field("default_tags", &self.default_tags)
+            .field("limits", &self.limits)
+            .field("baggage_restrictions", &self.baggage_restrictions)
+            .field("inherited_tags", &self.inherited_tags)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Failures injected into a channel by `testing::FaultInjector`.
+#[derive(Debug, Default)]
+pub(crate) struct Faults {
+    pub receiver_closed: AtomicBool,
+    pub channel_full: AtomicBool,
+    receiver_paused: Mutex<(bool, Option<Waker>)>,
+}
+impl Faults {
+    /// Returns `true` if finished spans should be discarded instead of being sent.
+    fn rejects(&self) -> bool {
+        self.receiver_closed.load(Ordering::Relaxed) || self.channel_full.load(Ordering::Relaxed)
+    }
+
+    pub fn set_receiver_paused(&self, paused: bool) {
+        let mut state = self
+            .receiver_paused
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        state.0 = paused;
+        if !paused {
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Returns `true` if the receiver is paused.
+    ///
+    /// If `cx` is given, it will be woken up when the receiver is resumed.
+    fn is_receiver_paused(&self, cx: Option<&mut Context<'_>>) -> bool {
+        let mut state = self
+            .receiver_paused
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if state.0 {
+            if let Some(cx) = cx {
+                state.1 = Some(cx.waker().clone());
+            }
+        }
+        state.0
+    }
+}
+
+/// Makes a new channel for delivering finished spans.
+///
+/// The channel consists of `options.shards` queues.
+/// Each thread sends finished spans to one of them and the receiver merges them.
+///
+/// If `options.capacity` is specified, it is divided equally among the queues
+/// and `options.overflow_policy` is applied to the full queues.
+pub(crate) fn channel<T>(
+    options: ChannelOptions,
+    sender_options: SenderOptions<T>,
+) -> (SpanSender<T>, SpanReceiver<T>) {
+    let shards = options.shards.max(1);
+    let (txs, rxs): (Vec<_>, Vec<_>) = (0..shards)
+        .map(|_| match options.capacity {
+            #[cfg(feature = "lock-free-queue")]
+            capacity if options.ring_poll_interval.is_some() => {
+                let capacity = capacity.unwrap_or(DEFAULT_RING_CAPACITY);
+                let (tx, rx) = ring::ring(capacity.div_ceil(shards));
+                let poll_interval = options.ring_poll_interval.unwrap_or_default();
+                (Tx::Ring(tx), Rx::Ring(RingReceiver::new(rx, poll_interval)))
+            }
+            #[cfg(feature = "tokio")]
+            None => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                (Tx::Unbounded(tx), Rx::Unbounded(rx))
+            }
+            #[cfg(feature = "tokio")]
+            Some(capacity) if options.overflow_policy == OverflowPolicy::DropNewest => {
+                let (tx, rx) = mpsc::channel(capacity.div_ceil(shards).max(1));
+                (Tx::Bounded(tx), Rx::Bounded(rx))
+            }
+            capacity => {
+                let (tx, rx) = queue::queue(capacity.map(|c| c.div_ceil(shards).max(1)));
+                (Tx::Queue(tx, options.overflow_policy), Rx::Queue(rx))
+            }
+        })
+        .unzip();
+    let batchers: Option<Arc<[Batcher<T>]>> = if options.batch_size > 1 {
+        Some(txs.iter().map(|_| Batcher::new(&options)).collect())
+    } else {
+        None
+    };
+    let progress = Arc::new(Progress::default());
+    let sender = SpanSender(Arc::new(SenderInner::new(
+        txs,
+        batchers.clone(),
+        options.faults.clone(),
+        Arc::clone(&progress),
+        sender_options,
+    )));
+    let receiver = SpanReceiver {
+        rxs,
+        next: 0,
+        batchers,
+        buffered: VecDeque::new(),
+        faults: options.faults,
+        progress,
+    };
+    (sender, receiver)
+}
+
+/// Makes a new sender which has no destination (see `SpanSender::is_disabled`).
+pub(crate) fn disabled_channel<T>(sender_options: SenderOptions<T>) -> SpanSender<T> {
+    SpanSender(Arc::new(SenderInner::new(
+        Vec::new(),
+        None,
+        None,
+        Arc::new(Progress::default()),
+        sender_options,
+    )))
+}
+
+/// Makes a new sender which hands finished spans to `reporter` synchronously.
+pub(crate) fn reporting_channel<T>(
+    reporter: SharedReporter<T>,
+    sender_options: SenderOptions<T>,
+) -> SpanSender<T> {
+    SpanSender(Arc::new(SenderInner::new(
+        vec![Tx::Reporter(reporter)],
+        None,
+        None,
+        Arc::new(Progress::default()),
+        sender_options,
+    )))
+}
+
+/// Makes a new sender which stores finished spans in the returned `SpanStore` synchronously.
+pub(crate) fn capturing_channel<T>(
+    sender_options: SenderOptions<T>,
+) -> (SpanSender<T>, Arc<SpanStore<T>>) {
+    let store = Arc::new(SpanStore {
+        spans: Mutex::new(Vec::new()),
+        added: Condvar::new(),
+    });
+    let sender = SpanSender(Arc::new(SenderInner::new(
+        vec![Tx::Capture(Arc::clone(&store))],
+        None,
+        None,
+        Arc::new(Progress::default()),
+        sender_options,
+    )));
+    (sender, store)
+}
+
+type SpanProcessor<T> = Box<dyn Fn(FinishedSpan<T>) -> Option<FinishedSpan<T>> + Send + Sync>;
+type FinishObserver<T> = Box<dyn Fn(&FinishedSpan<T>) + Send + Sync>;
+
+/// Chain of the processors applied to finished spans before they are sent.
+pub(crate) struct SpanProcessors<T>(Vec<SpanProcessor<T>>);
+impl<T> SpanProcessors<T> {
+    pub fn push<F>(&mut self, processor: F)
+    where
+        F: Fn(FinishedSpan<T>) -> Option<FinishedSpan<T>> + Send + Sync + 'static,
+    {
+        self.0.push(Box::new(processor));
+    }
+
+    /// Applies the processors in order.
+    ///
+    /// `None` means that one of them has discarded `span`.
+    fn apply(&self, span: FinishedSpan<T>) -> Option<FinishedSpan<T>> {
+        self.0
+            .iter()
+            .try_fold(span, |span, processor| processor(span))
+    }
+}
+impl<T> Default for SpanProcessors<T> {
+    fn default() -> Self {
+        SpanProcessors(Vec::new())
+    }
+}
+impl<T> std::fmt::Debug for SpanProcessors<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SpanProcessors")
+            .field("len", &self.0.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Hooks called with every span recorded by a tracer when it finishes.
+pub(crate) struct FinishObservers<T>(Vec<FinishObserver<T>>);
+impl<T> FinishObservers<T> {
+    pub fn push<F>(&mut self, observer: F)
+    where
+        F: Fn(&FinishedSpan<T>) + Send + Sync + 'static,
+    {
+        self.0.push(Box::new(observer));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn observe(&self, span: &FinishedSpan<T>) {
+        for observer in &self.0 {
+            observer(span);
+        }
+    }
+}
+impl<T> Default for FinishObservers<T> {
+    fn default() -> Self {
+        FinishObservers(Vec::new())
+    }
+}
+impl<T> std::fmt::Debug for FinishObservers<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FinishObservers")
+            .field("len", &self.0.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Store of the spans finished by a capturing channel.
+pub(crate) struct SpanStore<T> {
+    spans: Mutex<Vec<FinishedSpan<T>>>,
+    added: Condvar,
+}
+impl<T> SpanStore<T> {
+    fn push(&self, spans: impl IntoIterator<Item = FinishedSpan<T>>) {
+        self.lock().extend(spans);
+        self.added.notify_all();
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Vec<FinishedSpan<T>>> {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits until the store contains at least `n` spans or `timeout` elapses.
+    pub fn wait_for(&self, n: usize, timeout: Duration) -> MutexGuard<'_, Vec<FinishedSpan<T>>> {
+        let (spans, _) = self
+            .added
+            .wait_timeout_while(self.lock(), timeout, |spans| spans.len() < n)
+            .unwrap_or_else(|e| e.into_inner());
+        spans
+    }
+}
+impl<T> std::fmt::Debug for SpanStore<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SpanStore").finish_non_exhaustive()
+    }
+}
+
+/// Returns the index of the shard used by the current thread.
+fn shard_index(shards: usize) -> usize {
+    static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+    }
+    if shards == 1 {
+        0
+    } else {
+        SHARD.with(|shard| *shard % shards)
+    }
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // Boxing single spans would allocate for each span.
+enum Delivery<T> {
+    Single(FinishedSpan<T>),
+    Batch(Vec<FinishedSpan<T>>),
+}
+impl<T> Delivery<T> {
+    fn len(&self) -> usize {
+        match self {
+            Delivery::Single(_) => 1,
+            Delivery::Batch(spans) => spans.len(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Tx<T> {
+    #[cfg(feature = "tokio")]
+    Unbounded(mpsc::UnboundedSender<Delivery<T>>),
+    #[cfg(feature = "tokio")]
+    Bounded(mpsc::Sender<Delivery<T>>),
+    Queue(QueueTx<Delivery<T>>, OverflowPolicy),
+    #[cfg(feature = "lock-free-queue")]
+    Ring(RingTx<Delivery<T>>),
+    Capture(Arc<SpanStore<T>>),
+    Reporter(SharedReporter<T>),
+}
+impl<T> Tx<T> {
+    /// Sends `delivery`.
+    ///
+    /// If the queue is full or closed, spans are discarded according to the overflow policy and
+    /// the number of the discarded spans is returned.
+    /// This blocks only if the policy is `OverflowPolicy::BlockWithTimeout`.
+    fn send(&self, delivery: Delivery<T>) -> usize {
+        let len = delivery.len();
+        let result = match self {
+            #[cfg(feature = "tokio")]
+            Tx::Unbounded(tx) => tx.send(delivery).map_err(|_| ()),
+            #[cfg(feature = "tokio")]
+            Tx::Bounded(tx) => tx.try_send(delivery).map_err(|_| ()),
+            Tx::Queue(tx, OverflowPolicy::DropNewest) => tx.push(delivery).map_err(|_| ()),
+            Tx::Queue(tx, OverflowPolicy::DropOldest) => match tx.push_evicting(delivery) {
+                Ok(evicted) => return evicted.map_or(0, |d| d.len()),
+                Err(_) => Err(()),
+            },
+            Tx::Queue(tx, OverflowPolicy::BlockWithTimeout(timeout)) => {
+                tx.push_timeout(delivery, *timeout).map_err(|_| ())
+            }
+            #[cfg(feature = "lock-free-queue")]
+            Tx::Ring(tx) => tx.push(delivery).map_err(|_| ()),
+            Tx::Capture(store) => {
+                match delivery {
+                    Delivery::Single(span) => store.push(Some(span)),
+                    Delivery::Batch(spans) => store.push(spans),
+                }
+                Ok(())
+            }
+            Tx::Reporter(SharedReporter(reporter)) => {
+                match delivery {
+                    Delivery::Single(span) => reporter.report(span),
+                    Delivery::Batch(spans) => spans.into_iter().for_each(|s| reporter.report(s)),
+                }
+                Ok(())
+            }
+        };
+        if result.is_ok() {
+            0
+        } else {
+            len
+        }
+    }
+
+    /// Returns `true` if the spans sent by this are delivered synchronously.
+    fn is_synchronous(&self) -> bool {
+        matches!(self, Tx::Capture(_) | Tx::Reporter(_))
+    }
+
+    /// Blocks until the spans buffered by the destination are delivered.
+    fn flush(&self) {
+        if let Tx::Reporter(SharedReporter(reporter)) = self {
+            reporter.flush();
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Rx<T> {
+    #[cfg(feature = "tokio")]
+    Unbounded(mpsc::UnboundedReceiver<Delivery<T>>),
+    #[cfg(feature = "tokio")]
+    Bounded(mpsc::Receiver<Delivery<T>>),
+    Queue(QueueRx<Delivery<T>>),
+    #[cfg(feature = "lock-free-queue")]
+    Ring(RingReceiver<Delivery<T>>),
+}
+impl<T> Rx<T> {
+    fn try_recv(&mut self) -> Result<Delivery<T>, TryRecvError> {
+        match self {
+            #[cfg(feature = "tokio")]
+            Rx::Unbounded(rx) => rx.try_recv().map_err(TryRecvError::from),
+            #[cfg(feature = "tokio")]
+            Rx::Bounded(rx) => rx.try_recv().map_err(TryRecvError::from),
+            Rx::Queue(rx) => rx.pop().map_err(TryRecvError::from_closed),
+            #[cfg(feature = "lock-free-queue")]
+            Rx::Ring(rx) => rx.try_recv(),
+        }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Delivery<T>>> {
+        match self {
+            #[cfg(feature = "tokio")]
+            Rx::Unbounded(rx) => rx.poll_recv(cx),
+            #[cfg(feature = "tokio")]
+            Rx::Bounded(rx) => rx.poll_recv(cx),
+            Rx::Queue(rx) => rx.poll_pop(cx),
+            #[cfg(feature = "lock-free-queue")]
+            Rx::Ring(rx) => rx.poll_recv(cx),
+        }
+    }
+}
+
+/// Receiver of a lock-free queue.
+///
+/// Since the producers never wake up the receiver,
+/// the queue is checked every `poll_interval` while it is empty.
+#[cfg(feature = "lock-free-queue")]
+#[derive(Debug)]
+struct RingReceiver<T> {
+    rx: RingRx<T>,
+    poll_interval: Duration,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+#[cfg(feature = "lock-free-queue")]
+impl<T> RingReceiver<T> {
+    fn new(rx: RingRx<T>, poll_interval: Duration) -> Self {
+        RingReceiver {
+            rx,
+            poll_interval,
+            sleep: None,
+        }
+    }
+
+    fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.rx.pop().map_err(TryRecvError::from_closed)
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            match self.rx.pop() {
+                Ok(item) => {
+                    self.sleep = None;
+                    return Poll::Ready(Some(item));
+                }
+                Err(true) => return Poll::Ready(None),
+                Err(false) => {}
+            }
+            let poll_interval = self.poll_interval;
+            let sleep = self
+                .sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(poll_interval)));
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.sleep = None;
+        }
+    }
+}
+
+/// Sender of finished spans to the destination channel.
+///
+/// It also owns the state shared by the spans of a tracer
+/// (i.e., the pool used to recycle the buffers of the finished spans, the clock,
+/// the identifier generator and the span tracker).
+///
+/// This used to be an alias of `tokio::sync::mpsc::UnboundedSender<FinishedSpan<T>>`.
+/// Spans can no longer be sent through it directly; they are sent when they are finished.
+///
+/// If the channel is full or the receiver has been dropped, the span is discarded
+/// and counted as a dropped span, unless the overflow policy is `OverflowPolicy::BlockWithTimeout`,
+/// in which case sending blocks until there is room or the timeout elapses.
+/// Note that the runtime-agnostic queue, which is used if the `tokio` feature is disabled
+/// or an overflow policy is specified, is guarded by a mutex,
+/// so sending may briefly wait for another thread accessing it.
+/// Spans sent to a reporter (see `Tracer::with_reporter`) are reported by the sending thread.
+#[derive(Debug)]
+pub struct SpanSender<T>(Arc<SenderInner<T>>);
+impl<T> SpanSender<T> {
+    /// Applies the span processors to `span`.
+    pub(crate) fn process(&self, span: FinishedSpan<T>) -> Option<FinishedSpan<T>> {
+        self.0.processors.apply(span)
+    }
+
+    pub(crate) fn send(&self, span: FinishedSpan<T>) {
+        let inner = &self.0;
+        if self.is_disabled() {
+            return;
+        }
+        let Some(span) = inner.processors.apply(span) else {
+            return;
+        };
+        if inner.faults.as_ref().is_some_and(|f| f.rejects())
+            || inner.progress.shut_down.load(Ordering::Relaxed)
+        {
+            inner.dropped_spans.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let shard = shard_index(inner.txs.len());
+        inner.accepted[shard].0.fetch_add(1, Ordering::Relaxed);
+        if let Some(batchers) = inner.batchers.as_ref() {
+            batchers[shard].push(span, |delivery| self.deliver(shard, delivery));
+        } else {
+            self.deliver(shard, Delivery::Single(span));
+        }
+    }
+
+    fn deliver(&self, shard: usize, delivery: Delivery<T>) {
+        let inner = &self.0;
+        let len = delivery.len();
+        let tx = &inner.txs[shard];
+        let dropped = tx.send(delivery);
+        if dropped > 0 {
+            inner
+                .dropped_spans
+                .fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+        if tx.is_synchronous() {
+            inner.progress.settle(len);
+        } else if dropped > 0 {
+            inner.progress.settle(dropped);
+        }
+    }
+
+    /// Waits until all the spans accepted so far have been received or discarded.
+    pub(crate) async fn flush(&self) {
+        let inner = &self.0;
+        let accepted = inner
+            .accepted
+            .iter()
+            .map(|n| n.0.load(Ordering::SeqCst))
+            .sum();
+        for (shard, batcher) in inner
+            .batchers
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            batcher.take(|_, spans| {
+                if !spans.is_empty() {
+                    self.deliver(shard, Delivery::Batch(spans));
+                }
+            });
+        }
+        for tx in &inner.txs {
+            tx.flush();
+        }
+        poll_fn(|cx| inner.progress.poll_settled(accepted, cx)).await
+    }
+
+    /// Discards the spans finished after this call and makes the receiver return `None`
+    /// once it has received all the spans finished before.
+    pub(crate) async fn shutdown(&self) {
+        self.0.progress.shut_down();
+        self.flush().await;
+    }
+
+    /// Returns the number of the finished spans discarded because
+    /// the channel was full or the receiver had been dropped.
+    pub(crate) fn dropped_spans(&self) -> u64 {
+        self.0.dropped_spans.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn counters(&self) -> &Counters {
+        &self.0.counters
+    }
+
+    /// Returns `true` if this sender has no destination, in which case no span is started.
+    pub(crate) fn is_disabled(&self) -> bool {
+        self.0.txs.is_empty()
+    }
+
+    pub(crate) fn metrics(&self) -> TracerMetrics {
+        self.0.counters.snapshot(self.dropped_spans())
+    }
+
+    pub(crate) fn pool(&self) -> &Arc<SpanPool<T>> {
+        &self.0.pool
+    }
+
+    pub(crate) fn clock(&self) -> &dyn Clock {
+        &*self.0.clock
+    }
+
+    pub(crate) fn shared_clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.0.clock)
+    }
+
+    pub(crate) fn id_generator(&self) -> &dyn IdGenerator {
+        &*self.0.id_generator
+    }
+
+    pub(crate) fn tracker(&self) -> Option<&SpanTracker> {
+        self.0.tracker.as_ref()
+    }
+
+    pub(crate) fn finish_sampler(&self) -> Option<&(dyn FinishSampler<T> + Send + Sync)> {
+        self.0.finish_sampler.as_ref().map(|s| &*s.0)
+    }
+
+    /// Returns `true` if the spans rejected by the sampler are recorded speculatively,
+    /// for the finish sampler or the finish observers.
+    pub(crate) fn records_rejected_spans(&self) -> bool {
+        self.0.finish_sampler.is_some() || !self.0.finish_observers.is_empty()
+    }
+
+    /// Calls the finish observers with `span`.
+    pub(crate) fn observe(&self, span: &FinishedSpan<T>) {
+        self.0.finish_observers.observe(span);
+    }
+
+    pub(crate) fn default_tags(&self) -> Option<&SharedTags> {
+        self.0.default_tags.as_ref()
+    }
+
+    pub(crate) fn limits(&self) -> &SpanLimits {
+        &self.0.limits
+    }
+
+    pub(crate) fn baggage_restrictions(&self) -> Option<&BaggageRestrictions> {
+        self.0.baggage_restrictions.as_ref()
+    }
+
+    pub(crate) fn inherited_tag_names(&self) -> &[Cow<'static, str>] {
+        &self.0.inherited_tags
+    }
+}
+impl<T> Clone for SpanSender<T> {
+    fn clone(&self) -> Self {
+        SpanSender(Arc::clone(&self.0))
+    }
+}
+
+#[derive(Debug)]
+struct SenderInner<T> {
+    txs: Vec<Tx<T>>,
+    batchers: Option<Arc<[Batcher<T>]>>,
+
+    // The numbers of the spans accepted by each shard, which `flush` waits for.
+    // They are counted per shard so that finishing spans does not contend on one cache line.
+    accepted: Box<[ShardCounter]>,
+
+    pool: Arc<SpanPool<T>>,
+    dropped_spans: AtomicU64,
+    clock: Arc<dyn Clock>,
+    id_generator: Arc<dyn IdGenerator>,
+    faults: Option<Arc<Faults>>,
+    tracker: Option<SpanTracker>,
+    finish_sampler: Option<SharedFinishSampler<T>>,
+    finish_observers: FinishObservers<T>,
+    processors: SpanProcessors<T>,
+    default_tags: Option<SharedTags>,
+    limits: SpanLimits,
+    baggage_restrictions: Option<BaggageRestrictions>,
+    inherited_tags: Vec<Cow<'static, str>>,
+    counters: Counters,
+    progress: Arc<Progress>,
+}
+impl<T> SenderInner<T> {
+    fn new(
+        txs: Vec<Tx<T>>,
+        batchers: Option<Arc<[Batcher<T>]>>,
+        faults: Option<Arc<Faults>>,
+        progress: Arc<Progress>,
+        options: SenderOptions<T>,
+    ) -> Self {
+        if let Some(tracker) = options.tracker.as_ref() {
+            tracker.attach_clock(Arc::clone(&options.clock));
+        }
+        SenderInner {
+            accepted: txs.iter().map(|_| ShardCounter::default()).collect(),
+            txs,
+            batchers,
+            pool: Arc::new(SpanPool::new()),
+            dropped_spans: AtomicU64::new(0),
+            clock: options.clock,
+            id_generator: options.id_generator,
+            faults,
+            tracker: options.tracker,
+            finish_sampler: options.finish_sampler,
+            finish_observers: options.finish_observers,
+            processors: options.processors,
+            default_tags: options.default_tags,
+            limits: options.limits,
+            baggage_restrictions: options.baggage_restrictions,
+            inherited_tags: options.inherited_tags,
+            counters: Counters::default(),
+            progress,
+        }
+    }
+}
+
+/// Counter of a shard, aligned to occupy its own cache line.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct ShardCounter(AtomicU64);
+
+/// Progress of the delivery of the accepted spans, which flushing waits for.
+///
+/// Every span accepted by a sender is eventually settled:
+/// either received by the receiver or discarded.
+#[derive(Debug, Default)]
+struct Progress {
+    settled: AtomicU64,
+    shut_down: AtomicBool,
+    receiver_dropped: AtomicBool,
+    flushing: AtomicBool,
+    waiters: Mutex<Waiters>,
+}
+impl Progress {
+    fn settle(&self, n: usize) {
+        self.settled.fetch_add(n as u64, Ordering::SeqCst);
+        if self.flushing.load(Ordering::SeqCst) {
+            self.wake_flushers();
+        }
+    }
+
+    fn poll_settled(&self, accepted: u64, cx: &mut Context<'_>) -> Poll<()> {
+        let is_settled = || {
+            self.settled.load(Ordering::SeqCst) >= accepted
+                || self.receiver_dropped.load(Ordering::SeqCst)
+        };
+        if is_settled() {
+            return Poll::Ready(());
+        }
+        self.lock().flushers.push(cx.waker().clone());
+        self.flushing.store(true, Ordering::SeqCst);
+
+        // Checks again in case the spans were settled before registering the waker.
+        if is_settled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn shut_down(&self) {
+        self.shut_down.store(true, Ordering::SeqCst);
+        let receiver = self.lock().receiver.take();
+        if let Some(waker) = receiver {
+            waker.wake();
+        }
+    }
+
+    /// Returns `true` if the tracer has been shut down.
+    ///
+    /// Otherwise, `cx` will be woken up when the tracer is shut down.
+    fn poll_shut_down(&self, cx: &mut Context<'_>) -> bool {
+        if self.shut_down.load(Ordering::SeqCst) {
+            return true;
+        }
+        self.lock().receiver = Some(cx.waker().clone());
+        self.shut_down.load(Ordering::SeqCst)
+    }
+
+    fn close_receiver(&self) {
+        self.receiver_dropped.store(true, Ordering::SeqCst);
+        self.wake_flushers();
+    }
+
+    fn wake_flushers(&self) {
+        let mut waiters = self.lock();
+        self.flushing.store(false, Ordering::SeqCst);
+        let flushers = mem::take(&mut waiters.flushers);
+        drop(waiters);
+        for waker in flushers {
+            waker.wake();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Waiters> {
+        self.waiters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Waiters {
+    flushers: Vec<Waker>,
+    receiver: Option<Waker>,
+}
+
+/// Finished span receiver.
+///
+/// The spans finished in the same thread are received in the order they were finished.
+/// The order of the spans finished in different threads is unspecified.
+#[derive(Debug)]
+pub struct SpanReceiver<T> {
+    rxs: Vec<Rx<T>>,
+    next: usize,
+    batchers: Option<Arc<[Batcher<T>]>>,
+    buffered: VecDeque<FinishedSpan<T>>,
+    faults: Option<Arc<Faults>>,
+    progress: Arc<Progress>,
+}
+impl<T> SpanReceiver<T> {
+    /// Receives the next finished span.
+    ///
+    /// This returns `None` if all the tracers and spans associated with this receiver
+    /// have been dropped (or the tracer has been shut down by `Tracer::shutdown`)
+    /// and all the finished spans have been received.
+    pub async fn recv(&mut self) -> Option<FinishedSpan<T>> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Receives the next finished span, blocking the current thread until it is available.
+    ///
+    /// This is intended for programs without an async runtime
+    /// and must not be called from asynchronous code.
+    /// Note that the lock-free queues enabled by `TracerBuilder::lock_free_queue`
+    /// are polled with the tokio timer and require a tokio runtime.
+    pub fn blocking_recv(&mut self) -> Option<FinishedSpan<T>> {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(span) = self.poll_recv(&mut cx) {
+                return span;
+            }
+            thread::park();
+        }
+    }
+
+    /// Receives the next finished span, blocking the current thread for up to `timeout`.
+    ///
+    /// If no span is finished within `timeout`, `TryRecvError::Empty` is returned.
+    /// The same restrictions as `blocking_recv` apply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::TryRecvError;
+    /// use cf_rustracing::Tracer;
+    /// use std::time::Duration;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// std::thread::spawn(move || {
+    ///     tracer.span("foo").start_with_state(());
+    /// });
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.blocking_recv_timeout(Duration::from_secs(10)).unwrap();
+    /// assert_eq!(span.operation_name(), "foo");
+    /// # }
+    /// assert_eq!(
+    ///     span_rx.blocking_recv_timeout(Duration::from_millis(1)).err(),
+    ///     Some(TryRecvError::Disconnected)
+    /// );
+    /// ```
+    pub fn blocking_recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<FinishedSpan<T>, TryRecvError> {
+        let deadline = Instant::now() + timeout;
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match self.poll_recv(&mut cx) {
+                Poll::Ready(Some(span)) => return Ok(span),
+                Poll::Ready(None) => return Err(TryRecvError::Disconnected),
+                Poll::Pending => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(TryRecvError::Empty);
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+
+    /// Polls to receive the next finished span.
+    ///
+    /// This has the same contract as `Stream::poll_next` of the `futures` crate,
+    /// so the receiver can be turned into a stream without this crate depending on `futures`,
+    /// e.g., `futures::stream::poll_fn(move |cx| span_rx.poll_recv(cx))`.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<FinishedSpan<T>>> {
+        if let Some(faults) = self.faults.as_ref() {
+            if faults.is_receiver_paused(Some(cx)) {
+                return Poll::Pending;
+            }
+        }
+        match self.poll_next(cx) {
+            Poll::Ready(Some(span)) => {
+                self.progress.settle(1);
+                Poll::Ready(Some(span))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending if self.progress.poll_shut_down(cx) => {
+                // Receives the spans which raced with the shutdown, if any.
+                let span = self.try_recv_delivered().ok();
+                let span = span.or_else(|| self.take_pending(false));
+                if span.is_some() {
+                    self.progress.settle(1);
+                }
+                Poll::Ready(span)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Tries to receive the next finished span without waiting.
+    pub fn try_recv(&mut self) -> Result<FinishedSpan<T>, TryRecvError> {
+        if let Some(faults) = self.faults.as_ref() {
+            if faults.is_receiver_paused(None) {
+                return Err(TryRecvError::Empty);
+            }
+        }
+        let result = self
+            .try_recv_delivered()
+            .or_else(|e| self.take_pending(false).ok_or(e));
+        match result {
+            Ok(span) => {
+                self.progress.settle(1);
+                Ok(span)
+            }
+            Err(TryRecvError::Empty) if self.progress.shut_down.load(Ordering::SeqCst) => {
+                Err(TryRecvError::Disconnected)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<FinishedSpan<T>>> {
+        match self.try_recv_delivered() {
+            Ok(span) => return Poll::Ready(Some(span)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(self.take_pending(false)),
+            Err(TryRecvError::Empty) => {}
+        }
+        if let Some(span) = self.take_pending(true) {
+            return Poll::Ready(Some(span));
+        }
+
+        let mut pending = false;
+        for i in 0..self.rxs.len() {
+            match self.rxs[i].poll_recv(cx) {
+                Poll::Ready(Some(delivery)) => return Poll::Ready(Some(self.unpack(delivery))),
+                Poll::Ready(None) => {}
+                Poll::Pending => pending = true,
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(self.take_pending(false))
+        }
+    }
+
+    fn try_recv_delivered(&mut self) -> Result<FinishedSpan<T>, TryRecvError> {
+        if let Some(span) = self.buffered.pop_front() {
+            return Ok(span);
+        }
+
+        let mut error = TryRecvError::Disconnected;
+        for _ in 0..self.rxs.len() {
+            let i = self.next;
+            self.next = (self.next + 1) % self.rxs.len();
+            match self.rxs[i].try_recv() {
+                Ok(delivery) => return Ok(self.unpack(delivery)),
+                Err(TryRecvError::Empty) => error = TryRecvError::Empty,
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        Err(error)
+    }
+
+    fn unpack(&mut self, delivery: Delivery<T>) -> FinishedSpan<T> {
+        match delivery {
+            Delivery::Single(span) => span,
+            Delivery::Batch(spans) => {
+                self.buffered.extend(spans);
+                self.buffered.pop_front().expect("never fails")
+            }
+        }
+    }
+
+    /// Takes the spans which are buffered in the sender side but have not been delivered yet.
+    ///
+    /// If there are no such spans and `wait` is `true`,
+    /// the next span finished will be delivered immediately to wake up this receiver.
+    fn take_pending(&mut self, wait: bool) -> Option<FinishedSpan<T>> {
+        for batcher in self.batchers.as_deref().unwrap_or_default() {
+            batcher.take(|state, spans| {
+                state.receiver_waiting = wait && spans.is_empty();
+                self.buffered.extend(spans);
+            });
+        }
+        self.buffered.pop_front()
+    }
+}
+
+impl<T> Drop for SpanReceiver<T> {
+    fn drop(&mut self) {
+        self.progress.close_receiver();
+    }
+}
+
+/// Waker which unparks a thread blocked in `SpanReceiver::blocking_recv`.
+struct ThreadWaker(Thread);
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Accumulator of the finished spans to be delivered as a batch.
+///
+/// A batch is delivered when it reaches `max_size` spans,
+/// when a span is finished after `max_delay` has elapsed since the batch was started,
+/// or when the receiver has nothing else to receive.
+///
+/// Batches are delivered while the batch is locked, so they are delivered in order.
+/// The threads which fail to lock the batch hand their spans off to the thread holding the lock
+/// instead of waiting for it; the spans are added to the batch before the lock is released.
+#[derive(Debug)]
+struct Batcher<T> {
+    state: Mutex<BatchState<T>>,
+    handoff: Handoff<FinishedSpan<T>>,
+    max_size: usize,
+    max_delay: Duration,
+}
+impl<T> Batcher<T> {
+    fn new(options: &ChannelOptions) -> Self {
+        Batcher {
+            state: Mutex::new(BatchState {
+                spans: Vec::new(),
+                started_at: Instant::now(),
+                receiver_waiting: false,
+            }),
+            handoff: Handoff::new(),
+            max_size: options.batch_size,
+            max_delay: options.batch_delay,
+        }
+    }
+
+    /// Adds `span` to the current batch and passes the batch to `deliver` if it should be delivered.
+    ///
+    /// This never blocks: if another thread is accessing the batch,
+    /// `span` is handed off to that thread.
+    fn push<F>(&self, span: FinishedSpan<T>, mut deliver: F)
+    where
+        F: FnMut(Delivery<T>),
+    {
+        let mut span = Some(span);
+        loop {
+            let mut state = match self.state.try_lock() {
+                Ok(state) => state,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                Err(TryLockError::WouldBlock) => {
+                    // Retries once after handing `span` off, in case the lock has been released
+                    // before the handoff.
+                    match span.take() {
+                        Some(span) => {
+                            self.handoff.push(span);
+                            continue;
+                        }
+                        None => return,
+                    }
+                }
+            };
+            for span in self.handoff.take_all().into_iter().chain(span.take()) {
+                if state.spans.is_empty() {
+                    state.started_at = Instant::now();
+                    state.spans.reserve(self.max_size);
+                }
+                state.spans.push(span);
+            }
+            if !state.spans.is_empty()
+                && (state.receiver_waiting
+                    || state.spans.len() >= self.max_size
+                    || state.started_at.elapsed() >= self.max_delay)
+            {
+                state.receiver_waiting = false;
+                deliver(Delivery::Batch(mem::take(&mut state.spans)));
+            }
+            drop(state);
+            if self.handoff.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// Takes the current batch, including the spans handed off to this batcher,
+    /// and passes it to `f` before releasing the lock.
+    fn take<F>(&self, mut f: F)
+    where
+        F: FnMut(&mut BatchState<T>, Vec<FinishedSpan<T>>),
+    {
+        loop {
+            let mut state = self.lock();
+            let mut spans = mem::take(&mut state.spans);
+            spans.extend(self.handoff.take_all());
+            f(&mut state, spans);
+            drop(state);
+            if self.handoff.is_empty() {
+                return;
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BatchState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug)]
+struct BatchState<T> {
+    spans: Vec<FinishedSpan<T>>,
+    started_at: Instant,
+    receiver_waiting: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampler::AllSampler;
+    use crate::span::{FinishedSpan, OverflowPolicy, TryRecvError};
+    use crate::Tracer;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn blocking_receiver_works_without_runtime() {
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            tracer.span("a").start_with_state(());
+        });
+        assert_eq!(span_rx.blocking_recv().unwrap().operation_name(), "a");
+        handle.join().unwrap();
+        assert!(span_rx.blocking_recv().is_none());
+
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        let timeout = Duration::from_millis(10);
+        assert_eq!(
+            span_rx.blocking_recv_timeout(timeout).err(),
+            Some(TryRecvError::Empty)
+        );
+        tracer.span("b").start_with_state(());
+        let span = span_rx.blocking_recv_timeout(timeout).unwrap();
+        assert_eq!(span.operation_name(), "b");
+        drop(tracer);
+        assert_eq!(
+            span_rx.blocking_recv_timeout(timeout).err(),
+            Some(TryRecvError::Disconnected)
+        );
+    }
+
+    #[tokio::test]
+    async fn batched_spans_are_delivered() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .batch_size(3)
+            .batch_delay(Duration::from_secs(60))
+            .finish();
+        for name in ["a", "b"] {
+            tracer.span(name).start_with_state(());
+        }
+        // The batch is not full, but the receiver picks the pending spans up.
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "a");
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "b");
+        assert!(span_rx.try_recv().is_err());
+
+        for name in ["c", "d", "e", "f"] {
+            tracer.span(name).start_with_state(());
+        }
+        drop(tracer);
+        for name in ["c", "d", "e", "f"] {
+            assert_eq!(span_rx.recv().await.unwrap().operation_name(), name);
+        }
+        assert!(span_rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn batched_spans_keep_order_under_contention() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .shards(1)
+            .batch_size(3)
+            .batch_delay(Duration::from_secs(60))
+            .finish();
+        tracer.span("a").start_with_state(());
+
+        // Spans finished while the batch is locked are handed off without blocking.
+        let state = span_rx.batchers.as_ref().unwrap()[0].lock();
+        thread::spawn({
+            let tracer = tracer.clone();
+            move || {
+                for name in ["b", "c"] {
+                    tracer.span(name).start_with_state(());
+                }
+            }
+        })
+        .join()
+        .unwrap();
+        drop(state);
+
+        // ... and are added to the batch before the next span.
+        tracer.span("d").start_with_state(());
+        for name in ["a", "b", "c", "d"] {
+            assert_eq!(span_rx.try_recv().unwrap().operation_name(), name);
+        }
+        assert!(span_rx.try_recv().is_err());
+
+        let state = span_rx.batchers.as_ref().unwrap()[0].lock();
+        thread::spawn(move || tracer.span("e").start_with_state(()))
+            .join()
+            .unwrap();
+        drop(state);
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "e");
+    }
+
+    #[tokio::test]
+    async fn waiting_receiver_is_woken_up() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .batch_size(100)
+            .batch_delay(Duration::from_secs(60))
+            .finish();
+        let handle =
+            tokio::spawn(
+                async move { span_rx.recv().await.map(|s| s.operation_name().to_owned()) },
+            );
+        tokio::task::yield_now().await;
+        tracer.span("foo").start_with_state(());
+        assert_eq!(handle.await.unwrap().as_deref(), Some("foo"));
+    }
+
+    #[tokio::test]
+    async fn sharded_spans_are_delivered() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler).shards(4).finish();
+        let threads = (0..8)
+            .map(|_| {
+                let tracer = tracer.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        tracer.span("foo").start_with_state(());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        drop(tracer);
+
+        let mut count = 0;
+        while span_rx.recv().await.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 800);
+    }
+
+    #[tokio::test]
+    async fn spans_exceeding_capacity_are_discarded() {
+        let (tracer, mut span_rx) = Tracer::with_channel_capacity(AllSampler, 2);
+        for name in ["a", "b", "c"] {
+            tracer.span(name).start_with_state(());
+        }
+        assert_eq!(span_rx.recv().await.unwrap().operation_name(), "a");
+        assert_eq!(span_rx.recv().await.unwrap().operation_name(), "b");
+        assert!(span_rx.try_recv().is_err());
+
+        tracer.span("d").start_with_state(());
+        assert_eq!(span_rx.recv().await.unwrap().operation_name(), "d");
+        assert_eq!(tracer.dropped_spans(), 1);
+
+        drop(span_rx);
+        tracer.span("e").start_with_state(());
+        assert_eq!(tracer.dropped_spans(), 2);
+    }
+
+    #[test]
+    fn reporter_receives_finished_spans() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let tracer = Tracer::with_reporter(AllSampler, {
+            let spans = Arc::clone(&spans);
+            move |span: FinishedSpan<()>| {
+                spans.lock().unwrap().push(span.operation_name().to_owned());
+            }
+        });
+        {
+            let span = tracer.span("parent").start_with_state(());
+            let _child = span.child("child", |options| options.start_with_state(()));
+        }
+        thread::spawn(move || {
+            tracer.span("other").start_with_state(());
+        })
+        .join()
+        .unwrap();
+        assert_eq!(*spans.lock().unwrap(), ["child", "parent", "other"]);
+    }
+
+    #[tokio::test]
+    async fn flush_waits_for_delivery() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler).batch_size(10).finish();
+        tracer.span("a").start_with_state(());
+        let flush = tokio::spawn({
+            let tracer = tracer.clone();
+            async move { tracer.flush().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!flush.is_finished());
+        assert_eq!(span_rx.recv().await.unwrap().operation_name(), "a");
+        flush.await.unwrap();
+
+        // Spans finished after shutdown are discarded.
+        let span = tracer.span("b").start_with_state(());
+        tracer.span("c").start_with_state(());
+        let shutdown = tokio::spawn({
+            let tracer = tracer.clone();
+            async move { tracer.shutdown().await }
+        });
+        assert_eq!(span_rx.recv().await.unwrap().operation_name(), "c");
+        shutdown.await.unwrap();
+        drop(span);
+        assert!(span_rx.recv().await.is_none());
+        assert_eq!(tracer.dropped_spans(), 1);
+
+        // Dropping the receiver completes flushing.
+        let (tracer, span_rx) = Tracer::new(AllSampler);
+        tracer.span("a").start_with_state(());
+        drop(span_rx);
+        tracer.flush().await;
+    }
+
+    #[test]
+    fn span_processors_are_applied_in_order() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let tracer = Tracer::builder(AllSampler)
+            .span_processor(|mut span: FinishedSpan<()>| {
+                let name = format!("{}!", span.operation_name());
+                span.set_operation_name(name);
+                Some(span)
+            })
+            .span_processor(|span| (span.operation_name() != "noisy!").then_some(span))
+            .finish_with_reporter(Arc::clone(&spans));
+        for name in ["noisy", "useful"] {
+            tracer.span(name).start_with_state(());
+        }
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].operation_name(), "useful!");
+        assert_eq!(tracer.dropped_spans(), 0);
+    }
+
+    #[test]
+    fn overflow_policies_work() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .channel_capacity(2)
+            .overflow_policy(OverflowPolicy::DropOldest)
+            .finish();
+        for name in ["a", "b", "c"] {
+            tracer.span(name).start_with_state(());
+        }
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "b");
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "c");
+        assert_eq!(tracer.dropped_spans(), 1);
+
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .channel_capacity(1)
+            .overflow_policy(OverflowPolicy::BlockWithTimeout(Duration::from_secs(10)))
+            .finish();
+        tracer.span("a").start_with_state(());
+        let handle = thread::spawn(move || {
+            tracer.span("b").start_with_state(());
+            tracer
+        });
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "a");
+        let tracer = handle.join().unwrap();
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "b");
+        assert_eq!(tracer.dropped_spans(), 0);
+
+        drop(span_rx);
+        tracer.span("c").start_with_state(());
+        assert_eq!(tracer.dropped_spans(), 1);
+    }
+
+    #[cfg(feature = "lock-free-queue")]
+    #[tokio::test]
+    async fn lock_free_queue_works() {
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .channel_capacity(2)
+            .lock_free_queue(Duration::from_millis(1))
+            .finish();
+        let handle = tokio::spawn(async move {
+            let mut names = Vec::new();
+            while let Some(span) = span_rx.recv().await {
+                names.push(span.operation_name().to_owned());
+            }
+            names
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for name in ["a", "b"] {
+            tracer.span(name).start_with_state(());
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        tracer.span("c").start_with_state(());
+        drop(tracer);
+        assert_eq!(handle.await.unwrap(), ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,333 @@
+//! Time sources of span and log timestamps.
+use crate::{Error, ErrorKind, Result};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// `Clock` provides the current time to the spans started by a tracer.
+///
+/// The start time of a span is taken from the wall clock (`now`),
+/// while its duration is measured with the monotonic clock (`instant`),
+/// so adjustments of the system time do not distort span durations.
+///
+/// Clocks are read only for spans being recorded:
+/// starting, logging to, and finishing unsampled spans never read the clock.
+pub trait Clock: fmt::Debug + Send + Sync + 'static {
+    /// Returns the current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// Returns the current monotonic time.
+    ///
+    /// The default implementation returns `Instant::now()`.
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Returns `false` if `instant` cannot be read.
+    ///
+    /// On targets without a monotonic clock (e.g., `wasm32-unknown-unknown`,
+    /// where `Instant::now()` panics), spans measure their durations with `now` instead.
+    /// Note that the batching of the span channel, the rate-limiting samplers
+    /// and the utilities of the `trace` and `tracker` modules still require `instant`.
+    ///
+    /// The default implementation returns `true`.
+    fn has_instant(&self) -> bool {
+        true
+    }
+}
+
+/// The default clock which calls `SystemTime::now()` for every timestamp.
+#[derive(Debug, Default, Clone)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which returns a cached time refreshed periodically by a background thread.
+///
+/// Reading this clock is a single atomic load, so it is cheaper than `SystemClock`
+/// at the cost of precision (the time lags behind by up to the resolution).
+///
+/// The background thread exits when all the clones of the clock have been dropped.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::clock::CoarseClock;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use std::time::Duration;
+///
+/// let clock = CoarseClock::new(Duration::from_millis(1)).unwrap();
+/// let (tracer, _span_rx) = Tracer::<_, ()>::builder(AllSampler).clock(clock).finish();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CoarseClock {
+    unix_nanos: Arc<AtomicU64>,
+}
+impl CoarseClock {
+    /// Makes a new `CoarseClock` instance which refreshes the time every `resolution`.
+    ///
+    /// # Errors
+    ///
+    /// If the background thread cannot be spawned,
+    /// it will return an error with the kind `ErrorKind::Other`.
+    pub fn new(resolution: Duration) -> Result<Self> {
+        let unix_nanos = Arc::new(AtomicU64::new(unix_nanos_now()));
+        let weak = Arc::downgrade(&unix_nanos);
+        thread::Builder::new()
+            .name("coarse-clock".to_owned())
+            .spawn(move || {
+                while let Some(unix_nanos) = weak.upgrade() {
+                    unix_nanos.store(unix_nanos_now(), Ordering::Relaxed);
+                    drop(unix_nanos);
+                    thread::sleep(resolution);
+                }
+            })
+            .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))?;
+        Ok(CoarseClock { unix_nanos })
+    }
+}
+impl Clock for CoarseClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(self.unix_nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// A manually controlled clock for deterministic tests.
+///
+/// The clones of a `MockClock` share the same time,
+/// so a test can keep a clone to advance the time of the clock passed to a tracer.
+///
+/// `advance` moves both the wall clock and the monotonic clock,
+/// whereas `set` changes only the wall clock, like a step of the system time.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::clock::MockClock;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let clock = MockClock::new(UNIX_EPOCH);
+/// let (tracer, mut span_rx) = Tracer::<_, ()>::builder(AllSampler)
+///     .clock(clock.clone())
+///     .finish();
+/// {
+///     let _span = tracer.span("foo").start_with_state(());
+///     clock.advance(Duration::from_millis(10));
+/// }
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let span = span_rx.recv().await.unwrap();
+/// assert_eq!(span.start_time(), UNIX_EPOCH);
+/// assert_eq!(span.duration(), Duration::from_millis(10));
+/// # }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    base: Instant,
+    now: Arc<Mutex<(SystemTime, Duration)>>,
+}
+impl MockClock {
+    /// Makes a new `MockClock` instance whose current time is `now`.
+    pub fn new(now: SystemTime) -> Self {
+        MockClock {
+            base: Instant::now(),
+            now: Arc::new(Mutex::new((now, Duration::ZERO))),
+        }
+    }
+
+    /// Advances the time of this clock by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.lock();
+        now.0 += duration;
+        now.1 += duration;
+    }
+
+    /// Sets the wall-clock time of this clock to `now`.
+    ///
+    /// The monotonic time is not changed.
+    pub fn set(&self, now: SystemTime) {
+        self.lock().0 = now;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (SystemTime, Duration)> {
+        self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new(UNIX_EPOCH)
+    }
+}
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        self.lock().0
+    }
+
+    fn instant(&self) -> Instant {
+        self.base + self.lock().1
+    }
+}
+
+/// A clock which reads the wall-clock time from a function returning
+/// the milliseconds elapsed since the Unix epoch.
+///
+/// This is intended for targets where `SystemTime::now()` is unavailable,
+/// such as `wasm32-unknown-unknown`, on which the function can be bound to
+/// `Date.now()` or `performance.timeOrigin + performance.now()` (e.g., via `js-sys`).
+/// This clock never reads `Instant`, so span durations are measured with the wall clock.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::clock::{Clock, EpochMillisClock};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = EpochMillisClock::new(|| 1500.25);
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_micros(1_500_250));
+/// assert!(!clock.has_instant());
+/// ```
+#[derive(Clone)]
+pub struct EpochMillisClock(Arc<dyn Fn() -> f64 + Send + Sync>);
+impl EpochMillisClock {
+    /// Makes a new `EpochMillisClock` instance which reads the time from `f`.
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn() -> f64 + Send + Sync + 'static,
+    {
+        EpochMillisClock(Arc::new(f))
+    }
+}
+impl Clock for EpochMillisClock {
+    fn now(&self) -> SystemTime {
+        let micros = ((self.0)() * 1000.0).max(0.0) as u64;
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn instant(&self) -> Instant {
+        panic!("EpochMillisClock has no monotonic time")
+    }
+
+    fn has_instant(&self) -> bool {
+        false
+    }
+}
+impl fmt::Debug for EpochMillisClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EpochMillisClock").finish_non_exhaustive()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+
+    fn instant(&self) -> Instant {
+        (**self).instant()
+    }
+
+    fn has_instant(&self) -> bool {
+        (**self).has_instant()
+    }
+}
+
+fn unix_nanos_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct CountingClock(AtomicU64);
+    impl Clock for CountingClock {
+        fn now(&self) -> SystemTime {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            SystemTime::now()
+        }
+
+        fn instant(&self) -> Instant {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Instant::now()
+        }
+    }
+
+    #[test]
+    fn unsampled_spans_never_read_clock() {
+        use crate::sampler::NullSampler;
+        use crate::Tracer;
+
+        let clock = Arc::new(CountingClock::default());
+        let (tracer, _span_rx) = Tracer::<_, ()>::builder(NullSampler)
+            .clock(Arc::clone(&clock))
+            .finish();
+        {
+            let mut span = tracer.span("foo").start_with_state(());
+            span.log(|log| {
+                log.std().message("bar");
+            });
+        }
+        assert_eq!(clock.0.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn mock_clock_works() {
+        let clock = MockClock::default();
+        assert_eq!(clock.now(), UNIX_EPOCH);
+
+        clock.clone().advance(Duration::from_secs(3));
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(3));
+
+        let instant = clock.instant();
+        clock.set(UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(clock.instant(), instant);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn clocks_without_instant_measure_wall_clock_durations() {
+        use crate::sampler::AllSampler;
+        use crate::Tracer;
+
+        let millis = Arc::new(AtomicU64::new(1000));
+        let clock = EpochMillisClock::new({
+            let millis = Arc::clone(&millis);
+            move || millis.load(Ordering::SeqCst) as f64
+        });
+        let (tracer, mut span_rx) = Tracer::<_, ()>::builder(AllSampler).clock(clock).finish();
+        {
+            let _span = tracer.span("foo").start_with_state(());
+            millis.fetch_add(15, Ordering::SeqCst);
+        }
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.start_time(), UNIX_EPOCH + Duration::from_secs(1));
+        assert_eq!(span.duration(), Duration::from_millis(15));
+    }
+
+    #[test]
+    fn coarse_clock_works() {
+        let clock = track_try_unwrap!(CoarseClock::new(Duration::from_millis(1)));
+        let t0 = clock.now();
+        thread::sleep(Duration::from_millis(20));
+        let t1 = clock.now();
+        assert!(t0 < t1);
+
+        let now = SystemTime::now();
+        assert!(now.duration_since(t1).unwrap_or_default() < Duration::from_secs(1));
+    }
+}
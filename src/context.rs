@@ -0,0 +1,388 @@
+//! Ready-made span context state.
+//!
+//! `DefaultContext` can be used as the state of spans (i.e., `Tracer<S, DefaultContext>`)
+//! by applications which do not need a state specific to a tracing backend.
+//! It is propagated in the [W3C Trace Context] format (the `traceparent` field)
+//! with the baggage items in the [W3C Baggage] format (the `baggage` field)
+//! and the deadline as milliseconds since the Unix epoch (the `rustracing-deadline` field).
+//!
+//! [W3C Trace Context]: https://www.w3.org/TR/trace-context/
+//! [W3C Baggage]: https://www.w3.org/TR/baggage/
+use crate::carrier::{
+    format_baggage, parse_baggage, trace_flags, BinaryPropagator, CompactBinaryPropagator,
+    ExtractFromBinary, ExtractFromHttpHeader, ExtractFromTextMap, InjectToBinary,
+    InjectToHttpHeader, InjectToTextMap, IterHttpHeaderFields, SetHttpHeaderField, TextMap,
+    TextMapPropagator, TraceContextPropagator, TraceContextState, TraceParent,
+};
+use crate::span::{CandidateSpan, SpanContext};
+use crate::trace::TraceIdentity;
+use crate::{Error, ErrorKind, Result};
+use std::fmt;
+use std::io::{Read, Write};
+use std::str::{self, FromStr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use crate::carrier::{BAGGAGE, TRACEPARENT, TRACESTATE};
+
+/// The name of the field carrying the deadline.
+pub const DEADLINE: &str = "rustracing-deadline";
+
+/// Span context state consisting of a 128-bit trace identifier,
+/// a 64-bit span identifier and trace flags.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use std::collections::HashMap;
+///
+/// let (tracer, _span_rx) = Tracer::<_, DefaultContext>::new(AllSampler);
+/// let parent = tracer.span("parent").start();
+/// let child = parent.child("child", |options| options.start());
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let parent: &DefaultContext = parent.context().unwrap().state();
+/// let child: &DefaultContext = child.context().unwrap().state();
+/// assert_eq!(child.trace_id(), parent.trace_id());
+/// assert_ne!(child.span_id(), parent.span_id());
+///
+/// let mut carrier = HashMap::new();
+/// tracer.span("foo").start().context().unwrap().inject_to_http_header(&mut carrier).unwrap();
+/// assert!(carrier.contains_key("traceparent"));
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DefaultContext {
+    trace_id: u128,
+    span_id: u64,
+    flags: u8,
+}
+impl DefaultContext {
+    /// The flag indicating that the trace is sampled.
+    pub const SAMPLED: u8 = 0x01;
+
+    /// Makes a new `DefaultContext` instance.
+    pub fn new(trace_id: u128, span_id: u64, flags: u8) -> Self {
+        DefaultContext {
+            trace_id,
+            span_id,
+            flags,
+        }
+    }
+
+    /// Returns the identifier of the trace.
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// Returns the identifier of the span.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Returns the trace flags.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns `true` if the sampled flag is set.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & Self::SAMPLED != 0
+    }
+
+    /// Parses a `traceparent` value.
+    fn parse_traceparent(value: &str) -> Result<Self> {
+        let traceparent = track!(value.parse::<TraceParent>())?;
+        Ok(Self::from_trace_context(traceparent, None))
+    }
+}
+impl FromStr for DefaultContext {
+    type Err = Error;
+
+    /// Parses a `traceparent` value.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_traceparent(s)
+    }
+}
+impl fmt::Display for DefaultContext {
+    /// Formats this context as a `traceparent` value.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.traceparent().fmt(f)
+    }
+}
+impl<'a> From<CandidateSpan<'a, DefaultContext>> for DefaultContext {
+    /// Inherits the trace from the first reference of `span`, or starts a new sampled trace.
+    ///
+    /// The identifiers are taken from the identifier generator of the tracer.
+    /// Debug spans (see `CandidateSpan::is_debug`) are marked as sampled.
+    fn from(span: CandidateSpan<'a, DefaultContext>) -> Self {
+        let span_id = span.generate_id();
+        match span.references().first() {
+            Some(reference) => {
+                let parent = reference.span();
+                let mut flags = parent.flags;
+                if span.is_debug() {
+                    flags |= Self::SAMPLED;
+                }
+                DefaultContext::new(parent.trace_id, span_id, flags)
+            }
+            None => {
+                let trace_id = (u128::from(span.generate_id()) << 64) | u128::from(span_id);
+                DefaultContext::new(trace_id, span_id, Self::SAMPLED)
+            }
+        }
+    }
+}
+impl TraceIdentity for DefaultContext {
+    type TraceId = u128;
+    type SpanId = u64;
+
+    fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.flags & Self::SAMPLED != 0
+    }
+}
+
+impl TraceContextState for DefaultContext {
+    fn traceparent(&self) -> TraceParent {
+        TraceParent::new(self.trace_id, self.span_id, self.flags)
+    }
+
+    fn from_trace_context(traceparent: TraceParent, _tracestate: Option<&str>) -> Self {
+        DefaultContext::new(
+            traceparent.trace_id(),
+            traceparent.span_id(),
+            traceparent.flags(),
+        )
+    }
+}
+impl<C: TextMap> InjectToTextMap<C> for DefaultContext {
+    fn inject_to_text_map(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+        track!(TraceContextPropagator.inject(context, carrier))?;
+        if let Some(deadline) = context.deadline() {
+            carrier.set_fmt(DEADLINE, format_args!("{}", unix_millis(deadline)));
+        }
+        Ok(())
+    }
+}
+impl<C: TextMap> ExtractFromTextMap<C> for DefaultContext {
+    fn extract_from_text_map(carrier: &C) -> Result<Option<SpanContext<Self>>> {
+        let context = match track!(TraceContextPropagator.extract(carrier))? {
+            Some(context) => context,
+            None => return Ok(None),
+        };
+        let deadline = match carrier.get(DEADLINE) {
+            Some(value) => Some(track!(parse_deadline(value))?),
+            None => None,
+        };
+        Ok(Some(with_deadline(context, deadline)))
+    }
+}
+impl<C: SetHttpHeaderField> InjectToHttpHeader<C> for DefaultContext {
+    fn inject_to_http_header(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+        let traceparent = format_args!("{}", context.state());
+        track!(carrier.set_http_header_field_fmt(TRACEPARENT, traceparent))?;
+        if !context.trace_state().is_empty() {
+            let trace_state = format_args!("{}", context.trace_state());
+            track!(carrier.set_http_header_field_fmt(TRACESTATE, trace_state))?;
+        }
+        if !context.baggage_items().is_empty() {
+            let baggage = format_baggage(context.baggage_items());
+            track!(carrier.set_http_header_field(BAGGAGE, &baggage))?;
+        }
+        if let Some(deadline) = context.deadline() {
+            let deadline = format_args!("{}", unix_millis(deadline));
+            track!(carrier.set_http_header_field_fmt(DEADLINE, deadline))?;
+        }
+        Ok(())
+    }
+}
+impl<'a, C: IterHttpHeaderFields<'a>> ExtractFromHttpHeader<'a, C> for DefaultContext {
+    fn extract_from_http_header(carrier: &'a C) -> Result<Option<SpanContext<Self>>> {
+        let mut state = None;
+        let mut items = Vec::new();
+        let mut deadline = None;
+        let mut trace_state = String::new();
+        for (name, value) in carrier.fields() {
+            if name.eq_ignore_ascii_case(TRACEPARENT) {
+                let value = track!(utf8(value))?;
+                state = Some(track!(Self::parse_traceparent(value))?);
+            } else if name.eq_ignore_ascii_case(TRACESTATE) {
+                // Multiple `tracestate` fields are combined into one list.
+                let value = track!(utf8(value))?;
+                if !trace_state.is_empty() {
+                    trace_state.push(',');
+                }
+                trace_state.push_str(value);
+            } else if name.eq_ignore_ascii_case(BAGGAGE) {
+                let value = track!(utf8(value))?;
+                items.extend(track!(parse_baggage(value))?);
+            } else if name.eq_ignore_ascii_case(DEADLINE) {
+                let value = track!(utf8(value))?;
+                deadline = Some(track!(parse_deadline(value))?);
+            }
+        }
+        // Invalid `tracestate` values are discarded rather than failing the extraction.
+        let trace_state = trace_state.parse().unwrap_or_default();
+        Ok(state.map(|state| {
+            let context = SpanContext::new(state, items)
+                .with_trace_state(trace_state)
+                .with_flags(trace_flags(&state.traceparent()));
+            with_deadline(context, deadline)
+        }))
+    }
+}
+impl<C: Write> InjectToBinary<C> for DefaultContext {
+    /// Writes the context with `CompactBinaryPropagator`.
+    fn inject_to_binary(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+        track!(CompactBinaryPropagator.inject(context, carrier))
+    }
+}
+impl<C: Read> ExtractFromBinary<C> for DefaultContext {
+    fn extract_from_binary(carrier: &mut C) -> Result<Option<SpanContext<Self>>> {
+        track!(CompactBinaryPropagator.extract(carrier))
+    }
+}
+
+fn with_deadline(
+    context: SpanContext<DefaultContext>,
+    deadline: Option<SystemTime>,
+) -> SpanContext<DefaultContext> {
+    match deadline {
+        Some(deadline) => context.with_deadline(deadline),
+        None => context,
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn parse_deadline(value: &str) -> Result<SystemTime> {
+    let millis = value.trim().parse::<u64>().map_err(|_| invalid())?;
+    UNIX_EPOCH
+        .checked_add(Duration::from_millis(millis))
+        .ok_or_else(invalid)
+}
+
+fn invalid() -> Error {
+    Error::from(ErrorKind::CarrierParse)
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    str::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::BaggageItem;
+    use std::collections::HashMap;
+
+    #[test]
+    fn traceparent_works() {
+        let context = DefaultContext::new(0xabc, 0x12, DefaultContext::SAMPLED);
+        let value = context.to_string();
+        assert_eq!(
+            value,
+            "00-00000000000000000000000000000abc-0000000000000012-01"
+        );
+        assert_eq!(track_try_unwrap!(value.parse::<DefaultContext>()), context);
+        assert!("00-zz".parse::<DefaultContext>().is_err());
+    }
+
+    #[test]
+    fn deadlines_are_parsed() {
+        let deadline = track_try_unwrap!(parse_deadline(" 1500 "));
+        assert_eq!(deadline, UNIX_EPOCH + Duration::from_millis(1500));
+        assert!(parse_deadline("-1").is_err());
+
+        // Out-of-range deadlines must not panic, whatever the range of `SystemTime` is.
+        let _ = parse_deadline(&u64::MAX.to_string());
+    }
+
+    #[test]
+    fn http_header_fields_are_case_insensitive() {
+        let mut carrier = HashMap::new();
+        carrier.insert(
+            "TraceParent".to_owned(),
+            "00-00000000000000000000000000000abc-0000000000000012-00".to_owned(),
+        );
+        carrier.insert("Baggage".to_owned(), "foo=bar".to_owned());
+        carrier.insert("TraceState".to_owned(), "congo=t61rcWkgMzE".to_owned());
+        let context = track_try_unwrap!(SpanContext::<DefaultContext>::extract_from_http_header(
+            &carrier
+        ))
+        .unwrap();
+        assert_eq!(context.state().trace_id(), 0xabc);
+        assert!(!context.state().is_sampled());
+        assert_eq!(context.baggage_items()[0].value(), "bar");
+        assert_eq!(context.trace_state().get("congo"), Some("t61rcWkgMzE"));
+
+        let mut injected = HashMap::new();
+        track_try_unwrap!(context.inject_to_http_header(&mut injected));
+        assert_eq!(injected[TRACESTATE], "congo=t61rcWkgMzE");
+
+        let carrier: HashMap<String, String> = HashMap::new();
+        let context = track_try_unwrap!(SpanContext::<DefaultContext>::extract_from_http_header(
+            &carrier
+        ));
+        assert!(context.is_none());
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn codecs_work() {
+        use crate::testing::carrier::{assert_extraction_never_panics, assert_roundtrip};
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let context = SpanContext::new(
+            DefaultContext::new(u128::MAX, 1, DefaultContext::SAMPLED),
+            vec![
+                BaggageItem::new("foo", "bar baz"),
+                BaggageItem::new("x", ""),
+            ],
+        );
+        assert_roundtrip(&context);
+        assert_extraction_never_panics(&mut StdRng::seed_from_u64(0), &context, 1000);
+
+        let deadline = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let context = context.with_deadline(deadline);
+        assert_roundtrip(&context);
+        assert_extraction_never_panics(&mut StdRng::seed_from_u64(0), &context, 1000);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn ids_are_taken_from_id_generator() {
+        use crate::id::SequentialIdGenerator;
+        use crate::sampler::AllSampler;
+        use crate::testing::TestTracer;
+        use crate::Tracer;
+
+        let tracer = TestTracer::<DefaultContext>::from_builder(
+            Tracer::builder(AllSampler).id_generator(SequentialIdGenerator::new(1)),
+        );
+        {
+            let parent = tracer.span("parent").start();
+            parent.child("child", |options| options.start());
+        }
+        let spans = tracer.spans();
+        let child = spans[0].context().state();
+        let parent = spans[1].context().state();
+        assert_eq!(*parent, DefaultContext::new((2 << 64) | 1, 1, 1));
+        assert_eq!(*child, DefaultContext::new((2 << 64) | 1, 3, 1));
+    }
+}
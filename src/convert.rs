@@ -1,4 +1,15 @@
 //! Traits for conversions between types.
+use crate::carrier::{ExtractFromHttpHeader, IterHttpHeaderFields};
+use crate::context::DefaultContext;
+use crate::span::SpanContext;
+use crate::{Error, ErrorKind, Result};
+use std::collections::HashMap;
+
+pub mod chrome;
+pub mod otlp;
+pub mod zipkin;
+
+pub(crate) mod json;
 
 /// A cheap reference-to-reference conversion that has a possibility to fail.
 pub trait MaybeAsRef<T: ?Sized> {
@@ -14,6 +25,95 @@ where
     }
 }
 
+/// A conversion into a span context that has a possibility to fail.
+///
+/// This allows functions to accept anything carrying a span context
+/// (e.g., a `traceparent` value or HTTP headers) without extracting it at every call site.
+/// `Ok(None)` means that the input carries no span context.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::convert::TryIntoSpanContext;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::span::Span;
+/// use cf_rustracing::Tracer;
+/// use std::collections::HashMap;
+///
+/// type MyTracer = Tracer<AllSampler, DefaultContext>;
+///
+/// fn handle<C: TryIntoSpanContext<DefaultContext>>(tracer: &MyTracer, parent: C) -> Span<DefaultContext> {
+///     let parent = parent.try_into_span_context().unwrap();
+///     tracer.span("handle").child_of(&parent).start()
+/// }
+///
+/// let (tracer, _span_rx) = Tracer::new(AllSampler);
+/// let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+/// let span = handle(&tracer, traceparent);
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(span.context().unwrap().state().trace_id(), 0x0af7651916cd43dd8448eb211c80319c);
+///
+/// let mut headers = HashMap::new();
+/// headers.insert("traceparent".to_owned(), traceparent.to_owned());
+/// let span = handle(&tracer, &headers);
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(span.context().unwrap().state().trace_id(), 0x0af7651916cd43dd8448eb211c80319c);
+/// ```
+pub trait TryIntoSpanContext<T> {
+    /// Performs the conversion.
+    fn try_into_span_context(self) -> Result<Option<SpanContext<T>>>;
+}
+impl<T> TryIntoSpanContext<T> for SpanContext<T> {
+    fn try_into_span_context(self) -> Result<Option<SpanContext<T>>> {
+        Ok(Some(self))
+    }
+}
+impl<T> TryIntoSpanContext<T> for Option<SpanContext<T>> {
+    fn try_into_span_context(self) -> Result<Option<SpanContext<T>>> {
+        Ok(self)
+    }
+}
+impl<T: Clone> TryIntoSpanContext<T> for &SpanContext<T> {
+    fn try_into_span_context(self) -> Result<Option<SpanContext<T>>> {
+        Ok(Some(self.clone()))
+    }
+}
+impl TryIntoSpanContext<DefaultContext> for &str {
+    /// Parses `self` as a `traceparent` value.
+    fn try_into_span_context(self) -> Result<Option<SpanContext<DefaultContext>>> {
+        let state = track!(self.parse::<DefaultContext>())?;
+        Ok(Some(SpanContext::new(state, Vec::new())))
+    }
+}
+impl TryIntoSpanContext<DefaultContext> for (u128, u64) {
+    /// Makes a sampled context from the trace and span identifiers.
+    fn try_into_span_context(self) -> Result<Option<SpanContext<DefaultContext>>> {
+        (self.0, self.1, DefaultContext::SAMPLED).try_into_span_context()
+    }
+}
+impl TryIntoSpanContext<DefaultContext> for (u128, u64, u8) {
+    /// Makes a context from the trace and span identifiers and the trace flags.
+    fn try_into_span_context(self) -> Result<Option<SpanContext<DefaultContext>>> {
+        let (trace_id, span_id, flags) = self;
+        if trace_id == 0 || span_id == 0 {
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+        let state = DefaultContext::new(trace_id, span_id, flags);
+        Ok(Some(SpanContext::new(state, Vec::new())))
+    }
+}
+impl<'a, T, K, V, S> TryIntoSpanContext<T> for &'a HashMap<K, V, S>
+where
+    HashMap<K, V, S>: IterHttpHeaderFields<'a>,
+    T: ExtractFromHttpHeader<'a, HashMap<K, V, S>>,
+{
+    /// Extracts a context from `self` as HTTP header fields.
+    fn try_into_span_context(self) -> Result<Option<SpanContext<T>>> {
+        track!(T::extract_from_http_header(self))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -31,4 +131,25 @@ mod tests {
         let bar = Bar(Foo);
         assert!(bar.maybe_as_ref().is_some());
     }
+
+    #[test]
+    fn common_inputs_are_converted_into_contexts() {
+        let context = track_try_unwrap!((1u128, 2u64).try_into_span_context()).unwrap();
+        assert_eq!(
+            *context.state(),
+            DefaultContext::new(1, 2, DefaultContext::SAMPLED)
+        );
+
+        let traceparent = context.state().to_string();
+        let parsed = track_try_unwrap!(traceparent.as_str().try_into_span_context()).unwrap();
+        assert_eq!(parsed.state(), context.state());
+
+        let headers = HashMap::<String, String>::new();
+        let extracted: Option<SpanContext<DefaultContext>> =
+            track_try_unwrap!((&headers).try_into_span_context());
+        assert!(extracted.is_none());
+
+        assert!("00-zz".try_into_span_context().is_err());
+        assert!((0u128, 2u64).try_into_span_context().is_err());
+    }
 }
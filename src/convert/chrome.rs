@@ -0,0 +1,215 @@
+//! Conversion of finished spans into the [Chrome trace event format].
+//!
+//! The output can be loaded into `chrome://tracing` or [Perfetto](https://ui.perfetto.dev)
+//! to look at the spans of a process without running any tracing backend.
+//!
+//! [Chrome trace event format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+use super::json::{JsonStr, JsonValue};
+use crate::span::{FinishedSpan, SpanStatus};
+use std::cmp::Reverse;
+use std::fmt::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Renders `spans` into a Chrome trace event JSON object.
+///
+/// Each span becomes a complete event (`"ph":"X"`) whose arguments are the tags of the span,
+/// and each log becomes an instant event (`"ph":"i"`) whose arguments are the fields of the log.
+///
+/// Since spans do not record the threads they ran on, they are laid out on thread lanes
+/// of a single process: each span is put on the first lane where it nests in the spans
+/// already placed there (as trace viewers require), so concurrent spans get separate lanes.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::clock::MockClock;
+/// use cf_rustracing::convert::chrome::chrome_trace;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = MockClock::new(UNIX_EPOCH);
+/// let (tracer, mut span_rx) = Tracer::builder(AllSampler).clock(clock.clone()).finish();
+/// {
+///     let root = tracer.span("root").start_with_state(());
+///     let a = root.child("a", |options| options.start_with_state(()));
+///     clock.advance(Duration::from_millis(1));
+///     let b = root.child("b", |options| options.start_with_state(()));
+///     clock.advance(Duration::from_millis(1));
+///     drop(a);
+///     clock.advance(Duration::from_millis(1));
+///     drop(b);
+/// }
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let spans = std::iter::from_fn(|| span_rx.try_recv().ok()).collect::<Vec<_>>();
+/// // `b` overlaps `a` without nesting in it, so it is put on another lane.
+/// assert_eq!(
+///     chrome_trace(&spans),
+///     concat!(
+///         r#"{"traceEvents":["#,
+///         r#"{"name":"root","ph":"X","ts":0.000,"dur":3000.000,"pid":1,"tid":1},"#,
+///         r#"{"name":"a","ph":"X","ts":0.000,"dur":2000.000,"pid":1,"tid":1},"#,
+///         r#"{"name":"b","ph":"X","ts":1000.000,"dur":2000.000,"pid":1,"tid":2}"#,
+///         r#"],"displayTimeUnit":"ms"}"#
+///     )
+/// );
+/// # }
+/// ```
+pub fn chrome_trace<T>(spans: &[FinishedSpan<T>]) -> String {
+    let mut order = spans.iter().collect::<Vec<_>>();
+    order.sort_by_key(|s| (s.start_time(), Reverse(s.finish_time())));
+    let mut lanes = Lanes(Vec::new());
+    let mut out = String::from(r#"{"traceEvents":["#);
+    for (i, span) in order.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let tid = lanes.place(span.start_time(), span.finish_time()) + 1;
+        let _ = write_span(&mut out, span, tid);
+    }
+    out.push_str(r#"],"displayTimeUnit":"ms"}"#);
+    out
+}
+
+/// Stacks of the finish times of the spans open on each lane.
+struct Lanes(Vec<Vec<SystemTime>>);
+impl Lanes {
+    /// Returns the index of the lane for a span, which must not start before the previous ones.
+    fn place(&mut self, start: SystemTime, finish: SystemTime) -> usize {
+        for (i, lane) in self.0.iter_mut().enumerate() {
+            while lane.last().is_some_and(|&end| end <= start) {
+                lane.pop();
+            }
+            if lane.last().map_or(true, |&end| finish <= end) {
+                lane.push(finish);
+                return i;
+            }
+        }
+        self.0.push(vec![finish]);
+        self.0.len() - 1
+    }
+}
+
+fn write_span<T>(out: &mut String, span: &FinishedSpan<T>, tid: usize) -> fmt::Result {
+    write!(
+        out,
+        r#"{{"name":{},"ph":"X","ts":{},"dur":{},"pid":1,"tid":{}"#,
+        JsonStr(span.operation_name()),
+        Micros(span.start_unix_time().as_nanos()),
+        Micros(span.duration().as_nanos()),
+        tid
+    )?;
+    let error = match span.status() {
+        SpanStatus::Error { message } => Some(message),
+        _ => None,
+    };
+    let mut tags = span.all_tags().peekable();
+    if tags.peek().is_some() || error.is_some() {
+        out.push_str(r#","args":{"#);
+        for (i, tag) in tags.enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(
+                out,
+                "{}{}:{}",
+                sep,
+                JsonStr(tag.name()),
+                JsonValue(tag.value())
+            )?;
+        }
+        if let Some(message) = error {
+            let sep = if out.ends_with('{') { "" } else { "," };
+            write!(out, r#"{}"status":{}"#, sep, JsonStr(message))?;
+        }
+        out.push('}');
+    }
+    out.push('}');
+
+    for log in span.logs() {
+        let time = log.time().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let name = log
+            .fields()
+            .iter()
+            .find(|f| f.name() == "event")
+            .and_then(|f| f.value().as_str())
+            .unwrap_or("log");
+        write!(
+            out,
+            r#",{{"name":{},"ph":"i","s":"t","ts":{},"pid":1,"tid":{},"args":{{"#,
+            JsonStr(name),
+            Micros(time.as_nanos()),
+            tid
+        )?;
+        for (i, field) in log.fields().iter().enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(
+                out,
+                "{}{}:{}",
+                sep,
+                JsonStr(field.name()),
+                JsonValue(field.value())
+            )?;
+        }
+        out.push_str("}}");
+    }
+    Ok(())
+}
+
+/// Nanoseconds rendered as microseconds, which is the time unit of trace events.
+struct Micros(u128);
+impl fmt::Display for Micros {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:03}", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(rustracing_noop))]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::tag::Tag;
+    use crate::testing::TestTracer;
+    use std::time::Duration;
+
+    #[test]
+    fn spans_are_laid_out_on_lanes() {
+        let clock = MockClock::new(UNIX_EPOCH);
+        let tracer = TestTracer::<()>::with_clock(clock.clone());
+        {
+            let root = tracer.span("root").start_with_state(());
+            clock.advance(Duration::from_nanos(1500));
+            let mut first = root.child("first", |options| {
+                options.tag(Tag::new("n", 1)).start_with_state(())
+            });
+            clock.advance(Duration::from_micros(1));
+            let overlapping = root.child("overlapping", |options| options.start_with_state(()));
+            first.log(|log| {
+                log.std().event("retry");
+            });
+            first.set_status(|| SpanStatus::error("failed"));
+            clock.advance(Duration::from_micros(1));
+            drop(first);
+            clock.advance(Duration::from_micros(1));
+            drop(overlapping);
+            let _second = root.child("second", |options| options.start_with_state(()));
+            clock.advance(Duration::from_micros(1));
+        }
+
+        assert_eq!(
+            chrome_trace(&tracer.spans()),
+            concat!(
+                r#"{"traceEvents":["#,
+                r#"{"name":"root","ph":"X","ts":0.000,"dur":5.500,"pid":1,"tid":1},"#,
+                r#"{"name":"first","ph":"X","ts":1.500,"dur":2.000,"pid":1,"tid":1,"#,
+                r#""args":{"n":1,"status":"failed"}},"#,
+                r#"{"name":"retry","ph":"i","s":"t","ts":2.500,"pid":1,"tid":1,"#,
+                r#""args":{"event":"retry"}},"#,
+                r#"{"name":"overlapping","ph":"X","ts":2.500,"dur":2.000,"pid":1,"tid":2},"#,
+                r#"{"name":"second","ph":"X","ts":4.500,"dur":1.000,"pid":1,"tid":1}"#,
+                r#"],"displayTimeUnit":"ms"}"#
+            )
+        );
+    }
+}
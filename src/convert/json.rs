@@ -0,0 +1,59 @@
+//! Minimal JSON rendering shared by the converters.
+use crate::tag::TagValue;
+use std::fmt::{self, Write};
+
+/// JSON string literal of a string.
+pub(crate) struct JsonStr<'a>(pub &'a str);
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// JSON representation of a tag value.
+///
+/// Non-finite floats are rendered as `null`.
+pub(crate) struct JsonValue<'a>(pub &'a TagValue);
+impl fmt::Display for JsonValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            TagValue::String(v) => write!(f, "{}", JsonStr(v)),
+            TagValue::Boolean(v) => write!(f, "{}", v),
+            TagValue::Integer(v) => write!(f, "{}", v),
+            TagValue::Float(v) if v.is_finite() => write!(f, "{}", v),
+            TagValue::Float(_) => f.write_str("null"),
+            TagValue::Array(values) => {
+                f.write_char('[')?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", JsonValue(v))?;
+                }
+                f.write_char(']')
+            }
+            TagValue::Map(entries) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{}", JsonStr(k), JsonValue(v))?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
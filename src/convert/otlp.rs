@@ -0,0 +1,356 @@
+//! Conversion of finished spans into OpenTelemetry span data.
+//!
+//! The structures in this module mirror the span messages of [OTLP],
+//! so that finished spans can be exported by OpenTelemetry exporters
+//! by copying the fields into the exporter's own types.
+//!
+//! The conversion follows the [OpenTracing compatibility] rules of the OpenTelemetry specification:
+//!
+//! - Tags become attributes, except `span.kind` (which becomes `SpanData::kind`)
+//!   and `error` (which sets `SpanData::status` if it is unset).
+//! - Logs become events named after their `event` field (or `"log"` if it is absent).
+//!   Error logs become `"exception"` events with the fields renamed to the semantic conventions.
+//! - The first `ChildOf` reference (or the first reference if there are none) becomes the parent,
+//!   and the other references become links.
+//!
+//! [OTLP]: https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/trace/v1/trace.proto
+//! [OpenTracing compatibility]: https://opentelemetry.io/docs/specs/otel/compatibility/opentracing/
+//!
+//! # Examples
+//!
+//! ```
+//! use cf_rustracing::context::DefaultContext;
+//! use cf_rustracing::convert::otlp::{SpanData, SpanKind};
+//! use cf_rustracing::sampler::AllSampler;
+//! use cf_rustracing::tag::StdTag;
+//! use cf_rustracing::Tracer;
+//!
+//! let (tracer, mut span_rx) = Tracer::<_, DefaultContext>::new(AllSampler);
+//! let parent = tracer.span("parent").start();
+//! {
+//!     let mut span = tracer
+//!         .span("child")
+//!         .child_of(&parent)
+//!         .tag(StdTag::span_kind("client"))
+//!         .start();
+//!     span.error_log(|log| {
+//!         log.kind("Timeout").message("no response");
+//!     });
+//! }
+//!
+//! # #[cfg(not(rustracing_noop))]
+//! # {
+//! let span = span_rx.try_recv().unwrap();
+//! let data = SpanData::from(&span);
+//! assert_eq!(data.name, "child");
+//! assert_eq!(data.kind, SpanKind::Client);
+//! assert_eq!(data.parent_span_id, Some(parent.context().unwrap().state().span_id().to_be_bytes()));
+//! assert!(data.status.is_error());
+//! assert_eq!(data.events[0].name, "exception");
+//! # }
+//! ```
+use crate::log::Log;
+use crate::span::{FinishedSpan, SpanReference, SpanStatus};
+use crate::tag::{Tag, TagValue};
+use crate::trace::TraceIdentity;
+
+/// Span data in the shape of the OTLP `Span` message.
+#[derive(Debug, Clone)]
+pub struct SpanData {
+    /// Identifier of the trace (big-endian).
+    pub trace_id: [u8; 16],
+
+    /// Identifier of the span (big-endian).
+    pub span_id: [u8; 8],
+
+    /// W3C `tracestate` of the span.
+    pub trace_state: String,
+
+    /// Identifier of the parent span (big-endian), or `None` for root spans.
+    pub parent_span_id: Option<[u8; 8]>,
+
+    /// Operation name of the span.
+    pub name: String,
+
+    /// Kind of the span.
+    pub kind: SpanKind,
+
+    /// Start time of the span in nanoseconds since the UNIX epoch.
+    pub start_time_unix_nano: u64,
+
+    /// Finish time of the span in nanoseconds since the UNIX epoch.
+    pub end_time_unix_nano: u64,
+
+    /// Attributes of the span.
+    pub attributes: Vec<Tag>,
+
+    /// Number of the attributes dropped because of `SpanLimits`.
+    pub dropped_attributes_count: u32,
+
+    /// Events of the span.
+    pub events: Vec<Event>,
+
+    /// Number of the events dropped because of `SpanLimits`.
+    pub dropped_events_count: u32,
+
+    /// Links to the spans referred by the span, other than the parent.
+    pub links: Vec<Link>,
+
+    /// Status of the span.
+    pub status: SpanStatus,
+}
+impl<T> From<&FinishedSpan<T>> for SpanData
+where
+    T: TraceIdentity<TraceId = u128, SpanId = u64>,
+{
+    fn from(span: &FinishedSpan<T>) -> Self {
+        let state = span.context().state();
+        let mut kind = SpanKind::Internal;
+        let mut error = false;
+        let mut attributes = Vec::with_capacity(span.tags().len());
+        for tag in span.all_tags() {
+            match (tag.name(), tag.value()) {
+                ("span.kind", TagValue::String(v)) => match SpanKind::from_tag(v) {
+                    Some(k) => kind = k,
+                    None => attributes.push(tag.clone()),
+                },
+                ("error", TagValue::Boolean(v)) => error = *v,
+                _ => attributes.push(tag.clone()),
+            }
+        }
+
+        let references = span.references();
+        let parent = references
+            .iter()
+            .position(|r| r.is_child_of())
+            .or_else(|| (!references.is_empty()).then_some(0));
+        let links = references
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != parent)
+            .map(|(_, r)| Link::from(r))
+            .collect();
+
+        let status = match span.status() {
+            SpanStatus::Unset if error => SpanStatus::error(""),
+            status => status.clone(),
+        };
+        SpanData {
+            trace_id: state.trace_id().to_be_bytes(),
+            span_id: state.span_id().to_be_bytes(),
+            trace_state: span.context().trace_state().to_string(),
+            parent_span_id: parent.map(|i| references[i].span().span_id().to_be_bytes()),
+            name: span.operation_name().to_owned(),
+            kind,
+            start_time_unix_nano: span.start_unix_time().as_nanos() as u64,
+            end_time_unix_nano: span.finish_unix_time().as_nanos() as u64,
+            attributes,
+            dropped_attributes_count: span.dropped_tags_count(),
+            events: span.logs().iter().map(Event::from).collect(),
+            dropped_events_count: span.dropped_logs_count(),
+            links,
+            status,
+        }
+    }
+}
+
+/// Kind of a span, having the values of the OTLP `SpanKind` enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpanKind {
+    /// The kind is not specified.
+    Unspecified = 0,
+
+    /// The span represents an internal operation.
+    Internal = 1,
+
+    /// The span handles a remote request.
+    Server = 2,
+
+    /// The span sends a remote request.
+    Client = 3,
+
+    /// The span sends a message to a broker.
+    Producer = 4,
+
+    /// The span receives a message from a broker.
+    Consumer = 5,
+}
+impl SpanKind {
+    /// Returns the kind represented by the value of a `span.kind` tag.
+    fn from_tag(value: &str) -> Option<Self> {
+        match value {
+            "server" => Some(SpanKind::Server),
+            "client" => Some(SpanKind::Client),
+            "producer" => Some(SpanKind::Producer),
+            "consumer" => Some(SpanKind::Consumer),
+            _ => None,
+        }
+    }
+}
+
+/// Event in the shape of the OTLP `Span.Event` message.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// Time of the event in nanoseconds since the UNIX epoch.
+    pub time_unix_nano: u64,
+
+    /// Name of the event.
+    pub name: String,
+
+    /// Attributes of the event.
+    pub attributes: Vec<Tag>,
+}
+impl From<&Log> for Event {
+    fn from(log: &Log) -> Self {
+        let event = log
+            .fields()
+            .iter()
+            .find(|f| f.name() == "event")
+            .and_then(|f| f.value().as_str());
+        let exception = event == Some("error");
+        let attributes = log
+            .fields()
+            .iter()
+            .filter(|f| event.is_none() || f.name() != "event")
+            .map(|f| {
+                let name = match f.name() {
+                    "error.kind" if exception => "exception.type".to_owned(),
+                    "message" if exception => "exception.message".to_owned(),
+                    "stack" if exception => "exception.stacktrace".to_owned(),
+                    name => name.to_owned(),
+                };
+                Tag::new(name, f.value().clone())
+            })
+            .collect();
+        let name = match event {
+            Some("error") => "exception",
+            Some(event) => event,
+            None => "log",
+        };
+        Event {
+            time_unix_nano: log
+                .time()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64,
+            name: name.to_owned(),
+            attributes,
+        }
+    }
+}
+
+/// Link in the shape of the OTLP `Span.Link` message.
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// Identifier of the trace of the linked span (big-endian).
+    pub trace_id: [u8; 16],
+
+    /// Identifier of the linked span (big-endian).
+    pub span_id: [u8; 8],
+
+    /// Attributes of the link.
+    ///
+    /// This contains the `opentracing.ref_type` attribute telling the type of the reference.
+    pub attributes: Vec<Tag>,
+}
+impl<T> From<&SpanReference<T>> for Link
+where
+    T: TraceIdentity<TraceId = u128, SpanId = u64>,
+{
+    fn from(reference: &SpanReference<T>) -> Self {
+        let ref_type = if reference.is_child_of() {
+            "child_of"
+        } else {
+            "follows_from"
+        };
+        Link {
+            trace_id: reference.span().trace_id().to_be_bytes(),
+            span_id: reference.span().span_id().to_be_bytes(),
+            attributes: vec![Tag::new("opentracing.ref_type", ref_type)],
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(rustracing_noop))]
+mod tests {
+    use super::*;
+    use crate::context::DefaultContext;
+    use crate::sampler::AllSampler;
+    use crate::tag::StdTag;
+    use crate::Tracer;
+
+    #[test]
+    fn finished_spans_are_converted() {
+        let (tracer, mut span_rx) = Tracer::<_, DefaultContext>::new(AllSampler);
+        let parent = tracer.span("parent").start();
+        let cause = tracer.span("cause").start();
+        {
+            let mut span = tracer
+                .span("foo")
+                .follows_from(&cause)
+                .child_of(&parent)
+                .tag(StdTag::span_kind("server"))
+                .tag(StdTag::error())
+                .tag(Tag::new("http.route", "/users/:id"))
+                .start();
+            span.log(|log| {
+                log.std().event("retry").message("timed out");
+            });
+            span.log(|log| {
+                log.field_i64("attempts", 2);
+            });
+        }
+
+        let span = span_rx.try_recv().unwrap();
+        let data = SpanData::from(&span);
+        let state = span.context().state();
+        assert_eq!(data.trace_id, state.trace_id().to_be_bytes());
+        assert_eq!(data.span_id, state.span_id().to_be_bytes());
+        let parent_id = parent.context().unwrap().state().span_id();
+        assert_eq!(data.parent_span_id, Some(parent_id.to_be_bytes()));
+        assert_eq!(data.kind, SpanKind::Server);
+        assert_eq!(data.status, SpanStatus::error(""));
+        let names = data.attributes.iter().map(|a| a.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["http.route"]);
+
+        assert_eq!(data.events[0].name, "retry");
+        assert_eq!(data.events[0].attributes[0].name(), "message");
+        assert_eq!(data.events[1].name, "log");
+        assert_eq!(data.events[1].attributes[0].name(), "attempts");
+
+        let cause_id = cause.context().unwrap().state().span_id();
+        assert_eq!(data.links.len(), 1);
+        assert_eq!(data.links[0].span_id, cause_id.to_be_bytes());
+        assert_eq!(
+            data.links[0].attributes[0].value(),
+            &TagValue::from("follows_from")
+        );
+    }
+
+    #[test]
+    fn error_logs_become_exceptions() {
+        let (tracer, mut span_rx) = Tracer::<_, DefaultContext>::new(AllSampler);
+        {
+            let mut span = tracer.span("foo").start();
+            span.set_status(|| SpanStatus::Ok);
+            span.error_log(|log| {
+                log.kind("Timeout").message("no response");
+            });
+        }
+
+        let span = span_rx.try_recv().unwrap();
+        let data = SpanData::from(&span);
+        assert_eq!(data.kind, SpanKind::Internal);
+        assert_eq!(data.parent_span_id, None);
+        assert_eq!(data.status, SpanStatus::Ok);
+        let event = &data.events[0];
+        assert_eq!(event.name, "exception");
+        let names = event
+            .attributes
+            .iter()
+            .map(|a| a.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["exception.type", "exception.message"]);
+    }
+}
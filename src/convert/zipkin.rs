@@ -0,0 +1,306 @@
+//! Conversion of finished spans into [Zipkin v2 JSON].
+//!
+//! The conversion follows the conventions of the Zipkin OpenTracing bridges:
+//!
+//! - The `span.kind` tag becomes the kind of the span, and the `peer.service`, `peer.ipv4`,
+//!   `peer.ipv6` and `peer.port` tags become its remote endpoint.
+//!   The other tags become Zipkin tags, whose non-string values are rendered as JSON.
+//! - `SpanStatus::Error` becomes the `error` tag having the error message.
+//! - Logs become annotations.
+//!   Logs having only the `event` field are annotated with the event,
+//!   and the other logs with their fields rendered as a JSON object.
+//! - The first `ChildOf` reference (or the first reference if there are none) becomes the parent.
+//!
+//! [Zipkin v2 JSON]: https://zipkin.io/zipkin-api/#/default/post_spans
+//!
+//! # Examples
+//!
+//! ```
+//! use cf_rustracing::clock::MockClock;
+//! use cf_rustracing::convert::zipkin::ZipkinEncoder;
+//! use cf_rustracing::sampler::AllSampler;
+//! use cf_rustracing::tag::StdTag;
+//! use cf_rustracing::Tracer;
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1));
+//! let (tracer, mut span_rx) = Tracer::builder(AllSampler).clock(clock.clone()).finish();
+//! {
+//!     let mut span = tracer
+//!         .span("get")
+//!         .tag(StdTag::span_kind("client"))
+//!         .tag(StdTag::peer_service("db"))
+//!         .start_with_state(0xabc);
+//!     clock.advance(Duration::from_millis(5));
+//!     span.log(|log| {
+//!         log.std().event("retry");
+//!     });
+//! }
+//!
+//! // The states of the spans are mapped to the trace and span identifiers.
+//! let encoder = ZipkinEncoder::new(|state: &u64| (1, *state)).service_name("frontend");
+//! # #[cfg(not(rustracing_noop))]
+//! assert_eq!(
+//!     encoder.encode(&[span_rx.try_recv().unwrap()]),
+//!     concat!(
+//!         r#"[{"traceId":"0000000000000001","id":"0000000000000abc","name":"get","#,
+//!         r#""kind":"CLIENT","timestamp":1000000,"duration":5000,"#,
+//!         r#""localEndpoint":{"serviceName":"frontend"},"#,
+//!         r#""remoteEndpoint":{"serviceName":"db"},"#,
+//!         r#""annotations":[{"timestamp":1005000,"value":"retry"}]}]"#
+//!     )
+//! );
+//! ```
+use super::json::{JsonStr, JsonValue};
+use crate::log::Log;
+use crate::span::{FinishedSpan, SpanStatus};
+use crate::tag::{Tag, TagValue};
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+
+/// Encoder of finished spans into Zipkin v2 JSON.
+///
+/// `F` maps the states of span contexts to the pairs of their trace and span identifiers.
+/// Trace identifiers fitting in 64 bits are rendered in 16 hex digits, and the others in 32.
+pub struct ZipkinEncoder<F> {
+    ids: F,
+    service_name: Option<Cow<'static, str>>,
+}
+impl<F> ZipkinEncoder<F> {
+    /// Makes a new `ZipkinEncoder` instance.
+    pub fn new(ids: F) -> Self {
+        ZipkinEncoder {
+            ids,
+            service_name: None,
+        }
+    }
+
+    /// Sets the name of the local service, which is rendered as the local endpoint of spans.
+    ///
+    /// By default, spans have no local endpoint.
+    pub fn service_name<N>(mut self, name: N) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.service_name = Some(name.into());
+        self
+    }
+
+    /// Encodes `spans` into a JSON array, which can be POSTed to the `/api/v2/spans` endpoint
+    /// of Zipkin collectors.
+    pub fn encode<T>(&self, spans: &[FinishedSpan<T>]) -> String
+    where
+        F: Fn(&T) -> (u128, u64),
+    {
+        let mut out = String::from("[");
+        for (i, span) in spans.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = self.write_span(&mut out, span);
+        }
+        out.push(']');
+        out
+    }
+
+    fn write_span<T>(&self, out: &mut String, span: &FinishedSpan<T>) -> fmt::Result
+    where
+        F: Fn(&T) -> (u128, u64),
+    {
+        let (trace_id, span_id) = (self.ids)(span.context().state());
+        if trace_id >> 64 == 0 {
+            write!(out, r#"{{"traceId":"{:016x}""#, trace_id)?;
+        } else {
+            write!(out, r#"{{"traceId":"{:032x}""#, trace_id)?;
+        }
+        write!(out, r#","id":"{:016x}""#, span_id)?;
+        let references = span.references();
+        let parent = references
+            .iter()
+            .find(|r| r.is_child_of())
+            .or(references.first());
+        if let Some(parent) = parent {
+            write!(out, r#","parentId":"{:016x}""#, (self.ids)(parent.span()).1)?;
+        }
+        write!(out, r#","name":{}"#, JsonStr(span.operation_name()))?;
+
+        let mut kind = None;
+        let mut remote = Vec::new();
+        let mut tags = Vec::new();
+        for tag in span.all_tags() {
+            match (tag.name(), tag.value()) {
+                ("span.kind", TagValue::String(v)) => match zipkin_kind(v) {
+                    Some(k) => kind = Some(k),
+                    None => tags.push(tag),
+                },
+                ("peer.service", TagValue::String(_)) => remote.push(("serviceName", tag)),
+                ("peer.ipv4", TagValue::String(_)) => remote.push(("ipv4", tag)),
+                ("peer.ipv6", TagValue::String(_)) => remote.push(("ipv6", tag)),
+                ("peer.port", TagValue::Integer(_)) => remote.push(("port", tag)),
+                ("error", _) if span.status().is_error() => {}
+                _ => tags.push(tag),
+            }
+        }
+        if let Some(kind) = kind {
+            write!(out, r#","kind":"{}""#, kind)?;
+        }
+        write!(
+            out,
+            r#","timestamp":{},"duration":{}"#,
+            span.start_unix_time().as_micros(),
+            span.duration().as_micros().max(1)
+        )?;
+        if let Some(name) = &self.service_name {
+            write!(
+                out,
+                r#","localEndpoint":{{"serviceName":{}}}"#,
+                JsonStr(name)
+            )?;
+        }
+        if !remote.is_empty() {
+            out.push_str(r#","remoteEndpoint":{"#);
+            for (i, (key, tag)) in remote.into_iter().enumerate() {
+                let sep = if i > 0 { "," } else { "" };
+                write!(out, r#"{}"{}":{}"#, sep, key, JsonValue(tag.value()))?;
+            }
+            out.push('}');
+        }
+
+        if !span.logs().is_empty() {
+            out.push_str(r#","annotations":["#);
+            for (i, log) in span.logs().iter().enumerate() {
+                let sep = if i > 0 { "," } else { "" };
+                let timestamp = log
+                    .time()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_micros();
+                let value = annotation_value(log);
+                write!(
+                    out,
+                    r#"{}{{"timestamp":{},"value":{}}}"#,
+                    sep,
+                    timestamp,
+                    JsonStr(&value)
+                )?;
+            }
+            out.push(']');
+        }
+
+        let error = match span.status() {
+            SpanStatus::Error { message } if message.is_empty() => Some("true"),
+            SpanStatus::Error { message } => Some(message.as_ref()),
+            _ => None,
+        };
+        if !tags.is_empty() || error.is_some() {
+            out.push_str(r#","tags":{"#);
+            for (i, tag) in tags.iter().enumerate() {
+                let sep = if i > 0 { "," } else { "" };
+                write!(out, "{}{}:{}", sep, JsonStr(tag.name()), TagStr(tag))?;
+            }
+            if let Some(error) = error {
+                let sep = if tags.is_empty() { "" } else { "," };
+                write!(out, r#"{}"error":{}"#, sep, JsonStr(error))?;
+            }
+            out.push('}');
+        }
+        out.push('}');
+        Ok(())
+    }
+}
+impl<F> fmt::Debug for ZipkinEncoder<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZipkinEncoder")
+            .field("service_name", &self.service_name)
+            .finish_non_exhaustive()
+    }
+}
+
+fn zipkin_kind(value: &str) -> Option<&'static str> {
+    match value {
+        "client" => Some("CLIENT"),
+        "server" => Some("SERVER"),
+        "producer" => Some("PRODUCER"),
+        "consumer" => Some("CONSUMER"),
+        _ => None,
+    }
+}
+
+fn annotation_value(log: &Log) -> Cow<'_, str> {
+    if let [field] = log.fields() {
+        if let ("event", TagValue::String(event)) = (field.name(), field.value()) {
+            return Cow::Borrowed(event);
+        }
+    }
+    let mut value = String::from("{");
+    for (i, field) in log.fields().iter().enumerate() {
+        let sep = if i > 0 { "," } else { "" };
+        let _ = write!(
+            value,
+            "{}{}:{}",
+            sep,
+            JsonStr(field.name()),
+            JsonValue(field.value())
+        );
+    }
+    value.push('}');
+    Cow::Owned(value)
+}
+
+/// JSON string of the value of a Zipkin tag, which is a string.
+struct TagStr<'a>(&'a Tag);
+impl fmt::Display for TagStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.value() {
+            TagValue::String(v) => write!(f, "{}", JsonStr(v)),
+            value => write!(f, "{}", JsonStr(&JsonValue(value).to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(rustracing_noop))]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::tag::StdTag;
+    use crate::testing::TestTracer;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn spans_are_encoded() {
+        let clock = MockClock::new(UNIX_EPOCH);
+        let tracer = TestTracer::<u64>::with_clock(clock.clone());
+        {
+            let parent = tracer.span("parent").start_with_state(1);
+            let mut span = parent.child("child", |options| {
+                options
+                    .tag(StdTag::span_kind("server"))
+                    .tag(StdTag::peer_ip("127.0.0.1".parse().unwrap()))
+                    .tag(StdTag::peer_port(80))
+                    .tag(Tag::new("retries", 2))
+                    .tag(Tag::new("note", "say \"hi\"\n"))
+                    .start_with_state(2)
+            });
+            span.log(|log| {
+                log.std().event("retry").message("timed out");
+            });
+            span.set_status(|| SpanStatus::error("failed"));
+        }
+
+        let spans = tracer.spans();
+        let encoder = ZipkinEncoder::new(|state: &u64| (1 << 64 | 7, *state));
+        assert_eq!(
+            encoder.encode(&spans[..1]),
+            concat!(
+                r#"[{"traceId":"00000000000000010000000000000007","id":"0000000000000002","#,
+                r#""parentId":"0000000000000001","name":"child","kind":"SERVER","#,
+                r#""timestamp":0,"duration":1,"#,
+                r#""remoteEndpoint":{"ipv4":"127.0.0.1","port":80},"#,
+                r#""annotations":[{"timestamp":0,"value":"{\"event\":\"retry\",\"message\":\"timed out\"}"}],"#,
+                r#""tags":{"note":"say \"hi\"\n","retries":"2","error":"failed"}}]"#
+            )
+        );
+        assert_eq!(encoder.encode(&[]), "[]");
+    }
+}
@@ -1,17 +1,191 @@
+use std::error;
+use std::fmt;
+use std::sync::Arc;
 use trackable::error::ErrorKind as TrackableErrorKind;
 use trackable::error::TrackableError;
+use trackable::{History, Location, Trackable};
 
 /// This crate specific error type.
-#[derive(Debug, Clone, TrackableError)]
-pub struct Error(TrackableError<ErrorKind>);
+///
+/// Errors made from an `ErrorKind` alone (e.g., `Error::from(ErrorKind::InvalidInput)`)
+/// are a plain enum value: they neither allocate nor record the locations passed through
+/// by `track!`, so they are cheap enough to be returned from hot paths
+/// such as the extraction of span contexts from carriers.
+///
+/// Errors having a cause, as well as all errors if the `error-history` feature is enabled,
+/// carry a `TrackableError` which records the tracking history.
+///
+/// Failure modes can be distinguished by matching on `kind`,
+/// and the underlying error (if any) is available from `std::error::Error::source`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::{Error, ErrorKind};
+/// use std::error::Error as _;
+/// use std::io;
+///
+/// let e = Error::with_source(ErrorKind::CarrierIo, io::Error::other("broken pipe"));
+/// assert_eq!(*e.kind(), ErrorKind::CarrierIo);
+/// assert_eq!(e.source().map(|s| s.to_string()), Some("broken pipe".to_owned()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    source: Option<Arc<dyn error::Error + Send + Sync>>,
+    detail: Option<Box<TrackableError<ErrorKind>>>,
+}
+impl Error {
+    /// Makes a new `Error` instance of `kind` caused by `source`.
+    pub fn with_source<E>(kind: ErrorKind, source: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Error {
+            kind,
+            source: Some(Arc::from(source.into())),
+            detail: Some(Box::new(TrackableError::from(kind))),
+        }
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Returns the detail of this error if it is being tracked.
+    pub fn detail(&self) -> Option<&TrackableError<ErrorKind>> {
+        self.detail.as_deref()
+    }
+}
+impl From<ErrorKind> for Error {
+    #[inline]
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            kind,
+            source: None,
+            detail: if cfg!(feature = "error-history") {
+                Some(Box::new(TrackableError::from(kind)))
+            } else {
+                None
+            },
+        }
+    }
+}
+impl From<TrackableError<ErrorKind>> for Error {
+    fn from(f: TrackableError<ErrorKind>) -> Self {
+        Error {
+            kind: *f.kind(),
+            source: None,
+            detail: Some(Box::new(f)),
+        }
+    }
+}
+impl From<Error> for TrackableError<ErrorKind> {
+    fn from(f: Error) -> Self {
+        f.detail
+            .map(|detail| *detail)
+            .unwrap_or_else(|| TrackableError::from(f.kind))
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.detail {
+            Some(ref detail) => detail.fmt(f),
+            None => self.kind.display(f),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.source {
+            Some(ref source) => Some(&**source),
+            None => self
+                .detail
+                .as_deref()
+                .map(|detail| detail as &(dyn error::Error + 'static)),
+        }
+    }
+}
+impl Trackable for Error {
+    type Event = Location;
+
+    #[inline]
+    fn history(&self) -> Option<&History<Location>> {
+        self.detail.as_ref().and_then(|detail| detail.history())
+    }
+
+    #[inline]
+    fn history_mut(&mut self) -> Option<&mut History<Location>> {
+        self.detail.as_mut().and_then(|detail| detail.history_mut())
+    }
+}
 
 /// The list of the possible error kinds
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ErrorKind {
     /// Input data is invalid.
     InvalidInput,
 
-    /// Other errors (e.g., I/O error).
+    /// A carrier contains a malformed span context.
+    CarrierParse,
+
+    /// Reading from or writing to a carrier failed.
+    CarrierIo,
+
+    /// The channel of finished spans has been closed.
+    ChannelClosed,
+
+    /// Other errors (e.g., failures of the operating system).
     Other,
 }
-impl TrackableErrorKind for ErrorKind {}
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ErrorKind::InvalidInput => "invalid input",
+            ErrorKind::CarrierParse => "malformed span context in carrier",
+            ErrorKind::CarrierIo => "carrier I/O failed",
+            ErrorKind::ChannelClosed => "span channel closed",
+            ErrorKind::Other => "other error",
+        })
+    }
+}
+impl TrackableErrorKind for ErrorKind {
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trackable::error::ErrorKindExt;
+
+    #[test]
+    fn plain_errors_are_not_tracked() {
+        let e = track!(Error::from(ErrorKind::InvalidInput));
+        assert_eq!(*e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(e.in_tracking(), cfg!(feature = "error-history"));
+
+        let e = track!(Error::from(ErrorKind::Other.cause("foo")));
+        assert_eq!(*e.kind(), ErrorKind::Other);
+        assert_eq!(e.history().map(|h| h.events().len()), Some(1));
+    }
+
+    #[test]
+    fn sources_are_exposed() {
+        use std::error::Error as _;
+
+        let e = track!(Error::with_source(ErrorKind::Other, "foo"));
+        assert_eq!(*e.kind(), ErrorKind::Other);
+        assert_eq!(e.source().map(|s| s.to_string()), Some("foo".to_owned()));
+        assert_eq!(e.history().map(|h| h.events().len()), Some(1));
+
+        let e = Error::from(ErrorKind::CarrierParse);
+        assert_eq!(
+            e.to_string().lines().next(),
+            Some("malformed span context in carrier")
+        );
+    }
+}
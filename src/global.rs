@@ -0,0 +1,125 @@
+//! Process-wide tracers.
+//!
+//! Libraries deep in the call stack can start spans with the tracer registered by the application
+//! instead of having a `Tracer` threaded through every constructor.
+//! Since tracers are generic over the span state, one global tracer is registered per state type.
+use crate::sampler::{BoxSampler, NullSampler, Sampler};
+use crate::Tracer;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// The type of the global tracers.
+pub type GlobalTracer<T> = Tracer<BoxSampler<T>, T>;
+
+type Registry = RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>;
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Registers `tracer` as the global tracer of the spans having the state `T`.
+///
+/// If a tracer has already been registered for `T`, it is replaced and returned.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::global::{global_tracer, set_global_tracer};
+/// use cf_rustracing::sampler::{AllSampler, Sampler};
+/// use cf_rustracing::Tracer;
+///
+/// fn library_code() {
+///     let _span = global_tracer::<()>().span("library").start_with_state(());
+/// }
+///
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler.boxed());
+/// set_global_tracer::<()>(tracer);
+/// library_code();
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "library");
+/// ```
+pub fn set_global_tracer<T>(tracer: GlobalTracer<T>) -> Option<GlobalTracer<T>>
+where
+    T: Send + Sync + 'static,
+{
+    let mut tracers = registry().write().unwrap_or_else(|e| e.into_inner());
+    let old = tracers.insert(TypeId::of::<T>(), Box::new(tracer))?;
+    old.downcast::<GlobalTracer<T>>().ok().map(|old| *old)
+}
+
+/// Returns the global tracer of the spans having the state `T`.
+///
+/// If no tracer has been registered with `set_global_tracer`,
+/// this returns a shared tracer whose spans are never started (see `Tracer::noop`).
+pub fn global_tracer<T>() -> GlobalTracer<T>
+where
+    T: Send + Sync + 'static,
+{
+    static DISABLED: OnceLock<Registry> = OnceLock::new();
+    if let Some(tracer) = get(registry()) {
+        return tracer;
+    }
+    let disabled = DISABLED.get_or_init(Default::default);
+    if let Some(tracer) = get(disabled) {
+        return tracer;
+    }
+    let mut tracers = disabled.write().unwrap_or_else(|e| e.into_inner());
+    tracers
+        .entry(TypeId::of::<T>())
+        .or_insert_with(|| Box::new(GlobalTracer::<T>::disabled(NullSampler.boxed())))
+        .downcast_ref::<GlobalTracer<T>>()
+        .expect("never fails")
+        .clone()
+}
+
+fn get<T>(registry: &Registry) -> Option<GlobalTracer<T>>
+where
+    T: Send + Sync + 'static,
+{
+    let tracers = registry.read().unwrap_or_else(|e| e.into_inner());
+    tracers
+        .get(&TypeId::of::<T>())
+        .and_then(|tracer| tracer.downcast_ref::<GlobalTracer<T>>())
+        .cloned()
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+
+    #[derive(Debug, Clone)]
+    struct State;
+
+    #[test]
+    fn global_tracer_works() {
+        let span = global_tracer::<State>().span("foo").start_with_state(State);
+        assert!(!span.is_sampled());
+
+        let (tracer, mut span_rx) = Tracer::new(AllSampler.boxed());
+        assert!(set_global_tracer::<State>(tracer).is_none());
+        global_tracer().span("bar").start_with_state(State);
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "bar");
+
+        let (tracer, _) = Tracer::new(NullSampler.boxed());
+        assert!(set_global_tracer::<State>(tracer).is_some());
+    }
+
+    #[test]
+    fn unregistered_global_tracer_is_disabled() {
+        use crate::tag::StdTag;
+
+        #[derive(Debug, Clone)]
+        struct Unregistered;
+
+        for _ in 0..2 {
+            let span = global_tracer::<Unregistered>()
+                .span("foo")
+                .tag(StdTag::sampling_priority(1))
+                .start_with_state(Unregistered);
+            assert!(span.context().is_none());
+        }
+    }
+}
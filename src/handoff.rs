@@ -0,0 +1,113 @@
+//! Lock-free MPSC handoff list.
+//!
+//! Items are pushed by a single CAS and taken all at once in the order they were pushed.
+//! This is a Treiber stack whose only pop operation swaps the whole list out,
+//! so it is not subject to the ABA problem.
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub(crate) struct Handoff<T> {
+    head: AtomicPtr<Node<T>>,
+}
+
+struct Node<T> {
+    item: T,
+    next: *mut Node<T>,
+}
+
+// SAFETY: A node is owned by the pushing thread until it is published by the CAS on `head`,
+// and by the taking thread after it is unlinked by the swap on `head`.
+unsafe impl<T: Send> Send for Handoff<T> {}
+unsafe impl<T: Send> Sync for Handoff<T> {}
+
+impl<T> Handoff<T> {
+    pub fn new() -> Self {
+        Handoff {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn push(&self, item: T) {
+        let node = Box::into_raw(Box::new(Node {
+            item,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: The node has not been published yet.
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Returns `true` if there are no items to be taken.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    /// Takes all the items in the order they were pushed.
+    pub fn take_all(&self) -> Vec<T> {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::AcqRel);
+        let mut items = Vec::new();
+        while !node.is_null() {
+            // SAFETY: The list has been unlinked by the swap above, so this thread owns the nodes.
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next;
+            items.push(boxed.item);
+        }
+        items.reverse();
+        items
+    }
+}
+impl<T> Drop for Handoff<T> {
+    fn drop(&mut self) {
+        self.take_all();
+    }
+}
+impl<T> fmt::Debug for Handoff<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Handoff")
+            .field("is_empty", &self.is_empty())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn items_are_taken_in_push_order() {
+        let handoff = Arc::new(Handoff::new());
+        let threads = (0..4)
+            .map(|t| {
+                let handoff = Arc::clone(&handoff);
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        handoff.push((t, i));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut taken = Vec::new();
+        for thread in threads {
+            thread.join().unwrap();
+            taken.extend(handoff.take_all());
+        }
+        assert!(handoff.is_empty());
+        assert_eq!(taken.len(), 4000);
+        for t in 0..4 {
+            let items = taken.iter().filter(|x| x.0 == t).map(|x| x.1);
+            assert!(items.eq(0..1000));
+        }
+    }
+}
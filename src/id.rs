@@ -0,0 +1,140 @@
+//! Generators of the identifiers of traces and spans.
+//!
+//! The OpenTracing implementations can obtain identifiers for the states of new spans
+//! from the generator of the tracer via `CandidateSpan::generate_id`.
+//! Replacing the default `RandomIdGenerator` with a deterministic generator
+//! makes the identifiers stable across runs (e.g., for snapshot tests).
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// `IdGenerator` generates the identifiers of traces and spans.
+pub trait IdGenerator: fmt::Debug + Send + Sync + 'static {
+    /// Returns a new non-zero identifier.
+    fn generate(&self) -> u64;
+}
+impl<G: IdGenerator + ?Sized> IdGenerator for Arc<G> {
+    fn generate(&self) -> u64 {
+        (**self).generate()
+    }
+}
+
+/// The default generator which returns random identifiers.
+#[derive(Debug, Default, Clone)]
+pub struct RandomIdGenerator;
+impl IdGenerator for RandomIdGenerator {
+    fn generate(&self) -> u64 {
+        rand::thread_rng().gen_range(1..=u64::MAX)
+    }
+}
+
+/// A generator which returns sequential identifiers.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::id::{IdGenerator, SequentialIdGenerator};
+///
+/// let generator = SequentialIdGenerator::new(10);
+/// assert_eq!(generator.generate(), 10);
+/// assert_eq!(generator.generate(), 11);
+/// ```
+#[derive(Debug)]
+pub struct SequentialIdGenerator {
+    next: AtomicU64,
+}
+impl SequentialIdGenerator {
+    /// Makes a new `SequentialIdGenerator` instance which starts from `first`.
+    ///
+    /// If `first` is `0`, the generator starts from `1`.
+    pub fn new(first: u64) -> Self {
+        SequentialIdGenerator {
+            next: AtomicU64::new(first.max(1)),
+        }
+    }
+}
+impl Default for SequentialIdGenerator {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+impl IdGenerator for SequentialIdGenerator {
+    fn generate(&self) -> u64 {
+        loop {
+            let id = self.next.fetch_add(1, Ordering::Relaxed);
+            if id != 0 {
+                return id;
+            }
+        }
+    }
+}
+
+/// A generator which returns pseudo-random identifiers determined by a seed.
+#[derive(Debug)]
+pub struct SeededIdGenerator {
+    rng: Mutex<StdRng>,
+}
+impl SeededIdGenerator {
+    /// Makes a new `SeededIdGenerator` instance.
+    pub fn new(seed: u64) -> Self {
+        SeededIdGenerator {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+impl IdGenerator for SeededIdGenerator {
+    fn generate(&self) -> u64 {
+        self.rng
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .gen_range(1..=u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_id_generator_is_deterministic() {
+        let a = SeededIdGenerator::new(42);
+        let b = SeededIdGenerator::new(42);
+        for _ in 0..10 {
+            assert_eq!(a.generate(), b.generate());
+        }
+        assert_ne!(
+            SeededIdGenerator::new(1).generate(),
+            SeededIdGenerator::new(2).generate()
+        );
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn states_use_id_generator() {
+        use crate::sampler::AllSampler;
+        use crate::span::CandidateSpan;
+        use crate::testing::TestTracer;
+        use crate::Tracer;
+
+        #[derive(Debug, Clone, PartialEq)]
+        struct State(u64);
+        impl<'a> From<CandidateSpan<'a, State>> for State {
+            fn from(f: CandidateSpan<'a, State>) -> Self {
+                State(f.generate_id())
+            }
+        }
+
+        let builder = Tracer::builder(AllSampler).id_generator(SequentialIdGenerator::new(7));
+        let tracer = TestTracer::<State>::from_builder(builder);
+        tracer.span("a").start();
+        tracer.span("b").start();
+        let ids = tracer
+            .spans()
+            .iter()
+            .map(|s| s.context().state().0)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [7, 8]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::mem;
+
+/// A cheap value used to fill the unused inline slots of an `InlineVec`.
+pub(crate) trait Placeholder {
+    fn placeholder() -> Self;
+}
+
+/// A vector which stores up to `N` elements inline and spills to the heap beyond that.
+///
+/// Unused inline slots hold `T::placeholder()`.
+#[derive(Clone)]
+pub(crate) enum InlineVec<T, const N: usize> {
+    Inline { items: [T; N], len: usize },
+    Heap(Vec<T>),
+}
+impl<T: Placeholder, const N: usize> InlineVec<T, N> {
+    pub fn new() -> Self {
+        InlineVec::Inline {
+            items: std::array::from_fn(|_| T::placeholder()),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        match self {
+            InlineVec::Inline { items, len } if *len < N => {
+                items[*len] = item;
+                *len += 1;
+            }
+            InlineVec::Inline { items, len } => {
+                let mut heap = Vec::with_capacity(N * 2);
+                heap.extend(
+                    items
+                        .iter_mut()
+                        .take(*len)
+                        .map(|item| mem::replace(item, T::placeholder())),
+                );
+                heap.push(item);
+                *self = InlineVec::Heap(heap);
+            }
+            InlineVec::Heap(heap) => heap.push(item),
+        }
+    }
+
+    /// Removes consecutive elements for which `same_bucket(later, earlier)` returns `true`,
+    /// keeping the first one of each run.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        match self {
+            InlineVec::Inline { items, len } => {
+                let mut kept = 0;
+                for i in 0..*len {
+                    if kept > 0 {
+                        let (head, tail) = items.split_at_mut(i);
+                        if same_bucket(&mut tail[0], &mut head[kept - 1]) {
+                            continue;
+                        }
+                    }
+                    items.swap(kept, i);
+                    kept += 1;
+                }
+                for item in &mut items[kept..*len] {
+                    *item = T::placeholder();
+                }
+                *len = kept;
+            }
+            InlineVec::Heap(heap) => heap.dedup_by(same_bucket),
+        }
+    }
+
+    /// Retains only the elements for which `f` returns `true`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        match self {
+            InlineVec::Inline { items, len } => {
+                let mut kept = 0;
+                for i in 0..*len {
+                    if f(&items[i]) {
+                        items.swap(kept, i);
+                        kept += 1;
+                    }
+                }
+                for item in &mut items[kept..*len] {
+                    *item = T::placeholder();
+                }
+                *len = kept;
+            }
+            InlineVec::Heap(heap) => heap.retain(f),
+        }
+    }
+}
+impl<T, const N: usize> InlineVec<T, N> {
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            InlineVec::Inline { items, len } => &items[..*len],
+            InlineVec::Heap(heap) => heap,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            InlineVec::Inline { items, len } => &mut items[..*len],
+            InlineVec::Heap(heap) => heap,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+}
+impl<T: fmt::Debug, const N: usize> fmt::Debug for InlineVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Placeholder for u8 {
+        fn placeholder() -> Self {
+            0
+        }
+    }
+
+    #[test]
+    fn spills_to_heap() {
+        let mut v = InlineVec::<u8, 2>::new();
+        v.push(1);
+        v.push(2);
+        assert!(matches!(v, InlineVec::Inline { .. }));
+        v.push(3);
+        assert!(matches!(v, InlineVec::Heap(_)));
+        assert_eq!(v.as_slice(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn dedup_works() {
+        let mut v = InlineVec::<u8, 4>::new();
+        for i in [1, 1, 2, 1] {
+            v.push(i);
+        }
+        v.dedup_by(|a, b| a == b);
+        assert_eq!(v.as_slice(), [1, 2, 1]);
+    }
+}
@@ -39,6 +39,43 @@
 //! As an actual usage example of the crate and an implmentation of the [OpenTracing] API,
 //! it may be helpful to looking at [rustracing_jaeger] crate.
 //!
+//! # Compiling tracing out
+//!
+//! If the crate is built with `--cfg rustracing_noop`
+//! (e.g., `RUSTFLAGS="--cfg rustracing_noop" cargo build`), `Tracer`, `Span` and the other types
+//! used to record spans are replaced with zero-sized no-op implementations having the same API.
+//! Instrumented code compiles unchanged but never records nor sends any span.
+//!
+//! This is a configuration flag rather than a Cargo feature because it is not additive:
+//! it must be chosen by the final application, not by a library depending on this crate.
+//!
+//! # Async runtimes
+//!
+//! Spans are recorded and finished without any runtime.
+//! `SpanReceiver` can be polled from any executor or, in synchronous programs,
+//! blocked on with `SpanReceiver::blocking_recv`.
+//!
+//! The `tokio` feature (enabled by default) makes the span channel use tokio's channels;
+//! without it, a runtime-agnostic queue is used instead.
+//! The `lock-free-queue` feature requires a tokio runtime to poll the queues.
+//!
+//! # Attribute macro
+//!
+//! The `macros` feature provides the `#[trace]` attribute, which traces calls of functions
+//! with the global tracer (see `global`). See [`trace`] for details.
+//!
+//! # WebAssembly
+//!
+//! On `wasm32-unknown-unknown`, where neither the system time nor threads are available:
+//!
+//! - use `clock::EpochMillisClock` bound to a JavaScript time source as the clock of tracers,
+//! - disable the default features and receive spans with `SpanReceiver::recv`
+//!   (e.g., in a `wasm-bindgen-futures` task) or `SpanReceiver::try_recv`, and
+//! - enable the `js` feature of `getrandom` in the final crate, or give tracers
+//!   an `id::IdGenerator` which does not depend on `rand`.
+//!
+//! `clock::CoarseClock` cannot be used there since it spawns a thread.
+//!
 //! # References
 //!
 //! - [The OpenTracing Semantic Specification (v1.1)][specification]
@@ -48,26 +85,59 @@
 //! [rustracing_jaeger]: https://github.com/sile/rustracing_jaeger
 #![warn(missing_docs)]
 #![allow(clippy::new_ret_no_self)]
+// The recording machinery is left unused when `rustracing_noop` replaces the spans.
+#![cfg_attr(rustracing_noop, allow(dead_code, unused_imports))]
 #[macro_use]
 extern crate trackable;
 
 pub use crate::error::{Error, ErrorKind};
-pub use crate::tracer::Tracer;
+#[cfg(rustracing_noop)]
+pub use crate::noop::{Tracer, TracerBuilder};
+#[cfg(not(rustracing_noop))]
+pub use crate::tracer::{Tracer, TracerBuilder};
+#[cfg(feature = "macros")]
+pub use cf_rustracing_macros::trace;
 
 pub mod carrier;
+pub mod clock;
+pub mod context;
 pub mod convert;
+pub mod global;
+pub mod id;
 pub mod log;
+pub mod metrics;
+pub mod redact;
+pub mod reporter;
 pub mod sampler;
+pub mod scope;
 pub mod span;
 pub mod tag;
+#[cfg(not(rustracing_noop))]
+pub mod testing;
+pub mod trace;
+pub mod tracker;
+pub mod validate;
 
+#[cfg(not(rustracing_noop))]
+mod channel;
 mod error;
+#[cfg(not(rustracing_noop))]
+mod handoff;
+mod inline_vec;
+#[cfg(rustracing_noop)]
+mod noop;
+mod pool;
+#[cfg_attr(rustracing_noop, allow(dead_code))]
+mod queue;
+#[cfg(all(feature = "lock-free-queue", not(rustracing_noop)))]
+mod ring;
+#[cfg(not(rustracing_noop))]
 mod tracer;
 
 /// This crate specific `Result` type.
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[cfg(test)]
+#[cfg(all(test, not(rustracing_noop)))]
 mod tests {
     use super::*;
     use crate::sampler::AllSampler;
@@ -91,6 +161,47 @@ mod tests {
         assert_eq!(span.operation_name(), "it_works");
     }
 
+    #[tokio::test]
+    async fn finished_span_buffers_are_recycled() {
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        {
+            let mut span = tracer.span("first").start_with_state(());
+            span.set_tag(|| Tag::new("foo", "bar"));
+        }
+        let span = span_rx.recv().await.unwrap();
+        let tags = span.tags().as_ptr();
+        drop(span);
+
+        {
+            let mut span = tracer.span("second").start_with_state(());
+            span.set_tag(|| Tag::new("foo", "baz"));
+        }
+        let span = span_rx.recv().await.unwrap();
+        assert_eq!(span.tags().as_ptr(), tags);
+    }
+
+    #[tokio::test]
+    async fn shared_tags_are_attached() {
+        use crate::tag::SharedTags;
+
+        let common = SharedTags::new(vec![Tag::new("service", "foo"), Tag::new("class", "a")]);
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        {
+            let mut span = tracer
+                .span("shared")
+                .shared_tags(&common)
+                .start_with_state(());
+            span.set_tag(|| Tag::new("class", "b"));
+        }
+        let span = span_rx.recv().await.unwrap();
+        assert_eq!(span.tags().len(), 1);
+        let tags = span
+            .all_tags()
+            .map(|t| (t.name(), t.value().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, [("class", "b".into()), ("service", "foo".into())]);
+    }
+
     #[tokio::test]
     async fn example_code_works() {
         // Creates a tracer
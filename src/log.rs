@@ -1,13 +1,29 @@
 //! Span log.
+use crate::clock::Clock;
+use crate::inline_vec::{InlineVec, Placeholder};
+use crate::tag::{cmp_keys, keys_equal, TagValue};
 #[cfg(feature = "stacktrace")]
 use backtrace::Backtrace;
 use std::borrow::Cow;
+use std::error::Error;
 use std::time::SystemTime;
 
+/// The number of fields stored inline in a `Log`.
+///
+/// Most logs carry only an `event` or `message` field plus a value or two,
+/// so they can be recorded without allocating a vector.
+const INLINE_FIELDS: usize = 3;
+
+type LogFields = InlineVec<LogField, INLINE_FIELDS>;
+
+/// The name of the field counting the repeats collapsed into a log.
+#[cfg(not(rustracing_noop))]
+const REPEAT_COUNT: &str = "repeat_count";
+
 /// Span log builder.
 #[derive(Debug)]
 pub struct LogBuilder {
-    fields: Vec<LogField>,
+    fields: LogFields,
     time: Option<SystemTime>,
 }
 impl LogBuilder {
@@ -17,6 +33,30 @@ impl LogBuilder {
         self
     }
 
+    /// Adds the field having the integer value `value`.
+    pub fn field_i64<N>(&mut self, name: N, value: i64) -> &mut Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.field(LogField::new(name, value))
+    }
+
+    /// Adds the field having the floating point value `value`.
+    pub fn field_f64<N>(&mut self, name: N, value: f64) -> &mut Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.field(LogField::new(name, value))
+    }
+
+    /// Adds the field having the boolean value `value`.
+    pub fn field_bool<N>(&mut self, name: N, value: bool) -> &mut Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.field(LogField::new(name, value))
+    }
+
     /// Sets the value of timestamp to `time`.
     pub fn time(&mut self, time: SystemTime) -> &mut Self {
         self.time = Some(time);
@@ -24,33 +64,42 @@ impl LogBuilder {
     }
 
     /// Returns a specialized builder for the standard log fields.
-    pub fn std(&mut self) -> StdLogFieldsBuilder {
+    pub fn std(&mut self) -> StdLogFieldsBuilder<'_> {
         StdLogFieldsBuilder(self)
     }
 
     /// Returns a specialized builder for the standard error log fields.
-    pub fn error(&mut self) -> StdErrorLogFieldsBuilder {
+    pub fn error(&mut self) -> StdErrorLogFieldsBuilder<'_> {
         self.field(LogField::new("event", "error"));
         StdErrorLogFieldsBuilder(self)
     }
 
     pub(crate) fn new() -> Self {
         LogBuilder {
-            fields: Vec::new(),
+            fields: LogFields::new(),
             time: None,
         }
     }
 
-    pub(crate) fn finish(mut self) -> Option<Log> {
+    pub(crate) fn finish(self, clock: &dyn Clock) -> Option<Log> {
+        self.finish_with(|| clock.now())
+    }
+
+    /// Finishes the log, timestamping it with `now()` unless the time is set explicitly.
+    pub(crate) fn finish_with<F>(mut self, now: F) -> Option<Log>
+    where
+        F: FnOnce() -> SystemTime,
+    {
         if self.fields.is_empty() {
             None
         } else {
-            self.fields.reverse();
-            self.fields.sort_by(|a, b| a.name.cmp(&b.name));
-            self.fields.dedup_by(|a, b| a.name == b.name);
+            let fields = self.fields.as_mut_slice();
+            fields.reverse();
+            fields.sort_by(|a, b| cmp_keys(&a.name, &b.name));
+            self.fields.dedup_by(|a, b| keys_equal(&a.name, &b.name));
             Some(Log {
                 fields: self.fields,
-                time: self.time.unwrap_or_else(SystemTime::now),
+                time: self.time.unwrap_or_else(now),
             })
         }
     }
@@ -59,33 +108,97 @@ impl LogBuilder {
 /// Span log.
 #[derive(Debug, Clone)]
 pub struct Log {
-    fields: Vec<LogField>,
+    fields: LogFields,
     time: SystemTime,
 }
 impl Log {
     /// Returns the fields of this log.
     pub fn fields(&self) -> &[LogField] {
-        &self.fields
+        self.fields.as_slice()
     }
 
     /// Returns the timestamp of this log.
     pub fn time(&self) -> SystemTime {
         self.time
     }
+
+    pub(crate) fn fields_mut(&mut self) -> &mut [LogField] {
+        self.fields.as_mut_slice()
+    }
+
+    /// Drops the fields following the first `len` ones and returns the number of them.
+    #[cfg(not(rustracing_noop))]
+    pub(crate) fn truncate_fields(&mut self, len: usize) -> usize {
+        let mut i = 0;
+        self.fields.retain(|_| {
+            i += 1;
+            i <= len
+        });
+        i.saturating_sub(len)
+    }
+
+    /// Returns `true` if this log has the same fields as `other` except for `repeat_count`.
+    #[cfg(not(rustracing_noop))]
+    pub(crate) fn is_repeated_by(&self, other: &Log) -> bool {
+        fn fields(log: &Log) -> impl Iterator<Item = (&str, &TagValue)> {
+            log.fields()
+                .iter()
+                .filter(|f| f.name() != REPEAT_COUNT)
+                .map(|f| (f.name(), f.value()))
+        }
+        fields(self).eq(fields(other))
+    }
+
+    /// Increments the `repeat_count` field of this log.
+    #[cfg(not(rustracing_noop))]
+    pub(crate) fn count_repeat(&mut self) {
+        for field in self.fields.as_mut_slice() {
+            if field.name() == REPEAT_COUNT {
+                if let TagValue::Integer(n) = &mut field.value {
+                    *n += 1;
+                    return;
+                }
+            }
+        }
+        self.fields.push(LogField::new(REPEAT_COUNT, 1i64));
+        self.fields
+            .as_mut_slice()
+            .sort_by(|a, b| cmp_keys(&a.name, &b.name));
+    }
+
+    pub(crate) fn retain_fields<F>(&mut self, f: F)
+    where
+        F: FnMut(&LogField) -> bool,
+    {
+        self.fields.retain(f);
+    }
 }
 
 /// Span log field.
+///
+/// Like tags, fields have typed values, so that numbers and booleans reach the reporters as they are.
 #[derive(Debug, Clone)]
 pub struct LogField {
     name: Cow<'static, str>,
-    value: Cow<'static, str>,
+    value: TagValue,
 }
 impl LogField {
     /// Makes a new `LogField` instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::log::LogField;
+    /// use cf_rustracing::tag::TagValue;
+    ///
+    /// let field = LogField::new("retries", 3i64);
+    /// assert_eq!(field.value(), &TagValue::Integer(3));
+    /// assert_eq!(LogField::new("event", "retry").value().as_str(), Some("retry"));
+    /// ```
     pub fn new<N, V>(name: N, value: V) -> Self
     where
         N: Into<Cow<'static, str>>,
-        V: Into<Cow<'static, str>>,
+        V: Into<TagValue>,
     {
         LogField {
             name: name.into(),
@@ -99,14 +212,23 @@ impl LogField {
     }
 
     /// Returns the value of this field.
-    pub fn value(&self) -> &str {
-        self.value.as_ref()
+    pub fn value(&self) -> &TagValue {
+        &self.value
+    }
+
+    pub(crate) fn name_and_value_mut(&mut self) -> (&str, &mut TagValue) {
+        (&self.name, &mut self.value)
+    }
+}
+impl Placeholder for LogField {
+    fn placeholder() -> Self {
+        LogField::new("", "")
     }
 }
 impl<N, V> From<(N, V)> for LogField
 where
     N: Into<Cow<'static, str>>,
-    V: Into<Cow<'static, str>>,
+    V: Into<TagValue>,
 {
     fn from((n, v): (N, V)) -> Self {
         LogField::new(n, v)
@@ -133,7 +255,7 @@ impl<'a> StdLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("event", event));
+        self.0.field(LogField::new("event", event.into()));
         self
     }
 
@@ -146,7 +268,7 @@ impl<'a> StdLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("message", message));
+        self.0.field(LogField::new("message", message.into()));
         self
     }
 
@@ -176,7 +298,7 @@ impl<'a> StdErrorLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("error.kind", kind));
+        self.0.field(LogField::new("error.kind", kind.into()));
         self
     }
 
@@ -189,7 +311,39 @@ impl<'a> StdErrorLogFieldsBuilder<'a> {
     where
         T: Into<Cow<'static, str>>,
     {
-        self.0.field(LogField::new("message", message));
+        self.0.field(LogField::new("message", message.into()));
+        self
+    }
+
+    /// Adds the fields describing `error`.
+    ///
+    /// These are `error.kind` (the type name of `error` without the module path,
+    /// unless it is a trait object),
+    /// `message` (the `Display` representation of `error`),
+    /// and `error.source` (the errors in the source chain of `error`, separated by `": "`).
+    pub fn details<E>(&mut self, error: &E) -> &mut Self
+    where
+        E: Error + ?Sized,
+    {
+        let kind = std::any::type_name::<E>();
+        if !kind.starts_with("dyn ") {
+            let path_end = kind.find('<').unwrap_or(kind.len());
+            let name_start = kind[..path_end].rfind("::").map_or(0, |i| i + 2);
+            self.kind(&kind[name_start..]);
+        }
+        self.message(error.to_string());
+        let mut source = error.source();
+        let mut sources = String::new();
+        while let Some(e) = source {
+            if !sources.is_empty() {
+                sources.push_str(": ");
+            }
+            sources.push_str(&e.to_string());
+            source = e.source();
+        }
+        if !sources.is_empty() {
+            self.0.field(LogField::new("error.source", sources));
+        }
         self
     }
 
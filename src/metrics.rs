@@ -0,0 +1,64 @@
+//! Metrics of the tracing pipeline.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Snapshot of the counters of a tracer, returned by `Tracer::metrics`.
+///
+/// The counters are cumulative since the tracer was built
+/// and are shared by the clones of the tracer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TracerMetrics {
+    /// The number of the spans started and recorded
+    /// (including the ones recorded speculatively for the finish sampler).
+    pub spans_started: u64,
+
+    /// The number of the recorded spans finished.
+    pub spans_finished: u64,
+
+    /// The number of the spans rejected by the sampler, the finish sampler
+    /// or the `sampling.priority` tag.
+    pub spans_sampled_out: u64,
+
+    /// The number of the recorded spans discarded by `Span::abandon`.
+    pub spans_abandoned: u64,
+
+    /// The number of the finished spans discarded because
+    /// the span channel was full or the receiver had been dropped.
+    pub spans_dropped: u64,
+}
+
+/// Counters updated by spans.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    started: AtomicU64,
+    finished: AtomicU64,
+    sampled_out: AtomicU64,
+    abandoned: AtomicU64,
+}
+impl Counters {
+    pub fn span_started(&self) {
+        self.started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn span_finished(&self) {
+        self.finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn span_sampled_out(&self) {
+        self.sampled_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn span_abandoned(&self) {
+        self.abandoned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, spans_dropped: u64) -> TracerMetrics {
+        TracerMetrics {
+            spans_started: self.started.load(Ordering::Relaxed),
+            spans_finished: self.finished.load(Ordering::Relaxed),
+            spans_sampled_out: self.sampled_out.load(Ordering::Relaxed),
+            spans_abandoned: self.abandoned.load(Ordering::Relaxed),
+            spans_dropped,
+        }
+    }
+}
@@ -0,0 +1,872 @@
+//! Zero-sized no-op implementations of the tracing API.
+//!
+//! If the crate is built with `--cfg rustracing_noop`, these types replace `Tracer`, `Span` and their
+//! companions, so instrumented code compiles unchanged while tracing is compiled out:
+//! no span is ever sampled, recorded or sent, and the closures passed to spans are never called.
+use crate::carrier::TraceState;
+use crate::clock::Clock;
+use crate::convert::MaybeAsRef;
+use crate::id::{IdGenerator, RandomIdGenerator};
+use crate::log::{LogBuilder, StdErrorLogFieldsBuilder};
+use crate::metrics::TracerMetrics;
+use crate::redact::Redactor;
+use crate::reporter::SpanReporter;
+use crate::sampler::{AllSampler, FinishSampler, NullSampler, Sampler};
+use crate::scope::{ActiveSpanGuard, SpanScope};
+use crate::span::{
+    BaggageItem, BaggageRestrictions, CandidateSpan, CatchPanic, Extensions, FinishedSpan,
+    OverflowPolicy, SpanAnnotator, SpanContext, SpanLimits, SpanReference, SpanStatus, TimeScope,
+    TryRecvError,
+};
+use crate::tag::{SharedTags, Tag};
+use crate::tracker::SpanTracker;
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+/// Tracer.
+///
+/// This is a no-op implementation which never starts spans.
+pub struct Tracer<S, T>(PhantomData<fn() -> (S, T)>);
+impl<S: Sampler<T>, T> Tracer<S, T> {
+    /// Makes a new `Tracer` instance.
+    #[inline]
+    pub fn new(sampler: S) -> (Self, SpanReceiver<T>) {
+        Self::builder(sampler).finish()
+    }
+
+    /// Makes a new `Tracer` instance whose span channel can buffer up to `capacity` finished spans.
+    #[inline]
+    pub fn with_channel_capacity(sampler: S, capacity: usize) -> (Self, SpanReceiver<T>) {
+        Self::builder(sampler).channel_capacity(capacity).finish()
+    }
+
+    /// Makes a new `Tracer` instance which hands finished spans to `reporter`.
+    #[inline]
+    pub fn with_reporter<R>(sampler: S, reporter: R) -> Self
+    where
+        R: SpanReporter<T> + Send + Sync + 'static,
+    {
+        Self::builder(sampler).finish_with_reporter(reporter)
+    }
+
+    /// Makes a new `TracerBuilder` instance.
+    #[inline]
+    pub fn builder(sampler: S) -> TracerBuilder<S, T> {
+        TracerBuilder::new(sampler)
+    }
+
+    /// Makes a new `Tracer` instance whose spans are never started.
+    #[inline]
+    pub(crate) fn disabled(_sampler: S) -> Self {
+        Tracer(PhantomData)
+    }
+
+    /// Returns `StartSpanOptions` for starting a span which has the name `operation_name`.
+    #[inline]
+    pub fn span<N>(&self, _operation_name: N) -> StartSpanOptions<'_, S, T>
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        StartSpanOptions(PhantomData)
+    }
+}
+impl<T> Tracer<NullSampler, T> {
+    /// Makes a new `Tracer` instance whose spans are never started and which has no receiver.
+    #[inline]
+    pub fn noop() -> Self {
+        Tracer(PhantomData)
+    }
+}
+impl<S, T> Tracer<S, T> {
+    /// Returns the number of the finished spans discarded.
+    ///
+    /// This is always `0`.
+    #[inline]
+    pub fn dropped_spans(&self) -> u64 {
+        0
+    }
+
+    /// Returns the snapshot of the counters of this tracer.
+    ///
+    /// All the counters are always `0`.
+    #[inline]
+    pub fn metrics(&self) -> TracerMetrics {
+        TracerMetrics::default()
+    }
+
+    /// Returns a scope in which `span` is active.
+    #[inline]
+    pub fn scope(&self, span: Span<T>) -> SpanScope<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        SpanScope::new(span)
+    }
+
+    /// Returns the handle of the span active in the current scope.
+    ///
+    /// This is always `None`.
+    #[inline]
+    pub fn active_span(&self) -> Option<SpanHandle<T>> {
+        None
+    }
+
+    /// Waits until all the spans finished before this call have been delivered.
+    ///
+    /// This completes immediately.
+    #[inline]
+    pub async fn flush(&self) {}
+
+    /// Shuts down the tracer gracefully.
+    ///
+    /// This completes immediately.
+    #[inline]
+    pub async fn shutdown(&self) {}
+
+    /// Returns a new identifier from a `RandomIdGenerator`.
+    #[inline]
+    pub fn generate_id(&self) -> u64 {
+        RandomIdGenerator.generate()
+    }
+
+    /// Clone with the given `sampler`.
+    #[inline]
+    pub fn clone_with_sampler<U: Sampler<T>>(&self, _sampler: U) -> Tracer<U, T> {
+        Tracer(PhantomData)
+    }
+}
+impl<S, T> Clone for Tracer<S, T> {
+    fn clone(&self) -> Self {
+        Tracer(PhantomData)
+    }
+}
+impl<S, T> fmt::Debug for Tracer<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracer").finish()
+    }
+}
+
+/// `Tracer` builder.
+///
+/// This is a no-op implementation which ignores all the settings.
+pub struct TracerBuilder<S, T>(PhantomData<fn() -> (S, T)>);
+impl<S: Sampler<T>, T> TracerBuilder<S, T> {
+    /// Makes a new `TracerBuilder` instance.
+    #[inline]
+    pub fn new(_sampler: S) -> Self {
+        TracerBuilder(PhantomData)
+    }
+
+    /// Sets the capacity of the span channel.
+    #[inline]
+    pub fn channel_capacity(self, _capacity: usize) -> Self {
+        self
+    }
+
+    /// Sets the policy applied when finished spans are sent to the full span channel.
+    #[inline]
+    pub fn overflow_policy(self, _policy: OverflowPolicy) -> Self {
+        self
+    }
+
+    /// Sets the number of the internal queues of the span channel.
+    #[inline]
+    pub fn shards(self, _shards: usize) -> Self {
+        self
+    }
+
+    /// Sets the maximum number of finished spans delivered to the receiver at once.
+    #[inline]
+    pub fn batch_size(self, _size: usize) -> Self {
+        self
+    }
+
+    /// Sets the maximum delay of a batch of finished spans.
+    #[inline]
+    pub fn batch_delay(self, _delay: Duration) -> Self {
+        self
+    }
+
+    /// Sets the sampler deciding at finish time whether to keep the spans rejected by the sampler.
+    #[inline]
+    pub fn finish_sampler<F>(self, _finish_sampler: F) -> Self
+    where
+        F: FinishSampler<T> + Send + Sync + 'static,
+    {
+        self
+    }
+
+    /// Adds `observer`, which is called with every span finished by the tracer.
+    #[inline]
+    pub fn on_span_finished<F>(self, _observer: F) -> Self
+    where
+        F: Fn(&FinishedSpan<T>) + Send + Sync + 'static,
+    {
+        self
+    }
+
+    /// Appends `processor` to the chain of the processors applied to finished spans.
+    #[inline]
+    pub fn span_processor<F>(self, _processor: F) -> Self
+    where
+        F: Fn(FinishedSpan<T>) -> Option<FinishedSpan<T>> + Send + Sync + 'static,
+    {
+        self
+    }
+
+    /// Discards the finished spans shorter than `duration`, unless they failed.
+    #[inline]
+    pub fn report_only_slower_than(self, _duration: Duration) -> Self {
+        self
+    }
+
+    /// Attaches `tags` to every span started by the tracer.
+    #[inline]
+    pub fn default_tags<I>(self, _tags: I) -> Self
+    where
+        I: IntoIterator<Item = Tag>,
+    {
+        self
+    }
+
+    /// Makes the tags having the given names inherited by the descendants of spans.
+    #[inline]
+    pub fn inherited_tags<I, N>(self, _names: I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: Into<Cow<'static, str>>,
+    {
+        self
+    }
+
+    /// Sets the limits on the data recorded by each span.
+    #[inline]
+    pub fn span_limits(self, _limits: SpanLimits) -> Self {
+        self
+    }
+
+    /// Sets the restrictions on the baggage items of spans.
+    #[inline]
+    pub fn baggage_restrictions(self, _restrictions: BaggageRestrictions) -> Self {
+        self
+    }
+
+    /// Applies `redactor` to every finished span before it is sent.
+    #[inline]
+    pub fn redactor(self, _redactor: Redactor) -> Self {
+        self
+    }
+
+    /// Makes the span channel use lock-free ring buffers instead of tokio channels.
+    #[cfg(feature = "lock-free-queue")]
+    #[inline]
+    pub fn lock_free_queue(self, _poll_interval: Duration) -> Self {
+        self
+    }
+
+    /// Sets the clock used for the timestamps of spans and logs.
+    #[inline]
+    pub fn clock<C: Clock>(self, _clock: C) -> Self {
+        self
+    }
+
+    /// Sets the generator of the identifiers used for the states of new spans.
+    #[inline]
+    pub fn id_generator<G: IdGenerator>(self, _id_generator: G) -> Self {
+        self
+    }
+
+    /// Sets the tracker of the spans which are alive longer than expected.
+    #[inline]
+    pub fn span_tracker(self, _tracker: SpanTracker) -> Self {
+        self
+    }
+
+    /// Builds a `Tracer` and its `SpanReceiver`.
+    #[inline]
+    pub fn finish(self) -> (Tracer<S, T>, SpanReceiver<T>) {
+        (Tracer(PhantomData), SpanReceiver(PhantomData))
+    }
+
+    /// Builds a `Tracer` which hands finished spans to `reporter`.
+    #[inline]
+    pub fn finish_with_reporter<R>(self, _reporter: R) -> Tracer<S, T>
+    where
+        R: SpanReporter<T> + Send + Sync + 'static,
+    {
+        Tracer(PhantomData)
+    }
+}
+impl<S, T> fmt::Debug for TracerBuilder<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TracerBuilder").finish()
+    }
+}
+
+/// Span.
+///
+/// This is a no-op implementation which is never sampled.
+pub struct Span<T>(PhantomData<fn() -> T>);
+impl<T> Span<T> {
+    /// Makes an inactive span.
+    #[inline]
+    pub fn inactive() -> Self {
+        Span(PhantomData)
+    }
+
+    /// Makes this span the active span of the current thread until the returned guard is dropped.
+    #[inline]
+    pub fn activate(&self) -> ActiveSpanGuard {
+        ActiveSpanGuard::inactive()
+    }
+
+    /// Returns a handle of this span.
+    #[inline]
+    pub fn handle(&self) -> SpanHandle<T>
+    where
+        T: Clone,
+    {
+        SpanHandle(PhantomData)
+    }
+
+    /// Returns `true` if this span is sampled (i.e., being traced).
+    ///
+    /// This is always `false`.
+    #[inline]
+    pub fn is_sampled(&self) -> bool {
+        false
+    }
+
+    /// Returns the context of this span.
+    #[inline]
+    pub fn context(&self) -> Option<&SpanContext<T>> {
+        None
+    }
+
+    /// Returns the extensions of this span.
+    ///
+    /// This always returns `None`.
+    #[inline]
+    pub fn extensions(&self) -> Option<&Extensions> {
+        None
+    }
+
+    /// Returns the mutable extensions of this span.
+    ///
+    /// This always returns `None`.
+    #[inline]
+    pub fn extensions_mut(&mut self) -> Option<&mut Extensions> {
+        None
+    }
+
+    /// Returns the time remaining until the deadline of this span.
+    ///
+    /// This always returns `None`.
+    #[inline]
+    pub fn remaining_time(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Sets the operation name of this span.
+    #[inline]
+    pub fn set_operation_name<F, N>(&mut self, _f: F)
+    where
+        F: FnOnce() -> N,
+        N: Into<Cow<'static, str>>,
+    {
+    }
+
+    /// Sets the start time of this span.
+    #[inline]
+    pub fn set_start_time<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> SystemTime,
+    {
+    }
+
+    /// Sets the finish time of this span.
+    #[inline]
+    pub fn set_finish_time<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> SystemTime,
+    {
+    }
+
+    /// Finishes this span at `time`.
+    #[inline]
+    pub fn finish_at(self, _time: SystemTime) {}
+
+    /// Finishes this span and returns it instead of sending it.
+    ///
+    /// This always returns `None`.
+    #[inline]
+    pub fn finish(self) -> Option<FinishedSpan<T>> {
+        None
+    }
+
+    /// Discards this span without sending it.
+    #[inline]
+    pub fn abandon(self) {}
+
+    /// Sets the status of this span.
+    #[inline]
+    pub fn set_status<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> SpanStatus,
+    {
+    }
+
+    /// Sets the tag to this span.
+    #[inline]
+    pub fn set_tag<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> Tag,
+    {
+    }
+
+    /// Sets the tags to this span.
+    #[inline]
+    pub fn set_tags<F, I>(&mut self, _f: F)
+    where
+        F: FnOnce() -> I,
+        I: IntoIterator<Item = Tag>,
+    {
+    }
+
+    /// Adds a reference to this span.
+    #[inline]
+    pub fn add_reference<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> SpanReference<T>,
+    {
+    }
+
+    /// Attaches the shared set of tags to this span.
+    #[inline]
+    pub fn set_shared_tags<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> SharedTags,
+    {
+    }
+
+    /// Sets the baggage item to this span.
+    #[inline]
+    pub fn set_baggage_item<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> BaggageItem,
+    {
+    }
+
+    /// Gets the baggage item that has the name `name`.
+    #[inline]
+    pub fn get_baggage_item(&self, _name: &str) -> Option<&BaggageItem> {
+        None
+    }
+
+    /// Returns the value of the baggage item that has the name `name`.
+    ///
+    /// This always returns `None`.
+    #[inline]
+    pub fn baggage_item(&self, _name: &str) -> Option<&str> {
+        None
+    }
+
+    /// Updates the `TraceState` entries of this span.
+    #[inline]
+    pub fn update_trace_state<F>(&mut self, _f: F)
+    where
+        F: FnOnce(&mut TraceState),
+    {
+    }
+
+    /// Makes this span a child of `context` after it has started.
+    #[inline]
+    pub fn set_parent<C>(&mut self, _context: &C)
+    where
+        C: MaybeAsRef<SpanContext<T>>,
+        T: Clone + for<'b> From<CandidateSpan<'b, T>>,
+    {
+    }
+
+    /// Replaces the references of this span.
+    #[inline]
+    pub fn replace_references<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> Vec<SpanReference<T>>,
+    {
+    }
+
+    /// Logs structured data.
+    #[inline]
+    pub fn log<F>(&mut self, _f: F)
+    where
+        F: FnOnce(&mut LogBuilder),
+    {
+    }
+
+    /// Starts timing a phase of this span, which ends when the returned guard is dropped.
+    #[inline]
+    pub fn time_scope<N>(&mut self, name: N) -> TimeScope<'_, T>
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        TimeScope::new(self, name.into())
+    }
+
+    #[inline]
+    pub(crate) fn clock(&self) -> Option<&dyn Clock> {
+        None
+    }
+
+    /// Returns a cloneable handle adding tags and logs to this span from other tasks.
+    #[inline]
+    pub fn annotator(&mut self) -> SpanAnnotator {
+        SpanAnnotator::inactive()
+    }
+
+    /// Logs an error.
+    #[inline]
+    pub fn error_log<F>(&mut self, _f: F)
+    where
+        F: FnOnce(&mut StdErrorLogFieldsBuilder),
+    {
+    }
+
+    /// Records `error` following the OpenTracing conventions.
+    #[inline]
+    pub fn record_error<E>(&mut self, _error: &E)
+    where
+        E: std::error::Error + ?Sized,
+    {
+    }
+
+    /// Records the outcome of the operation of this span from `result` and finishes this span.
+    #[inline]
+    pub fn finish_with_result<R, E>(self, _result: &std::result::Result<R, E>)
+    where
+        E: fmt::Display,
+    {
+    }
+
+    /// Calls `f`.
+    #[inline]
+    pub fn catch_panic<F, R>(self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        f()
+    }
+
+    /// Makes a future which polls `future`.
+    #[inline]
+    pub fn catch_panic_future<F: Future>(self, future: F) -> CatchPanic<F, T> {
+        CatchPanic::new(future, self)
+    }
+
+    /// Starts a `ChildOf` span if this span is sampled.
+    #[inline]
+    pub fn child<N, F>(&self, _operation_name: N, _f: F) -> Span<T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone,
+        F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
+    {
+        Span::inactive()
+    }
+
+    /// Starts a `FollowsFrom` span if this span is sampled.
+    #[inline]
+    pub fn follower<N, F>(&self, _operation_name: N, _f: F) -> Span<T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone,
+        F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
+    {
+        Span::inactive()
+    }
+
+    /// Converts this span into a `SharedSpan` which can be cloned and shared among tasks.
+    #[inline]
+    pub fn into_shared(self) -> SharedSpan<T> {
+        SharedSpan(self)
+    }
+}
+impl<T> MaybeAsRef<SpanContext<T>> for Span<T> {
+    fn maybe_as_ref(&self) -> Option<&SpanContext<T>> {
+        None
+    }
+}
+impl<T> MaybeAsRef<SpanContext<T>> for SpanHandle<T> {
+    fn maybe_as_ref(&self) -> Option<&SpanContext<T>> {
+        None
+    }
+}
+impl<T> fmt::Debug for Span<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Span").finish()
+    }
+}
+
+/// Cloneable span shared among tasks.
+///
+/// This is a no-op implementation which is never sampled.
+pub struct SharedSpan<T>(Span<T>);
+impl<T> Clone for SharedSpan<T> {
+    fn clone(&self) -> Self {
+        SharedSpan(Span::inactive())
+    }
+}
+impl<T> std::ops::Deref for SharedSpan<T> {
+    type Target = Span<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T> From<Span<T>> for SharedSpan<T> {
+    fn from(f: Span<T>) -> Self {
+        f.into_shared()
+    }
+}
+impl<T> fmt::Debug for SharedSpan<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedSpan").finish()
+    }
+}
+
+/// Immutable handle of `Span`.
+///
+/// This is a no-op implementation which is never sampled.
+pub struct SpanHandle<T>(PhantomData<fn() -> T>);
+impl<T> SpanHandle<T> {
+    /// Returns `true` if this span is sampled (i.e., being traced).
+    #[inline]
+    pub fn is_sampled(&self) -> bool {
+        false
+    }
+
+    /// Returns the context of this span.
+    #[inline]
+    pub fn context(&self) -> Option<&SpanContext<T>> {
+        None
+    }
+
+    /// Gets the baggage item that has the name `name`.
+    #[inline]
+    pub fn get_baggage_item(&self, _name: &str) -> Option<&BaggageItem> {
+        None
+    }
+
+    /// Starts a `ChildOf` span if this span is sampled.
+    #[inline]
+    pub fn child<N, F>(&self, _operation_name: N, _f: F) -> Span<T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone,
+        F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
+    {
+        Span::inactive()
+    }
+
+    /// Starts a `FollowsFrom` span if this span is sampled.
+    #[inline]
+    pub fn follower<N, F>(&self, _operation_name: N, _f: F) -> Span<T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone,
+        F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
+    {
+        Span::inactive()
+    }
+}
+impl<T> Clone for SpanHandle<T> {
+    fn clone(&self) -> Self {
+        SpanHandle(PhantomData)
+    }
+}
+impl<T> fmt::Debug for SpanHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanHandle").finish()
+    }
+}
+
+/// Options for starting a span.
+///
+/// This is a no-op implementation which ignores all the options.
+pub struct StartSpanOptions<'a, S: 'a, T: 'a>(PhantomData<fn() -> (&'a S, T)>);
+impl<'a, S: 'a, T: 'a> StartSpanOptions<'a, S, T>
+where
+    S: Sampler<T>,
+{
+    /// Sets the start time of this span.
+    #[inline]
+    pub fn start_time(self, _time: SystemTime) -> Self {
+        self
+    }
+
+    /// Sets the deadline of this span.
+    #[inline]
+    pub fn deadline(self, _deadline: SystemTime) -> Self {
+        self
+    }
+
+    /// Sets the tag to this span.
+    #[inline]
+    pub fn tag(self, _tag: Tag) -> Self {
+        self
+    }
+
+    /// Attaches the shared set of tags to this span.
+    #[inline]
+    pub fn shared_tags(self, _tags: &SharedTags) -> Self {
+        self
+    }
+
+    /// Forces this span to be sampled for debugging.
+    #[inline]
+    pub fn debug(self) -> Self {
+        self
+    }
+
+    /// Adds the `ChildOf` reference to the active span, if any.
+    #[inline]
+    pub fn child_of_active(self) -> Self {
+        self
+    }
+
+    /// Adds the `ChildOf` reference to this span.
+    #[inline]
+    pub fn child_of<C>(self, _context: &C) -> Self
+    where
+        C: MaybeAsRef<SpanContext<T>>,
+        T: Clone,
+    {
+        self
+    }
+
+    /// Adds the `FollowsFrom` reference to this span.
+    #[inline]
+    pub fn follows_from<C>(self, _context: &C) -> Self
+    where
+        C: MaybeAsRef<SpanContext<T>>,
+        T: Clone,
+    {
+        self
+    }
+
+    /// Starts a new span.
+    #[inline]
+    pub fn start(self) -> Span<T>
+    where
+        T: for<'b> From<CandidateSpan<'b, T>>,
+    {
+        Span::inactive()
+    }
+
+    /// Starts a new span with the explicit `state`.
+    #[inline]
+    pub fn start_with_state(self, _state: T) -> Span<T> {
+        Span::inactive()
+    }
+}
+impl<S, T> fmt::Debug for StartSpanOptions<'_, S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StartSpanOptions").finish()
+    }
+}
+
+/// Sender of finished spans to the destination channel.
+///
+/// This is a no-op implementation which never sends spans.
+pub struct SpanSender<T>(PhantomData<fn() -> T>);
+impl<T> Clone for SpanSender<T> {
+    fn clone(&self) -> Self {
+        SpanSender(PhantomData)
+    }
+}
+impl<T> fmt::Debug for SpanSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanSender").finish()
+    }
+}
+
+/// Receiver of finished spans.
+///
+/// This is a no-op implementation which never receives spans.
+pub struct SpanReceiver<T>(PhantomData<fn() -> T>);
+impl<T> SpanReceiver<T> {
+    /// Receives the next finished span.
+    ///
+    /// This always returns `None` immediately.
+    pub async fn recv(&mut self) -> Option<FinishedSpan<T>> {
+        None
+    }
+
+    /// Receives the next finished span, blocking the current thread.
+    ///
+    /// This always returns `None` immediately.
+    pub fn blocking_recv(&mut self) -> Option<FinishedSpan<T>> {
+        None
+    }
+
+    /// Receives the next finished span, blocking the current thread for up to `timeout`.
+    ///
+    /// This always returns `Err(TryRecvError::Disconnected)` immediately.
+    pub fn blocking_recv_timeout(
+        &mut self,
+        _timeout: Duration,
+    ) -> Result<FinishedSpan<T>, TryRecvError> {
+        Err(TryRecvError::Disconnected)
+    }
+
+    /// Polls to receive the next finished span.
+    ///
+    /// This always returns `Poll::Ready(None)`.
+    pub fn poll_recv(&mut self, _cx: &mut Context<'_>) -> Poll<Option<FinishedSpan<T>>> {
+        Poll::Ready(None)
+    }
+
+    /// Tries to receive the next finished span.
+    ///
+    /// This always returns `Err(TryRecvError::Disconnected)`.
+    pub fn try_recv(&mut self) -> Result<FinishedSpan<T>, TryRecvError> {
+        Err(TryRecvError::Disconnected)
+    }
+}
+impl<T> fmt::Debug for SpanReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanReceiver").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::StdTag;
+    use std::mem::size_of;
+
+    #[test]
+    fn noop_types_are_zero_sized() {
+        assert_eq!(size_of::<Tracer<AllSampler, [u64; 4]>>(), 0);
+        assert_eq!(size_of::<Span<[u64; 4]>>(), 0);
+        assert_eq!(size_of::<SpanReceiver<[u64; 4]>>(), 0);
+    }
+
+    #[tokio::test]
+    async fn instrumentation_compiles_to_nothing() {
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        {
+            let parent = tracer.span("parent").start_with_state(());
+            let mut child = parent.child("child", |_| unreachable!());
+            child.set_tag(|| unreachable!());
+            child.log(|_| unreachable!());
+            child.set_tags(|| StdTag::peer_addr("127.0.0.1:80".parse().unwrap()));
+            assert!(!child.is_sampled());
+        }
+        assert!(span_rx.recv().await.is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::log::Log;
+use crate::span::{BaggageItem, SpanReference};
+use crate::tag::Tag;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The maximum number of buffers of each kind retained by a `SpanPool`.
+const DEFAULT_POOL_CAPACITY: usize = 1024;
+
+/// The maximum capacity of the buffers retained by a `SpanPool`.
+///
+/// Larger buffers are freed, so that a few unusually large spans do not pin their allocations
+/// for the life of the tracer.
+const DEFAULT_MAX_BUFFER_CAPACITY: usize = 256;
+
+/// Pool of the heap buffers owned by spans.
+///
+/// The buffers of a finished span are returned to the pool of the tracer
+/// that started it when the `FinishedSpan` is dropped,
+/// and handed out again to the next spans started by the tracer.
+pub(crate) struct SpanPool<T> {
+    pub tags: BufferPool<Tag>,
+    pub logs: BufferPool<Log>,
+    pub references: BufferPool<SpanReference<T>>,
+    pub baggage_items: BufferPool<BaggageItem>,
+}
+impl<T> SpanPool<T> {
+    pub fn new() -> Self {
+        SpanPool {
+            tags: BufferPool::new(DEFAULT_POOL_CAPACITY, DEFAULT_MAX_BUFFER_CAPACITY),
+            logs: BufferPool::new(DEFAULT_POOL_CAPACITY, DEFAULT_MAX_BUFFER_CAPACITY),
+            references: BufferPool::new(DEFAULT_POOL_CAPACITY, DEFAULT_MAX_BUFFER_CAPACITY),
+            baggage_items: BufferPool::new(DEFAULT_POOL_CAPACITY, DEFAULT_MAX_BUFFER_CAPACITY),
+        }
+    }
+}
+impl<T> fmt::Debug for SpanPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanPool").finish_non_exhaustive()
+    }
+}
+
+/// Pool of `Vec<V>` buffers.
+///
+/// The pool never blocks: if it is being accessed by another thread,
+/// `take` falls back to an empty vector and `put` discards the buffer.
+/// Buffers whose capacities exceed `max_buffer_capacity` are discarded too.
+#[derive(Debug)]
+pub(crate) struct BufferPool<V> {
+    buffers: Mutex<Vec<Vec<V>>>,
+    capacity: usize,
+    max_buffer_capacity: usize,
+}
+impl<V> BufferPool<V> {
+    fn new(capacity: usize, max_buffer_capacity: usize) -> Self {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            capacity,
+            max_buffer_capacity,
+        }
+    }
+
+    /// Takes an empty buffer from the pool.
+    pub fn take(&self) -> Vec<V> {
+        self.buffers
+            .try_lock()
+            .ok()
+            .and_then(|mut buffers| buffers.pop())
+            .unwrap_or_default()
+    }
+
+    /// Replaces `buffer` with a pooled one if it has not been allocated yet.
+    pub fn reserve(&self, buffer: &mut Vec<V>) {
+        if buffer.capacity() == 0 {
+            *buffer = self.take();
+        }
+    }
+
+    /// Clears `buffer` and returns it to the pool.
+    pub fn put(&self, mut buffer: Vec<V>) {
+        if buffer.capacity() == 0 || buffer.capacity() > self.max_buffer_capacity {
+            return;
+        }
+        buffer.clear();
+        if let Ok(mut buffers) = self.buffers.try_lock() {
+            if buffers.len() < self.capacity {
+                buffers.push(buffer);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_recycled() {
+        let pool = BufferPool::new(1, 16);
+        assert_eq!(pool.take().capacity(), 0);
+
+        pool.put(Vec::<u8>::with_capacity(17)); // Exceeds the maximum capacity of buffers
+        assert_eq!(pool.len(), 0);
+
+        let mut buffer: Vec<u8> = Vec::with_capacity(16);
+        buffer.push(1);
+        pool.put(buffer);
+        pool.put(Vec::with_capacity(8)); // Exceeds the capacity of the pool
+        assert_eq!(pool.len(), 1);
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), 16);
+        assert_eq!(pool.len(), 0);
+    }
+}
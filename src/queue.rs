@@ -0,0 +1,237 @@
+//! Runtime-agnostic MPSC queue.
+//!
+//! This is used as the span channel if the `tokio` feature is disabled
+//! or if an overflow policy other than dropping the newest spans is selected.
+//! The receiver is woken up through the `Waker` of the task polling it,
+//! so it works with any executor (or none, by parking the thread).
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// Makes a new queue which holds at most `capacity` items (unlimited if `None`).
+pub(crate) fn queue<T>(capacity: Option<usize>) -> (QueueTx<T>, QueueRx<T>) {
+    let queue = Arc::new(Queue {
+        capacity,
+        state: Mutex::new(QueueState {
+            items: VecDeque::new(),
+            waker: None,
+            closed: false,
+        }),
+        popped: Condvar::new(),
+    });
+    (QueueTx(Arc::clone(&queue)), QueueRx(queue))
+}
+
+/// Producer side of a queue.
+///
+/// When this is dropped, the queue is closed.
+#[derive(Debug)]
+pub(crate) struct QueueTx<T>(Arc<Queue<T>>);
+impl<T> QueueTx<T> {
+    /// Pushes `item` to the queue.
+    ///
+    /// If the queue is full or the consumer has been dropped, `item` is returned.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        if Arc::strong_count(&self.0) == 1 {
+            return Err(item);
+        }
+        let mut state = self.0.lock();
+        if self.0.is_full(&state) {
+            return Err(item);
+        }
+        state.items.push_back(item);
+        Queue::wake(state);
+        Ok(())
+    }
+
+    /// Pushes `item` to the queue, evicting the oldest item if the queue is full.
+    ///
+    /// The evicted item is returned.
+    /// If the consumer has been dropped, `item` is returned as an error.
+    pub fn push_evicting(&self, item: T) -> Result<Option<T>, T> {
+        if Arc::strong_count(&self.0) == 1 {
+            return Err(item);
+        }
+        let mut state = self.0.lock();
+        let evicted = if self.0.is_full(&state) {
+            state.items.pop_front()
+        } else {
+            None
+        };
+        state.items.push_back(item);
+        Queue::wake(state);
+        Ok(evicted)
+    }
+
+    /// Pushes `item` to the queue, waiting up to `timeout` for the consumer to make room.
+    ///
+    /// If the queue is still full after `timeout` or the consumer has been dropped,
+    /// `item` is returned.
+    pub fn push_timeout(&self, item: T, timeout: Duration) -> Result<(), T> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.0.lock();
+        loop {
+            if Arc::strong_count(&self.0) == 1 {
+                return Err(item);
+            }
+            if !self.0.is_full(&state) {
+                break;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(item);
+            }
+            state = self
+                .0
+                .popped
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        state.items.push_back(item);
+        Queue::wake(state);
+        Ok(())
+    }
+}
+impl<T> Drop for QueueTx<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.closed = true;
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Consumer side of a queue.
+#[derive(Debug)]
+pub(crate) struct QueueRx<T>(Arc<Queue<T>>);
+impl<T> QueueRx<T> {
+    /// Pops the oldest item from the queue.
+    ///
+    /// `Err(true)` means that the queue is empty and has been closed.
+    pub fn pop(&mut self) -> Result<T, bool> {
+        let mut state = self.0.lock();
+        let item = state.items.pop_front().ok_or(state.closed)?;
+        self.0.popped.notify_one();
+        Ok(item)
+    }
+
+    /// Polls to pop the oldest item from the queue.
+    ///
+    /// `Ready(None)` means that the queue is empty and has been closed.
+    pub fn poll_pop(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.0.lock();
+        if let Some(item) = state.items.pop_front() {
+            self.0.popped.notify_one();
+            return Poll::Ready(Some(item));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for QueueRx<T> {
+    fn drop(&mut self) {
+        // Wakes up the producers waiting for room, which will see that the consumer is gone.
+        let _state = self.0.lock();
+        self.0.popped.notify_all();
+    }
+}
+
+#[derive(Debug)]
+struct Queue<T> {
+    capacity: Option<usize>,
+    state: Mutex<QueueState<T>>,
+    popped: Condvar,
+}
+impl<T> Queue<T> {
+    fn lock(&self) -> MutexGuard<'_, QueueState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_full(&self, state: &QueueState<T>) -> bool {
+        self.capacity.is_some_and(|c| state.items.len() >= c)
+    }
+
+    /// Unlocks `state` and wakes up the consumer.
+    fn wake(mut state: MutexGuard<'_, QueueState<T>>) {
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct QueueState<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct Flag(Mutex<bool>);
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            *self.0.lock().unwrap() = true;
+        }
+    }
+
+    #[test]
+    fn queue_works() {
+        let (tx, mut rx) = queue(Some(2));
+        assert!(tx.push(0).is_ok());
+        assert!(tx.push(1).is_ok());
+        assert_eq!(tx.push(2), Err(2));
+        assert_eq!(rx.pop(), Ok(0));
+        assert_eq!(rx.pop(), Ok(1));
+        assert_eq!(rx.pop(), Err(false));
+
+        let flag = Arc::new(Flag(Mutex::new(false)));
+        let waker = Waker::from(Arc::clone(&flag));
+        let mut cx = Context::from_waker(&waker);
+        assert!(rx.poll_pop(&mut cx).is_pending());
+        assert!(tx.push(3).is_ok());
+        assert!(*flag.0.lock().unwrap());
+        assert_eq!(rx.poll_pop(&mut cx), Poll::Ready(Some(3)));
+
+        drop(tx);
+        assert_eq!(rx.pop(), Err(true));
+        assert_eq!(rx.poll_pop(&mut cx), Poll::Ready(None));
+    }
+
+    #[test]
+    fn overflow_policies_work() {
+        let (tx, mut rx) = queue(Some(2));
+        assert_eq!(tx.push_evicting(0), Ok(None));
+        assert_eq!(tx.push_evicting(1), Ok(None));
+        assert_eq!(tx.push_evicting(2), Ok(Some(0)));
+        assert_eq!(tx.push_timeout(3, Duration::from_millis(10)), Err(3));
+
+        let tx = Arc::new(tx);
+        let handle = std::thread::spawn({
+            let tx = Arc::clone(&tx);
+            move || tx.push_timeout(3, Duration::from_secs(10))
+        });
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(rx.pop(), Ok(1));
+        assert_eq!(handle.join().unwrap(), Ok(()));
+        assert_eq!(rx.pop(), Ok(2));
+        assert_eq!(rx.pop(), Ok(3));
+
+        drop(rx);
+        assert_eq!(tx.push_timeout(4, Duration::from_secs(10)), Err(4));
+    }
+}
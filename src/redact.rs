@@ -0,0 +1,245 @@
+//! Redaction of sensitive data from finished spans.
+//!
+//! A `Redactor` attached to a tracer with `TracerBuilder::redactor` is applied to every
+//! finished span before it leaves the tracer, so that personally identifiable information
+//! and secrets recorded by instrumented code never reach the receiver.
+use crate::sampler::glob_match;
+use crate::span::FinishedSpan;
+use crate::tag::TagValue;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+type Scrubber = Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>;
+
+/// Redaction rules applied to finished spans.
+///
+/// - Tags and log fields whose names match a denied pattern are removed.
+/// - The string values of tags (including the ones in arrays and maps) and log fields
+///   are passed to the scrubbers, which may replace them.
+/// - Baggage items whose names match a stripped pattern are removed from span contexts.
+///
+/// Patterns may contain the wildcards `*` (any sequence of characters) and `?` (any character).
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::redact::Redactor;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::tag::{Tag, TagValue};
+/// use cf_rustracing::Tracer;
+///
+/// let redactor = Redactor::new()
+///     .deny_tag("http.request.header.authorization")
+///     .deny_tag("*.password")
+///     .scrub(|_name, value| value.contains('@').then(|| "<email>".to_owned()));
+/// let (tracer, mut span_rx) = Tracer::builder(AllSampler).redactor(redactor).finish();
+/// tracer
+///     .span("login")
+///     .tag(Tag::new("user.password", "secret"))
+///     .tag(Tag::new("user.email", "foo@example.com"))
+///     .start_with_state(());
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let span = span_rx.try_recv().unwrap();
+/// assert_eq!(span.tags().len(), 1);
+/// assert_eq!(span.tags()[0].value(), &TagValue::from("<email>"));
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Redactor {
+    denied_tags: Vec<String>,
+    stripped_baggage: Vec<String>,
+    scrubbers: Vec<Scrubber>,
+}
+impl Redactor {
+    /// Makes a new `Redactor` instance without rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes the tags and log fields whose names match `pattern`.
+    pub fn deny_tag<P: Into<String>>(mut self, pattern: P) -> Self {
+        self.denied_tags.push(pattern.into());
+        self
+    }
+
+    /// Removes the baggage items whose names match `pattern`.
+    pub fn strip_baggage_item<P: Into<String>>(mut self, pattern: P) -> Self {
+        self.stripped_baggage.push(pattern.into());
+        self
+    }
+
+    /// Removes all the baggage items.
+    pub fn strip_baggage(self) -> Self {
+        self.strip_baggage_item("*")
+    }
+
+    /// Adds a scrubber of string values.
+    ///
+    /// `scrubber` is called with the name and the value of every string tag and log field,
+    /// and replaces the value if it returns `Some`.
+    /// Scrubbers are called in the order they were added.
+    pub fn scrub<F>(mut self, scrubber: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.scrubbers.push(Arc::new(scrubber));
+        self
+    }
+
+    /// Applies the rules to `span`.
+    pub fn redact<T>(&self, span: &mut FinishedSpan<T>) {
+        if self.touches_shared_tags(span) {
+            span.unshare_tags();
+        }
+        span.tags_mut().retain(|tag| !self.is_denied(tag.name()));
+        for tag in span.tags_mut() {
+            let (name, value) = tag.name_and_value_mut();
+            self.scrub_value(name, value);
+        }
+        for log in span.logs_mut() {
+            log.retain_fields(|field| !self.is_denied(field.name()));
+            for field in log.fields_mut() {
+                let (name, value) = field.name_and_value_mut();
+                self.scrub_value(name, value);
+            }
+        }
+        if !self.stripped_baggage.is_empty() {
+            span.context_mut().retain_baggage_items(|item| {
+                !self
+                    .stripped_baggage
+                    .iter()
+                    .any(|p| glob_match(p.as_bytes(), item.name().as_bytes()))
+            });
+        }
+    }
+
+    fn is_denied(&self, name: &str) -> bool {
+        self.denied_tags
+            .iter()
+            .any(|p| glob_match(p.as_bytes(), name.as_bytes()))
+    }
+
+    /// Scrubs the string values in `value`, including the ones nested in arrays and maps.
+    fn scrub_value(&self, name: &str, value: &mut TagValue) {
+        match value {
+            TagValue::String(v) => {
+                if let Some(scrubbed) = self.scrubbed(name, v) {
+                    *v = Cow::Owned(scrubbed);
+                }
+            }
+            TagValue::Array(values) => {
+                for v in values {
+                    self.scrub_value(name, v);
+                }
+            }
+            TagValue::Map(entries) => {
+                for v in entries.values_mut() {
+                    self.scrub_value(name, v);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns `true` if `scrub_value` would change `value`.
+    fn needs_scrubbing(&self, name: &str, value: &TagValue) -> bool {
+        match value {
+            TagValue::String(v) => self.scrubbed(name, v).is_some(),
+            TagValue::Array(values) => values.iter().any(|v| self.needs_scrubbing(name, v)),
+            TagValue::Map(entries) => entries.values().any(|v| self.needs_scrubbing(name, v)),
+            _ => false,
+        }
+    }
+
+    fn scrubbed(&self, name: &str, value: &str) -> Option<String> {
+        let mut scrubbed: Option<String> = None;
+        for scrubber in &self.scrubbers {
+            let current = scrubbed.as_deref().unwrap_or(value);
+            if let Some(s) = scrubber(name, current) {
+                scrubbed = Some(s);
+            }
+        }
+        scrubbed
+    }
+
+    /// Returns `true` if the shared tags of `span` have to be redacted.
+    ///
+    /// Since shared tags are shared by many spans, they are copied to `span` before redaction.
+    fn touches_shared_tags<T>(&self, span: &FinishedSpan<T>) -> bool {
+        let Some(shared) = span.shared_tags() else {
+            return false;
+        };
+        shared
+            .tags()
+            .iter()
+            .any(|tag| self.is_denied(tag.name()) || self.needs_scrubbing(tag.name(), tag.value()))
+    }
+}
+impl fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Redactor")
+            .field("denied_tags", &self.denied_tags)
+            .field("stripped_baggage", &self.stripped_baggage)
+            .field("scrubbers", &self.scrubbers.len())
+            .finish()
+    }
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::log::LogField;
+    use crate::sampler::AllSampler;
+    use crate::span::BaggageItem;
+    use crate::tag::{SharedTags, Tag};
+    use crate::Tracer;
+
+    #[test]
+    fn redactor_works() {
+        let redactor = Redactor::new()
+            .deny_tag("secret.*")
+            .strip_baggage_item("user.*")
+            .scrub(|name, _| name.starts_with("email").then(|| "***".to_owned()));
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler).redactor(redactor).finish();
+
+        let shared = SharedTags::new(vec![Tag::new("secret.key", "foo"), Tag::new("env", "prod")]);
+        {
+            let mut span = tracer
+                .span("foo")
+                .shared_tags(&shared)
+                .tag(Tag::new("email", "foo@example.com"))
+                .tag(Tag::array("emails", ["foo@example.com"]))
+                .start_with_state(());
+            span.set_baggage_item(|| BaggageItem::new("user.id", "1"));
+            span.set_baggage_item(|| BaggageItem::new("tenant", "a"));
+            span.log(|log| {
+                log.field(LogField::new("secret.token", "bar"))
+                    .field(LogField::new("email", "bar@example.com"));
+            });
+        }
+
+        let span = span_rx.try_recv().unwrap();
+        let mut tags = span
+            .all_tags()
+            .map(|t| (t.name(), t.value().clone()))
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(
+            tags,
+            [
+                ("email", TagValue::from("***")),
+                ("emails", TagValue::from(vec!["***"])),
+                ("env", TagValue::from("prod"))
+            ]
+        );
+        let fields = span.logs()[0].fields();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].value().as_str(), Some("***"));
+        let baggage = span.context().baggage_items();
+        assert_eq!(baggage.len(), 1);
+        assert_eq!(baggage[0].name(), "tenant");
+    }
+}
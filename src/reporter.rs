@@ -0,0 +1,414 @@
+//! Sinks of finished spans.
+//!
+//! By default, a tracer sends its finished spans to a channel consumed through a `SpanReceiver`.
+//! A `SpanReporter` given to `Tracer::with_reporter` replaces the channel:
+//! each finished span is handed to it synchronously on the thread finishing the span.
+//!
+//! `BatchProcessor` is a reporter which groups finished spans into batches for exporters,
+//! `AsyncReporter` hands finished spans to an asynchronous function,
+//! `JsonFileReporter` appends finished spans to a file as JSON lines,
+//! `ConsoleReporter` prints finished spans as trace trees,
+//! and `Broadcast` hands every finished span to multiple reporters.
+pub use self::asynchronous::AsyncReporter;
+pub use self::batch::BatchProcessor;
+pub use self::console::{ConsoleOptions, ConsoleReporter};
+pub use self::file::JsonFileReporter;
+
+use crate::span::FinishedSpan;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+mod asynchronous;
+mod batch;
+mod console;
+mod file;
+
+/// Sink of finished spans.
+///
+/// `report` is called on the thread finishing the span, so it should not block for long.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::reporter::SpanReporter;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::span::FinishedSpan;
+/// use cf_rustracing::Tracer;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicUsize);
+/// impl<T> SpanReporter<T> for Counter {
+///     fn report(&self, _span: FinishedSpan<T>) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counter = Arc::new(Counter::default());
+/// let tracer = Tracer::with_reporter(AllSampler, Arc::clone(&counter));
+/// tracer.span("foo").start_with_state(());
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+/// ```
+pub trait SpanReporter<T> {
+    /// Reports `span`.
+    fn report(&self, span: FinishedSpan<T>);
+
+    /// Delivers the spans buffered by this reporter, blocking until they are delivered.
+    ///
+    /// This is called by `Tracer::flush`. The default implementation does nothing.
+    fn flush(&self) {}
+}
+impl<T, F> SpanReporter<T> for F
+where
+    F: Fn(FinishedSpan<T>),
+{
+    fn report(&self, span: FinishedSpan<T>) {
+        self(span)
+    }
+}
+impl<T, R: SpanReporter<T> + ?Sized> SpanReporter<T> for Arc<R> {
+    fn report(&self, span: FinishedSpan<T>) {
+        (**self).report(span)
+    }
+
+    fn flush(&self) {
+        (**self).flush()
+    }
+}
+impl<T> SpanReporter<T> for Mutex<Vec<FinishedSpan<T>>> {
+    fn report(&self, span: FinishedSpan<T>) {
+        self.lock().unwrap_or_else(|e| e.into_inner()).push(span);
+    }
+}
+
+/// `SpanReporter` handing every finished span to multiple reporters.
+///
+/// Each reporter receives its own copy of every span, so independent consumers
+/// (e.g., an exporter and an in-process debugging UI) can be attached to the same tracer.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::reporter::{BatchProcessor, Broadcast};
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// let exporter = BatchProcessor::new(100, Duration::from_secs(1), |_batch: Vec<_>| {
+///     // Sends the batch to the backend.
+/// })
+/// .unwrap();
+/// let (ui_tx, ui_rx) = mpsc::channel();
+/// let broadcast = Broadcast::new()
+///     .reporter(exporter)
+///     .reporter(move |span| {
+///         let _ = ui_tx.send(span);
+///     });
+///
+/// let tracer = Tracer::with_reporter(AllSampler, broadcast);
+/// tracer.span("foo").start_with_state(());
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(ui_rx.try_recv().unwrap().operation_name(), "foo");
+/// ```
+pub struct Broadcast<T> {
+    reporters: Vec<Box<dyn SpanReporter<T> + Send + Sync>>,
+}
+impl<T> Broadcast<T> {
+    /// Makes a new `Broadcast` instance without reporters.
+    pub fn new() -> Self {
+        Broadcast {
+            reporters: Vec::new(),
+        }
+    }
+
+    /// Adds `reporter` to the reporters receiving finished spans.
+    pub fn reporter<R>(mut self, reporter: R) -> Self
+    where
+        R: SpanReporter<T> + Send + Sync + 'static,
+    {
+        self.reporters.push(Box::new(reporter));
+        self
+    }
+
+    /// Returns the number of the reporters.
+    pub fn len(&self) -> usize {
+        self.reporters.len()
+    }
+
+    /// Returns `true` if there are no reporters.
+    pub fn is_empty(&self) -> bool {
+        self.reporters.is_empty()
+    }
+}
+impl<T> Default for Broadcast<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: Clone> SpanReporter<T> for Broadcast<T> {
+    fn report(&self, span: FinishedSpan<T>) {
+        if let Some((last, others)) = self.reporters.split_last() {
+            for reporter in others {
+                reporter.report(span.clone());
+            }
+            last.report(span);
+        }
+    }
+
+    fn flush(&self) {
+        for reporter in &self.reporters {
+            reporter.flush();
+        }
+    }
+}
+impl<T> fmt::Debug for Broadcast<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Broadcast")
+            .field("len", &self.reporters.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reporter shared by the spans of a tracer.
+pub(crate) struct SharedReporter<T>(pub Arc<dyn SpanReporter<T> + Send + Sync>);
+impl<T> fmt::Debug for SharedReporter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedReporter").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::span::SpanStatus;
+    use crate::tag::Tag;
+    use crate::Tracer;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn batch_processor_works() {
+        assert!(BatchProcessor::<()>::new(0, Duration::from_secs(1), |_| {}).is_err());
+
+        let (batch_tx, batch_rx) = mpsc::channel();
+        let processor = Arc::new(track_try_unwrap!(BatchProcessor::new(
+            3,
+            Duration::from_millis(50),
+            move |batch: Vec<FinishedSpan<()>>| {
+                let names = batch
+                    .iter()
+                    .map(|s| s.operation_name().to_owned())
+                    .collect::<Vec<_>>();
+                let _ = batch_tx.send(names);
+            }
+        )));
+        let tracer = Tracer::with_reporter(AllSampler, Arc::clone(&processor));
+
+        // Flushed by the size.
+        for name in ["a", "b", "c", "d"] {
+            tracer.span(name).start_with_state(());
+        }
+        let timeout = Duration::from_secs(10);
+        assert_eq!(batch_rx.recv_timeout(timeout).unwrap(), ["a", "b", "c"]);
+
+        // Flushed by the delay.
+        assert_eq!(batch_rx.recv_timeout(timeout).unwrap(), ["d"]);
+
+        // Flushed explicitly.
+        let (batch_tx, batch_rx) = mpsc::channel();
+        let processor = Arc::new(track_try_unwrap!(BatchProcessor::new(
+            3,
+            Duration::from_secs(60),
+            move |batch: Vec<FinishedSpan<()>>| {
+                let _ = batch_tx.send(batch.len());
+            }
+        )));
+        let tracer = Tracer::with_reporter(AllSampler, Arc::clone(&processor));
+        tracer.span("e").start_with_state(());
+        assert_eq!(processor.buffered_spans(), 1);
+        processor.flush();
+        assert_eq!(batch_rx.try_recv().unwrap(), 1);
+        processor.flush();
+        assert!(batch_rx.try_recv().is_err());
+
+        // Flushed when dropped.
+        tracer.span("f").start_with_state(());
+        drop(tracer);
+        drop(processor);
+        assert_eq!(batch_rx.try_recv().unwrap(), 1);
+    }
+
+    #[test]
+    fn json_file_reporter_works() {
+        let path = std::env::temp_dir().join(format!("rustracing-{}.jsonl", std::process::id()));
+        let rotated = path.with_extension("jsonl.1");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&rotated);
+
+        let reporter = track_try_unwrap!(JsonFileReporter::new(&path)).max_file_size(450);
+        let tracer = Tracer::with_reporter(AllSampler, reporter);
+        {
+            let parent = tracer.span("parent").start_with_state(1);
+            let mut child = parent.child("child", |options| {
+                options.tag(Tag::new("quote", "\"x\"")).start_with_state(2)
+            });
+            child.log(|log| {
+                log.field_i64("n", 3);
+            });
+            child.set_status(|| SpanStatus::error("failed"));
+        }
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(concat!(
+            r#"{"name":"child","context":"2","#,
+            r#""references":[{"type":"child_of","context":"1"}],"#
+        )));
+        assert!(lines[0].contains(r#""status":"error","status_message":"failed","#));
+        assert!(lines[0].contains(r#""tags":{"quote":"\"x\""}"#));
+        assert!(lines[0].contains(r#""fields":{"n":3}"#));
+        assert!(lines[1].starts_with(r#"{"name":"parent","context":"1","#));
+
+        // Rotated by the size.
+        tracer.span("foo").start_with_state(3);
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap(), content);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with(r#"{"name":"foo""#));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rotated).unwrap();
+    }
+
+    #[test]
+    fn console_reporter_works() {
+        use crate::clock::MockClock;
+        use crate::tag::StdTag;
+        use crate::trace::TraceIdentity;
+        use std::io;
+
+        #[derive(Debug, Clone)]
+        struct State(u64, u64);
+        impl TraceIdentity for State {
+            type TraceId = u64;
+            type SpanId = u64;
+
+            fn trace_id(&self) -> u64 {
+                self.0
+            }
+            fn span_id(&self) -> u64 {
+                self.1
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let output = Buffer::default();
+        let take_output = || String::from_utf8(std::mem::take(&mut *output.0.lock().unwrap()));
+
+        let options = ConsoleOptions::new()
+            .delay(Duration::from_secs(60))
+            .key_tags(["http.method"])
+            .writer(output.clone());
+        let reporter = Arc::new(track_try_unwrap!(ConsoleReporter::new(options)));
+        let clock = MockClock::default();
+        let tracer = Tracer::builder(AllSampler)
+            .clock(clock.clone())
+            .finish_with_reporter(Arc::clone(&reporter));
+        {
+            let root = tracer
+                .span("GET /users")
+                .tag(StdTag::http_method("GET"))
+                .start_with_state(State(1, 1));
+            let mut query = root.child("query", |options| options.start_with_state(State(1, 2)));
+            clock.advance(Duration::from_millis(2));
+            query.set_status(|| SpanStatus::error("timeout"));
+            drop(query);
+            let _cache = root.child("cache", |options| options.start_with_state(State(1, 3)));
+            clock.advance(Duration::from_millis(1));
+        }
+        assert_eq!(
+            take_output().unwrap(),
+            concat!(
+                "trace 1\n",
+                "  GET /users 3ms http.method=GET\n",
+                "    query 2ms ERROR \"timeout\"\n",
+                "    cache 1ms\n",
+            )
+        );
+
+        // Incomplete traces are printed when flushed.
+        let root = tracer.span("root").start_with_state(State(2, 1));
+        root.child("orphan", |options| options.start_with_state(State(2, 2)));
+        assert_eq!(take_output().unwrap(), "");
+        reporter.flush();
+        assert_eq!(
+            take_output().unwrap(),
+            "trace 2 (incomplete)\n  orphan 0ns\n"
+        );
+    }
+
+    #[test]
+    fn broadcast_works() {
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let broadcast = Broadcast::new()
+            .reporter(Arc::clone(&first))
+            .reporter(Arc::clone(&second));
+        assert_eq!(broadcast.len(), 2);
+
+        let tracer = Tracer::with_reporter(AllSampler, broadcast);
+        tracer.span("foo").start_with_state(());
+        for spans in [first, second] {
+            let spans = spans.lock().unwrap();
+            assert_eq!(spans.len(), 1);
+            assert_eq!(spans[0].operation_name(), "foo");
+        }
+    }
+
+    #[test]
+    fn async_reporter_works() {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let (reporter, reporting) = track_try_unwrap!(AsyncReporter::new(16, {
+            let names = Arc::clone(&names);
+            move |span: FinishedSpan<()>| {
+                let names = Arc::clone(&names);
+                async move {
+                    tokio::task::yield_now().await;
+                    names.lock().unwrap().push(span.operation_name().to_owned());
+                }
+            }
+        }));
+        rt.spawn(reporting);
+        let reporter = Arc::new(reporter);
+        let tracer = Tracer::with_reporter(AllSampler, Arc::clone(&reporter));
+        tracer.span("foo").start_with_state(());
+        tracer.span("bar").start_with_state(());
+        reporter.flush();
+        assert_eq!(*names.lock().unwrap(), ["foo", "bar"]);
+
+        // The spans are discarded while the queue is full.
+        let (reporter, _reporting) = track_try_unwrap!(AsyncReporter::new(1, |_| async {}));
+        let reporter = Arc::new(reporter);
+        let tracer = Tracer::with_reporter(AllSampler, Arc::clone(&reporter));
+        tracer.span("foo").start_with_state(());
+        tracer.span("bar").start_with_state(());
+        assert_eq!(reporter.dropped_spans(), 1);
+        assert!(AsyncReporter::<()>::new(0, |_| async {}).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use super::SpanReporter;
+use crate::queue::{self, QueueTx};
+use crate::span::FinishedSpan;
+use crate::{ErrorKind, Result};
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// `SpanReporter` handing finished spans to an asynchronous function.
+///
+/// This allows post-processing finished spans with I/O (e.g., looking up enrichment data)
+/// without blocking the threads finishing them.
+/// The spans are queued and passed one by one to the function by a future,
+/// which has to be spawned on an executor.
+///
+/// `SpanReporter::flush` waits until the spans queued so far are handled,
+/// so it must not be called on the thread polling the future.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::reporter::AsyncReporter;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use tokio::sync::mpsc;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (name_tx, mut name_rx) = mpsc::unbounded_channel();
+/// let (reporter, reporting) = AsyncReporter::new(1024, move |span| {
+///     let name_tx = name_tx.clone();
+///     async move {
+///         // Enriches and exports the span.
+///         let _ = name_tx.send(span.operation_name().to_owned());
+///     }
+/// })
+/// .unwrap();
+/// tokio::spawn(reporting);
+///
+/// let tracer = Tracer::with_reporter(AllSampler, reporter);
+/// tracer.span("foo").start_with_state(());
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(name_rx.recv().await.unwrap(), "foo");
+/// # }
+/// ```
+pub struct AsyncReporter<T> {
+    tx: QueueTx<FinishedSpan<T>>,
+    shared: Arc<Shared>,
+}
+impl<T: Send + 'static> AsyncReporter<T> {
+    /// Makes a new `AsyncReporter` instance and the future passing the reported spans to `report`.
+    ///
+    /// At most `capacity` spans wait for `report`,
+    /// and the spans reported while the queue is full are discarded.
+    /// The future completes when the reporter is dropped and the queued spans are handled.
+    ///
+    /// # Errors
+    ///
+    /// If `capacity` is `0`, it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new<F, Fut>(
+        capacity: usize,
+        mut report: F,
+    ) -> Result<(Self, impl Future<Output = ()> + Send + 'static)>
+    where
+        F: FnMut(FinishedSpan<T>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        track_assert!(capacity > 0, ErrorKind::InvalidInput);
+        let (tx, mut rx) = queue::queue(Some(capacity));
+        let shared = Arc::new(Shared {
+            state: Mutex::new(Progress::default()),
+            settled: Condvar::new(),
+            dropped_spans: AtomicU64::new(0),
+        });
+        let closing = Closing(Arc::clone(&shared));
+        let reporting = async move {
+            let closing = closing;
+            while let Some(span) = poll_fn(|cx| rx.poll_pop(cx)).await {
+                report(span).await;
+                closing.0.lock().settled += 1;
+                closing.0.settled.notify_all();
+            }
+        };
+        Ok((AsyncReporter { tx, shared }, reporting))
+    }
+}
+impl<T> AsyncReporter<T> {
+    /// Returns the number of the spans discarded because the queue was full
+    /// or the future had been dropped.
+    pub fn dropped_spans(&self) -> u64 {
+        self.shared.dropped_spans.load(Ordering::Relaxed)
+    }
+}
+impl<T> SpanReporter<T> for AsyncReporter<T> {
+    fn report(&self, span: FinishedSpan<T>) {
+        let mut state = self.shared.lock();
+        if self.tx.push(span).is_ok() {
+            state.accepted += 1;
+        } else {
+            self.shared.dropped_spans.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {
+        let mut state = self.shared.lock();
+        let accepted = state.accepted;
+        while state.settled < accepted && !state.closed {
+            state = self
+                .shared
+                .settled
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+impl<T> fmt::Debug for AsyncReporter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncReporter")
+            .field("dropped_spans", &self.dropped_spans())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<Progress>,
+    settled: Condvar,
+    dropped_spans: AtomicU64,
+}
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Progress> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    accepted: u64,
+    settled: u64,
+
+    // Whether the reporting future has completed or been dropped.
+    closed: bool,
+}
+
+/// Marks the reporting future as closed when it is dropped, releasing the waiting flushes.
+struct Closing(Arc<Shared>);
+impl Drop for Closing {
+    fn drop(&mut self) {
+        self.0.lock().closed = true;
+        self.0.settled.notify_all();
+    }
+}
@@ -0,0 +1,221 @@
+use super::SpanReporter;
+use crate::span::FinishedSpan;
+use crate::{Error, ErrorKind, Result};
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// `SpanReporter` accumulating finished spans and exporting them in batches.
+///
+/// A batch is exported when it reaches `max_batch_size` spans
+/// or when `max_delay` has elapsed since its first span was reported.
+/// Batches are exported by a background thread, so slow exporters (e.g., ones sending batches
+/// over the network) never block the threads finishing spans.
+///
+/// `SpanReporter::flush` exports the buffered spans immediately and waits until they are exported.
+/// When the processor is dropped, the remaining spans are exported
+/// and the background thread is joined.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::reporter::BatchProcessor;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+/// use std::sync::mpsc;
+/// use std::time::Duration;
+///
+/// let (batch_tx, batch_rx) = mpsc::channel();
+/// let processor = BatchProcessor::new(2, Duration::from_secs(60), move |batch: Vec<_>| {
+///     let _ = batch_tx.send(batch.len());
+/// })
+/// .unwrap();
+/// let tracer = Tracer::with_reporter(AllSampler, processor);
+/// for _ in 0..3 {
+///     tracer.span("foo").start_with_state(());
+/// }
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(batch_rx.recv().unwrap(), 2);
+///
+/// drop(tracer); // The remaining span is exported.
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(batch_rx.recv().unwrap(), 1);
+/// ```
+pub struct BatchProcessor<T> {
+    shared: Arc<Shared<T>>,
+    worker: Option<JoinHandle<()>>,
+}
+impl<T: Send + 'static> BatchProcessor<T> {
+    /// Makes a new `BatchProcessor` instance which passes batches to `export`.
+    ///
+    /// # Errors
+    ///
+    /// If `max_batch_size` is `0`, it will return an error with the kind `ErrorKind::InvalidInput`.
+    ///
+    /// If the background thread cannot be spawned,
+    /// it will return an error with the kind `ErrorKind::Other`.
+    pub fn new<F>(max_batch_size: usize, max_delay: Duration, mut export: F) -> Result<Self>
+    where
+        F: FnMut(Vec<FinishedSpan<T>>) + Send + 'static,
+    {
+        track_assert!(max_batch_size > 0, ErrorKind::InvalidInput);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(BatchState {
+                spans: Vec::new(),
+                started_at: Instant::now(),
+                flush_requests: 0,
+                flushed: 0,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            flushed: Condvar::new(),
+            max_batch_size,
+            max_delay,
+        });
+        let worker = thread::Builder::new()
+            .name("batch-processor".to_owned())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                move || {
+                    while let Some((batch, flush)) = shared.next_batch() {
+                        export(batch);
+                        if let Some(flush) = flush {
+                            shared.complete_flush(flush);
+                        }
+                    }
+                }
+            })
+            .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))?;
+        Ok(BatchProcessor {
+            shared,
+            worker: Some(worker),
+        })
+    }
+}
+impl<T> BatchProcessor<T> {
+    /// Returns the number of the spans waiting to be exported.
+    pub fn buffered_spans(&self) -> usize {
+        self.shared.lock().spans.len()
+    }
+}
+impl<T> SpanReporter<T> for BatchProcessor<T> {
+    fn report(&self, span: FinishedSpan<T>) {
+        let mut state = self.shared.lock();
+        if state.spans.is_empty() {
+            state.started_at = Instant::now();
+            state.spans.reserve(self.shared.max_batch_size);
+        }
+        state.spans.push(span);
+        let len = state.spans.len();
+        drop(state);
+
+        // The worker needs to be woken up to start the delay of a new batch or to export a full one.
+        if len == 1 || len >= self.shared.max_batch_size {
+            self.shared.changed.notify_one();
+        }
+    }
+
+    fn flush(&self) {
+        let mut state = self.shared.lock();
+        state.flush_requests += 1;
+        let request = state.flush_requests;
+        self.shared.changed.notify_one();
+        while state.flushed < request && !state.closed {
+            state = self
+                .shared
+                .flushed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+impl<T> Drop for BatchProcessor<T> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_one();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+impl<T> fmt::Debug for BatchProcessor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BatchProcessor")
+            .field("max_batch_size", &self.shared.max_batch_size)
+            .field("max_delay", &self.shared.max_delay)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<BatchState<T>>,
+    changed: Condvar,
+    flushed: Condvar,
+    max_batch_size: usize,
+    max_delay: Duration,
+}
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, BatchState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for the next batch to be exported.
+    ///
+    /// If the batch completes a flush, the number of the flush request is also returned.
+    /// `None` means that the processor has been dropped and all the spans have been exported.
+    fn next_batch(&self) -> Option<(Vec<FinishedSpan<T>>, Option<u64>)> {
+        let mut state = self.lock();
+        loop {
+            if state.spans.len() >= self.max_batch_size {
+                let rest = state.spans.split_off(self.max_batch_size);
+                state.started_at = Instant::now();
+                return Some((mem::replace(&mut state.spans, rest), None));
+            }
+            let flush_requested = state.flushed < state.flush_requests;
+            if state.spans.is_empty() {
+                if flush_requested {
+                    // All the spans reported before the request have been exported.
+                    let request = state.flush_requests;
+                    drop(state);
+                    self.complete_flush(request);
+                    state = self.lock();
+                    continue;
+                }
+                if state.closed {
+                    return None;
+                }
+                state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+
+            let elapsed = state.started_at.elapsed();
+            if flush_requested {
+                return Some((mem::take(&mut state.spans), Some(state.flush_requests)));
+            }
+            if state.closed || elapsed >= self.max_delay {
+                return Some((mem::take(&mut state.spans), None));
+            }
+            state = self
+                .changed
+                .wait_timeout(state, self.max_delay - elapsed)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    fn complete_flush(&self, request: u64) {
+        let mut state = self.lock();
+        state.flushed = state.flushed.max(request);
+        self.flushed.notify_all();
+    }
+}
+
+struct BatchState<T> {
+    spans: Vec<FinishedSpan<T>>,
+    started_at: Instant,
+    flush_requests: u64,
+    flushed: u64,
+    closed: bool,
+}
@@ -0,0 +1,334 @@
+use super::SpanReporter;
+use crate::span::{FinishedSpan, SpanStatus};
+use crate::tag::TagValue;
+use crate::trace::{Trace, TraceAssembler, TraceIdentity};
+use crate::{Error, ErrorKind, Result};
+use std::borrow::Cow;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// `SpanReporter` printing finished spans as indented trace trees, for local development.
+///
+/// Spans are grouped by trace with a `TraceAssembler`:
+/// a trace is printed as soon as it is complete,
+/// or once no span of it has been reported for `ConsoleOptions::delay`.
+/// Each span is printed with its duration, its key tags and an `ERROR` marker if it failed.
+///
+/// The remaining traces are printed by `SpanReporter::flush` and when the reporter is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::reporter::{ConsoleOptions, ConsoleReporter};
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+///
+/// let reporter = ConsoleReporter::new(ConsoleOptions::new().colors(true)).unwrap();
+/// let tracer = Tracer::<_, DefaultContext>::with_reporter(AllSampler, reporter);
+/// {
+///     let root = tracer.span("GET /users").start();
+///     let _query = tracer.span("SELECT users").child_of(&root).start();
+/// }
+/// // Prints the following to stderr:
+/// //
+/// // trace 14576827793038113322513871894673895836
+/// //   GET /users 1.2ms
+/// //     SELECT users 1.1ms
+/// ```
+pub struct ConsoleReporter<T: TraceIdentity> {
+    shared: Arc<Shared<T>>,
+    worker: Option<JoinHandle<()>>,
+}
+impl<T> ConsoleReporter<T>
+where
+    T: TraceIdentity + Send + 'static,
+    T::TraceId: Send,
+{
+    /// Makes a new `ConsoleReporter` instance.
+    ///
+    /// # Errors
+    ///
+    /// If the background thread printing expired traces cannot be spawned,
+    /// it will return an error with the kind `ErrorKind::Other`.
+    pub fn new(options: ConsoleOptions) -> Result<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(ConsoleState {
+                assembler: TraceAssembler::new(options.delay),
+                writer: options.writer,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            delay: options.delay,
+            colors: options.colors,
+            key_tags: options.key_tags,
+        });
+        let worker = thread::Builder::new()
+            .name("console-reporter".to_owned())
+            .spawn({
+                let shared = Arc::clone(&shared);
+                move || shared.print_expired_traces()
+            })
+            .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))?;
+        Ok(ConsoleReporter {
+            shared,
+            worker: Some(worker),
+        })
+    }
+}
+impl<T: TraceIdentity> SpanReporter<T> for ConsoleReporter<T> {
+    fn report(&self, span: FinishedSpan<T>) {
+        let mut state = self.shared.lock();
+        if let Some(trace) = state.assembler.push(span) {
+            self.shared.print(&mut state, &[trace]);
+        }
+    }
+
+    fn flush(&self) {
+        let mut state = self.shared.lock();
+        let traces = state.assembler.flush();
+        self.shared.print(&mut state, &traces);
+    }
+}
+impl<T: TraceIdentity> Drop for ConsoleReporter<T> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_one();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+impl<T: TraceIdentity> fmt::Debug for ConsoleReporter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConsoleReporter")
+            .field("delay", &self.shared.delay)
+            .field("colors", &self.shared.colors)
+            .field("key_tags", &self.shared.key_tags)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Options of `ConsoleReporter`.
+pub struct ConsoleOptions {
+    delay: Duration,
+    colors: bool,
+    key_tags: Vec<Cow<'static, str>>,
+    writer: Box<dyn Write + Send>,
+}
+impl ConsoleOptions {
+    /// Makes a new `ConsoleOptions` instance with the default values.
+    pub fn new() -> Self {
+        ConsoleOptions {
+            delay: Duration::from_secs(1),
+            colors: false,
+            key_tags: [
+                "http.method",
+                "http.route",
+                "http.status_code",
+                "db.system",
+                "db.operation",
+                "rpc.service",
+                "rpc.method",
+                "messaging.system",
+                "peer.service",
+            ]
+            .into_iter()
+            .map(Cow::Borrowed)
+            .collect(),
+            writer: Box::new(io::stderr()),
+        }
+    }
+
+    /// Sets how long incomplete traces wait for their remaining spans before being printed.
+    ///
+    /// The default value is one second.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Makes the output colored with ANSI escape sequences.
+    ///
+    /// The default value is `false`.
+    pub fn colors(mut self, enabled: bool) -> Self {
+        self.colors = enabled;
+        self
+    }
+
+    /// Sets the names of the tags printed next to spans.
+    ///
+    /// The default names are the ones of the standard tags identifying HTTP, database, RPC
+    /// and messaging operations (e.g., `http.method` and `db.system`).
+    pub fn key_tags<I, N>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: Into<Cow<'static, str>>,
+    {
+        self.key_tags = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the destination of the output.
+    ///
+    /// The default value is the standard error.
+    pub fn writer<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.writer = Box::new(writer);
+        self
+    }
+}
+impl Default for ConsoleOptions {
+    fn default() -> Self {
+        ConsoleOptions::new()
+    }
+}
+impl fmt::Debug for ConsoleOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConsoleOptions")
+            .field("delay", &self.delay)
+            .field("colors", &self.colors)
+            .field("key_tags", &self.key_tags)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Shared<T: TraceIdentity> {
+    state: Mutex<ConsoleState<T>>,
+    changed: Condvar,
+    delay: Duration,
+    colors: bool,
+    key_tags: Vec<Cow<'static, str>>,
+}
+impl<T: TraceIdentity> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, ConsoleState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Prints the expired traces periodically until the reporter is dropped.
+    fn print_expired_traces(&self) {
+        let mut state = self.lock();
+        loop {
+            let closed = state.closed;
+            let traces = if closed {
+                state.assembler.flush()
+            } else {
+                state.assembler.expired()
+            };
+            self.print(&mut state, &traces);
+            if closed {
+                return;
+            }
+            state = self
+                .changed
+                .wait_timeout(state, self.delay)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    fn print(&self, state: &mut ConsoleState<T>, traces: &[Trace<T>]) {
+        if traces.is_empty() {
+            return;
+        }
+        let mut out = String::new();
+        for trace in traces {
+            let _ = self.write_trace(&mut out, trace);
+        }
+        let _ = state.writer.write_all(out.as_bytes());
+        let _ = state.writer.flush();
+    }
+
+    fn write_trace(&self, out: &mut String, trace: &Trace<T>) -> fmt::Result {
+        write!(out, "trace {:?}", trace.trace_id())?;
+        if !trace.is_complete() {
+            out.push_str(" (incomplete)");
+        }
+        out.push('\n');
+        let mut roots = trace.roots().collect::<Vec<_>>();
+        roots.sort_by_key(|&i| trace.spans()[i].start_time());
+        for root in roots {
+            self.write_span(out, trace, root, 1)?;
+        }
+        Ok(())
+    }
+
+    fn write_span(
+        &self,
+        out: &mut String,
+        trace: &Trace<T>,
+        i: usize,
+        depth: usize,
+    ) -> fmt::Result {
+        let span = &trace.spans()[i];
+        let (bold, dim, red, reset) = if self.colors {
+            ("\x1b[1m", "\x1b[2m", "\x1b[31m", "\x1b[0m")
+        } else {
+            ("", "", "", "")
+        };
+        write!(
+            out,
+            "{:indent$}{}{}{} {}{:?}{}",
+            "",
+            bold,
+            span.operation_name(),
+            reset,
+            dim,
+            span.duration(),
+            reset,
+            indent = depth * 2
+        )?;
+        let error_tag = span
+            .all_tags()
+            .any(|t| t.name() == "error" && *t.value() == TagValue::Boolean(true));
+        match span.status() {
+            SpanStatus::Error { message } if !message.is_empty() => {
+                write!(out, " {}ERROR{} {:?}", red, reset, message)?;
+            }
+            SpanStatus::Error { .. } => write!(out, " {}ERROR{}", red, reset)?,
+            _ if error_tag => write!(out, " {}ERROR{}", red, reset)?,
+            _ => {}
+        }
+        for name in &self.key_tags {
+            if let Some(tag) = span.all_tags().find(|t| t.name() == name) {
+                write!(
+                    out,
+                    " {}{}={}{}",
+                    dim,
+                    name,
+                    DisplayValue(tag.value()),
+                    reset
+                )?;
+            }
+        }
+        out.push('\n');
+
+        let mut children = trace.children(i).to_vec();
+        children.sort_by_key(|&i| trace.spans()[i].start_time());
+        for child in children {
+            self.write_span(out, trace, child, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+struct ConsoleState<T: TraceIdentity> {
+    assembler: TraceAssembler<T>,
+    writer: Box<dyn Write + Send>,
+    closed: bool,
+}
+
+struct DisplayValue<'a>(&'a TagValue);
+impl fmt::Display for DisplayValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            TagValue::String(v) => f.write_str(v),
+            TagValue::Boolean(v) => write!(f, "{}", v),
+            TagValue::Integer(v) => write!(f, "{}", v),
+            TagValue::Float(v) => write!(f, "{}", v),
+            v => write!(f, "{:?}", v),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use super::SpanReporter;
+use crate::convert::json::{JsonStr, JsonValue};
+use crate::span::{FinishedSpan, SpanStatus};
+use crate::{Error, ErrorKind, Result};
+use std::fmt::{self, Display, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
+
+/// `SpanReporter` appending each finished span to a file as a line of JSON.
+///
+/// This is handy for debugging in environments where no collector is reachable.
+/// The states of span contexts are rendered with `Display`
+/// (e.g., as `traceparent` values for `DefaultContext`).
+///
+/// When the file would exceed `max_file_size` bytes, it is rotated:
+/// `{path}` is renamed to `{path}.1`, `{path}.1` to `{path}.2` and so on,
+/// keeping at most `max_rotated_files` old files.
+///
+/// Since spans are written on the threads finishing them, this is not intended for hot paths.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::reporter::JsonFileReporter;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+///
+/// let path = std::env::temp_dir().join(format!("spans-{}.jsonl", std::process::id()));
+/// let reporter = JsonFileReporter::new(&path).unwrap().max_file_size(10 * 1024 * 1024);
+/// let tracer = Tracer::with_reporter(AllSampler, reporter);
+/// tracer.span("foo").start_with_state(1);
+///
+/// # #[cfg(not(rustracing_noop))]
+/// assert!(std::fs::read_to_string(&path).unwrap().starts_with(r#"{"name":"foo","context":"1""#));
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub struct JsonFileReporter {
+    path: PathBuf,
+    max_file_size: u64,
+    max_rotated_files: usize,
+    file: Mutex<Output>,
+    failed_writes: AtomicU64,
+}
+impl JsonFileReporter {
+    /// Makes a new `JsonFileReporter` instance appending spans to the file at `path`.
+    ///
+    /// The file is created if it does not exist.
+    /// By default, files are rotated at 64 MiB and one rotated file is kept.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be opened, it will return an error with the kind `ErrorKind::Other`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = track!(open(&path))?;
+        let size = file.metadata().map_or(0, |m| m.len());
+        Ok(JsonFileReporter {
+            path,
+            max_file_size: 64 * 1024 * 1024,
+            max_rotated_files: 1,
+            file: Mutex::new(Output { file, size }),
+            failed_writes: AtomicU64::new(0),
+        })
+    }
+
+    /// Sets the size in bytes at which the file is rotated.
+    pub fn max_file_size(mut self, size: u64) -> Self {
+        self.max_file_size = size;
+        self
+    }
+
+    /// Sets the number of the rotated files to keep.
+    ///
+    /// If it is `0`, the file is truncated instead of being rotated.
+    pub fn max_rotated_files(mut self, n: usize) -> Self {
+        self.max_rotated_files = n;
+        self
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of the spans which could not be written.
+    pub fn failed_writes(&self) -> u64 {
+        self.failed_writes.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Output> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn rotate(&self, output: &mut Output) -> Result<()> {
+        let rotated = |i: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", i));
+            PathBuf::from(path)
+        };
+        for i in (1..self.max_rotated_files).rev() {
+            let from = rotated(i);
+            if from.exists() {
+                fs::rename(&from, rotated(i + 1))
+                    .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))?;
+            }
+        }
+        if self.max_rotated_files > 0 {
+            fs::rename(&self.path, rotated(1))
+                .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))?;
+        } else {
+            fs::remove_file(&self.path)
+                .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))?;
+        }
+        output.file = track!(open(&self.path))?;
+        output.size = 0;
+        Ok(())
+    }
+}
+impl<T: Display> SpanReporter<T> for JsonFileReporter {
+    fn report(&self, span: FinishedSpan<T>) {
+        let mut line = String::new();
+        let _ = write_span(&mut line, &span);
+        line.push('\n');
+
+        let mut output = self.lock();
+        if output.size > 0 && output.size + line.len() as u64 > self.max_file_size {
+            // If the rotation fails, the span is appended to the current file.
+            if self.rotate(&mut output).is_err() {
+                self.failed_writes.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if output.file.write_all(line.as_bytes()).is_ok() {
+            output.size += line.len() as u64;
+        } else {
+            self.failed_writes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.lock().file.flush();
+    }
+}
+impl fmt::Debug for JsonFileReporter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JsonFileReporter")
+            .field("path", &self.path)
+            .field("max_file_size", &self.max_file_size)
+            .field("max_rotated_files", &self.max_rotated_files)
+            .field("failed_writes", &self.failed_writes())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Output {
+    file: File,
+    size: u64,
+}
+
+fn open(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| track!(Error::with_source(ErrorKind::Other, e)))
+}
+
+fn write_span<T: Display>(out: &mut String, span: &FinishedSpan<T>) -> fmt::Result {
+    write!(
+        out,
+        r#"{{"name":{},"context":{}"#,
+        JsonStr(span.operation_name()),
+        JsonStr(&span.context().state().to_string())
+    )?;
+    if !span.references().is_empty() {
+        out.push_str(r#","references":["#);
+        for (i, reference) in span.references().iter().enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            let kind = if reference.is_child_of() {
+                "child_of"
+            } else {
+                "follows_from"
+            };
+            write!(
+                out,
+                r#"{}{{"type":"{}","context":{}}}"#,
+                sep,
+                kind,
+                JsonStr(&reference.span().to_string())
+            )?;
+        }
+        out.push(']');
+    }
+    write!(
+        out,
+        r#","start_time_unix_nano":{},"end_time_unix_nano":{}"#,
+        span.start_unix_time().as_nanos(),
+        span.finish_unix_time().as_nanos()
+    )?;
+    match span.status() {
+        SpanStatus::Unset => {}
+        SpanStatus::Ok => out.push_str(r#","status":"ok""#),
+        SpanStatus::Error { message } => write!(
+            out,
+            r#","status":"error","status_message":{}"#,
+            JsonStr(message)
+        )?,
+    }
+
+    let mut tags = span.all_tags().peekable();
+    if tags.peek().is_some() {
+        out.push_str(r#","tags":{"#);
+        for (i, tag) in tags.enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(
+                out,
+                "{}{}:{}",
+                sep,
+                JsonStr(tag.name()),
+                JsonValue(tag.value())
+            )?;
+        }
+        out.push('}');
+    }
+    if !span.logs().is_empty() {
+        out.push_str(r#","logs":["#);
+        for (i, log) in span.logs().iter().enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            let time = log.time().duration_since(UNIX_EPOCH).unwrap_or_default();
+            write!(
+                out,
+                r#"{}{{"time_unix_nano":{},"fields":{{"#,
+                sep,
+                time.as_nanos()
+            )?;
+            for (i, field) in log.fields().iter().enumerate() {
+                let sep = if i > 0 { "," } else { "" };
+                write!(
+                    out,
+                    "{}{}:{}",
+                    sep,
+                    JsonStr(field.name()),
+                    JsonValue(field.value())
+                )?;
+            }
+            out.push_str("}}");
+        }
+        out.push(']');
+    }
+    let items = span.context().baggage_items();
+    if !items.is_empty() {
+        out.push_str(r#","baggage":{"#);
+        for (i, item) in items.iter().enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(
+                out,
+                "{}{}:{}",
+                sep,
+                JsonStr(item.name()),
+                JsonStr(item.value())
+            )?;
+        }
+        out.push('}');
+    }
+    out.push('}');
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! Bounded lock-free MPSC ring buffer.
+//!
+//! This is an implementation of [Dmitry Vyukov's bounded MPMC queue]:
+//! pushing and popping an item are a single successful CAS each,
+//! and producers never touch wakers.
+//!
+//! [Dmitry Vyukov's bounded MPMC queue]: https://www.1024cores.net/home/lock-free-algorithms/queues/bounded-mpmc-queue
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Makes a new ring which can hold at least `capacity` items.
+pub(crate) fn ring<T>(capacity: usize) -> (RingTx<T>, RingRx<T>) {
+    let ring = Arc::new(Ring::new(capacity));
+    (RingTx(Arc::clone(&ring)), RingRx(ring))
+}
+
+/// Producer side of a ring.
+///
+/// When this is dropped, the ring is closed.
+#[derive(Debug)]
+pub(crate) struct RingTx<T>(Arc<Ring<T>>);
+impl<T> RingTx<T> {
+    /// Pushes `item` to the ring.
+    ///
+    /// If the ring is full or the consumer has been dropped, `item` is returned.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        if Arc::strong_count(&self.0) == 1 {
+            return Err(item);
+        }
+        self.0.push(item)
+    }
+}
+impl<T> Drop for RingTx<T> {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Consumer side of a ring.
+#[derive(Debug)]
+pub(crate) struct RingRx<T>(Arc<Ring<T>>);
+impl<T> RingRx<T> {
+    /// Pops the oldest item from the ring.
+    ///
+    /// `Err(true)` means that the ring is empty and has been closed.
+    pub fn pop(&mut self) -> Result<T, bool> {
+        if let Some(item) = self.0.pop() {
+            return Ok(item);
+        }
+        if !self.0.closed.load(Ordering::Acquire) {
+            return Err(false);
+        }
+        // Items pushed before the ring was closed.
+        self.0.pop().ok_or(true)
+    }
+}
+
+struct Slot<T> {
+    sequence: AtomicUsize,
+    item: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Ring<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    enqueue_pos: AtomicUsize,
+    dequeue_pos: AtomicUsize,
+    closed: AtomicBool,
+}
+
+// SAFETY: An item is accessed only by the thread that has claimed its slot
+// by advancing `enqueue_pos` or `dequeue_pos`, and the `sequence` of the slot
+// publishes the item to the other side.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                item: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        Ring {
+            slots,
+            mask: capacity - 1,
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, item: T) -> Result<(), T> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence.wrapping_sub(pos) as isize;
+            if diff == 0 {
+                match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The slot has been claimed by this thread.
+                        unsafe { (*slot.item.get()).write(item) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return Err(item);
+            } else {
+                pos = self.enqueue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.dequeue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence.wrapping_sub(pos.wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.dequeue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The slot has been claimed by this thread and
+                        // its item has been published by the producer.
+                        let item = unsafe { (*slot.item.get()).assume_init_read() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+                        return Some(item);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                pos = self.dequeue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+impl<T> fmt::Debug for Ring<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Ring")
+            .field("capacity", &self.slots.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn ring_works() {
+        let (tx, mut rx) = ring(3);
+        for i in 0..4 {
+            assert!(tx.push(i).is_ok());
+        }
+        assert_eq!(tx.push(4), Err(4));
+
+        assert_eq!(rx.pop(), Ok(0));
+        assert!(tx.push(4).is_ok());
+        drop(tx);
+        assert_eq!((1..5).map(|_| rx.pop().unwrap()).sum::<i32>(), 10);
+        assert_eq!(rx.pop(), Err(true));
+    }
+
+    #[test]
+    fn concurrent_pushes_are_received() {
+        let (tx, mut rx) = ring(1024);
+        let tx = Arc::new(tx);
+        let threads = (0..4)
+            .map(|t| {
+                let tx = Arc::clone(&tx);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        tx.push(t * 100 + i).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            t.join().unwrap();
+        }
+        drop(tx);
+
+        let mut items = Vec::new();
+        while let Ok(item) = rx.pop() {
+            items.push(item);
+        }
+        items.sort_unstable();
+        assert_eq!(items, (0..400).collect::<Vec<_>>());
+        assert_eq!(rx.pop(), Err(true));
+    }
+}
@@ -1,13 +1,30 @@
 //! `Sampler` trait and its built-in implementations.
-use crate::span::CandidateSpan;
+use crate::span::{CandidateSpan, FinishedSpan};
+use crate::tag::{Tag, TagValue};
+use crate::trace::TraceIdentity;
 use crate::{ErrorKind, Result};
 use rand::{self, Rng};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 /// `Sampler` decides whether a new trace should be sampled or not.
 pub trait Sampler<T> {
     /// This method decides whether a trace with given `span` should be sampled.
     fn is_sampled(&self, span: &CandidateSpan<T>) -> bool;
 
+    /// Decides whether a trace with given `span` should be sampled,
+    /// returning the tags describing the decision.
+    ///
+    /// The tracer adds the tags to sampled spans unless they already have tags with the same names.
+    /// The default implementation calls `is_sampled` and returns no tags.
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        SamplingDecision::new(self.is_sampled(span))
+    }
+
     /// Returns the sampler that samples a trace if `self` or `other` decides to sample it.
     fn or<U>(self, other: U) -> OrSampler<Self, U>
     where
@@ -26,6 +43,14 @@ pub trait Sampler<T> {
         AndSampler(self, other)
     }
 
+    /// Returns the sampler that samples a trace if `self` decides not to sample it.
+    fn not(self) -> NotSampler<Self>
+    where
+        Self: Sized,
+    {
+        NotSampler(self)
+    }
+
     /// Converts into `BoxSampler`.
     fn boxed(self) -> BoxSampler<T>
     where
@@ -38,6 +63,9 @@ impl<T> Sampler<T> for BoxSampler<T> {
     fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
         (**self).is_sampled(span)
     }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        (**self).sample(span)
+    }
     fn boxed(self) -> BoxSampler<T>
     where
         Self: Sized + Send + 'static,
@@ -49,6 +77,118 @@ impl<T> Sampler<T> for BoxSampler<T> {
 /// Boxed version of `Sampler`.
 pub type BoxSampler<T> = Box<dyn Sampler<T> + Send + Sync + 'static>;
 
+/// `FinishSampler` decides whether a speculatively recorded span should be kept when it finishes.
+///
+/// If a tracer has a finish sampler (see `TracerBuilder::finish_sampler`),
+/// the spans rejected by its `Sampler` are recorded anyway,
+/// and the finish sampler decides whether to send them with their final tags and durations.
+///
+/// This is implemented for closures taking `&FinishedSpan<T>`.
+pub trait FinishSampler<T> {
+    /// This method decides whether the speculatively recorded `span` should be kept.
+    fn is_sampled(&self, span: &FinishedSpan<T>) -> bool;
+}
+impl<T, F> FinishSampler<T> for F
+where
+    F: Fn(&FinishedSpan<T>) -> bool,
+{
+    fn is_sampled(&self, span: &FinishedSpan<T>) -> bool {
+        self(span)
+    }
+}
+
+/// This keeps the finished spans tagged with `error=true` (see `StdTag::error`).
+#[derive(Debug, Clone)]
+pub struct ErrorSampler;
+impl<T> FinishSampler<T> for ErrorSampler {
+    fn is_sampled(&self, span: &FinishedSpan<T>) -> bool {
+        span.all_tags()
+            .any(|t| t.name() == "error" && *t.value() == TagValue::Boolean(true))
+    }
+}
+
+/// Finish sampler shared by a tracer and its spans.
+pub(crate) struct SharedFinishSampler<T>(pub Arc<dyn FinishSampler<T> + Send + Sync>);
+impl<T> Clone for SharedFinishSampler<T> {
+    fn clone(&self) -> Self {
+        SharedFinishSampler(Arc::clone(&self.0))
+    }
+}
+impl<T> fmt::Debug for SharedFinishSampler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedFinishSampler")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sampling decision with the tags describing it.
+///
+/// Built-in samplers describe their decisions with the `sampler.type` and `sampler.param` tags,
+/// which backends use to extrapolate metrics from sampled traces.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::ProbabilisticSampler;
+/// use cf_rustracing::tag::TagValue;
+/// use cf_rustracing::Tracer;
+///
+/// let (tracer, mut span_rx) = Tracer::new(ProbabilisticSampler::new(1.0).unwrap());
+/// tracer.span("foo").start_with_state(());
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let span = span_rx.try_recv().unwrap();
+/// let tag = span.tags().iter().find(|t| t.name() == "sampler.type").unwrap();
+/// assert_eq!(tag.value(), &TagValue::from("probabilistic"));
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SamplingDecision {
+    sampled: bool,
+    tags: Vec<Tag>,
+}
+impl SamplingDecision {
+    /// Makes a new `SamplingDecision` instance without tags.
+    pub fn new(sampled: bool) -> Self {
+        SamplingDecision {
+            sampled,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Adds the tag to this decision.
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    /// Returns `true` if the trace is sampled.
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// Returns the tags describing this decision.
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    pub(crate) fn into_tags(self) -> Vec<Tag> {
+        self.tags
+    }
+
+    /// Makes a decision tagged with `sampler.type` and `sampler.param` if sampled.
+    fn described(sampled: bool, sampler_type: &'static str, param: f64) -> Self {
+        let decision = SamplingDecision::new(sampled);
+        if sampled {
+            decision
+                .tag(Tag::new("sampler.type", sampler_type))
+                .tag(Tag::new("sampler.param", param))
+        } else {
+            decision
+        }
+    }
+}
+
 /// This samples a certain percentage of traces.
 #[derive(Debug, Clone)]
 pub struct ProbabilisticSampler {
@@ -71,6 +211,596 @@ impl<T> Sampler<T> for ProbabilisticSampler {
     fn is_sampled(&self, _span: &CandidateSpan<T>) -> bool {
         rand::thread_rng().gen_range(0.0..1.0) < self.sampling_rate
     }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        let sampled = self.is_sampled(span);
+        SamplingDecision::described(sampled, "probabilistic", self.sampling_rate)
+    }
+}
+
+/// This samples a certain percentage of traces, deciding from a key of each trace.
+///
+/// The decision is made by hashing the key returned by the key extractor with a fixed hash function,
+/// so every service (and every span) given the same key makes the same decision.
+/// If the key extractor returns `None`, a random decision is made as `ProbabilisticSampler` does.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::context::DefaultContext;
+/// use cf_rustracing::sampler::DeterministicSampler;
+/// use cf_rustracing::span::SpanContext;
+/// use cf_rustracing::Tracer;
+///
+/// let sampler = DeterministicSampler::by_trace_id(0.5).unwrap();
+/// let (tracer, _span_rx) = Tracer::<_, DefaultContext>::new(sampler);
+/// let parent = DefaultContext::new(0xabc, 0x12, DefaultContext::SAMPLED);
+/// let parent = SpanContext::new(parent, Vec::new());
+///
+/// let a = tracer.span("a").child_of(&parent).start().is_sampled();
+/// let b = tracer.span("b").child_of(&parent).start().is_sampled();
+/// assert_eq!(a, b);
+/// ```
+pub struct DeterministicSampler<F> {
+    sampling_rate: f64,
+    key: F,
+}
+impl<F> DeterministicSampler<F> {
+    /// Makes a new `DeterministicSampler` instance which samples traces keyed by `key`.
+    ///
+    /// # Errors
+    ///
+    /// If `sampling_rate` is not in the range `0.0...1.0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(sampling_rate: f64, key: F) -> Result<Self> {
+        track_assert!(0.0 <= sampling_rate, ErrorKind::InvalidInput);
+        track_assert!(sampling_rate <= 1.0, ErrorKind::InvalidInput);
+        Ok(DeterministicSampler { sampling_rate, key })
+    }
+}
+impl<T: TraceIdentity> DeterministicSampler<fn(&CandidateSpan<T>) -> Option<T::TraceId>> {
+    /// Makes a new `DeterministicSampler` instance which samples traces keyed by their identifiers.
+    ///
+    /// The key is taken from the first reference of a span,
+    /// so root spans are sampled randomly.
+    ///
+    /// # Errors
+    ///
+    /// If `sampling_rate` is not in the range `0.0...1.0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn by_trace_id(sampling_rate: f64) -> Result<Self> {
+        track!(Self::new(sampling_rate, referenced_trace_id))
+    }
+}
+impl<F> fmt::Debug for DeterministicSampler<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeterministicSampler")
+            .field("sampling_rate", &self.sampling_rate)
+            .finish_non_exhaustive()
+    }
+}
+impl<T, F, K> Sampler<T> for DeterministicSampler<F>
+where
+    F: Fn(&CandidateSpan<T>) -> Option<K>,
+    K: Hash,
+{
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        let Some(key) = (self.key)(span) else {
+            return rand::thread_rng().gen_range(0.0..1.0) < self.sampling_rate;
+        };
+        let mut hasher = Fnv1aHasher::default();
+        key.hash(&mut hasher);
+        let x = (mix64(hasher.finish()) >> 11) as f64 / (1u64 << 53) as f64;
+        x < self.sampling_rate
+    }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        let sampled = self.is_sampled(span);
+        SamplingDecision::described(sampled, "probabilistic", self.sampling_rate)
+    }
+}
+
+fn referenced_trace_id<T: TraceIdentity>(span: &CandidateSpan<T>) -> Option<T::TraceId> {
+    span.references().first().map(|r| r.span().trace_id())
+}
+
+/// 64-bit FNV-1a, which gives the same hashes in every process unlike `DefaultHasher`.
+struct Fnv1aHasher(u64);
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Fnv1aHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Spreads the bits of `x` uniformly (the finalizer of SplitMix64).
+fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// This samples traces at a rate of at most a certain number of traces per second.
+///
+/// Only children of spans started in this process (see `CandidateSpan::has_local_parent`)
+/// are exempt from the rate, because they continue traces which are already sampled.
+/// The other spans, including the ones continuing remote traces or only following from other spans,
+/// are counted against the rate.
+/// Combine this with other samplers (e.g., `ProbabilisticSampler::or`)
+/// to keep a bounded number of traces of low-traffic operations.
+///
+/// The rate is enforced by a token bucket whose state is a single atomic variable,
+/// so concurrent sampling decisions never block each other.
+#[derive(Debug)]
+pub struct RateLimitingSampler {
+    interval_nanos: u64,
+    tolerance_nanos: u64,
+    base: Instant,
+
+    // The "theoretical arrival time" of the next trace in nanoseconds since `base`
+    // (see the Generic Cell Rate Algorithm).
+    next_nanos: AtomicU64,
+}
+impl RateLimitingSampler {
+    /// Makes a new `RateLimitingSampler` instance.
+    ///
+    /// The sampler allows bursts of up to `max(1, max_traces_per_second)` traces.
+    ///
+    /// # Errors
+    ///
+    /// If `max_traces_per_second` is not a positive number,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(max_traces_per_second: f64) -> Result<Self> {
+        track!(Self::with_burst(
+            max_traces_per_second,
+            max_traces_per_second.max(1.0)
+        ))
+    }
+
+    /// Makes a new `RateLimitingSampler` instance which allows bursts of up to `max_burst` traces.
+    ///
+    /// # Errors
+    ///
+    /// If `max_traces_per_second` is not a positive number or `max_burst` is less than `1.0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn with_burst(max_traces_per_second: f64, max_burst: f64) -> Result<Self> {
+        track_assert!(0.0 < max_traces_per_second, ErrorKind::InvalidInput);
+        track_assert!(1.0 <= max_burst, ErrorKind::InvalidInput);
+        let interval = 1_000_000_000.0 / max_traces_per_second;
+        Ok(RateLimitingSampler {
+            interval_nanos: interval as u64,
+            tolerance_nanos: ((max_burst - 1.0) * interval) as u64,
+            base: Instant::now(),
+            next_nanos: AtomicU64::new(0),
+        })
+    }
+
+    fn try_acquire(&self) -> bool {
+        let now = nanos_since(self.base);
+        let mut current = self.next_nanos.load(Ordering::Relaxed);
+        loop {
+            let next = current.max(now);
+            if next - now > self.tolerance_nanos {
+                return false;
+            }
+            match self.next_nanos.compare_exchange_weak(
+                current,
+                // The interval saturates to `u64::MAX` for tiny rates.
+                next.saturating_add(self.interval_nanos),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+impl<T> Sampler<T> for RateLimitingSampler {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        span.has_local_parent() || self.try_acquire()
+    }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        if span.has_local_parent() {
+            return SamplingDecision::new(true);
+        }
+        let max_traces_per_second = 1_000_000_000.0 / self.interval_nanos as f64;
+        SamplingDecision::described(self.try_acquire(), "ratelimiting", max_traces_per_second)
+    }
+}
+
+/// This samples traces probabilistically,
+/// adjusting the sampling rate so that about a certain number of traces per second are sampled.
+///
+/// The sampling rate is recalculated once per second from the number of the observed candidates.
+/// All the state is kept in atomic variables, so concurrent sampling decisions never block each other.
+#[derive(Debug)]
+pub struct AdaptiveSampler {
+    target_traces_per_second: f64,
+    min_sampling_rate: f64,
+    base: Instant,
+    window_start_nanos: AtomicU64,
+    window_candidates: AtomicU64,
+    sampling_rate: AtomicF64,
+}
+impl AdaptiveSampler {
+    /// Makes a new `AdaptiveSampler` instance.
+    ///
+    /// The sampling rate starts from `1.0` and never goes below `0.0001`.
+    ///
+    /// # Errors
+    ///
+    /// If `target_traces_per_second` is not a positive number,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn new(target_traces_per_second: f64) -> Result<Self> {
+        track_assert!(0.0 < target_traces_per_second, ErrorKind::InvalidInput);
+        Ok(AdaptiveSampler {
+            target_traces_per_second,
+            min_sampling_rate: 0.0001,
+            base: Instant::now(),
+            window_start_nanos: AtomicU64::new(0),
+            window_candidates: AtomicU64::new(0),
+            sampling_rate: AtomicF64::new(1.0),
+        })
+    }
+
+    /// Sets the lower bound of the sampling rate.
+    ///
+    /// # Errors
+    ///
+    /// If `rate` is not in the range `0.0...1.0`,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn min_sampling_rate(mut self, rate: f64) -> Result<Self> {
+        track_assert!(0.0 <= rate, ErrorKind::InvalidInput);
+        track_assert!(rate <= 1.0, ErrorKind::InvalidInput);
+        self.min_sampling_rate = rate;
+        Ok(self)
+    }
+
+    /// Returns the current sampling rate.
+    pub fn sampling_rate(&self) -> f64 {
+        self.sampling_rate.load()
+    }
+
+    fn update_sampling_rate(&self) {
+        const WINDOW_NANOS: u64 = 1_000_000_000;
+
+        let now = nanos_since(self.base);
+        let window_start = self.window_start_nanos.load(Ordering::Relaxed);
+        let elapsed = now.saturating_sub(window_start);
+        if elapsed < WINDOW_NANOS {
+            return;
+        }
+        if self
+            .window_start_nanos
+            .compare_exchange(window_start, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            // Another thread is updating the rate.
+            return;
+        }
+
+        let candidates = self.window_candidates.swap(0, Ordering::Relaxed) as f64;
+        let candidates_per_second = candidates * 1_000_000_000.0 / elapsed as f64;
+        let rate = if candidates_per_second > 0.0 {
+            self.target_traces_per_second / candidates_per_second
+        } else {
+            1.0
+        };
+        self.sampling_rate
+            .store(rate.clamp(self.min_sampling_rate, 1.0));
+    }
+}
+impl<T> Sampler<T> for AdaptiveSampler {
+    fn is_sampled(&self, _span: &CandidateSpan<T>) -> bool {
+        self.window_candidates.fetch_add(1, Ordering::Relaxed);
+        self.update_sampling_rate();
+        rand::thread_rng().gen_range(0.0..1.0) < self.sampling_rate.load()
+    }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        let sampled = self.is_sampled(span);
+        SamplingDecision::described(sampled, "probabilistic", self.sampling_rate.load())
+    }
+}
+
+/// Sampling strategy which can be distributed by a control plane.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SamplingStrategy {
+    /// Samples traces by `ProbabilisticSampler`.
+    Probabilistic {
+        /// The sampling rate.
+        sampling_rate: f64,
+    },
+
+    /// Samples traces by `RateLimitingSampler`.
+    RateLimiting {
+        /// The maximum number of traces per second.
+        max_traces_per_second: f64,
+    },
+
+    /// Samples traces by `PerOperationSampler`.
+    PerOperation {
+        /// The strategy for the operations matching none of `operations`.
+        default: Box<SamplingStrategy>,
+
+        /// The operation names or glob patterns and their strategies.
+        operations: Vec<(String, SamplingStrategy)>,
+    },
+}
+impl SamplingStrategy {
+    /// Builds the sampler implementing this strategy.
+    ///
+    /// # Errors
+    ///
+    /// If a rate is out of the range accepted by the corresponding sampler,
+    /// it will return an error with the kind `ErrorKind::InvalidInput`.
+    pub fn build<T: 'static>(&self) -> Result<BoxSampler<T>> {
+        Ok(match self {
+            SamplingStrategy::Probabilistic { sampling_rate } => {
+                track!(ProbabilisticSampler::new(*sampling_rate))?.boxed()
+            }
+            SamplingStrategy::RateLimiting {
+                max_traces_per_second,
+            } => track!(RateLimitingSampler::new(*max_traces_per_second))?.boxed(),
+            SamplingStrategy::PerOperation {
+                default,
+                operations,
+            } => {
+                let mut sampler = PerOperationSampler::new(track!(default.build())?);
+                for (name, strategy) in operations {
+                    sampler = sampler.operation(name, track!(strategy.build())?);
+                }
+                sampler.boxed()
+            }
+        })
+    }
+}
+
+/// Source of sampling strategies (e.g., a client of a control plane).
+pub trait StrategyProvider {
+    /// Fetches the latest strategy.
+    ///
+    /// If the strategy has not changed since the last call, this may return `Ok(None)`.
+    fn fetch_strategy(&mut self) -> Result<Option<SamplingStrategy>>;
+}
+
+/// Sampler whose strategy can be replaced at runtime.
+///
+/// Clones share the current sampler, so a background task can keep one
+/// and swap in new strategies without recreating the `Tracer` holding another.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::{NullSampler, ReloadableSampler, SamplingStrategy};
+/// use cf_rustracing::Tracer;
+///
+/// let sampler = ReloadableSampler::new(NullSampler);
+/// let (tracer, _span_rx) = Tracer::new(sampler.clone());
+/// assert!(!tracer.span("foo").start_with_state(()).is_sampled());
+///
+/// sampler
+///     .set_strategy(&SamplingStrategy::Probabilistic { sampling_rate: 1.0 })
+///     .unwrap();
+/// # #[cfg(not(rustracing_noop))]
+/// assert!(tracer.span("foo").start_with_state(()).is_sampled());
+/// ```
+pub struct ReloadableSampler<T> {
+    current: Arc<RwLock<BoxSampler<T>>>,
+}
+impl<T> ReloadableSampler<T> {
+    /// Makes a new `ReloadableSampler` instance which samples traces by `sampler` until reloaded.
+    pub fn new<S>(sampler: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        ReloadableSampler {
+            current: Arc::new(RwLock::new(sampler.boxed())),
+        }
+    }
+
+    /// Replaces the current sampler with `sampler`.
+    pub fn set_sampler<S>(&self, sampler: S)
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = sampler.boxed();
+    }
+
+    /// Replaces the current sampler with the one built from `strategy`.
+    ///
+    /// # Errors
+    ///
+    /// If `strategy` is invalid, this returns an error and keeps the current sampler.
+    pub fn set_strategy(&self, strategy: &SamplingStrategy) -> Result<()>
+    where
+        T: 'static,
+    {
+        let sampler = track!(strategy.build())?;
+        self.set_sampler(sampler);
+        Ok(())
+    }
+
+    /// Fetches the latest strategy from `provider` and applies it.
+    ///
+    /// This returns `true` if the sampler has been replaced.
+    /// It is intended to be called periodically, for example, from a background thread or task.
+    pub fn reload<P>(&self, provider: &mut P) -> Result<bool>
+    where
+        P: StrategyProvider + ?Sized,
+        T: 'static,
+    {
+        match track!(provider.fetch_strategy())? {
+            Some(strategy) => {
+                track!(self.set_strategy(&strategy))?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+impl<T> Clone for ReloadableSampler<T> {
+    fn clone(&self) -> Self {
+        ReloadableSampler {
+            current: Arc::clone(&self.current),
+        }
+    }
+}
+impl<T> fmt::Debug for ReloadableSampler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReloadableSampler").finish_non_exhaustive()
+    }
+}
+impl<T> Sampler<T> for ReloadableSampler<T> {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        let sampler = self.current.read().unwrap_or_else(|e| e.into_inner());
+        sampler.is_sampled(span)
+    }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        let sampler = self.current.read().unwrap_or_else(|e| e.into_inner());
+        sampler.sample(span)
+    }
+}
+
+#[derive(Debug)]
+struct AtomicF64(AtomicU64);
+impl AtomicF64 {
+    fn new(value: f64) -> Self {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+fn nanos_since(base: Instant) -> u64 {
+    let elapsed: Duration = base.elapsed();
+    elapsed.as_nanos() as u64
+}
+
+/// This delegates sampling decisions to samplers chosen by the operation names of spans.
+///
+/// Operations are given either as exact names or as glob patterns,
+/// in which `*` matches any sequence of characters and `?` matches any single character.
+/// Exact names take precedence over patterns, and patterns are tried in the order they were added.
+/// Spans matching none of them are sampled by the default sampler.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::{AllSampler, PerOperationSampler, ProbabilisticSampler};
+/// use cf_rustracing::Tracer;
+///
+/// let sampler = PerOperationSampler::new(ProbabilisticSampler::new(0.01).unwrap())
+///     .operation("GET /health*", ProbabilisticSampler::new(0.001).unwrap())
+///     .operation("admin.*", AllSampler);
+/// let (tracer, _span_rx) = Tracer::<_, ()>::new(sampler);
+/// # #[cfg(not(rustracing_noop))]
+/// assert!(tracer.span("admin.reload").start_with_state(()).is_sampled());
+/// ```
+pub struct PerOperationSampler<T> {
+    operations: HashMap<String, BoxSampler<T>>,
+    patterns: Vec<(String, BoxSampler<T>)>,
+    default: BoxSampler<T>,
+}
+impl<T> PerOperationSampler<T> {
+    /// Makes a new `PerOperationSampler` instance which samples all operations by `default`.
+    pub fn new<S>(default: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        PerOperationSampler {
+            operations: HashMap::new(),
+            patterns: Vec::new(),
+            default: default.boxed(),
+        }
+    }
+
+    /// Samples the operations matching `name` by `sampler`.
+    ///
+    /// If `name` contains `*` or `?`, it is treated as a glob pattern.
+    /// Adding the same name again replaces its sampler.
+    pub fn operation<S>(mut self, name: &str, sampler: S) -> Self
+    where
+        S: Sampler<T> + Send + Sync + 'static,
+    {
+        if name.contains(['*', '?']) {
+            self.patterns.retain(|(p, _)| p != name);
+            self.patterns.push((name.to_owned(), sampler.boxed()));
+        } else {
+            self.operations.insert(name.to_owned(), sampler.boxed());
+        }
+        self
+    }
+
+    fn sampler(&self, operation_name: &str) -> &BoxSampler<T> {
+        if let Some(sampler) = self.operations.get(operation_name) {
+            return sampler;
+        }
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern.as_bytes(), operation_name.as_bytes()))
+            .map_or(&self.default, |(_, sampler)| sampler)
+    }
+}
+impl<T> fmt::Debug for PerOperationSampler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PerOperationSampler")
+            .field("operations", &self.operations.keys().collect::<Vec<_>>())
+            .field(
+                "patterns",
+                &self.patterns.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+impl<T> Sampler<T> for PerOperationSampler<T> {
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        self.sampler(span.operation_name()).is_sampled(span)
+    }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        self.sampler(span.operation_name()).sample(span)
+    }
+}
+
+/// Matches `name` against the glob `pattern`, backtracking only to the last `*`.
+pub(crate) fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
 }
 
 /// This samples traces which have one or more references.
@@ -111,6 +841,14 @@ where
     fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
         self.0.is_sampled(span) || self.1.is_sampled(span)
     }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        let decision = self.0.sample(span);
+        if decision.is_sampled() {
+            decision
+        } else {
+            self.1.sample(span)
+        }
+    }
 }
 
 /// `and` combinator.
@@ -124,4 +862,240 @@ where
     fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
         self.0.is_sampled(span) && self.1.is_sampled(span)
     }
+    fn sample(&self, span: &CandidateSpan<T>) -> SamplingDecision {
+        let mut decision = self.0.sample(span);
+        if decision.is_sampled() {
+            let other = self.1.sample(span);
+            decision.sampled = other.sampled;
+            decision.tags.extend(other.tags);
+        }
+        decision
+    }
+}
+
+/// `not` combinator.
+#[derive(Debug, Clone)]
+pub struct NotSampler<A>(A);
+impl<A, T> Sampler<T> for NotSampler<A>
+where
+    A: Sampler<T>,
+{
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        !self.0.is_sampled(span)
+    }
+}
+
+/// Sampler deciding with a closure (see `fn_sampler`).
+#[derive(Clone)]
+pub struct FnSampler<F>(F);
+impl<F> fmt::Debug for FnSampler<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FnSampler").finish_non_exhaustive()
+    }
+}
+impl<F, T> Sampler<T> for FnSampler<F>
+where
+    F: Fn(&CandidateSpan<T>) -> bool,
+{
+    fn is_sampled(&self, span: &CandidateSpan<T>) -> bool {
+        (self.0)(span)
+    }
+}
+
+/// Makes a sampler which samples a trace if `f` returns `true`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::{fn_sampler, PassiveSampler, Sampler};
+/// use cf_rustracing::Tracer;
+///
+/// let is_health_check = fn_sampler(|span| span.operation_name().starts_with("health"));
+/// let sampler = Sampler::<()>::or(PassiveSampler, is_health_check.not());
+/// let (tracer, _span_rx) = Tracer::new(sampler);
+/// # #[cfg(not(rustracing_noop))]
+/// assert!(tracer.span("get_user").start_with_state(()).is_sampled());
+/// assert!(!tracer.span("health_check").start_with_state(()).is_sampled());
+/// ```
+pub fn fn_sampler<T, F>(f: F) -> FnSampler<F>
+where
+    F: Fn(&CandidateSpan<T>) -> bool,
+{
+    FnSampler(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tracer;
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn rate_limiting_sampler_works() {
+        let sampler = track_try_unwrap!(RateLimitingSampler::with_burst(0.001, 2.0));
+        let (tracer, _span_rx) = Tracer::new(sampler);
+        assert!(tracer.span("a").start_with_state(()).is_sampled());
+        let b = tracer.span("b").start_with_state(());
+        assert!(b.is_sampled());
+        assert!(!tracer.span("c").start_with_state(()).is_sampled());
+
+        // Children of sampled spans are not limited.
+        let child = b.child("child", |options| options.start_with_state(()));
+        assert!(child.is_sampled());
+
+        // Spans continuing remote traces or following from sampled spans are limited.
+        let remote = crate::span::SpanContext::new((), Vec::new());
+        let span = tracer.span("d").child_of(&remote).start_with_state(());
+        assert!(!span.is_sampled());
+        let span = tracer.span("e").follows_from(&b).start_with_state(());
+        assert!(!span.is_sampled());
+
+        assert!(RateLimitingSampler::new(0.0).is_err());
+        assert!(RateLimitingSampler::with_burst(1.0, 0.5).is_err());
+
+        // Tiny rates do not overflow the token bucket.
+        let sampler = track_try_unwrap!(RateLimitingSampler::new(1e-12));
+        let (tracer, _span_rx) = Tracer::new(sampler);
+        assert!(tracer.span("a").start_with_state(()).is_sampled());
+        assert!(!tracer.span("b").start_with_state(()).is_sampled());
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn probabilistic_sampler_works() {
+        use crate::testing::SamplingStats;
+
+        for rate in [0.0, 0.01, 0.5, 1.0] {
+            let sampler = track_try_unwrap!(ProbabilisticSampler::new(rate));
+            SamplingStats::measure::<_, ()>(&sampler, 10_000).assert_rate(rate);
+        }
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn deterministic_sampler_works() {
+        use crate::testing::SamplingStats;
+
+        let key = |_: &CandidateSpan<()>| Some("foo");
+        let sampler = track_try_unwrap!(DeterministicSampler::new(0.5, key));
+        let stats = SamplingStats::measure(&sampler, 100);
+        assert!(stats.sampled == 0 || stats.sampled == 100);
+        assert!(DeterministicSampler::new(1.5, key).is_err());
+
+        for rate in [0.0, 0.1, 0.5, 1.0] {
+            let counter = AtomicU64::new(0);
+            let key = |_: &CandidateSpan<()>| Some(counter.fetch_add(1, Ordering::Relaxed));
+            let sampler = track_try_unwrap!(DeterministicSampler::new(rate, key));
+            SamplingStats::measure(&sampler, 10_000).assert_rate(rate);
+        }
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn per_operation_sampler_works() {
+        let sampler = PerOperationSampler::new(NullSampler)
+            .operation("admin.*", AllSampler)
+            .operation("admin.secret", NullSampler)
+            .operation("get_?", AllSampler);
+        let (tracer, _span_rx) = Tracer::new(sampler);
+        let is_sampled = |name: &'static str| tracer.span(name).start_with_state(()).is_sampled();
+        assert!(is_sampled("admin.reload"));
+        assert!(!is_sampled("admin.secret"));
+        assert!(is_sampled("get_x"));
+        assert!(!is_sampled("get_xy"));
+        assert!(!is_sampled("health"));
+
+        assert!(glob_match(b"*a*b", b"xaxxab"));
+        assert!(glob_match(b"**", b""));
+        assert!(!glob_match(b"a*b", b"abc"));
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn reloadable_sampler_works() {
+        struct Provider(Vec<SamplingStrategy>);
+        impl StrategyProvider for Provider {
+            fn fetch_strategy(&mut self) -> Result<Option<SamplingStrategy>> {
+                Ok(self.0.pop())
+            }
+        }
+
+        let sampler = ReloadableSampler::new(AllSampler);
+        let (tracer, _span_rx) = Tracer::new(sampler.clone());
+        let is_sampled = |name: &'static str| tracer.span(name).start_with_state(()).is_sampled();
+        assert!(is_sampled("foo"));
+
+        let mut provider = Provider(vec![
+            SamplingStrategy::PerOperation {
+                default: Box::new(SamplingStrategy::Probabilistic { sampling_rate: 0.0 }),
+                operations: vec![(
+                    "bar".to_owned(),
+                    SamplingStrategy::RateLimiting {
+                        max_traces_per_second: 1000.0,
+                    },
+                )],
+            },
+            SamplingStrategy::Probabilistic { sampling_rate: 2.0 },
+        ]);
+        assert!(sampler.reload(&mut provider).is_err());
+        assert!(is_sampled("foo"));
+        assert!(track_try_unwrap!(sampler.reload(&mut provider)));
+        assert!(!is_sampled("foo"));
+        assert!(is_sampled("bar"));
+        assert!(!track_try_unwrap!(sampler.reload(&mut provider)));
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn sampling_decisions_are_tagged() {
+        use crate::tag::TagValue;
+
+        let sampler = Sampler::<()>::and(
+            track_try_unwrap!(ProbabilisticSampler::new(1.0)),
+            track_try_unwrap!(RateLimitingSampler::new(10.0)),
+        );
+        let (tracer, mut span_rx) = Tracer::new(sampler);
+        tracer
+            .span("foo")
+            .tag(Tag::new("sampler.param", "custom"))
+            .start_with_state(());
+        let span = span_rx.try_recv().unwrap();
+        let tag = |name| {
+            span.tags()
+                .iter()
+                .filter(|t| t.name() == name)
+                .map(|t| t.value().clone())
+                .collect::<Vec<_>>()
+        };
+        // The tags of the first sampler take precedence.
+        assert_eq!(tag("sampler.type"), [TagValue::from("probabilistic")]);
+        assert_eq!(tag("sampler.param"), [TagValue::from("custom")]);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn combinators_work() {
+        let is_admin = fn_sampler(|span: &CandidateSpan<()>| span.operation_name() == "admin");
+        let sampler = is_admin.clone().not().and(AllSampler).boxed();
+        let (tracer, _span_rx) = Tracer::new(sampler.or(is_admin));
+        assert!(tracer.span("admin").start_with_state(()).is_sampled());
+        assert!(tracer.span("user").start_with_state(()).is_sampled());
+
+        let (tracer, _span_rx) = Tracer::new(Sampler::<()>::not(AllSampler));
+        assert!(!tracer.span("user").start_with_state(()).is_sampled());
+    }
+
+    #[test]
+    fn adaptive_sampler_works() {
+        let mut sampler = track_try_unwrap!(AdaptiveSampler::new(10.0));
+        assert_eq!(sampler.sampling_rate(), 1.0);
+
+        // 1000 candidates in the last second.
+        sampler.base = Instant::now() - Duration::from_secs(1);
+        sampler.window_candidates.store(1000, Ordering::Relaxed);
+        sampler.update_sampling_rate();
+        assert!((sampler.sampling_rate() - 0.01).abs() < 0.001);
+
+        assert!(AdaptiveSampler::new(0.0).is_err());
+    }
 }
@@ -0,0 +1,455 @@
+//! Active span scopes.
+//!
+//! A span entered with `SpanScope::enter` becomes the active span while the given future is polled,
+//! so code deep inside the future can start child spans of it with `Tracer::active_span`
+//! or `StartSpanOptions::child_of_active` instead of having the parent passed around.
+//!
+//! The active span is kept in a thread-local variable which is set only during each poll of
+//! the future, so scopes work with any executor and are never leaked to other tasks.
+//! Synchronous code can activate a span on the current thread with `Span::activate`.
+//!
+//! `InstrumentSpan::in_span` is a shorthand of `SpanScope::enter`.
+use crate::log::LogField;
+use crate::span::{Span, SpanHandle};
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+type ActiveSpan = Arc<dyn Any + Send + Sync>;
+
+thread_local! {
+    static ACTIVE_SPAN: RefCell<Option<ActiveSpan>> = const { RefCell::new(None) };
+}
+
+/// Returns the handle of the span active in the current scope.
+///
+/// `None` is returned outside of scopes, or if the active span does not have the state `T`.
+pub fn active_span<T>() -> Option<SpanHandle<T>>
+where
+    T: Clone + 'static,
+{
+    ACTIVE_SPAN.with(|active| {
+        active
+            .borrow()
+            .as_ref()
+            .and_then(|span| span.downcast_ref::<SpanHandle<T>>())
+            .cloned()
+    })
+}
+
+/// Scope in which a span is active.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+///
+/// async fn query(tracer: &Tracer<AllSampler, ()>) {
+///     let parent = tracer.active_span();
+///     let _span = tracer.span("query").child_of(&parent).start_with_state(());
+/// }
+///
+/// let span = tracer.span("request").start_with_state(());
+/// tracer.scope(span).enter(query(&tracer)).await;
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let child = span_rx.try_recv().unwrap();
+/// let parent = span_rx.try_recv().unwrap();
+/// assert_eq!(child.operation_name(), "query");
+/// assert_eq!(parent.operation_name(), "request");
+/// assert_eq!(child.references()[0].span(), parent.context().state());
+/// # }
+/// # }
+/// ```
+pub struct SpanScope<T> {
+    span: Span<T>,
+    handle: ActiveSpan,
+}
+impl<T> SpanScope<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    /// Makes a new `SpanScope` instance in which `span` is active.
+    pub fn new(span: Span<T>) -> Self {
+        let handle = Arc::new(span.handle());
+        SpanScope { span, handle }
+    }
+
+    /// Makes a new `SpanScope` instance in which the span of `handle` is active.
+    ///
+    /// Unlike `new`, the scope does not own the span, so the span is not finished by the scope.
+    pub fn from_handle(handle: SpanHandle<T>) -> Self {
+        SpanScope {
+            span: Span::inactive(),
+            handle: Arc::new(handle),
+        }
+    }
+
+    /// Makes `future` run in this scope.
+    ///
+    /// The span is finished when the future completes or is dropped.
+    pub fn enter<F: Future>(self, future: F) -> Scoped<F, T> {
+        Scoped {
+            future,
+            span: Some(self.span),
+            handle: self.handle,
+            poll_gaps: None,
+        }
+    }
+
+    /// Calls `f` in this scope and finishes the span.
+    pub fn run<F, R>(self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let _guard = Guard::activate(Arc::clone(&self.handle));
+        f()
+    }
+}
+impl<T> fmt::Debug for SpanScope<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanScope").finish_non_exhaustive()
+    }
+}
+
+/// Extension trait running futures in the scopes of spans.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::scope::InstrumentSpan;
+/// use cf_rustracing::Tracer;
+/// use std::time::Duration;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+/// let span = tracer.span("task").start_with_state(());
+/// let n = async { 1 + 1 }
+///     .in_span(span)
+///     .log_poll_gaps(Duration::from_millis(100))
+///     .await;
+/// assert_eq!(n, 2);
+/// # #[cfg(not(rustracing_noop))]
+/// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "task");
+/// # }
+/// ```
+pub trait InstrumentSpan: Future + Sized {
+    /// Runs this future in the scope of `span`, finishing the span when the future
+    /// completes or is dropped.
+    fn in_span<T>(self, span: Span<T>) -> Scoped<Self, T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        SpanScope::new(span).enter(self)
+    }
+}
+impl<F: Future> InstrumentSpan for F {}
+
+/// Future returned by `SpanScope::enter`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Scoped<F, T> {
+    future: F,
+    span: Option<Span<T>>,
+    handle: ActiveSpan,
+    poll_gaps: Option<PollGaps>,
+}
+impl<F, T> Scoped<F, T> {
+    /// Logs the gaps between polls of the future which are longer than `threshold`.
+    ///
+    /// A gap is the time the future spent suspended after it returned `Poll::Pending`.
+    /// It is logged with the fields `event=poll_gap` and `gap_us={microseconds}`.
+    pub fn log_poll_gaps(mut self, threshold: Duration) -> Self {
+        self.poll_gaps = Some(PollGaps {
+            threshold,
+            suspended_at: None,
+        });
+        self
+    }
+
+    /// Records the outcome of the future from its `Result` output when it completes
+    /// (see `Span::finish_with_result`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::scope::InstrumentSpan;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// let span = tracer.span("fetch").start_with_state(());
+    /// let result = async { Err::<(), _>("connection refused") }
+    ///     .in_span(span)
+    ///     .finish_with_result()
+    ///     .await;
+    /// assert!(result.is_err());
+    /// # #[cfg(not(rustracing_noop))]
+    /// assert_eq!(span_rx.try_recv().unwrap().tags()[0].name(), "error");
+    /// # }
+    /// ```
+    pub fn finish_with_result(self) -> FinishWithResult<F, T> {
+        FinishWithResult(self)
+    }
+
+    /// Polls the future in the scope, returning the span instead of finishing it on completion.
+    fn poll_scoped(self: Pin<&mut Self>, cx: &mut Context) -> Poll<(F::Output, Option<Span<T>>)>
+    where
+        F: Future,
+    {
+        // SAFETY: `future` is never moved out of `self`, and the other fields are not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let (Some(gaps), Some(span)) = (this.poll_gaps.as_mut(), this.span.as_mut()) {
+            gaps.log(span);
+        }
+        let poll = {
+            let _guard = Guard::activate(Arc::clone(&this.handle));
+            future.poll(cx)
+        };
+        match poll {
+            Poll::Ready(output) => Poll::Ready((output, this.span.take())),
+            Poll::Pending => {
+                if let Some(gaps) = this.poll_gaps.as_mut() {
+                    gaps.suspended_at = Some(Instant::now());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+impl<F: Future, T> Future for Scoped<F, T> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.poll_scoped(cx).map(|(output, _span)| output)
+    }
+}
+impl<F, T> fmt::Debug for Scoped<F, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scoped").finish_non_exhaustive()
+    }
+}
+
+/// Future returned by `Scoped::finish_with_result`.
+#[must_use = "futures do nothing unless polled"]
+pub struct FinishWithResult<F, T>(Scoped<F, T>);
+impl<F, T, R, E> Future for FinishWithResult<F, T>
+where
+    F: Future<Output = Result<R, E>>,
+    E: fmt::Display,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // SAFETY: the inner future is structurally pinned.
+        let scoped = unsafe { self.map_unchecked_mut(|this| &mut this.0) };
+        scoped.poll_scoped(cx).map(|(output, span)| {
+            if let Some(span) = span {
+                span.finish_with_result(&output);
+            }
+            output
+        })
+    }
+}
+impl<F, T> fmt::Debug for FinishWithResult<F, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FinishWithResult").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct PollGaps {
+    threshold: Duration,
+    suspended_at: Option<Instant>,
+}
+impl PollGaps {
+    fn log<T>(&mut self, span: &mut Span<T>) {
+        let Some(suspended_at) = self.suspended_at.take() else {
+            return;
+        };
+        let gap = suspended_at.elapsed();
+        if gap > self.threshold {
+            span.log(|log| {
+                log.std().event("poll_gap");
+                log.field(LogField::new("gap_us", gap.as_micros().to_string()));
+            });
+        }
+    }
+}
+
+/// Guard keeping a span active on the current thread, returned by `Span::activate`.
+///
+/// When the guard is dropped, the span which was active before it is activated again,
+/// so guards form a stack and have to be dropped in the reverse order of their creation.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+///
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+///
+/// fn query(tracer: &Tracer<AllSampler, ()>) {
+///     let _span = tracer.span("query").child_of_active().start_with_state(());
+/// }
+///
+/// {
+///     let span = tracer.span("request").start_with_state(());
+///     let _guard = span.activate();
+///     query(&tracer);
+/// }
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let child = span_rx.try_recv().unwrap();
+/// assert_eq!(child.operation_name(), "query");
+/// assert_eq!(child.references().len(), 1);
+/// # }
+/// ```
+#[must_use = "the span is deactivated when the guard is dropped"]
+pub struct ActiveSpanGuard {
+    _guard: Option<Guard>,
+    _not_send: PhantomData<*const ()>,
+}
+impl ActiveSpanGuard {
+    #[cfg(not(rustracing_noop))]
+    pub(crate) fn new<T>(span: SpanHandle<T>) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        ActiveSpanGuard {
+            _guard: Some(Guard::activate(Arc::new(span))),
+            _not_send: PhantomData,
+        }
+    }
+
+    #[cfg(rustracing_noop)]
+    pub(crate) fn inactive() -> Self {
+        ActiveSpanGuard {
+            _guard: None,
+            _not_send: PhantomData,
+        }
+    }
+}
+impl fmt::Debug for ActiveSpanGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ActiveSpanGuard").finish_non_exhaustive()
+    }
+}
+
+/// Restores the previously active span when dropped.
+struct Guard(Option<ActiveSpan>);
+impl Guard {
+    fn activate(span: ActiveSpan) -> Self {
+        Guard(ACTIVE_SPAN.with(|active| active.replace(Some(span))))
+    }
+}
+impl Drop for Guard {
+    fn drop(&mut self) {
+        ACTIVE_SPAN.with(|active| *active.borrow_mut() = self.0.take());
+    }
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::context::DefaultContext;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+
+    #[tokio::test]
+    async fn scopes_are_nested() {
+        let (tracer, mut span_rx) = Tracer::<_, DefaultContext>::new(AllSampler);
+        assert!(tracer.active_span().is_none());
+
+        let outer = tracer.span("outer").start();
+        let outer_context = *outer.context().unwrap().state();
+        let scoped = tracer.scope(outer).enter({
+            let tracer = tracer.clone();
+            async move {
+                tokio::task::yield_now().await;
+                let inner = tracer.span("inner").child_of(&tracer.active_span()).start();
+                tracer.scope(inner).run(|| {
+                    assert!(tracer.active_span().is_some());
+                    assert!(active_span::<()>().is_none());
+                });
+                tokio::task::yield_now().await;
+                tracer.active_span().unwrap().is_sampled()
+            }
+        });
+        assert!(tokio::spawn(scoped).await.unwrap());
+        assert!(tracer.active_span().is_none());
+
+        let inner = span_rx.try_recv().unwrap();
+        assert_eq!(inner.operation_name(), "inner");
+        assert_eq!(inner.references()[0].span(), &outer_context);
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "outer");
+    }
+
+    #[tokio::test]
+    async fn poll_gaps_are_logged() {
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        let span = tracer.span("foo").start_with_state(());
+        tokio::task::yield_now()
+            .in_span(span)
+            .log_poll_gaps(Duration::ZERO)
+            .await;
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.logs().len(), 1);
+        assert_eq!(
+            span.logs()[0].fields()[0].value().as_str(),
+            Some("poll_gap")
+        );
+
+        // Spans are finished when the futures are dropped.
+        let span = tracer.span("bar").start_with_state(());
+        drop(std::future::pending::<()>().in_span(span));
+        assert_eq!(span_rx.try_recv().unwrap().operation_name(), "bar");
+    }
+
+    #[test]
+    fn guards_form_stack() {
+        let (tracer, mut span_rx) = Tracer::<_, DefaultContext>::new(AllSampler);
+        let outer = tracer.span("outer").start();
+        let outer_context = *outer.context().unwrap().state();
+        {
+            let _outer = outer.activate();
+            {
+                let inner = tracer.span("inner").child_of_active().start();
+                let _inner = inner.activate();
+                let inner_context = *inner.context().unwrap().state();
+                let context = *tracer.active_span().unwrap().context().unwrap().state();
+                assert_eq!(context, inner_context);
+            }
+            let context = *tracer.active_span().unwrap().context().unwrap().state();
+            assert_eq!(context, outer_context);
+        }
+        assert!(tracer.active_span().is_none());
+        let _ = tracer.span("root").child_of_active().start();
+        drop(outer);
+
+        let inner = span_rx.try_recv().unwrap();
+        assert_eq!(inner.references()[0].span(), &outer_context);
+        let root = span_rx.try_recv().unwrap();
+        assert_eq!(root.operation_name(), "root");
+        assert!(root.references().is_empty());
+    }
+}
@@ -1,26 +1,155 @@
 //! Span.
-use crate::carrier;
+pub use self::annotator::SpanAnnotator;
+pub use self::baggage::BaggageRestrictions;
+pub use self::extensions::Extensions;
+pub use self::limits::SpanLimits;
+pub use self::panic::CatchPanic;
+pub use self::time_scope::TimeScope;
+#[cfg(not(rustracing_noop))]
+pub use crate::channel::{SpanReceiver, SpanSender};
+#[cfg(rustracing_noop)]
+pub use crate::noop::{SharedSpan, Span, SpanHandle, SpanReceiver, SpanSender, StartSpanOptions};
+
+#[cfg(not(rustracing_noop))]
+use self::annotator::Annotations;
+use self::limits::DroppedCounts;
+#[cfg(not(rustracing_noop))]
+use self::limits::LogWindow;
+use crate::carrier::{self, TraceState};
+#[cfg(not(rustracing_noop))]
+use crate::clock::Clock;
 use crate::convert::MaybeAsRef;
+use crate::id::IdGenerator;
 use crate::log::{Log, LogBuilder, StdErrorLogFieldsBuilder};
+use crate::pool::SpanPool;
 use crate::sampler::{AllSampler, Sampler};
-use crate::tag::{StdTag, Tag, TagValue};
+#[cfg(not(rustracing_noop))]
+use crate::scope::{self, ActiveSpanGuard};
+use crate::tag::{cmp_keys, keys_equal, SharedTags, StdTag, Tag, TagValue};
 use crate::Result;
 use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::future::Future;
 use std::io::{Read, Write};
-use std::time::SystemTime;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod annotator;
+mod baggage;
+mod extensions;
+mod limits;
+mod panic;
+mod time_scope;
+
+/// Error returned by `SpanReceiver::try_recv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// No finished span is available now.
+    Empty,
+
+    /// All the tracers and spans associated with the receiver have been dropped
+    /// and all the finished spans have been received.
+    Disconnected,
+}
+impl TryRecvError {
+    /// Converts the error of a queue which reports only whether it has been closed.
+    pub(crate) fn from_closed(closed: bool) -> Self {
+        if closed {
+            TryRecvError::Disconnected
+        } else {
+            TryRecvError::Empty
+        }
+    }
+}
+#[cfg(feature = "tokio")]
+impl From<tokio::sync::mpsc::error::TryRecvError> for TryRecvError {
+    fn from(f: tokio::sync::mpsc::error::TryRecvError) -> Self {
+        match f {
+            tokio::sync::mpsc::error::TryRecvError::Empty => TryRecvError::Empty,
+            tokio::sync::mpsc::error::TryRecvError::Disconnected => TryRecvError::Disconnected,
+        }
+    }
+}
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TryRecvError::Empty => f.write_str("receiving on an empty span channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed span channel"),
+        }
+    }
+}
+impl error::Error for TryRecvError {}
 
-/// Finished span receiver.
-pub type SpanReceiver<T> = mpsc::UnboundedReceiver<FinishedSpan<T>>;
-/// Sender of finished spans to the destination channel.
-pub type SpanSender<T> = mpsc::UnboundedSender<FinishedSpan<T>>;
+/// Policy applied when a finished span is sent to a full span channel.
+///
+/// See `TracerBuilder::overflow_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discards the newly finished span.
+    #[default]
+    DropNewest,
+
+    /// Discards the oldest span in the channel to make room for the newly finished span.
+    DropOldest,
+
+    /// Blocks the thread finishing the span until the channel has room,
+    /// and discards the span if the timeout elapses first.
+    BlockWithTimeout(Duration),
+}
+
+/// Status of a span.
+///
+/// This is independent of the `error` tag, so that converters to other formats
+/// (e.g., OTLP or Zipkin) can map it to their canonical status without looking at tags.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SpanStatus {
+    /// The status has not been set.
+    #[default]
+    Unset,
+
+    /// The operation completed successfully.
+    Ok,
+
+    /// The operation failed.
+    Error {
+        /// Description of the error.
+        message: Cow<'static, str>,
+    },
+}
+impl SpanStatus {
+    /// Makes a `SpanStatus::Error` having the message `message`.
+    pub fn error<M>(message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        SpanStatus::Error {
+            message: message.into(),
+        }
+    }
+
+    /// Returns `true` if this is `SpanStatus::Error`.
+    pub fn is_error(&self) -> bool {
+        matches!(self, SpanStatus::Error { .. })
+    }
+}
 
+#[cfg(not(rustracing_noop))]
 /// Span.
 ///
 /// When this span is dropped, it will be converted to `FinishedSpan` and
 /// it will be sent to the associated `SpanReceiver`.
+///
+/// Spans are often held across `.await` points, so they are kept small:
+/// on 64-bit targets, `Span<T>` occupies `152 + 2 * size_of::<T>()` bytes
+/// (plus padding) because rarely used fields are stored in separate allocations.
+///
+/// All the recording methods first check whether the span is sampled,
+/// so calling them on an unsampled span is a single branch
+/// without evaluating the given closures.
 #[derive(Debug)]
 pub struct Span<T>(Option<SpanInner<T>>);
+#[cfg(not(rustracing_noop))]
 impl<T> Span<T> {
     /// Makes an inactive span.
     ///
@@ -38,29 +167,76 @@ impl<T> Span<T> {
         Span(None)
     }
 
+    /// Makes this span the active span of the current thread until the returned guard is dropped.
+    ///
+    /// See `ActiveSpanGuard` for an example.
+    pub fn activate(&self) -> ActiveSpanGuard
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        ActiveSpanGuard::new(self.handle())
+    }
+
     /// Returns a handle of this span.
     pub fn handle(&self) -> SpanHandle<T>
     where
         T: Clone,
     {
-        SpanHandle(
-            self.0
-                .as_ref()
-                .map(|inner| (inner.context.clone(), inner.span_tx.clone())),
-        )
+        SpanHandle(self.0.as_ref().map(|inner| {
+            (
+                inner.context.clone(),
+                inner.span_tx.clone(),
+                inner.lineage(),
+            )
+        }))
     }
 
     /// Returns `true` if this span is sampled (i.e., being traced).
+    #[inline]
     pub fn is_sampled(&self) -> bool {
         self.0.is_some()
     }
 
     /// Returns the context of this span.
+    #[inline]
     pub fn context(&self) -> Option<&SpanContext<T>> {
         self.0.as_ref().map(|x| &x.context)
     }
 
+    /// Returns the extensions of this span.
+    ///
+    /// This returns `None` if this span is not sampled.
+    #[inline]
+    pub fn extensions(&self) -> Option<&Extensions> {
+        static EMPTY: Extensions = Extensions::new();
+        self.0
+            .as_ref()
+            .map(|inner| inner.cold.as_ref().map_or(&EMPTY, |cold| &cold.extensions))
+    }
+
+    /// Returns the mutable extensions of this span.
+    ///
+    /// This returns `None` if this span is not sampled.
+    #[inline]
+    pub fn extensions_mut(&mut self) -> Option<&mut Extensions> {
+        self.0
+            .as_mut()
+            .map(|inner| &mut inner.cold_mut().extensions)
+    }
+
+    /// Returns the time remaining until the deadline of this span.
+    ///
+    /// This returns `None` if this span has no deadline or is not sampled,
+    /// and `Some(Duration::ZERO)` if the deadline has passed.
+    pub fn remaining_time(&self) -> Option<Duration> {
+        let inner = self.0.as_ref()?;
+        let deadline = inner.context.deadline()?;
+        let now = inner.span_tx.clock().now();
+        Some(deadline.duration_since(now).unwrap_or_default())
+    }
+
     /// Sets the operation name of this span.
+    #[inline]
     pub fn set_operation_name<F, N>(&mut self, f: F)
     where
         F: FnOnce() -> N,
@@ -72,26 +248,191 @@ impl<T> Span<T> {
     }
 
     /// Sets the start time of this span.
+    ///
+    /// The duration of the span is then measured with the wall clock
+    /// instead of the monotonic clock.
+    #[inline]
     pub fn set_start_time<F>(&mut self, f: F)
     where
         F: FnOnce() -> SystemTime,
     {
         if let Some(inner) = self.0.as_mut() {
             inner.start_time = f();
+            inner.start_instant = None;
         }
     }
 
     /// Sets the finish time of this span.
+    #[inline]
     pub fn set_finish_time<F>(&mut self, f: F)
     where
         F: FnOnce() -> SystemTime,
     {
         if let Some(inner) = self.0.as_mut() {
-            inner.finish_time = Some(f());
+            inner.cold_mut().finish_time = Some(f());
+        }
+    }
+
+    /// Finishes this span at `time`.
+    ///
+    /// Together with `StartSpanOptions::start_time`, this allows spans to be recorded
+    /// retroactively (e.g., from access logs).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    /// tracer
+    ///     .span("GET /")
+    ///     .start_time(start)
+    ///     .start_with_state(())
+    ///     .finish_at(start + Duration::from_millis(25));
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.start_time(), start);
+    /// assert_eq!(span.duration(), Duration::from_millis(25));
+    /// # }
+    /// ```
+    #[inline]
+    pub fn finish_at(mut self, time: SystemTime) {
+        self.set_finish_time(|| time);
+    }
+
+    /// Records the outcome of the operation of this span from `result` and finishes this span.
+    ///
+    /// If `result` is `Ok`, the status of this span is set to `SpanStatus::Ok`.
+    /// Otherwise, the status is set to `SpanStatus::Error` having the message of the error,
+    /// and the `error` tag and an error log having the message are added.
+    ///
+    /// See `Scoped::finish_with_result` for futures.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::SpanStatus;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// let result: Result<u16, String> = Ok(8080);
+    /// tracer.span("parse").start_with_state(()).finish_with_result(&result);
+    /// let result: Result<u16, String> = Err("invalid digit".to_owned());
+    /// tracer.span("parse").start_with_state(()).finish_with_result(&result);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// assert_eq!(span_rx.try_recv().unwrap().status(), &SpanStatus::Ok);
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.status(), &SpanStatus::error("invalid digit"));
+    /// assert_eq!(span.tags()[0].name(), "error");
+    /// assert_eq!(span.logs()[0].fields()[1].value().as_str(), Some("invalid digit"));
+    /// # }
+    /// ```
+    #[inline]
+    pub fn finish_with_result<R, E>(mut self, result: &std::result::Result<R, E>)
+    where
+        E: fmt::Display,
+    {
+        match result {
+            Ok(_) => self.set_status(|| SpanStatus::Ok),
+            Err(e) => {
+                self.set_status(|| SpanStatus::error(e.to_string()));
+                self.error_log(|log| {
+                    log.message(e.to_string());
+                });
+            }
+        }
+    }
+
+    /// Finishes this span and returns it instead of sending it.
+    ///
+    /// The finish sampler and the span processors of the tracer are applied to the span as usual.
+    /// This returns `None` if this span is not sampled or is discarded by them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// let span = tracer.span("foo").start_with_state(());
+    /// let finished = span.finish();
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// assert_eq!(finished.unwrap().operation_name(), "foo");
+    /// assert!(span_rx.try_recv().is_err());
+    /// ```
+    pub fn finish(mut self) -> Option<FinishedSpan<T>> {
+        let (finished, span_tx) = self.0.take()?.finish();
+        span_tx.process(finished?)
+    }
+
+    /// Discards this span without sending it.
+    ///
+    /// This is useful for speculative work which turns out to be unnecessary.
+    /// The children of this span are not affected.
+    /// Abandoned spans are counted by `TracerMetrics::spans_abandoned`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// tracer.span("foo").start_with_state(()).abandon();
+    ///
+    /// assert!(span_rx.try_recv().is_err());
+    /// # #[cfg(not(rustracing_noop))]
+    /// assert_eq!(tracer.metrics().spans_abandoned, 1);
+    /// ```
+    pub fn abandon(mut self) {
+        if let Some(inner) = self.0.take() {
+            inner.abandon();
+        }
+    }
+
+    /// Sets the status of this span.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::SpanStatus;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// {
+    ///     let mut span = tracer.span("foo").start_with_state(());
+    ///     span.set_status(|| SpanStatus::error("connection refused"));
+    /// }
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.status(), &SpanStatus::error("connection refused"));
+    /// assert!(span.tags().is_empty());
+    /// # }
+    /// ```
+    #[inline]
+    pub fn set_status<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> SpanStatus,
+    {
+        if let Some(inner) = self.0.as_mut() {
+            inner.cold_mut().status = f();
         }
     }
 
     /// Sets the tag to this span.
+    #[inline]
     pub fn set_tag<F>(&mut self, f: F)
     where
         F: FnOnce() -> Tag,
@@ -101,41 +442,223 @@ impl<T> Span<T> {
     }
 
     /// Sets the tags to this span.
+    #[inline]
     pub fn set_tags<F, I>(&mut self, f: F)
     where
         F: FnOnce() -> I,
         I: IntoIterator<Item = Tag>,
     {
         if let Some(inner) = self.0.as_mut() {
+            inner.span_tx.pool().tags.reserve(&mut inner.tags);
             for tag in f() {
-                inner.tags.retain(|x| x.name() != tag.name());
-                inner.tags.push(tag);
+                inner.push_tag(tag);
+            }
+        }
+    }
+
+    /// Adds a reference to this span.
+    ///
+    /// This is useful if a causal predecessor of this span becomes known after it has started
+    /// (e.g., the producer of a message consumed in this span).
+    /// Note that the state of this span, which was made when it started, is not changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::SpanReference;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// let mut span = tracer.span("consume").start_with_state(1);
+    /// span.add_reference(|| SpanReference::FollowsFrom(0));
+    /// span.add_reference(|| SpanReference::FollowsFrom(2));
+    /// drop(span);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert!(span.references()[0].is_follows_from());
+    /// let states = span.references().iter().map(|r| *r.span()).collect::<Vec<_>>();
+    /// assert_eq!(states, [0, 2]);
+    /// # }
+    /// ```
+    #[inline]
+    pub fn add_reference<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> SpanReference<T>,
+    {
+        if let Some(inner) = self.0.as_mut() {
+            let reference = f();
+            if inner.reference.is_none() {
+                inner.reference = Some(reference);
+            } else {
+                let pool = Arc::clone(inner.span_tx.pool());
+                let references = &mut inner.cold_mut().references;
+                pool.references.reserve(references);
+                references.push(reference);
             }
         }
     }
 
+    /// Attaches the shared set of tags to this span.
+    ///
+    /// The tags of this span take precedence over the shared tags having the same names.
+    /// If a set has already been attached, it is replaced.
+    #[inline]
+    pub fn set_shared_tags<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> SharedTags,
+    {
+        if let Some(inner) = self.0.as_mut() {
+            inner.shared_tags = Some(f());
+        }
+    }
+
     /// Sets the baggage item to this span.
+    ///
+    /// The item is dropped if it violates the restrictions set by
+    /// `TracerBuilder::baggage_restrictions`.
+    #[inline]
     pub fn set_baggage_item<F>(&mut self, f: F)
     where
         F: FnOnce() -> BaggageItem,
     {
         if let Some(inner) = self.0.as_mut() {
-            let item = f();
-            inner.context.baggage_items.retain(|x| x.name != item.name);
-            inner.context.baggage_items.push(item);
+            let mut item = f();
+            let limits = *inner.span_tx.limits();
+            let restrictions = inner.span_tx.baggage_restrictions();
+            let pool = inner.span_tx.pool();
+            let baggage_items = inner.context.baggage_items_mut(pool);
+
+            // Replacing an existing item does not add one, and a rejected item leaves it intact.
+            let replaced = baggage_items.iter().any(|x| x.name == item.name);
+            let n = baggage_items.len() - usize::from(replaced);
+            let allowed = restrictions.map_or(true, |r| r.allows(&item) && r.allows_item(n));
+            if !allowed || !limits.allows_baggage_item(n) {
+                inner.cold_mut().dropped.baggage_items += 1;
+                return;
+            }
+            baggage_items.retain(|x| x.name != item.name);
+            limits.truncate_str(&mut item.value);
+            baggage_items.push(item);
         }
     }
 
     /// Gets the baggage item that has the name `name`.
+    #[inline]
     pub fn get_baggage_item(&self, name: &str) -> Option<&BaggageItem> {
-        if let Some(inner) = self.0.as_ref() {
-            inner.context.baggage_items.iter().find(|x| x.name == name)
-        } else {
-            None
+        self.0
+            .as_ref()
+            .and_then(|inner| inner.context.get_baggage_item(name))
+    }
+
+    /// Returns the value of the baggage item that has the name `name`.
+    #[inline]
+    pub fn baggage_item(&self, name: &str) -> Option<&str> {
+        self.get_baggage_item(name).map(BaggageItem::value)
+    }
+
+    /// Updates the `TraceState` entries of this span.
+    ///
+    /// The entries are inherited by the children of this span and propagated by
+    /// `carrier::TraceContextPropagator`.
+    #[inline]
+    pub fn update_trace_state<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut TraceState),
+    {
+        if let Some(inner) = self.0.as_mut() {
+            f(inner.context.trace_state_mut());
+        }
+    }
+
+    /// Makes this span a child of `context` after it has started.
+    ///
+    /// This is intended for spans which must be started before the contexts of their callers
+    /// are known (e.g., in the middleware running before the extraction of the contexts),
+    /// so that they are still reported as parts of the distributed traces.
+    ///
+    /// The references of this span are replaced with the `ChildOf` reference to `context`,
+    /// and this span takes over the baggage items (unless it has items having the same names),
+    /// the deadline, the `TraceState` entries and the debug flag of `context`.
+    /// Then the state of this span is remade with `T::from`, so that it joins the trace.
+    ///
+    /// Note that the sampling decision made when this span started is kept,
+    /// and that the spans which have already referred to this span keep referring to its old state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::context::DefaultContext;
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::SpanContext;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, _span_rx) = Tracer::new(AllSampler);
+    /// let mut span = tracer.span("request").start();
+    ///
+    /// // The context of the caller is extracted later.
+    /// let remote = SpanContext::new(DefaultContext::new(0xabc, 0x12, 1), Vec::new());
+    /// span.set_parent(&remote);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// assert_eq!(span.context().unwrap().state().trace_id(), 0xabc);
+    /// ```
+    pub fn set_parent<C>(&mut self, context: &C)
+    where
+        C: MaybeAsRef<SpanContext<T>>,
+        T: Clone + for<'b> From<CandidateSpan<'b, T>>,
+    {
+        let (Some(inner), Some(parent)) = (self.0.as_mut(), context.maybe_as_ref()) else {
+            return;
+        };
+        let mut references = inner.span_tx.pool().references.take();
+        references.push(SpanReference::ChildOf(parent.state().clone()));
+        inner.replace_references(references);
+        inner.inherit_baggage_items(parent);
+        if let Some(deadline) = parent.deadline() {
+            let own = &mut inner.context.cold_mut().deadline;
+            *own = Some(own.map_or(deadline, |d| d.min(deadline)));
+        }
+        if inner.context.trace_state().is_empty() && !parent.trace_state().is_empty() {
+            *inner.context.trace_state_mut() = parent.trace_state().clone();
+        }
+        if parent.flags.is_debug() {
+            inner.context.flags |= TraceFlags::DEBUG;
+        }
+
+        let span = CandidateSpan::new(
+            &inner.operation_name,
+            &inner.tags,
+            inner.reference.as_slice(),
+            inner.context.baggage_items(),
+            ParentKinds {
+                remote: parent.is_remote(),
+                local: !parent.is_remote(),
+            },
+            inner.context.flags.is_debug(),
+            inner.span_tx.id_generator(),
+        );
+        inner.context.state = T::from(span);
+    }
+
+    /// Replaces the references of this span.
+    ///
+    /// Unlike `set_parent`, this changes neither the state nor the baggage items of this span,
+    /// so this is intended for states which do not depend on the references.
+    #[inline]
+    pub fn replace_references<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> Vec<SpanReference<T>>,
+    {
+        if let Some(inner) = self.0.as_mut() {
+            inner.replace_references(f());
         }
     }
 
     /// Logs structured data.
+    #[inline]
     pub fn log<F>(&mut self, f: F)
     where
         F: FnOnce(&mut LogBuilder),
@@ -143,9 +666,73 @@ impl<T> Span<T> {
         if let Some(inner) = self.0.as_mut() {
             let mut builder = LogBuilder::new();
             f(&mut builder);
-            if let Some(log) = builder.finish() {
-                inner.logs.push(log);
+            if let Some(log) = builder.finish(inner.span_tx.clock()) {
+                inner.push_log(log);
+            }
+        }
+    }
+
+    /// Starts timing a phase of this span, which ends when the returned guard is dropped.
+    ///
+    /// Then a log having the `event` field set to `name` and the `elapsed_ms` field
+    /// (the elapsed time of the phase in milliseconds) is added to this span.
+    /// This is lighter than starting a child span for the phase.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::clock::MockClock;
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::tag::{Tag, TagValue};
+    /// use cf_rustracing::Tracer;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler).clock(clock.clone()).finish();
+    /// let mut span = tracer.span("request").start_with_state(());
+    /// {
+    ///     let mut phase = span.time_scope("parse");
+    ///     phase.set_tag(|| Tag::new("size", 512));
+    ///     clock.advance(Duration::from_micros(1500));
+    /// }
+    /// drop(span);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// let fields = span.logs()[0].fields();
+    /// assert_eq!(fields[0].value(), &TagValue::from(1.5));
+    /// assert_eq!(fields[1].value(), &TagValue::from("parse"));
+    /// # }
+    /// ```
+    #[inline]
+    pub fn time_scope<N>(&mut self, name: N) -> TimeScope<'_, T>
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        TimeScope::new(self, name.into())
+    }
+
+    /// Returns the clock of the tracer if this span is sampled.
+    pub(crate) fn clock(&self) -> Option<&dyn Clock> {
+        self.0.as_ref().map(|inner| inner.span_tx.clock())
+    }
+
+    /// Returns a cloneable handle adding tags and logs to this span from other tasks.
+    ///
+    /// See `SpanAnnotator` for an example.
+    pub fn annotator(&mut self) -> SpanAnnotator {
+        match self.0.as_mut() {
+            Some(inner) => {
+                let clock = inner.span_tx.shared_clock();
+                let limits = *inner.span_tx.limits();
+                let annotations = inner
+                    .cold_mut()
+                    .annotations
+                    .get_or_insert_with(|| Arc::new(Annotations::new(clock, limits)));
+                SpanAnnotator::new(annotations)
             }
+            None => SpanAnnotator::inactive(),
         }
     }
 
@@ -153,6 +740,7 @@ impl<T> Span<T> {
     ///
     /// This is a simple wrapper of `log` method
     /// except that the `StdTag::error()` tag will be set in this method.
+    #[inline]
     pub fn error_log<F>(&mut self, f: F)
     where
         F: FnOnce(&mut StdErrorLogFieldsBuilder),
@@ -160,33 +748,177 @@ impl<T> Span<T> {
         if let Some(inner) = self.0.as_mut() {
             let mut builder = LogBuilder::new();
             f(&mut builder.error());
-            if let Some(log) = builder.finish() {
-                inner.logs.push(log);
+            if let Some(log) = builder.finish(inner.span_tx.clock()) {
+                inner.push_log(log);
             }
-            if !inner.tags.iter().any(|x| x.name() == "error") {
+            if !inner.tags.iter().any(|x| keys_equal(x.name(), "error")) {
+                inner.span_tx.pool().tags.reserve(&mut inner.tags);
                 inner.tags.push(StdTag::error());
             }
         }
     }
 
+    /// Records `error` following the OpenTracing conventions.
+    ///
+    /// This sets the `error` tag and logs `event=error` with the fields
+    /// added by `StdErrorLogFieldsBuilder::details`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    /// use std::{fmt, io};
+    ///
+    /// #[derive(Debug)]
+    /// struct ConfigError(io::Error);
+    /// impl fmt::Display for ConfigError {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         f.write_str("cannot load the config")
+    ///     }
+    /// }
+    /// impl std::error::Error for ConfigError {
+    ///     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    ///         Some(&self.0)
+    ///     }
+    /// }
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// {
+    ///     let mut span = tracer.span("load").start_with_state(());
+    ///     let error = ConfigError(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+    ///     span.record_error(&error);
+    /// }
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.tags()[0].name(), "error");
+    /// let fields = span.logs()[0]
+    ///     .fields()
+    ///     .iter()
+    ///     .map(|f| (f.name(), f.value().as_str().unwrap()))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(
+    ///     fields,
+    ///     [
+    ///         ("error.kind", "ConfigError"),
+    ///         ("error.source", "no such file"),
+    ///         ("event", "error"),
+    ///         ("message", "cannot load the config"),
+    ///     ]
+    /// );
+    /// # }
+    /// ```
+    #[inline]
+    pub fn record_error<E>(&mut self, error: &E)
+    where
+        E: error::Error + ?Sized,
+    {
+        self.error_log(|log| {
+            log.details(error);
+        });
+    }
+
+    /// Calls `f` and finishes this span.
+    ///
+    /// If `f` panics, the panic is recorded as an error log having the fields `error.kind=panic`,
+    /// `message` (the panic message) and `panic.location`, the `error` tag is set,
+    /// and the span is finished before the panic is resumed.
+    ///
+    /// The location is recorded by a panic hook installed on the first call,
+    /// which calls the previously installed hook.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    /// use std::panic;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+    /// let span = tracer.span("request").start_with_state(());
+    /// let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+    ///     span.catch_panic(|| panic!("oops"))
+    /// }));
+    /// assert!(result.is_err());
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.tags()[0].name(), "error");
+    /// let fields = span.logs()[0].fields();
+    /// assert!(fields.iter().any(|f| f.name() == "message" && f.value().as_str() == Some("oops")));
+    /// assert!(fields.iter().any(|f| f.name() == "panic.location"));
+    /// # }
+    /// ```
+    pub fn catch_panic<F, R>(mut self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        if !self.is_sampled() {
+            return f();
+        }
+        panic::install_hook();
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+            Ok(value) => value,
+            Err(payload) => {
+                panic::record_panic(&mut self, &*payload);
+                drop(self);
+                std::panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    /// Makes a future which polls `future` and finishes this span when it completes.
+    ///
+    /// If `future` panics, the panic is recorded as `catch_panic` does.
+    pub fn catch_panic_future<F: Future>(self, future: F) -> CatchPanic<F, T> {
+        if self.is_sampled() {
+            panic::install_hook();
+        }
+        CatchPanic::new(future, self)
+    }
+
     /// Starts a `ChildOf` span if this span is sampled.
+    #[inline]
     pub fn child<N, F>(&self, operation_name: N, f: F) -> Span<T>
     where
         N: Into<Cow<'static, str>>,
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        self.handle().child(operation_name, f)
+        if let Some(inner) = self.0.as_ref() {
+            let parent = SpanReference::ChildOf(&inner.context);
+            f(inner
+                .lineage()
+                .options(operation_name, &inner.span_tx, parent))
+        } else {
+            Span::inactive()
+        }
     }
 
     /// Starts a `FollowsFrom` span if this span is sampled.
+    #[inline]
     pub fn follower<N, F>(&self, operation_name: N, f: F) -> Span<T>
     where
         N: Into<Cow<'static, str>>,
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        self.handle().follower(operation_name, f)
+        if let Some(inner) = self.0.as_ref() {
+            let parent = SpanReference::FollowsFrom(&inner.context);
+            f(inner
+                .lineage()
+                .options(operation_name, &inner.span_tx, parent))
+        } else {
+            Span::inactive()
+        }
+    }
+
+    /// Converts this span into a `SharedSpan` which can be cloned and shared among tasks.
+    pub fn into_shared(self) -> SharedSpan<T> {
+        SharedSpan(Arc::new(self))
     }
 
     pub(crate) fn new(
@@ -198,64 +930,406 @@ impl<T> Span<T> {
         baggage_items: Vec<BaggageItem>,
         span_tx: SpanSender<T>,
     ) -> Self {
-        let context = SpanContext::new(state, baggage_items);
-        let inner = SpanInner {
+        let mut context = SpanContext::new(state, baggage_items);
+        context.local = true;
+        let mut inner = SpanInner {
             operation_name,
             start_time,
-            finish_time: None,
-            references,
+            start_instant: None,
             tags,
+            shared_tags: None,
             logs: Vec::new(),
             context,
+            reference: None,
+            cold: None,
             span_tx,
         };
+        inner.set_references(references);
         Span(Some(inner))
     }
 }
+#[cfg(not(rustracing_noop))]
 impl<T> Drop for Span<T> {
     fn drop(&mut self) {
         if let Some(inner) = self.0.take() {
-            let finished = FinishedSpan {
-                operation_name: inner.operation_name,
-                start_time: inner.start_time,
-                finish_time: inner.finish_time.unwrap_or_else(SystemTime::now),
-                references: inner.references,
-                tags: inner.tags,
-                logs: inner.logs,
-                context: inner.context,
-            };
-            let _ = inner.span_tx.send(finished);
+            let (finished, span_tx) = inner.finish();
+            if let Some(finished) = finished {
+                span_tx.send(finished);
+            }
         }
     }
 }
+#[cfg(not(rustracing_noop))]
 impl<T> MaybeAsRef<SpanContext<T>> for Span<T> {
     fn maybe_as_ref(&self) -> Option<&SpanContext<T>> {
         self.context()
     }
 }
+#[cfg(not(rustracing_noop))]
+impl<T> MaybeAsRef<SpanContext<T>> for SpanHandle<T> {
+    fn maybe_as_ref(&self) -> Option<&SpanContext<T>> {
+        self.context()
+    }
+}
 
+#[cfg(not(rustracing_noop))]
 #[derive(Debug)]
 struct SpanInner<T> {
     operation_name: Cow<'static, str>,
     start_time: SystemTime,
-    finish_time: Option<SystemTime>,
-    references: Vec<SpanReference<T>>,
+
+    // The monotonic time corresponding to `start_time`, used to measure the duration.
+    // This is `None` if the start time was given explicitly.
+    start_instant: Option<Instant>,
+
     tags: Vec<Tag>,
+    shared_tags: Option<SharedTags>,
     logs: Vec<Log>,
     context: SpanContext<T>,
+
+    // The first reference (most spans have at most one).
+    reference: Option<SpanReference<T>>,
+
+    // Rarely used fields are boxed to keep `Span<T>` small.
+    cold: Option<Box<ColdFields<T>>>,
+
     span_tx: SpanSender<T>,
 }
+#[cfg(not(rustracing_noop))]
+impl<T> SpanInner<T> {
+    fn cold_mut(&mut self) -> &mut ColdFields<T> {
+        self.cold.get_or_insert_with(|| {
+            Box::new(ColdFields {
+                finish_time: None,
+                references: Vec::new(),
+                tracking_key: None,
+                deferred: false,
+                priority: None,
+                status: SpanStatus::Unset,
+                dropped: DroppedCounts::default(),
+                log_window: LogWindow::default(),
+                extensions: Extensions::new(),
+                annotations: None,
+            })
+        })
+    }
 
-/// Finished span.
-#[derive(Debug)]
-pub struct FinishedSpan<T> {
-    operation_name: Cow<'static, str>,
+    /// Finishes this span, returning `None` if it is sampled out by the finish sampler.
+    fn finish(mut self) -> (Option<FinishedSpan<T>>, SpanSender<T>) {
+        self.untrack();
+        self.merge_annotations();
+        let (status, dropped, extensions) = self
+            .cold
+            .as_mut()
+            .map(|cold| {
+                (
+                    std::mem::take(&mut cold.status),
+                    cold.dropped,
+                    std::mem::take(&mut cold.extensions),
+                )
+            })
+            .unwrap_or_default();
+        let (finish_time, references, keep) = self.take_cold_fields();
+        let clock = self.span_tx.clock();
+        let duration = match (finish_time, self.start_instant) {
+            (None, Some(start)) => clock.instant().saturating_duration_since(start),
+            (finish_time, _) => finish_time
+                .unwrap_or_else(|| clock.now())
+                .duration_since(self.start_time)
+                .unwrap_or_default(),
+        };
+        let finished = FinishedSpan {
+            operation_name: self.operation_name,
+            start_time: self.start_time,
+            duration,
+            references,
+            tags: self.tags,
+            shared_tags: self.shared_tags,
+            logs: self.logs,
+            context: self.context,
+            status,
+            dropped,
+            extensions,
+            pool: Some(Arc::clone(self.span_tx.pool())),
+        };
+        self.span_tx.observe(&finished);
+        let keep = keep.unwrap_or_else(|| {
+            self.span_tx
+                .finish_sampler()
+                .is_some_and(|s| s.is_sampled(&finished))
+        });
+        let counters = self.span_tx.counters();
+        counters.span_finished();
+        if keep {
+            (Some(finished), self.span_tx)
+        } else {
+            counters.span_sampled_out();
+            (None, self.span_tx)
+        }
+    }
+
+    /// Discards this span, returning its buffers to the pool.
+    fn abandon(mut self) {
+        self.untrack();
+        let (_, references, _) = self.take_cold_fields();
+        let pool = self.span_tx.pool();
+        pool.tags.put(self.tags);
+        pool.logs.put(self.logs);
+        pool.references.put(references);
+        pool.baggage_items.put(self.context.take_baggage_items());
+        self.span_tx.counters().span_abandoned();
+    }
+
+    fn untrack(&self) {
+        if let Some(key) = self.cold.as_ref().and_then(|cold| cold.tracking_key) {
+            if let Some(tracker) = self.span_tx.tracker() {
+                tracker.unregister(key);
+            }
+        }
+    }
+
+    fn push_tag(&mut self, mut tag: Tag) {
+        let limits = *self.span_tx.limits();
+        if is_inherited(self.span_tx.inherited_tag_names(), &tag) {
+            let inherited = self.context.inherited_tags().map(SharedTags::tags);
+            let tags = inherited.unwrap_or_default().iter();
+            let tags = tags.filter(|x| !keys_equal(x.name(), tag.name())).cloned();
+            self.context.cold_mut().inherited_tags = Some(tags.chain(Some(tag.clone())).collect());
+        }
+        self.tags.retain(|x| !keys_equal(x.name(), tag.name()));
+        if let Some(priority) = sampling_priority(std::slice::from_ref(&tag)) {
+            self.cold_mut().priority = Some(priority);
+        }
+        if !limits.allows_tag(self.tags.len()) {
+            self.cold_mut().dropped.tags += 1;
+            return;
+        }
+        limits.truncate_value(tag.name_and_value_mut().1);
+        self.tags.push(tag);
+    }
+
+    /// Merges the tags and logs added by the annotators of this span.
+    fn merge_annotations(&mut self) {
+        let Some(annotations) = self.cold.as_mut().and_then(|cold| cold.annotations.take()) else {
+            return;
+        };
+        let (tags, logs, dropped) = annotations.close();
+        if dropped != DroppedCounts::default() {
+            let cold = self.cold_mut();
+            cold.dropped.tags += dropped.tags;
+            cold.dropped.logs += dropped.logs;
+        }
+        if !tags.is_empty() {
+            self.span_tx.pool().tags.reserve(&mut self.tags);
+            for tag in tags {
+                self.push_tag(tag);
+            }
+        }
+        if !logs.is_empty() {
+            for log in logs {
+                self.push_log(log);
+            }
+            self.logs.sort_by_key(|log| log.time());
+        }
+    }
+
+    fn push_log(&mut self, mut log: Log) {
+        let limits = *self.span_tx.limits();
+        let mut dropped = DroppedCounts::default();
+        limits.limit_log(&mut log, &mut dropped);
+        if limits.collapses_repeated_logs() {
+            if let Some(last) = self.logs.last_mut() {
+                if last.is_repeated_by(&log) {
+                    last.count_repeat();
+                    return;
+                }
+            }
+        }
+        if !limits.allows_log(self.logs.len())
+            || (limits.limits_log_rate()
+                && !limits.allows_log_at(&mut self.cold_mut().log_window, log.time()))
+        {
+            self.cold_mut().dropped.logs += 1;
+            return;
+        }
+        if dropped.log_fields > 0 {
+            self.cold_mut().dropped.log_fields += dropped.log_fields;
+        }
+        self.span_tx.pool().logs.reserve(&mut self.logs);
+        self.logs.push(log);
+    }
+
+    fn set_references(&mut self, mut references: Vec<SpanReference<T>>) {
+        if references.len() > 1 {
+            self.reference = Some(references.remove(0));
+            self.cold_mut().references = references;
+        } else {
+            self.reference = references.pop();
+            self.span_tx.pool().references.put(references);
+        }
+    }
+
+    fn replace_references(&mut self, references: Vec<SpanReference<T>>) {
+        self.reference = None;
+        if let Some(cold) = self.cold.as_mut() {
+            let old = std::mem::take(&mut cold.references);
+            self.span_tx.pool().references.put(old);
+        }
+        self.set_references(references);
+    }
+
+    /// Takes over the baggage items of `context` which this span does not have.
+    fn inherit_baggage_items(&mut self, context: &SpanContext<T>) {
+        if context.baggage_items().is_empty() {
+            return;
+        }
+        let limits = *self.span_tx.limits();
+        let restrictions = self.span_tx.baggage_restrictions();
+        let pool = self.span_tx.pool();
+        let items = self.context.baggage_items_mut(pool);
+        let mut dropped = 0;
+        for item in context.baggage_items() {
+            if items.iter().any(|x| x.name == item.name) {
+                continue;
+            }
+            let allowed =
+                restrictions.map_or(true, |r| r.allows(item) && r.allows_item(items.len()));
+            if !allowed || !limits.allows_baggage_item(items.len()) {
+                dropped += 1;
+                continue;
+            }
+            let mut item = item.clone();
+            limits.truncate_str(&mut item.value);
+            items.push(item);
+        }
+        if dropped > 0 {
+            self.cold_mut().dropped.baggage_items += dropped;
+        }
+    }
+
+    fn is_deferred(&self) -> bool {
+        self.cold.as_ref().is_some_and(|cold| cold.deferred)
+    }
+
+    fn lineage(&self) -> Lineage {
+        Lineage {
+            tracking_key: self.cold.as_ref().and_then(|cold| cold.tracking_key),
+            deferred: self.is_deferred(),
+            priority: self.cold.as_ref().and_then(|cold| cold.priority),
+        }
+    }
+
+    /// Takes the finish time, the references and whether to keep this span
+    /// (`None` if it is left to the finish sampler).
+    fn take_cold_fields(&mut self) -> (Option<SystemTime>, Vec<SpanReference<T>>, Option<bool>) {
+        let (finish_time, mut references, keep) = match self.cold.take() {
+            Some(cold) => {
+                let keep = match cold.priority {
+                    Some(priority) => Some(priority > 0),
+                    None if cold.deferred => None,
+                    None => Some(true),
+                };
+                (cold.finish_time, cold.references, keep)
+            }
+            None => (None, Vec::new(), Some(true)),
+        };
+        if let Some(reference) = self.reference.take() {
+            self.span_tx.pool().references.reserve(&mut references);
+            references.insert(0, reference);
+        }
+        (finish_time, references, keep)
+    }
+}
+
+/// The state of a span passed on to its children and followers.
+#[cfg(not(rustracing_noop))]
+#[derive(Debug, Clone, Copy)]
+struct Lineage {
+    // The key of the span in the span tracker, which is the parent of the children.
+    tracking_key: Option<u64>,
+
+    // Whether the span is recorded speculatively, and so are its descendants.
+    deferred: bool,
+
+    // The sampling priority of the span, which is inherited by the descendants.
+    priority: Option<i64>,
+}
+#[cfg(not(rustracing_noop))]
+impl Lineage {
+    /// Returns `StartSpanOptions` for starting a span referring to `parent`.
+    fn options<'a, N, T>(
+        self,
+        operation_name: N,
+        span_tx: &'a SpanSender<T>,
+        parent: SpanReference<&SpanContext<T>>,
+    ) -> StartSpanOptions<'a, AllSampler, T>
+    where
+        N: Into<Cow<'static, str>>,
+        T: Clone,
+    {
+        let options = StartSpanOptions::new(operation_name, span_tx, &AllSampler);
+        let mut options = match parent {
+            SpanReference::ChildOf(context) => {
+                let mut options = options.child_of(context);
+                options.tracked_parent = self.tracking_key;
+                options
+            }
+            SpanReference::FollowsFrom(context) => options.follows_from(context),
+        };
+        options.deferred = self.deferred;
+        match self.priority {
+            Some(priority) => options.tag(Tag::new("sampling.priority", priority)),
+            None => options,
+        }
+    }
+}
+
+#[cfg(not(rustracing_noop))]
+#[derive(Debug)]
+struct ColdFields<T> {
+    finish_time: Option<SystemTime>,
+
+    // The references except the first one.
+    references: Vec<SpanReference<T>>,
+
+    // The key of this span in the span tracker of the tracer.
+    tracking_key: Option<u64>,
+
+    // Whether this span was rejected by the sampler and is recorded speculatively
+    // until the finish sampler decides whether to keep it.
+    deferred: bool,
+
+    // The value of the `sampling.priority` tag, which overrides the sampling decisions.
+    priority: Option<i64>,
+
+    status: SpanStatus,
+
+    // The numbers of the items dropped because of the `SpanLimits` of the tracer.
+    dropped: DroppedCounts,
+
+    // The window in which the logs are rate-limited.
+    log_window: LogWindow,
+
+    extensions: Extensions,
+
+    // The tags and logs added by the annotators of this span.
+    annotations: Option<Arc<Annotations>>,
+}
+
+/// Finished span.
+pub struct FinishedSpan<T> {
+    operation_name: Cow<'static, str>,
     start_time: SystemTime,
-    finish_time: SystemTime,
+    duration: Duration,
     references: Vec<SpanReference<T>>,
     tags: Vec<Tag>,
+    shared_tags: Option<SharedTags>,
     logs: Vec<Log>,
     context: SpanContext<T>,
+    status: SpanStatus,
+    dropped: DroppedCounts,
+    extensions: Extensions,
+    pool: Option<Arc<SpanPool<T>>>,
 }
 impl<T> FinishedSpan<T> {
     /// Returns the operation name of this span.
@@ -263,14 +1337,40 @@ impl<T> FinishedSpan<T> {
         self.operation_name.as_ref()
     }
 
-    /// Returns the start time of this span.
+    /// Returns the start time of this span taken from the wall clock.
     pub fn start_time(&self) -> SystemTime {
         self.start_time
     }
 
-    /// Returns the finish time of this span.
+    /// Returns the duration of this span.
+    ///
+    /// Unless the start or finish time was set explicitly,
+    /// this is measured with the monotonic clock (see `Clock::instant`).
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the finish time of this span (i.e., `start_time() + duration()`).
     pub fn finish_time(&self) -> SystemTime {
-        self.finish_time
+        self.start_time + self.duration
+    }
+
+    /// Returns the start time of this span as the duration since the UNIX epoch.
+    ///
+    /// Start times before the epoch are converted to zero.
+    pub fn start_unix_time(&self) -> Duration {
+        self.start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    /// Returns the finish time of this span as the duration since the UNIX epoch.
+    ///
+    /// Finish times before the epoch are converted to zero.
+    pub fn finish_unix_time(&self) -> Duration {
+        self.finish_time()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
     }
 
     /// Returns the logs recorded during this span.
@@ -279,10 +1379,28 @@ impl<T> FinishedSpan<T> {
     }
 
     /// Returns the tags of this span.
+    ///
+    /// Note that this does not include the shared tags (see `all_tags`).
     pub fn tags(&self) -> &[Tag] {
         &self.tags
     }
 
+    /// Returns the shared tags attached to this span.
+    pub fn shared_tags(&self) -> Option<&SharedTags> {
+        self.shared_tags.as_ref()
+    }
+
+    /// Returns an iterator over the tags of this span followed by
+    /// the shared tags not overridden by them.
+    pub fn all_tags(&self) -> impl Iterator<Item = &Tag> {
+        let shared = self.shared_tags.as_ref().map_or(&[][..], |x| x.tags());
+        self.tags.iter().chain(
+            shared
+                .iter()
+                .filter(|x| !self.tags.iter().any(|t| keys_equal(t.name(), x.name()))),
+        )
+    }
+
     /// Returns the references of this span.
     pub fn references(&self) -> &[SpanReference<T>] {
         &self.references
@@ -292,6 +1410,325 @@ impl<T> FinishedSpan<T> {
     pub fn context(&self) -> &SpanContext<T> {
         &self.context
     }
+
+    /// Returns the status of this span.
+    pub fn status(&self) -> &SpanStatus {
+        &self.status
+    }
+
+    /// Returns the extensions of this span.
+    ///
+    /// Note that the extensions are not cloned when this span is cloned.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns the mutable extensions of this span.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Returns the number of the tags dropped because of `SpanLimits`.
+    pub fn dropped_tags_count(&self) -> u32 {
+        self.dropped.tags
+    }
+
+    /// Returns the number of the logs dropped because of `SpanLimits`.
+    pub fn dropped_logs_count(&self) -> u32 {
+        self.dropped.logs
+    }
+
+    /// Returns the number of the log fields dropped because of `SpanLimits`.
+    pub fn dropped_log_fields_count(&self) -> u32 {
+        self.dropped.log_fields
+    }
+
+    /// Returns the number of the baggage items dropped because of `SpanLimits`
+    /// or `BaggageRestrictions`.
+    pub fn dropped_baggage_items_count(&self) -> u32 {
+        self.dropped.baggage_items
+    }
+
+    /// Sets the operation name of this span.
+    pub fn set_operation_name<N>(&mut self, operation_name: N)
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.operation_name = operation_name.into();
+    }
+
+    /// Sets the tag to this span.
+    ///
+    /// If this span already has a tag with the same name, it is replaced.
+    pub fn set_tag(&mut self, tag: Tag) {
+        self.tags.retain(|x| !keys_equal(x.name(), tag.name()));
+        self.tags.push(tag);
+    }
+
+    /// Sets the status of this span.
+    pub fn set_status(&mut self, status: SpanStatus) {
+        self.status = status;
+    }
+
+    pub(crate) fn tags_mut(&mut self) -> &mut Vec<Tag> {
+        &mut self.tags
+    }
+
+    pub(crate) fn logs_mut(&mut self) -> &mut Vec<Log> {
+        &mut self.logs
+    }
+
+    pub(crate) fn context_mut(&mut self) -> &mut SpanContext<T> {
+        &mut self.context
+    }
+
+    /// Copies the shared tags not overridden by the tags of this span to this span
+    /// and detaches the shared tags.
+    pub(crate) fn unshare_tags(&mut self) {
+        if let Some(shared) = self.shared_tags.take() {
+            let own = self.tags.len();
+            for tag in shared.tags() {
+                if !self.tags[..own]
+                    .iter()
+                    .any(|t| keys_equal(t.name(), tag.name()))
+                {
+                    self.tags.push(tag.clone());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn set_times(&mut self, start_time: SystemTime, duration: Duration) {
+        self.start_time = start_time;
+        self.duration = duration;
+    }
+
+    pub(crate) fn push_tag(&mut self, tag: Tag) {
+        self.tags.push(tag);
+    }
+}
+
+impl<T: Clone> Clone for FinishedSpan<T> {
+    fn clone(&self) -> Self {
+        FinishedSpan {
+            operation_name: self.operation_name.clone(),
+            start_time: self.start_time,
+            duration: self.duration,
+            references: self.references.clone(),
+            tags: self.tags.clone(),
+            shared_tags: self.shared_tags.clone(),
+            logs: self.logs.clone(),
+            context: self.context.clone(),
+            status: self.status.clone(),
+            dropped: self.dropped,
+            extensions: Extensions::new(),
+            pool: None,
+        }
+    }
+}
+impl<T: fmt::Debug> fmt::Debug for FinishedSpan<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FinishedSpan")
+            .field("operation_name", &self.operation_name)
+            .field("start_time", &self.start_time)
+            .field("duration", &self.duration)
+            .field("references", &self.references)
+            .field("tags", &self.tags)
+            .field("shared_tags", &self.shared_tags)
+            .field("logs", &self.logs)
+            .field("context", &self.context)
+            .field("status", &self.status)
+            .field("dropped", &self.dropped)
+            .field("extensions", &self.extensions)
+            .finish()
+    }
+}
+impl<T> Drop for FinishedSpan<T> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            use std::mem::take;
+            pool.tags.put(take(&mut self.tags));
+            pool.logs.put(take(&mut self.logs));
+            pool.references.put(take(&mut self.references));
+            pool.baggage_items.put(self.context.take_baggage_items());
+        }
+    }
+}
+
+/// Builder of `FinishedSpan` instances.
+///
+/// This makes finished spans directly (e.g., as the inputs of tests of reporters and converters)
+/// without starting and finishing spans with a tracer.
+/// By default, the span starts at the UNIX epoch, lasts zero seconds and has no references.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::span::{FinishedSpanBuilder, SpanStatus};
+/// use cf_rustracing::tag::Tag;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let span = FinishedSpanBuilder::new("GET /users", 2)
+///     .child_of(1)
+///     .start_time(UNIX_EPOCH + Duration::from_secs(10))
+///     .duration(Duration::from_millis(5))
+///     .tag(Tag::new("http.status_code", 500))
+///     .log(|log| {
+///         log.std().event("retry");
+///     })
+///     .status(SpanStatus::error("internal error"))
+///     .finish();
+///
+/// assert_eq!(span.context().state(), &2);
+/// assert_eq!(span.references()[0].span(), &1);
+/// assert_eq!(span.finish_unix_time(), Duration::from_millis(10_005));
+/// assert_eq!(span.logs()[0].time(), span.start_time());
+/// assert!(span.status().is_error());
+/// ```
+#[derive(Debug)]
+pub struct FinishedSpanBuilder<T> {
+    operation_name: Cow<'static, str>,
+    state: T,
+    start_time: SystemTime,
+    duration: Duration,
+    finish_time: Option<SystemTime>,
+    references: Vec<SpanReference<T>>,
+    tags: Vec<Tag>,
+    shared_tags: Option<SharedTags>,
+    logs: Vec<LogBuilder>,
+    baggage_items: Vec<BaggageItem>,
+    status: SpanStatus,
+}
+impl<T> FinishedSpanBuilder<T> {
+    /// Makes a new `FinishedSpanBuilder` instance for the span
+    /// whose context has the given operation-implementation-dependent state.
+    pub fn new<N>(operation_name: N, state: T) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        FinishedSpanBuilder {
+            operation_name: operation_name.into(),
+            state,
+            start_time: UNIX_EPOCH,
+            duration: Duration::ZERO,
+            finish_time: None,
+            references: Vec::new(),
+            tags: Vec::new(),
+            shared_tags: None,
+            logs: Vec::new(),
+            baggage_items: Vec::new(),
+            status: SpanStatus::Unset,
+        }
+    }
+
+    /// Sets the start time of the span.
+    pub fn start_time(mut self, time: SystemTime) -> Self {
+        self.start_time = time;
+        self
+    }
+
+    /// Sets the finish time of the span.
+    ///
+    /// This overrides `duration`. Finish times before the start time are converted to it.
+    pub fn finish_time(mut self, time: SystemTime) -> Self {
+        self.finish_time = Some(time);
+        self
+    }
+
+    /// Sets the duration of the span.
+    ///
+    /// This overrides `finish_time`.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self.finish_time = None;
+        self
+    }
+
+    /// Adds the `ChildOf` reference to the span having the state `state`.
+    pub fn child_of(self, state: T) -> Self {
+        self.reference(SpanReference::ChildOf(state))
+    }
+
+    /// Adds the `FollowsFrom` reference to the span having the state `state`.
+    pub fn follows_from(self, state: T) -> Self {
+        self.reference(SpanReference::FollowsFrom(state))
+    }
+
+    /// Adds the reference.
+    pub fn reference(mut self, reference: SpanReference<T>) -> Self {
+        self.references.push(reference);
+        self
+    }
+
+    /// Sets the tag to the span.
+    ///
+    /// If the span already has a tag with the same name, it is replaced.
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.tags.retain(|x| !keys_equal(x.name(), tag.name()));
+        self.tags.push(tag);
+        self
+    }
+
+    /// Attaches the shared set of tags to the span.
+    pub fn shared_tags(mut self, tags: &SharedTags) -> Self {
+        self.shared_tags = Some(tags.clone());
+        self
+    }
+
+    /// Adds the log built by `f` to the span.
+    ///
+    /// Unless the time of the log is set explicitly, it is the start time of the span.
+    /// Logs having no fields are ignored.
+    pub fn log<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut LogBuilder),
+    {
+        let mut builder = LogBuilder::new();
+        f(&mut builder);
+        self.logs.push(builder);
+        self
+    }
+
+    /// Adds the baggage item to the context of the span.
+    pub fn baggage_item(mut self, name: &str, value: &str) -> Self {
+        self.baggage_items.push(BaggageItem::new(name, value));
+        self
+    }
+
+    /// Sets the status of the span.
+    pub fn status(mut self, status: SpanStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Builds the `FinishedSpan` instance.
+    pub fn finish(self) -> FinishedSpan<T> {
+        let start_time = self.start_time;
+        let duration = self.finish_time.map_or(self.duration, |t| {
+            t.duration_since(start_time).unwrap_or_default()
+        });
+        FinishedSpan {
+            operation_name: self.operation_name,
+            start_time,
+            duration,
+            references: self.references,
+            tags: self.tags,
+            shared_tags: self.shared_tags,
+            logs: self
+                .logs
+                .into_iter()
+                .filter_map(|b| b.finish_with(|| start_time))
+                .collect(),
+            context: SpanContext {
+                local: true,
+                ..SpanContext::new(self.state, self.baggage_items)
+            },
+            status: self.status,
+            dropped: DroppedCounts::default(),
+            extensions: Extensions::new(),
+            pool: None,
+        }
+    }
 }
 
 /// Span context.
@@ -300,10 +1737,22 @@ impl<T> FinishedSpan<T> {
 ///
 /// - `T`: OpenTracing-implementation-dependent state (for example, trace and span ids) needed to refer to a distinct `Span` across a process boundary
 /// - `BaggageItems`: These are just key:value pairs that cross process boundaries
+///
+/// A context may also carry the deadline of the operation it belongs to
+/// and the vendor-specific `TraceState` entries,
+/// both of which are inherited by the spans referring to the context,
+/// as well as the `TraceFlags` of its trace.
 #[derive(Debug, Clone)]
 pub struct SpanContext<T> {
     state: T,
-    baggage_items: Vec<BaggageItem>,
+
+    // Most contexts have no baggage items nor the other rarely used fields,
+    // so they are boxed to keep spans small.
+    cold: Option<Box<ColdContextFields>>,
+
+    // Whether this context belongs to a span started in this process.
+    local: bool,
+    flags: TraceFlags,
 }
 impl<T> SpanContext<T> {
     /// Makes a new `SpanContext` instance.
@@ -313,7 +1762,16 @@ impl<T> SpanContext<T> {
         baggage_items.dedup_by(|a, b| a.name() == b.name());
         SpanContext {
             state,
-            baggage_items,
+            local: false,
+            flags: TraceFlags::default(),
+            cold: if baggage_items.is_empty() {
+                None
+            } else {
+                Some(Box::new(ColdContextFields {
+                    baggage_items,
+                    ..ColdContextFields::default()
+                }))
+            },
         }
     }
 
@@ -322,9 +1780,126 @@ impl<T> SpanContext<T> {
         &self.state
     }
 
+    /// Returns `true` unless this context belongs to a span started in this process.
+    ///
+    /// For example, contexts extracted from carriers are remote.
+    pub fn is_remote(&self) -> bool {
+        !self.local
+    }
+
+    /// Returns the sampling flags of the trace this context belongs to.
+    ///
+    /// The flags of extracted contexts are the ones carried by the carriers,
+    /// and the ones of the contexts of spans are set when the spans start
+    /// (see `StartSpanOptions::debug`).
+    pub fn flags(&self) -> TraceFlags {
+        self.flags
+    }
+
+    /// Sets the sampling flags of the trace this context belongs to.
+    ///
+    /// This is mainly intended for the extraction of contexts from carriers.
+    pub fn with_flags(mut self, flags: TraceFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Returns the deadline associated with this context.
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.cold.as_ref().and_then(|cold| cold.deadline)
+    }
+
+    /// Sets the deadline associated with this context.
+    ///
+    /// This is mainly intended for the extraction of contexts from carriers.
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.cold_mut().deadline = Some(deadline);
+        self
+    }
+
+    /// Returns the `TraceState` entries associated with this context.
+    pub fn trace_state(&self) -> &TraceState {
+        static EMPTY: TraceState = TraceState::new();
+        self.cold.as_ref().map_or(&EMPTY, |cold| &cold.trace_state)
+    }
+
+    /// Returns a mutable reference to the `TraceState` entries associated with this context.
+    pub fn trace_state_mut(&mut self) -> &mut TraceState {
+        &mut self.cold_mut().trace_state
+    }
+
+    /// Sets the `TraceState` entries associated with this context.
+    ///
+    /// This is mainly intended for the extraction of contexts from carriers.
+    pub fn with_trace_state(mut self, trace_state: TraceState) -> Self {
+        if !trace_state.is_empty() || self.cold.is_some() {
+            self.cold_mut().trace_state = trace_state;
+        }
+        self
+    }
+
+    fn inherited_tags(&self) -> Option<&SharedTags> {
+        self.cold.as_ref()?.inherited_tags.as_ref()
+    }
+
+    fn cold_mut(&mut self) -> &mut ColdContextFields {
+        self.cold.get_or_insert_with(Box::default)
+    }
+
     /// Returns the baggage items associated with this context.
     pub fn baggage_items(&self) -> &[BaggageItem] {
-        &self.baggage_items
+        self.cold.as_ref().map_or(&[], |cold| &cold.baggage_items)
+    }
+
+    /// Gets the baggage item that has the name `name`.
+    pub fn get_baggage_item(&self, name: &str) -> Option<&BaggageItem> {
+        self.baggage_items().iter().find(|x| &*x.name == name)
+    }
+
+    /// Drops the baggage items violating `restrictions`, returning the number of the dropped items.
+    ///
+    /// This is intended for contexts extracted from carriers which are forwarded
+    /// without starting spans; spans enforce the restrictions of their tracers by themselves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::span::{BaggageItem, BaggageRestrictions, SpanContext};
+    ///
+    /// let mut context = SpanContext::new(
+    ///     (),
+    ///     vec![BaggageItem::new("a", "1"), BaggageItem::new("b", "2")],
+    /// );
+    /// assert_eq!(context.restrict_baggage(&BaggageRestrictions::new().max_items(1)), 1);
+    /// assert_eq!(context.baggage_items()[0].name(), "a");
+    /// ```
+    pub fn restrict_baggage(&mut self, restrictions: &BaggageRestrictions) -> usize {
+        match self.cold.as_mut() {
+            Some(cold) => restrictions.restrict(&mut cold.baggage_items),
+            None => 0,
+        }
+    }
+
+    pub(crate) fn retain_baggage_items<F>(&mut self, f: F)
+    where
+        F: FnMut(&BaggageItem) -> bool,
+    {
+        if let Some(cold) = self.cold.as_mut() {
+            cold.baggage_items.retain(f);
+        }
+    }
+
+    fn baggage_items_mut(&mut self, pool: &SpanPool<T>) -> &mut Vec<BaggageItem> {
+        let items = &mut self.cold_mut().baggage_items;
+        pool.baggage_items.reserve(items);
+        items
+    }
+
+    fn take_baggage_items(&mut self) -> Vec<BaggageItem> {
+        self.cold
+            .as_mut()
+            .map(|cold| std::mem::take(&mut cold.baggage_items))
+            .unwrap_or_default()
     }
 
     /// Injects this context to the **Text Map** `carrier`.
@@ -387,6 +1962,16 @@ impl<T> MaybeAsRef<SpanContext<T>> for SpanContext<T> {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+struct ColdContextFields {
+    baggage_items: Vec<BaggageItem>,
+    deadline: Option<SystemTime>,
+    trace_state: TraceState,
+
+    // The tags inherited by the spans referring to this context (see `TracerBuilder::inherited_tags`).
+    inherited_tags: Option<SharedTags>,
+}
+
 /// Baggage item.
 ///
 /// `BaggageItem`s are key:value string pairs that apply to a `Span`, its `SpanContext`,
@@ -401,17 +1986,19 @@ impl<T> MaybeAsRef<SpanContext<T>> for SpanContext<T> {
 /// Use this feature thoughtfully and with care.
 /// Every key and value is copied into every local and remote child of the associated `Span`,
 /// and that can add up to a lot of network and cpu overhead.
+///
+/// The strings are reference-counted, so local children share them instead of copying them.
 #[derive(Debug, Clone)]
 pub struct BaggageItem {
-    name: String,
-    value: String,
+    name: Arc<str>,
+    value: Arc<str>,
 }
 impl BaggageItem {
     /// Makes a new `BaggageItem` instance.
     pub fn new(name: &str, value: &str) -> Self {
         BaggageItem {
-            name: name.to_owned(),
-            value: value.to_owned(),
+            name: Arc::from(name),
+            value: Arc::from(value),
         }
     }
 
@@ -446,20 +2033,126 @@ impl<T> SpanReference<T> {
         matches!(*self, SpanReference::ChildOf(_))
     }
 
-    /// Returns `true` if this is a `FollowsFrom` reference.
-    pub fn is_follows_from(&self) -> bool {
-        matches!(*self, SpanReference::FollowsFrom(_))
+    /// Returns `true` if this is a `FollowsFrom` reference.
+    pub fn is_follows_from(&self) -> bool {
+        matches!(*self, SpanReference::FollowsFrom(_))
+    }
+}
+
+/// Sampling flags of a trace, which are propagated along with span contexts.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::span::TraceFlags;
+///
+/// let flags = TraceFlags::SAMPLED | TraceFlags::DEBUG;
+/// assert!(flags.is_sampled());
+/// assert!(flags.is_debug());
+/// assert_eq!(flags.bits(), 0x03);
+/// assert!(!TraceFlags::default().is_sampled());
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceFlags(u8);
+impl TraceFlags {
+    /// The flag indicating that the trace is sampled.
+    pub const SAMPLED: TraceFlags = TraceFlags(0x01);
+
+    /// The flag indicating that the trace is forcibly sampled for debugging
+    /// (e.g., because a client has asked for it).
+    ///
+    /// Spans referring to a context having this flag are always sampled and inherit the flag.
+    pub const DEBUG: TraceFlags = TraceFlags(0x02);
+
+    /// Makes a new `TraceFlags` instance from the raw bits.
+    pub const fn from_bits(bits: u8) -> Self {
+        TraceFlags(bits)
+    }
+
+    /// Returns the raw bits of the flags.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns `true` if all the flags in `other` are set.
+    pub const fn contains(self, other: TraceFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if the sampled flag is set.
+    pub const fn is_sampled(self) -> bool {
+        self.contains(Self::SAMPLED)
+    }
+
+    /// Returns `true` if the debug flag is set.
+    pub const fn is_debug(self) -> bool {
+        self.contains(Self::DEBUG)
+    }
+}
+impl std::ops::BitOr for TraceFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        TraceFlags(self.0 | other.0)
+    }
+}
+impl std::ops::BitOrAssign for TraceFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// Kinds of the contexts which a span is a child of.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ParentKinds {
+    pub remote: bool,
+    pub local: bool,
+}
+
+/// Candidate span for tracing.
+#[derive(Debug)]
+pub struct CandidateSpan<'a, T: 'a> {
+    operation_name: &'a str,
+    tags: &'a [Tag],
+    references: &'a [SpanReference<T>],
+    baggage_items: &'a [BaggageItem],
+    parents: ParentKinds,
+    debug: bool,
+    id_generator: &'a dyn IdGenerator,
+}
+impl<'a, T: 'a> CandidateSpan<'a, T> {
+    pub(crate) fn new(
+        operation_name: &'a str,
+        tags: &'a [Tag],
+        references: &'a [SpanReference<T>],
+        baggage_items: &'a [BaggageItem],
+        parents: ParentKinds,
+        debug: bool,
+        id_generator: &'a dyn IdGenerator,
+    ) -> Self {
+        CandidateSpan {
+            operation_name,
+            tags,
+            references,
+            baggage_items,
+            parents,
+            debug,
+            id_generator,
+        }
+    }
+
+    /// Returns a new identifier from the identifier generator of the tracer.
+    ///
+    /// This is intended to be used for making the state of this span (e.g., in `T::from`).
+    pub fn generate_id(&self) -> u64 {
+        self.id_generator.generate()
+    }
+
+    /// Returns the operation name of this span.
+    pub fn operation_name(&self) -> &str {
+        self.operation_name
     }
-}
 
-/// Candidate span for tracing.
-#[derive(Debug)]
-pub struct CandidateSpan<'a, T: 'a> {
-    tags: &'a [Tag],
-    references: &'a [SpanReference<T>],
-    baggage_items: &'a [BaggageItem],
-}
-impl<'a, T: 'a> CandidateSpan<'a, T> {
     /// Returns the tags of this span.
     pub fn tags(&self) -> &[Tag] {
         self.tags
@@ -474,19 +2167,85 @@ impl<'a, T: 'a> CandidateSpan<'a, T> {
     pub fn baggage_items(&self) -> &[BaggageItem] {
         self.baggage_items
     }
+
+    /// Returns `true` if this span has a `ChildOf` reference to a remote context
+    /// (see `SpanContext::is_remote`), such as the context of an incoming request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::{ProbabilisticSampler, Sampler};
+    /// use cf_rustracing::span::{CandidateSpan, SpanContext};
+    /// use cf_rustracing::Tracer;
+    ///
+    /// // Samples all the entry points of requests from other services,
+    /// // and 1% of the other root spans.
+    /// struct EntrySampler(ProbabilisticSampler);
+    /// impl Sampler<u64> for EntrySampler {
+    ///     fn is_sampled(&self, span: &CandidateSpan<u64>) -> bool {
+    ///         span.has_remote_parent()
+    ///             || (span.references().is_empty() && self.0.is_sampled(span))
+    ///     }
+    /// }
+    ///
+    /// let sampler = EntrySampler(ProbabilisticSampler::new(0.01).unwrap());
+    /// let (tracer, _span_rx) = Tracer::new(sampler);
+    /// let remote = SpanContext::new(1, Vec::new());
+    /// let span = tracer.span("GET /users").child_of(&remote).start_with_state(2);
+    /// # #[cfg(not(rustracing_noop))]
+    /// assert!(span.is_sampled());
+    /// ```
+    pub fn has_remote_parent(&self) -> bool {
+        self.parents.remote
+    }
+
+    /// Returns `true` if this span has a `ChildOf` reference to the context of a span
+    /// started in this process.
+    ///
+    /// Such parents are recorded, so their children continue traces which are already sampled.
+    pub fn has_local_parent(&self) -> bool {
+        self.parents.local
+    }
+
+    /// Returns `true` if this span is forcibly sampled for debugging
+    /// (see `StartSpanOptions::debug`).
+    ///
+    /// The states of such spans are expected to be marked as sampled.
+    pub fn is_debug(&self) -> bool {
+        self.debug
+    }
 }
 
+#[cfg(not(rustracing_noop))]
 /// Options for starting a span.
 #[derive(Debug)]
 pub struct StartSpanOptions<'a, S: 'a, T: 'a> {
     operation_name: Cow<'static, str>,
     start_time: Option<SystemTime>,
     tags: Vec<Tag>,
+    shared_tags: Option<SharedTags>,
     references: Vec<SpanReference<T>>,
     baggage_items: Vec<BaggageItem>,
+    deadline: Option<SystemTime>,
+    trace_state: Option<Box<TraceState>>,
+    inherited_tags: Option<SharedTags>,
+    tracked_parent: Option<u64>,
+
+    // Whether this span is started by a speculatively recorded span, and so is recorded speculatively.
+    deferred: bool,
+
+    // Whether this span has `ChildOf` references to remote and local contexts.
+    parents: ParentKinds,
+
+    // Whether this span is forcibly sampled for debugging.
+    debug: bool,
+
+    // The number of the baggage items dropped because of the `BaggageRestrictions` of the tracer.
+    dropped_baggage_items: u32,
     span_tx: &'a SpanSender<T>,
     sampler: &'a S,
 }
+#[cfg(not(rustracing_noop))]
 impl<'a, S: 'a, T: 'a> StartSpanOptions<'a, S, T>
 where
     S: Sampler<T>,
@@ -497,12 +2256,71 @@ where
         self
     }
 
+    /// Sets the deadline of this span.
+    ///
+    /// Spans inherit the deadlines of the contexts they refer to,
+    /// and the earliest one of them and the one given here is used.
+    pub fn deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        self
+    }
+
     /// Sets the tag to this span.
     pub fn tag(mut self, tag: Tag) -> Self {
+        self.span_tx.pool().tags.reserve(&mut self.tags);
         self.tags.push(tag);
         self
     }
 
+    /// Attaches the shared set of tags to this span.
+    ///
+    /// The tags of this span take precedence over the shared tags having the same names.
+    /// The set replaces the default tags of the tracer (see `TracerBuilder::default_tags`).
+    pub fn shared_tags(mut self, tags: &SharedTags) -> Self {
+        self.shared_tags = Some(tags.clone());
+        self
+    }
+
+    /// Forces this span to be sampled for debugging, whatever the sampler and
+    /// the `sampling.priority` tag decide.
+    ///
+    /// The context of this span gets `TraceFlags::DEBUG`,
+    /// so the spans referring to it (including the ones in other processes,
+    /// if the propagator carries the flag) are also sampled.
+    /// Spans referring to contexts having the flag are treated in the same way
+    /// without calling this method.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::NullSampler;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, _span_rx) = Tracer::new(NullSampler);
+    /// let span = tracer.span("forced").debug().start_with_state(());
+    /// let child = span.child("child", |options| options.start_with_state(()));
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// assert!(span.is_sampled());
+    /// assert!(child.context().unwrap().flags().is_debug());
+    /// # }
+    /// ```
+    pub fn debug(mut self) -> Self {
+        self.debug = true;
+        self
+    }
+
+    /// Adds the `ChildOf` reference to the active span (see `Tracer::active_span`), if any.
+    pub fn child_of_active(self) -> Self
+    where
+        T: Clone + 'static,
+    {
+        match scope::active_span::<T>() {
+            Some(active) => self.child_of(&active),
+            None => self,
+        }
+    }
+
     /// Adds the `ChildOf` reference to this span.
     pub fn child_of<C>(mut self, context: &C) -> Self
     where
@@ -511,9 +2329,20 @@ where
     {
         if let Some(context) = context.maybe_as_ref() {
             let reference = SpanReference::ChildOf(context.state().clone());
+            if context.is_remote() {
+                self.parents.remote = true;
+            } else {
+                self.parents.local = true;
+            }
+            self.debug |= context.flags.is_debug();
+            self.reserve_references(context);
             self.references.push(reference);
             self.baggage_items
                 .extend(context.baggage_items().iter().cloned());
+            if let Some(deadline) = context.deadline() {
+                self = self.deadline(deadline);
+            }
+            self.inherit_trace_state(context);
         }
         self
     }
@@ -526,49 +2355,47 @@ where
     {
         if let Some(context) = context.maybe_as_ref() {
             let reference = SpanReference::FollowsFrom(context.state().clone());
+            self.debug |= context.flags.is_debug();
+            self.reserve_references(context);
             self.references.push(reference);
             self.baggage_items
                 .extend(context.baggage_items().iter().cloned());
+            if let Some(deadline) = context.deadline() {
+                self = self.deadline(deadline);
+            }
+            self.inherit_trace_state(context);
         }
         self
     }
 
     /// Starts a new span.
+    ///
+    /// If the span is not sampled (nor recorded speculatively for the finish sampler of the tracer),
+    /// this neither calls `T::from` nor reads the clock.
     pub fn start(mut self) -> Span<T>
     where
         T: for<'b> From<CandidateSpan<'b, T>>,
     {
         self.normalize();
-        if !self.is_sampled() {
+        let Some(deferred) = self.sampling() else {
+            self.recycle();
             return Span(None);
-        }
+        };
         let state = T::from(self.span());
-        Span::new(
-            self.operation_name,
-            self.start_time.unwrap_or_else(SystemTime::now),
-            self.references,
-            self.tags,
-            state,
-            self.baggage_items,
-            self.span_tx.clone(),
-        )
+        self.start_recording(state, deferred)
     }
 
     /// Starts a new span with the explicit `state`.
+    ///
+    /// If the span is not sampled (nor recorded speculatively for the finish sampler of the tracer),
+    /// this does not read the clock.
     pub fn start_with_state(mut self, state: T) -> Span<T> {
         self.normalize();
-        if !self.is_sampled() {
+        let Some(deferred) = self.sampling() else {
+            self.recycle();
             return Span(None);
-        }
-        Span::new(
-            self.operation_name,
-            self.start_time.unwrap_or_else(SystemTime::now),
-            self.references,
-            self.tags,
-            state,
-            self.baggage_items,
-            self.span_tx.clone(),
-        )
+        };
+        self.start_recording(state, deferred)
     }
 
     pub(crate) fn new<N>(operation_name: N, span_tx: &'a SpanSender<T>, sampler: &'a S) -> Self
@@ -579,48 +2406,223 @@ where
             operation_name: operation_name.into(),
             start_time: None,
             tags: Vec::new(),
+            shared_tags: span_tx.default_tags().cloned(),
             references: Vec::new(),
             baggage_items: Vec::new(),
+            deadline: None,
+            trace_state: None,
+            inherited_tags: None,
+            tracked_parent: None,
+            deferred: false,
+            parents: ParentKinds::default(),
+            debug: false,
+            dropped_baggage_items: 0,
             span_tx,
             sampler,
         }
     }
 
+    /// Inherits the `TraceState` entries and the inherited tags of the first referred context having any.
+    fn inherit_trace_state(&mut self, context: &SpanContext<T>) {
+        if self.trace_state.is_none() && !context.trace_state().is_empty() {
+            self.trace_state = Some(Box::new(context.trace_state().clone()));
+        }
+        if self.inherited_tags.is_none() {
+            self.inherited_tags = context.inherited_tags().cloned();
+        }
+    }
+
+    /// Starts a span which records its data.
+    ///
+    /// This is the only place where the start time of a span is taken from the clock,
+    /// so no timestamp is taken for spans which are not recorded.
+    fn start_recording(mut self, state: T, deferred: bool) -> Span<T> {
+        self.span_tx.counters().span_started();
+        let priority = sampling_priority(&self.tags);
+        let mut dropped = DroppedCounts::default();
+        self.span_tx
+            .limits()
+            .limit_tags(&mut self.tags, &mut dropped);
+        dropped.baggage_items = self.dropped_baggage_items;
+        let clock = self.span_tx.clock();
+        let (start_time, start_instant) = match self.start_time {
+            Some(start_time) => (start_time, None),
+            None => (clock.now(), clock.has_instant().then(|| clock.instant())),
+        };
+        let mut span = Span::new(
+            self.operation_name,
+            start_time,
+            self.references,
+            self.tags,
+            state,
+            self.baggage_items,
+            self.span_tx.clone(),
+        );
+        if let Some(inner) = span.0.as_mut() {
+            inner.start_instant = start_instant;
+            inner.shared_tags = self.shared_tags;
+            if let Some(deadline) = self.deadline {
+                inner.context.cold_mut().deadline = Some(deadline);
+            }
+            if let Some(trace_state) = self.trace_state {
+                inner.context.cold_mut().trace_state = *trace_state;
+            }
+            if !deferred {
+                inner.context.flags |= TraceFlags::SAMPLED;
+            }
+            if self.debug {
+                inner.context.flags |= TraceFlags::DEBUG;
+            }
+            if let Some(tags) = inherited_tags(
+                self.span_tx.inherited_tag_names(),
+                &inner.tags,
+                self.inherited_tags,
+            ) {
+                inner.context.cold_mut().inherited_tags = Some(tags);
+            }
+            if deferred || priority.is_some() || dropped != DroppedCounts::default() {
+                let cold = inner.cold_mut();
+                cold.deferred = deferred;
+                cold.priority = priority;
+                cold.dropped = dropped;
+            }
+            if let Some(tracker) = self.span_tx.tracker() {
+                let key = tracker.register(inner.operation_name.clone(), self.tracked_parent);
+                inner.cold_mut().tracking_key = Some(key);
+            }
+        }
+        span
+    }
+
+    fn reserve_references(&mut self, context: &SpanContext<T>) {
+        let pool = self.span_tx.pool();
+        pool.references.reserve(&mut self.references);
+        if !context.baggage_items().is_empty() {
+            pool.baggage_items.reserve(&mut self.baggage_items);
+        }
+    }
+
+    /// Recycles the buffers of this span rejected by the sampler.
+    fn recycle(self) {
+        self.span_tx.counters().span_sampled_out();
+        let pool = self.span_tx.pool();
+        pool.tags.put(self.tags);
+        pool.references.put(self.references);
+        pool.baggage_items.put(self.baggage_items);
+    }
+
     fn normalize(&mut self) {
+        if let Some(inherited) = self.inherited_tags.as_ref() {
+            // The inherited tags are placed first, so that the tags of this span take precedence.
+            self.span_tx.pool().tags.reserve(&mut self.tags);
+            self.tags.splice(0..0, inherited.tags().iter().cloned());
+        }
         self.tags.reverse();
-        self.tags.sort_by(|a, b| a.name().cmp(b.name()));
-        self.tags.dedup_by(|a, b| a.name() == b.name());
+        self.tags.sort_by(|a, b| cmp_keys(a.name(), b.name()));
+        self.tags.dedup_by(|a, b| keys_equal(a.name(), b.name()));
 
         self.baggage_items.reverse();
         self.baggage_items.sort_by(|a, b| a.name().cmp(b.name()));
         self.baggage_items.dedup_by(|a, b| a.name() == b.name());
+        if let Some(restrictions) = self.span_tx.baggage_restrictions() {
+            self.dropped_baggage_items = restrictions.restrict(&mut self.baggage_items) as u32;
+        }
     }
 
-    fn span(&self) -> CandidateSpan<T> {
-        CandidateSpan {
-            references: &self.references,
-            tags: &self.tags,
-            baggage_items: &self.baggage_items,
+    fn span(&self) -> CandidateSpan<'_, T> {
+        CandidateSpan::new(
+            &self.operation_name,
+            &self.tags,
+            &self.references,
+            &self.baggage_items,
+            self.parents,
+            self.debug,
+            self.span_tx.id_generator(),
+        )
+    }
+
+    /// Decides whether this span is recorded, adding the tags of the sampler's decision.
+    ///
+    /// This returns `Some(true)` if the span is recorded speculatively for the finish sampler,
+    /// and `None` if it is not recorded.
+    /// This must be called after `normalize` because the tags are inserted in order.
+    fn sampling(&mut self) -> Option<bool> {
+        if self.span_tx.is_disabled() {
+            return None;
+        }
+        if self.debug {
+            return Some(false);
+        }
+        if let Some(priority) = sampling_priority(&self.tags) {
+            return (priority > 0).then_some(false);
+        }
+        let decision = self.sampler.sample(&self.span());
+        if !decision.is_sampled() {
+            return self.span_tx.records_rejected_spans().then_some(true);
         }
+        for tag in decision.into_tags() {
+            if let Err(i) = self
+                .tags
+                .binary_search_by(|t| cmp_keys(t.name(), tag.name()))
+            {
+                self.tags.insert(i, tag);
+            }
+        }
+        Some(self.deferred)
     }
+}
 
-    fn is_sampled(&self) -> bool {
-        if let Some(&TagValue::Integer(n)) = self
-            .tags
-            .iter()
-            .find(|t| t.name() == "sampling.priority")
-            .map(|t| t.value())
-        {
-            n > 0
-        } else {
-            self.sampler.is_sampled(&self.span())
+/// Returns the value of the `sampling.priority` tag in `tags`.
+#[cfg(not(rustracing_noop))]
+fn sampling_priority(tags: &[Tag]) -> Option<i64> {
+    tags.iter()
+        .find(|t| keys_equal(t.name(), "sampling.priority"))
+        .and_then(|t| match *t.value() {
+            TagValue::Integer(n) => Some(n),
+            _ => None,
+        })
+}
+
+/// Returns `true` if `tag` is inherited by the descendants of spans.
+#[cfg(not(rustracing_noop))]
+fn is_inherited(names: &[Cow<'static, str>], tag: &Tag) -> bool {
+    names.iter().any(|name| keys_equal(name, tag.name()))
+}
+
+/// Returns the tags in `tags` inherited by the descendants of a span.
+///
+/// `parent` is reused if the span has not overridden any of them.
+#[cfg(not(rustracing_noop))]
+fn inherited_tags(
+    names: &[Cow<'static, str>],
+    tags: &[Tag],
+    parent: Option<SharedTags>,
+) -> Option<SharedTags> {
+    if names.is_empty() {
+        return None;
+    }
+    let inherited = || tags.iter().filter(|tag| is_inherited(names, tag));
+    if let Some(parent) = parent {
+        let unchanged = inherited().count() == parent.tags().len()
+            && inherited().all(|tag| {
+                parent
+                    .tags()
+                    .iter()
+                    .any(|p| keys_equal(p.name(), tag.name()) && p.value() == tag.value())
+            });
+        if unchanged {
+            return Some(parent);
         }
     }
+    let inherited = inherited().cloned().collect::<Vec<_>>();
+    (!inherited.is_empty()).then(|| SharedTags::new(inherited))
 }
 
+#[cfg(not(rustracing_noop))]
 /// Immutable handle of `Span`.
 #[derive(Debug, Clone)]
-pub struct SpanHandle<T>(Option<(SpanContext<T>, SpanSender<T>)>);
+pub struct SpanHandle<T>(Option<(SpanContext<T>, SpanSender<T>, Lineage)>);
+#[cfg(not(rustracing_noop))]
 impl<T> SpanHandle<T> {
     /// Returns `true` if this span is sampled (i.e., being traced).
     pub fn is_sampled(&self) -> bool {
@@ -629,16 +2631,13 @@ impl<T> SpanHandle<T> {
 
     /// Returns the context of this span.
     pub fn context(&self) -> Option<&SpanContext<T>> {
-        self.0.as_ref().map(|(context, _)| context)
+        self.0.as_ref().map(|(context, _, _)| context)
     }
 
     /// Gets the baggage item that has the name `name`.
     pub fn get_baggage_item(&self, name: &str) -> Option<&BaggageItem> {
-        if let Some(context) = self.context() {
-            context.baggage_items.iter().find(|x| x.name == name)
-        } else {
-            None
-        }
+        self.context()
+            .and_then(|context| context.get_baggage_item(name))
     }
 
     /// Starts a `ChildOf` span if this span is sampled.
@@ -648,10 +2647,8 @@ impl<T> SpanHandle<T> {
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        if let Some((context, span_tx)) = self.0.as_ref() {
-            let options =
-                StartSpanOptions::new(operation_name, span_tx, &AllSampler).child_of(context);
-            f(options)
+        if let Some((context, span_tx, lineage)) = self.0.as_ref() {
+            f(lineage.options(operation_name, span_tx, SpanReference::ChildOf(context)))
         } else {
             Span::inactive()
         }
@@ -664,12 +2661,685 @@ impl<T> SpanHandle<T> {
         T: Clone,
         F: FnOnce(StartSpanOptions<AllSampler, T>) -> Span<T>,
     {
-        if let Some((context, span_tx)) = self.0.as_ref() {
-            let options =
-                StartSpanOptions::new(operation_name, span_tx, &AllSampler).follows_from(context);
-            f(options)
+        if let Some((context, span_tx, lineage)) = self.0.as_ref() {
+            f(lineage.options(operation_name, span_tx, SpanReference::FollowsFrom(context)))
         } else {
             Span::inactive()
         }
     }
 }
+
+#[cfg(not(rustracing_noop))]
+/// Cloneable span shared among tasks.
+///
+/// The span is finished when the last clone is dropped.
+/// Dropping a clone is a single atomic decrement, so spans whose handles are dropped
+/// from many tasks (e.g., a parent span of fan-out requests) never take a lock.
+///
+/// The span can no longer be modified once it has been shared,
+/// but the immutable methods of `Span` (e.g., `child`) are available via `Deref`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+/// let span = tracer.span("parent").start_with_state(()).into_shared();
+/// let tasks = (0..4)
+///     .map(|_| {
+///         let span = span.clone();
+///         tokio::spawn(async move {
+///             let _child = span.child("child", |options| options.start_with_state(()));
+///         })
+///     })
+///     .collect::<Vec<_>>();
+/// drop(span);
+/// for task in tasks {
+///     task.await.unwrap();
+/// }
+///
+/// let mut names = Vec::new();
+/// for _ in 0..5 {
+///     names.push(span_rx.recv().await.unwrap().operation_name().to_owned());
+/// }
+/// assert_eq!(names.iter().filter(|name| *name == "parent").count(), 1);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SharedSpan<T>(Arc<Span<T>>);
+#[cfg(not(rustracing_noop))]
+impl<T> Clone for SharedSpan<T> {
+    fn clone(&self) -> Self {
+        SharedSpan(Arc::clone(&self.0))
+    }
+}
+#[cfg(not(rustracing_noop))]
+impl<T> std::ops::Deref for SharedSpan<T> {
+    type Target = Span<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+#[cfg(not(rustracing_noop))]
+impl<T> From<Span<T>> for SharedSpan<T> {
+    fn from(f: Span<T>) -> Self {
+        f.into_shared()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    #[cfg(all(target_pointer_width = "64", not(rustracing_noop)))]
+    fn span_size() {
+        // See the documentation of `Span`.
+        assert_eq!(size_of::<Span<()>>(), 152);
+        assert_eq!(size_of::<Span<[u64; 4]>>(), 152 + 2 * 32);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn duration_is_measured_with_monotonic_clock() {
+        use crate::clock::MockClock;
+        use crate::testing::TestTracer;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let start = UNIX_EPOCH + Duration::from_secs(100);
+        let clock = MockClock::new(start);
+        let tracer = TestTracer::with_clock(clock.clone());
+        {
+            let _span = tracer.span("foo").start_with_state(());
+            clock.advance(Duration::from_secs(1));
+            clock.set(UNIX_EPOCH);
+        }
+        {
+            let _span = tracer.span("bar").start_time(start).start_with_state(());
+            clock.set(start + Duration::from_secs(2));
+        }
+
+        let spans = tracer.spans();
+        assert_eq!(spans[0].start_time(), start);
+        assert_eq!(spans[0].duration(), Duration::from_secs(1));
+        assert_eq!(spans[0].finish_time(), start + Duration::from_secs(1));
+        assert_eq!(spans[1].duration(), Duration::from_secs(2));
+        assert_eq!(spans[1].finish_unix_time(), Duration::from_secs(102));
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn panics_are_recorded() {
+        use crate::sampler::AllSampler;
+        use crate::Tracer;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::task::{Context, Poll, Waker};
+
+        let (tracer, mut span_rx) = Tracer::new(AllSampler);
+        let span = tracer.span("ok").start_with_state(());
+        assert_eq!(span.catch_panic(|| 1), 1);
+        assert!(span_rx.try_recv().unwrap().logs().is_empty());
+
+        let span = tracer.span("future").start_with_state(());
+        let mut future = Box::pin(span.catch_panic_future(async {
+            std::future::ready(()).await;
+            panic!("{}", "failed");
+        }));
+        let mut cx = Context::from_waker(Waker::noop());
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let _: Poll<()> = future.as_mut().poll(&mut cx);
+        }));
+        assert!(result.is_err());
+
+        let span = span_rx.try_recv().unwrap();
+        assert_eq!(span.operation_name(), "future");
+        assert_eq!(span.tags()[0].name(), "error");
+        let fields = span.logs()[0].fields();
+        let field = |name| {
+            fields
+                .iter()
+                .find(|f| f.name() == name)
+                .and_then(|f| f.value().as_str())
+        };
+        assert_eq!(field("error.kind"), Some("panic"));
+        assert_eq!(field("message"), Some("failed"));
+        assert!(field("panic.location").unwrap().starts_with("src/span.rs:"));
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn default_tags_are_attached() {
+        use crate::sampler::AllSampler;
+        use crate::Tracer;
+
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .default_tags(vec![Tag::new("service.version", "1.0")])
+            .finish();
+        tracer.span("foo").start_with_state(());
+        let shared = SharedTags::new(vec![Tag::new("tenant", "a")]);
+        tracer.span("bar").shared_tags(&shared).start_with_state(());
+
+        let span = span_rx.try_recv().unwrap();
+        assert!(span.tags().is_empty());
+        let names = span.all_tags().map(|t| t.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["service.version"]);
+        let span = span_rx.try_recv().unwrap();
+        let names = span.all_tags().map(|t| t.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["tenant"]);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn span_limits_are_enforced() {
+        use crate::sampler::AllSampler;
+        use crate::Tracer;
+
+        let limits = SpanLimits::new()
+            .max_tags(2)
+            .max_log_fields(1)
+            .max_baggage_items(1)
+            .max_value_len(4);
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler).span_limits(limits).finish();
+        let mut span = tracer
+            .span("foo")
+            .tag(Tag::new("a", "héllo"))
+            .tag(Tag::new("b", 1))
+            .tag(Tag::new("c", 2))
+            .start_with_state(());
+        span.set_tag(|| Tag::new("d", 3));
+        span.set_tag(|| Tag::new("a", "x"));
+        span.log(|log| {
+            log.field(("event", "foo")).field(("message", "bar"));
+        });
+        span.set_baggage_item(|| BaggageItem::new("user", "alice"));
+        span.set_baggage_item(|| BaggageItem::new("tenant", "a"));
+        drop(span);
+
+        let span = span_rx.try_recv().unwrap();
+        let names = span.tags().iter().map(|t| t.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a"]);
+        assert_eq!(span.dropped_tags_count(), 2);
+        assert_eq!(span.logs()[0].fields().len(), 1);
+        assert_eq!(span.dropped_log_fields_count(), 1);
+        assert_eq!(span.context().baggage_items()[0].value(), "alic");
+        assert_eq!(span.dropped_baggage_items_count(), 1);
+        assert_eq!(span.dropped_logs_count(), 0);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn tags_are_inherited() {
+        use crate::sampler::AllSampler;
+        use crate::Tracer;
+
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+            .inherited_tags(["tenant.id", "request.id"])
+            .finish();
+        let mut root = tracer
+            .span("root")
+            .tag(Tag::new("tenant.id", "acme"))
+            .tag(Tag::new("user", "alice"))
+            .start_with_state(());
+        root.set_tag(|| Tag::new("request.id", 1));
+        let child = root.child("child", |options| {
+            options.tag(Tag::new("request.id", 2)).start_with_state(())
+        });
+        let grandchild = child.child("grandchild", |options| options.start_with_state(()));
+        let sibling = root.follower("sibling", |options| options.start_with_state(()));
+        drop((grandchild, child, sibling, root));
+
+        let tags = |span: FinishedSpan<()>| {
+            let tags = span.tags().iter();
+            tags.map(|t| (t.name().to_owned(), t.value().clone()))
+                .collect::<Vec<_>>()
+        };
+        let expected = |request_id: i64| {
+            vec![
+                ("request.id".to_owned(), TagValue::Integer(request_id)),
+                ("tenant.id".to_owned(), TagValue::from("acme")),
+            ]
+        };
+        assert_eq!(tags(span_rx.try_recv().unwrap()), expected(2));
+        assert_eq!(tags(span_rx.try_recv().unwrap()), expected(2));
+        assert_eq!(tags(span_rx.try_recv().unwrap()), expected(1));
+        assert_eq!(tags(span_rx.try_recv().unwrap()).len(), 3);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn logs_are_rate_limited() {
+        use crate::sampler::AllSampler;
+        use crate::Tracer;
+
+        let limits = SpanLimits::new().max_logs_per_second(2);
+        let (tracer, mut span_rx) = Tracer::builder(AllSampler).span_limits(limits).finish();
+        let mut span = tracer.span("foo").start_with_state(());
+        for millis in [0, 100, 200, 900, 1000, 1100, 1200] {
+            span.log(|log| {
+                log.field_i64("millis", millis)
+                    .time(UNIX_EPOCH + Duration::from_millis(millis as u64));
+            });
+        }
+        drop(span);
+
+        let span = span_rx.try_recv().unwrap();
+        let millis = span
+            .logs()
+            .iter()
+            .map(|log| log.fields()[0].value().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(millis, [0, 100, 1000, 1100].map(TagValue::Integer));
+        assert_eq!(span.dropped_logs_count(), 3);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn baggage_items_are_inherited() {
+        use crate::context::DefaultContext;
+        use crate::testing::TestTracer;
+        use std::collections::HashMap;
+
+        let tracer = TestTracer::<DefaultContext>::new();
+        let mut parent = tracer.span("parent").start();
+        parent.set_baggage_item(|| BaggageItem::new("user", "alice"));
+        parent.set_baggage_item(|| BaggageItem::new("user", "bob"));
+        assert_eq!(parent.baggage_item("user"), Some("bob"));
+
+        let child = parent.child("child", |options| options.start());
+        assert_eq!(child.baggage_item("user"), Some("bob"));
+        assert_eq!(
+            child.handle().get_baggage_item("user").unwrap().value(),
+            "bob"
+        );
+
+        let mut carrier = HashMap::new();
+        track_try_unwrap!(child.context().unwrap().inject_to_text_map(&mut carrier));
+        let extracted = track_try_unwrap!(SpanContext::<DefaultContext>::extract_from_text_map(
+            &carrier
+        ))
+        .unwrap();
+        assert_eq!(extracted.get_baggage_item("user").unwrap().value(), "bob");
+        assert!(extracted.get_baggage_item("other").is_none());
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn trace_state_is_inherited() {
+        use crate::context::DefaultContext;
+        use crate::testing::TestTracer;
+        use std::collections::HashMap;
+
+        let tracer = TestTracer::<DefaultContext>::new();
+        let mut parent = tracer.span("parent").start();
+        parent.update_trace_state(|s| track_try_unwrap!(s.insert("rojo", "00f067aa0ba902b7")));
+        let mut child = parent.child("child", |options| options.start());
+        child.update_trace_state(|s| track_try_unwrap!(s.insert("congo", "t61rcWkgMzE")));
+        assert_eq!(parent.context().unwrap().trace_state().len(), 1);
+
+        let mut carrier = HashMap::new();
+        track_try_unwrap!(child.context().unwrap().inject_to_text_map(&mut carrier));
+        assert_eq!(
+            carrier["tracestate"],
+            "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7"
+        );
+        let extracted = track_try_unwrap!(SpanContext::<DefaultContext>::extract_from_text_map(
+            &carrier
+        ))
+        .unwrap();
+        assert_eq!(
+            extracted.trace_state(),
+            child.context().unwrap().trace_state()
+        );
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn rejected_spans_are_sampled_at_finish() {
+        use crate::clock::MockClock;
+        use crate::sampler::NullSampler;
+        use crate::Tracer;
+
+        let clock = MockClock::default();
+        let (tracer, mut span_rx) = Tracer::builder(NullSampler)
+            .clock(clock.clone())
+            .finish_sampler(|span: &FinishedSpan<()>| span.duration() >= Duration::from_secs(1))
+            .finish();
+        {
+            let span = tracer.span("slow").start_with_state(());
+            assert!(span.is_sampled());
+            span.child("fast", |options| options.start_with_state(()));
+            clock.advance(Duration::from_secs(1));
+        }
+        let span = tracer
+            .span("unsampled")
+            .tag(Tag::new("sampling.priority", 0))
+            .start_with_state(());
+        assert!(!span.is_sampled());
+
+        let names = std::iter::from_fn(|| span_rx.try_recv().ok())
+            .map(|s| s.operation_name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["slow"]);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn sampling_priority_overrides_decisions() {
+        use crate::sampler::NullSampler;
+        use crate::tag::StdTag;
+        use crate::Tracer;
+
+        let (tracer, mut span_rx) = Tracer::builder(NullSampler)
+            .finish_sampler(|_: &FinishedSpan<()>| false)
+            .finish();
+        {
+            let mut keep = tracer.span("keep").start_with_state(());
+            keep.set_tag(|| StdTag::sampling_priority(1));
+            keep.child("keep_child", |options| options.start_with_state(()));
+
+            // Handles pass the priority and the speculative state on as well.
+            let handle = keep.handle();
+            handle.child("handle_child", |options| options.start_with_state(()));
+            handle.follower("handle_follower", |options| options.start_with_state(()));
+            let deferred = tracer.span("deferred").start_with_state(());
+            let child = deferred
+                .handle()
+                .child("deferred_child", |options| options.start_with_state(()));
+            assert!(child.is_sampled());
+
+            let mut drop = keep.child("drop", |options| options.start_with_state(()));
+            drop.set_tag(|| StdTag::sampling_priority(0));
+            let child = drop.child("drop_child", |options| options.start_with_state(()));
+            assert!(!child.is_sampled());
+            let child = drop
+                .handle()
+                .child("drop_child", |options| options.start_with_state(()));
+            assert!(!child.is_sampled());
+        }
+        let names = std::iter::from_fn(|| span_rx.try_recv().ok())
+            .map(|s| s.operation_name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["keep_child", "handle_child", "handle_follower", "keep"]
+        );
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn baggage_restrictions_are_enforced() {
+        use crate::testing::TestTracer;
+        use crate::Tracer;
+
+        let restrictions = BaggageRestrictions::new()
+            .allow_key("ok*")
+            .max_items(2)
+            .max_value_len(3);
+        let tracer = TestTracer::<()>::from_builder(
+            Tracer::builder(AllSampler).baggage_restrictions(restrictions),
+        );
+        let remote = SpanContext::new(
+            (),
+            vec![BaggageItem::new("ok1", "a"), BaggageItem::new("ng", "b")],
+        );
+        {
+            let mut span = tracer.span("foo").child_of(&remote).start_with_state(());
+            span.set_baggage_item(|| BaggageItem::new("ng", "c"));
+            span.set_baggage_item(|| BaggageItem::new("ok2", "d"));
+            span.set_baggage_item(|| BaggageItem::new("ok3", "e"));
+
+            // Rejected updates leave the existing items intact,
+            // and accepted ones are not counted against `max_items`.
+            span.set_baggage_item(|| BaggageItem::new("ok1", "too long"));
+            span.set_baggage_item(|| BaggageItem::new("ok2", "f"));
+        }
+        let span = &tracer.spans()[0];
+        let items = span
+            .context()
+            .baggage_items()
+            .iter()
+            .map(|item| (item.name(), item.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(items, [("ok1", "a"), ("ok2", "f")]);
+        assert_eq!(span.dropped_baggage_items_count(), 4);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn spans_are_reparented() {
+        use crate::context::DefaultContext;
+        use crate::testing::TestTracer;
+        use std::time::Duration;
+
+        let tracer = TestTracer::<DefaultContext>::new();
+        let deadline = UNIX_EPOCH + Duration::from_secs(10);
+        let remote = SpanContext::new(
+            DefaultContext::new(0xabc, 0x12, 1),
+            vec![
+                BaggageItem::new("a", "remote"),
+                BaggageItem::new("b", "remote"),
+            ],
+        )
+        .with_deadline(deadline)
+        .with_flags(TraceFlags::DEBUG);
+        {
+            let first = tracer.span("first").start();
+            let second = tracer.span("second").start();
+            let mut span = tracer
+                .span("span")
+                .child_of(&first)
+                .follows_from(&second)
+                .start();
+            span.set_baggage_item(|| BaggageItem::new("a", "local"));
+            span.set_parent(&remote);
+
+            let context = span.context().unwrap();
+            assert_eq!(context.state().trace_id(), 0xabc);
+            assert_eq!(context.deadline(), Some(deadline));
+            assert!(context.flags().is_debug());
+            assert_eq!(span.baggage_item("a"), Some("local"));
+            assert_eq!(span.baggage_item("b"), Some("remote"));
+        }
+        let span = &tracer.find_by_operation("span")[0];
+        let parents = span
+            .references()
+            .iter()
+            .map(|r| *r.span())
+            .collect::<Vec<_>>();
+        assert_eq!(parents, [*remote.state()]);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn debug_contexts_force_sampling() {
+        use crate::context::DefaultContext;
+        use crate::sampler::NullSampler;
+        use crate::Tracer;
+
+        let (tracer, _span_rx) = Tracer::new(NullSampler);
+        let remote = SpanContext::new(DefaultContext::new(0xabc, 0x12, 0), Vec::new())
+            .with_flags(TraceFlags::DEBUG);
+        let span = tracer.span("server").child_of(&remote).start();
+        let context = span.context().unwrap();
+        assert_eq!(context.flags(), TraceFlags::SAMPLED | TraceFlags::DEBUG);
+        assert!(context.state().is_sampled());
+
+        let remote = SpanContext::new(*remote.state(), Vec::new());
+        let span = tracer.span("server").child_of(&remote).start();
+        assert!(!span.is_sampled());
+        let span = tracer
+            .span("forced")
+            .tag(Tag::new("sampling.priority", 0))
+            .debug()
+            .start();
+        assert!(span.is_sampled());
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn deadlines_are_inherited() {
+        use crate::clock::MockClock;
+        use crate::testing::TestTracer;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let clock = MockClock::new(UNIX_EPOCH);
+        let tracer = TestTracer::with_clock(clock.clone());
+        let deadline = UNIX_EPOCH + Duration::from_secs(10);
+        let parent = tracer
+            .span("parent")
+            .deadline(deadline)
+            .start_with_state(());
+        assert_eq!(parent.remaining_time(), Some(Duration::from_secs(10)));
+
+        // Children cannot extend the deadlines of their parents.
+        let child = parent.child("child", |options| {
+            options
+                .deadline(deadline + Duration::from_secs(1))
+                .start_with_state(())
+        });
+        assert_eq!(child.context().unwrap().deadline(), Some(deadline));
+        let child = parent.child("child", |options| {
+            options
+                .deadline(deadline - Duration::from_secs(5))
+                .start_with_state(())
+        });
+        assert_eq!(child.remaining_time(), Some(Duration::from_secs(5)));
+
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(parent.remaining_time(), Some(Duration::ZERO));
+        assert_eq!(
+            tracer.span("foo").start_with_state(()).remaining_time(),
+            None
+        );
+    }
+
+    #[test]
+    fn concurrent_types_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Span<()>>();
+        assert_send_sync::<SharedSpan<()>>();
+        assert_send_sync::<SpanHandle<()>>();
+        assert_send_sync::<SpanSender<()>>();
+        assert_send_sync::<crate::Tracer<crate::sampler::AllSampler, ()>>();
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn shared_span_is_finished_once() {
+        use crate::testing::{assert_parent_child, TestTracer};
+        use std::thread;
+
+        let tracer = TestTracer::<u32>::new();
+        let parent = tracer.span("parent").start_with_state(0).into_shared();
+        let threads = (1..=8)
+            .map(|i| {
+                let parent = parent.clone();
+                thread::spawn(move || {
+                    for j in 0..10 {
+                        parent.child("child", |options| options.start_with_state(i * 100 + j));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(parent);
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let spans = tracer.spans();
+        assert_eq!(spans.len(), 81);
+        let parents = tracer.find_by_operation("parent");
+        assert_eq!(parents.len(), 1);
+        for child in &spans[..80] {
+            assert_parent_child(&parents[0], child);
+        }
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn samplers_see_tags_and_remote_parents() {
+        use crate::sampler::Sampler;
+        use crate::Tracer;
+
+        struct TestSampler;
+        impl Sampler<u64> for TestSampler {
+            fn is_sampled(&self, span: &CandidateSpan<u64>) -> bool {
+                span.has_remote_parent()
+                    || span
+                        .tags()
+                        .iter()
+                        .any(|t| t.name() == "http.method" && t.value().as_str() == Some("DELETE"))
+            }
+        }
+
+        let (tracer, _span_rx) = Tracer::new(TestSampler);
+        let remote = SpanContext::new(1, Vec::new());
+        assert!(remote.is_remote());
+        let span = tracer.span("entry").child_of(&remote).start_with_state(2);
+        assert!(span.is_sampled());
+        assert!(!span.context().unwrap().is_remote());
+
+        let local = span.context().unwrap().clone();
+        let child = tracer.span("child").child_of(&local).start_with_state(3);
+        assert!(!child.is_sampled());
+        let follower = tracer
+            .span("follower")
+            .follows_from(&remote)
+            .start_with_state(4);
+        assert!(!follower.is_sampled());
+        let delete = tracer
+            .span("delete")
+            .tag(Tag::new("http.method", "DELETE"))
+            .start_with_state(5);
+        assert!(delete.is_sampled());
+    }
+
+    #[test]
+    fn finished_spans_are_built() {
+        use std::time::Duration;
+
+        let start = UNIX_EPOCH + Duration::from_secs(1);
+        let span = FinishedSpanBuilder::new("foo", 3)
+            .follows_from(1)
+            .child_of(2)
+            .duration(Duration::from_secs(5))
+            .start_time(start)
+            .finish_time(start + Duration::from_secs(2))
+            .tag(Tag::new("a", 1))
+            .tag(Tag::new("a", 2))
+            .log(|log| {
+                log.std().event("first");
+            })
+            .log(|log| {
+                log.time(start + Duration::from_secs(1))
+                    .std()
+                    .event("second");
+            })
+            .log(|_| {})
+            .baggage_item("user", "alice")
+            .finish();
+
+        assert_eq!(span.operation_name(), "foo");
+        assert_eq!(span.duration(), Duration::from_secs(2));
+        assert!(span.references()[0].is_follows_from());
+        assert!(span.references()[1].is_child_of());
+        assert_eq!(span.tags().len(), 1);
+        assert_eq!(span.tags()[0].value(), &TagValue::Integer(2));
+        let times = span.logs().iter().map(|l| l.time()).collect::<Vec<_>>();
+        assert_eq!(times, [start, start + Duration::from_secs(1)]);
+        assert_eq!(span.context().baggage_items()[0].value(), "alice");
+        assert_eq!(span.status(), &SpanStatus::Unset);
+
+        let span = FinishedSpanBuilder::new("bar", ())
+            .start_time(start)
+            .finish_time(UNIX_EPOCH)
+            .finish();
+        assert_eq!(span.duration(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,218 @@
+use super::{DroppedCounts, SpanLimits};
+use crate::clock::Clock;
+use crate::log::{Log, LogBuilder, StdErrorLogFieldsBuilder};
+use crate::tag::{keys_equal, StdTag, Tag};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+/// Cloneable handle adding tags and logs to an open span.
+///
+/// Annotators are returned by `Span::annotator` and can be shared by the tasks working for the span
+/// (e.g., the workers of a fan-out request handler).
+/// The tags and logs added by them are merged into the span when it finishes.
+/// Annotators do not keep the span alive: after it finishes, they silently do nothing.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::tag::Tag;
+/// use cf_rustracing::Tracer;
+///
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+/// let mut span = tracer.span("fan_out").start_with_state(());
+/// let annotator = span.annotator();
+/// let workers = (0..3)
+///     .map(|i| {
+///         let annotator = annotator.clone();
+///         std::thread::spawn(move || {
+///             annotator.log(|log| {
+///                 log.field_i64("worker", i);
+///             });
+///         })
+///     })
+///     .collect::<Vec<_>>();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+/// annotator.set_tag(|| Tag::new("workers", 3));
+/// drop(span);
+/// assert!(!annotator.is_open());
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let span = span_rx.try_recv().unwrap();
+/// assert_eq!(span.tags()[0].name(), "workers");
+/// assert_eq!(span.logs().len(), 3);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SpanAnnotator(Weak<Annotations>);
+impl SpanAnnotator {
+    /// Returns `true` if the span annotated by this handle is still open.
+    pub fn is_open(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    /// Sets the tag to the span.
+    pub fn set_tag<F>(&self, f: F)
+    where
+        F: FnOnce() -> Tag,
+    {
+        if let Some(annotations) = self.0.upgrade() {
+            let tag = f();
+            annotations.lock().push(Some(tag), None);
+        }
+    }
+
+    /// Logs structured data to the span.
+    pub fn log<F>(&self, f: F)
+    where
+        F: FnOnce(&mut LogBuilder),
+    {
+        if let Some(annotations) = self.0.upgrade() {
+            let mut builder = LogBuilder::new();
+            f(&mut builder);
+            let log = builder.finish(&*annotations.clock);
+            annotations.lock().push(None, log);
+        }
+    }
+
+    /// Logs an error to the span, setting the `StdTag::error()` tag.
+    pub fn error_log<F>(&self, f: F)
+    where
+        F: FnOnce(&mut StdErrorLogFieldsBuilder),
+    {
+        if let Some(annotations) = self.0.upgrade() {
+            let mut builder = LogBuilder::new();
+            f(&mut builder.error());
+            let log = builder.finish(&*annotations.clock);
+            annotations.lock().push(Some(StdTag::error()), log);
+        }
+    }
+
+    pub(crate) fn new(annotations: &Arc<Annotations>) -> Self {
+        SpanAnnotator(Arc::downgrade(annotations))
+    }
+
+    pub(crate) fn inactive() -> Self {
+        SpanAnnotator(Weak::new())
+    }
+}
+impl fmt::Debug for SpanAnnotator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanAnnotator")
+            .field("is_open", &self.is_open())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The tags and logs added to a span by its annotators.
+///
+/// The `SpanLimits` of the span are applied while they are buffered,
+/// so that annotators outliving their work cannot grow the span without bound.
+pub(crate) struct Annotations {
+    clock: Arc<dyn Clock>,
+    pending: Mutex<Pending>,
+}
+impl Annotations {
+    pub fn new(clock: Arc<dyn Clock>, limits: SpanLimits) -> Self {
+        Annotations {
+            clock,
+            pending: Mutex::new(Pending {
+                tags: Vec::new(),
+                logs: Vec::new(),
+                limits,
+                dropped: DroppedCounts::default(),
+                closed: false,
+            }),
+        }
+    }
+
+    /// Takes the tags and logs added so far and the numbers of the dropped ones,
+    /// ignoring the ones added afterwards.
+    pub fn close(&self) -> (Vec<Tag>, Vec<Log>, DroppedCounts) {
+        let mut pending = self.lock();
+        pending.closed = true;
+        (
+            std::mem::take(&mut pending.tags),
+            std::mem::take(&mut pending.logs),
+            pending.dropped,
+        )
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Pending> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+impl fmt::Debug for Annotations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Annotations").finish_non_exhaustive()
+    }
+}
+
+struct Pending {
+    tags: Vec<Tag>,
+    logs: Vec<Log>,
+    limits: SpanLimits,
+    dropped: DroppedCounts,
+
+    // Whether the span has taken the annotations to finish.
+    closed: bool,
+}
+impl Pending {
+    fn push(&mut self, tag: Option<Tag>, log: Option<Log>) {
+        if self.closed {
+            return;
+        }
+        if let Some(tag) = tag {
+            self.tags.retain(|x| !keys_equal(x.name(), tag.name()));
+            if self.limits.allows_tag(self.tags.len()) {
+                self.tags.push(tag);
+            } else {
+                self.dropped.tags += 1;
+            }
+        }
+        if let Some(log) = log {
+            if self.limits.allows_log(self.logs.len()) {
+                self.logs.push(log);
+            } else {
+                self.dropped.logs += 1;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    #[test]
+    fn buffered_annotations_are_limited() {
+        let limits = SpanLimits::new().max_tags(2).max_logs(2);
+        let annotations = Arc::new(Annotations::new(Arc::new(SystemClock), limits));
+        let annotator = SpanAnnotator::new(&annotations);
+        for (name, value) in [("a", 1), ("a", 2), ("b", 3), ("c", 4)] {
+            annotator.set_tag(|| Tag::new(name, value));
+        }
+        for i in 0..5 {
+            annotator.log(|log| {
+                log.field_i64("i", i);
+            });
+        }
+
+        let (tags, logs, dropped) = annotations.close();
+        let tags = tags
+            .iter()
+            .map(|tag| (tag.name(), tag.value().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, [("a", 2.into()), ("b", 3.into())]);
+        assert_eq!(logs.len(), 2);
+        assert_eq!(dropped.tags, 1);
+        assert_eq!(dropped.logs, 3);
+
+        annotator.set_tag(|| Tag::new("d", 5));
+        assert!(annotations.close().0.is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use super::BaggageItem;
+use std::borrow::Cow;
+
+/// Restrictions on the baggage items propagated through a tracer.
+///
+/// The restrictions are enforced by `Span::set_baggage_item` and when spans take over
+/// the baggage items of the contexts they refer to (e.g., contexts extracted from carriers),
+/// so that a misbehaving upstream cannot pass unbounded data through the process.
+/// Items violating them are dropped rather than truncated,
+/// and counted by `FinishedSpan::dropped_baggage_items_count`.
+///
+/// Contexts which are forwarded without starting spans can be restricted
+/// with `SpanContext::restrict_baggage`.
+///
+/// All restrictions are disabled by default.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::span::{BaggageItem, BaggageRestrictions, SpanContext};
+/// use cf_rustracing::Tracer;
+///
+/// let restrictions = BaggageRestrictions::new()
+///     .allow_key("user_id")
+///     .allow_key("tenant.*")
+///     .max_value_len(16);
+/// let (tracer, _span_rx) = Tracer::builder(AllSampler)
+///     .baggage_restrictions(restrictions)
+///     .finish();
+///
+/// let remote = SpanContext::new(
+///     1,
+///     vec![
+///         BaggageItem::new("user_id", "42"),
+///         BaggageItem::new("tenant.name", "acme"),
+///         BaggageItem::new("tenant.plan", "a value which is too long"),
+///         BaggageItem::new("secret", "hunter2"),
+///     ],
+/// );
+/// let span = tracer.span("foo").child_of(&remote).start_with_state(2);
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let items = span.context().unwrap().baggage_items();
+/// let names = items.iter().map(|item| item.name()).collect::<Vec<_>>();
+/// assert_eq!(names, ["tenant.name", "user_id"]);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaggageRestrictions {
+    allowed_keys: Option<Vec<Cow<'static, str>>>,
+    max_items: usize,
+    max_value_len: usize,
+}
+impl BaggageRestrictions {
+    /// Makes a new `BaggageRestrictions` instance without any restrictions.
+    pub fn new() -> Self {
+        BaggageRestrictions {
+            allowed_keys: None,
+            max_items: usize::MAX,
+            max_value_len: usize::MAX,
+        }
+    }
+
+    /// Allows the baggage items whose names match `pattern`.
+    ///
+    /// A pattern ending with `*` matches the names starting with the rest of it,
+    /// and the other patterns match only the names equal to them.
+    /// Once a pattern is added, the items whose names match none of the patterns are dropped.
+    pub fn allow_key<P>(mut self, pattern: P) -> Self
+    where
+        P: Into<Cow<'static, str>>,
+    {
+        self.allowed_keys
+            .get_or_insert_with(Vec::new)
+            .push(pattern.into());
+        self
+    }
+
+    /// Sets the maximum number of the baggage items of a context.
+    pub fn max_items(mut self, n: usize) -> Self {
+        self.max_items = n;
+        self
+    }
+
+    /// Sets the maximum length in bytes of the values of baggage items.
+    pub fn max_value_len(mut self, len: usize) -> Self {
+        self.max_value_len = len;
+        self
+    }
+
+    /// Returns `true` if `item` is allowed, regardless of the number of the items.
+    pub fn allows(&self, item: &BaggageItem) -> bool {
+        item.value().len() <= self.max_value_len && self.allows_key(item.name())
+    }
+
+    fn allows_key(&self, name: &str) -> bool {
+        let Some(patterns) = &self.allowed_keys else {
+            return true;
+        };
+        patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// Returns `true` if a context having `n` baggage items can have one more item.
+    pub(crate) fn allows_item(&self, n: usize) -> bool {
+        n < self.max_items
+    }
+
+    /// Drops the items violating the restrictions, returning the number of the dropped items.
+    ///
+    /// If there are too many items, the ones following the first `max_items` ones are dropped.
+    pub(crate) fn restrict(&self, items: &mut Vec<BaggageItem>) -> usize {
+        let len = items.len();
+        items.retain(|item| self.allows(item));
+        items.truncate(self.max_items);
+        len - items.len()
+    }
+}
+impl Default for BaggageRestrictions {
+    fn default() -> Self {
+        BaggageRestrictions::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_are_restricted() {
+        let restrictions = BaggageRestrictions::new()
+            .allow_key("id")
+            .allow_key("app.*")
+            .max_items(2)
+            .max_value_len(3);
+        let mut items = vec![
+            BaggageItem::new("app.a", "1"),
+            BaggageItem::new("app.b", "1234"),
+            BaggageItem::new("app", "1"),
+            BaggageItem::new("id", "1"),
+            BaggageItem::new("idx", "1"),
+            BaggageItem::new("app.c", "1"),
+        ];
+        assert_eq!(restrictions.restrict(&mut items), 4);
+        let names = items.iter().map(|item| item.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["app.a", "id"]);
+
+        let mut items = vec![BaggageItem::new("anything", "")];
+        assert_eq!(BaggageRestrictions::new().restrict(&mut items), 0);
+        assert!(BaggageRestrictions::new().allow_key("*").allows(&items[0]));
+    }
+}
@@ -0,0 +1,97 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+/// A map of values keyed by their types, attached to a span.
+///
+/// This allows layers of an application to pass data to each other (or to span processors)
+/// through spans without encoding it into tags.
+/// Extensions are never reported.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::Tracer;
+///
+/// struct Retries(u32);
+///
+/// let (tracer, mut span_rx) = Tracer::new(AllSampler);
+/// let mut span = tracer.span("foo").start_with_state(());
+/// if let Some(extensions) = span.extensions_mut() {
+///     extensions.insert(Retries(1));
+/// }
+/// if let Some(retries) = span.extensions_mut().and_then(|e| e.get_mut::<Retries>()) {
+///     retries.0 += 1;
+/// }
+/// drop(span);
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let span = span_rx.try_recv().unwrap();
+/// assert_eq!(span.extensions().get::<Retries>().map(|r| r.0), Some(2));
+/// # }
+/// ```
+#[derive(Default)]
+#[allow(clippy::box_collection)] // Boxed to keep `FinishedSpan` small.
+pub struct Extensions(Option<Box<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>);
+impl Extensions {
+    /// Makes a new empty `Extensions` instance.
+    pub const fn new() -> Self {
+        Extensions(None)
+    }
+
+    /// Inserts `value`, returning the previous value of the same type if any.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.0
+            .get_or_insert_with(Default::default)
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    /// Returns a reference to the value of the type `T`.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.0
+            .as_ref()
+            .and_then(|map| map.get(&TypeId::of::<T>()))
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns a mutable reference to the value of the type `T`.
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.0
+            .as_mut()
+            .and_then(|map| map.get_mut(&TypeId::of::<T>()))
+            .and_then(|value| value.downcast_mut())
+    }
+
+    /// Removes the value of the type `T` and returns it.
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.0
+            .as_mut()
+            .and_then(|map| map.remove(&TypeId::of::<T>()))
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+
+    /// Returns the number of the values.
+    pub fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |map| map.len())
+    }
+
+    /// Returns `true` if there are no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+// Extensions are opaque to the library, so spans keep being unwind safe as they were
+// before extensions were added.
+impl UnwindSafe for Extensions {}
+impl RefUnwindSafe for Extensions {}
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
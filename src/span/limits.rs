@@ -0,0 +1,281 @@
+#[cfg(not(rustracing_noop))]
+use crate::log::Log;
+#[cfg(not(rustracing_noop))]
+use crate::tag::{Tag, TagValue};
+#[cfg(not(rustracing_noop))]
+use std::borrow::Cow;
+#[cfg(not(rustracing_noop))]
+use std::sync::Arc;
+#[cfg(not(rustracing_noop))]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Limits on the data recorded by each span.
+///
+/// The data exceeding the limits is dropped when it is recorded,
+/// and the numbers of the dropped items are reported by
+/// `FinishedSpan::dropped_tags_count` and its siblings.
+/// Values longer than `max_value_len` are truncated instead.
+///
+/// All limits are disabled by default.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::span::SpanLimits;
+/// use cf_rustracing::Tracer;
+///
+/// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+///     .span_limits(SpanLimits::new().max_logs(2))
+///     .finish();
+/// let mut span = tracer.span("foo").start_with_state(());
+/// for i in 0..5 {
+///     span.log(|log| {
+///         log.field_i64("i", i);
+///     });
+/// }
+/// drop(span);
+///
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// let span = span_rx.try_recv().unwrap();
+/// assert_eq!(span.logs().len(), 2);
+/// assert_eq!(span.dropped_logs_count(), 3);
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanLimits {
+    max_tags: usize,
+    max_logs: usize,
+    max_log_fields: usize,
+    max_baggage_items: usize,
+    max_value_len: usize,
+    max_logs_per_second: u32,
+    collapse_repeated_logs: bool,
+}
+impl SpanLimits {
+    /// Makes a new `SpanLimits` instance without any limits.
+    pub fn new() -> Self {
+        SpanLimits {
+            max_tags: usize::MAX,
+            max_logs: usize::MAX,
+            max_log_fields: usize::MAX,
+            max_baggage_items: usize::MAX,
+            max_value_len: usize::MAX,
+            max_logs_per_second: u32::MAX,
+            collapse_repeated_logs: false,
+        }
+    }
+
+    /// Sets the maximum number of the tags of a span.
+    ///
+    /// The shared tags are not counted.
+    pub fn max_tags(mut self, n: usize) -> Self {
+        self.max_tags = n;
+        self
+    }
+
+    /// Sets the maximum number of the logs of a span.
+    pub fn max_logs(mut self, n: usize) -> Self {
+        self.max_logs = n;
+        self
+    }
+
+    /// Sets the maximum number of the fields of a log.
+    ///
+    /// Since the fields of a log are sorted by name, the fields following the first `n` ones
+    /// in that order are dropped.
+    pub fn max_log_fields(mut self, n: usize) -> Self {
+        self.max_log_fields = n;
+        self
+    }
+
+    /// Sets the maximum number of the baggage items of a span.
+    pub fn max_baggage_items(mut self, n: usize) -> Self {
+        self.max_baggage_items = n;
+        self
+    }
+
+    /// Sets the maximum number of the logs recorded by a span per second.
+    ///
+    /// The logs exceeding the rate are dropped and counted by `FinishedSpan::dropped_logs_count`.
+    /// The rate is measured with the timestamps of the logs.
+    pub fn max_logs_per_second(mut self, n: u32) -> Self {
+        self.max_logs_per_second = n;
+        self
+    }
+
+    /// Makes spans collapse each log into the previous one if they have the same fields.
+    ///
+    /// The number of the collapsed logs is recorded in the `repeat_count` field of the previous log,
+    /// and they are not counted by `max_logs` nor `max_logs_per_second`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::SpanLimits;
+    /// use cf_rustracing::tag::TagValue;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+    ///     .span_limits(SpanLimits::new().collapse_repeated_logs(true))
+    ///     .finish();
+    /// let mut span = tracer.span("foo").start_with_state(());
+    /// for _ in 0..3 {
+    ///     span.log(|log| {
+    ///         log.std().message("retrying");
+    ///     });
+    /// }
+    /// drop(span);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.logs().len(), 1);
+    /// let field = &span.logs()[0].fields()[1];
+    /// assert_eq!(field.name(), "repeat_count");
+    /// assert_eq!(field.value(), &TagValue::Integer(2));
+    /// # }
+    /// ```
+    pub fn collapse_repeated_logs(mut self, collapse: bool) -> Self {
+        self.collapse_repeated_logs = collapse;
+        self
+    }
+
+    /// Sets the maximum length in bytes of the string values of tags, log fields and baggage items.
+    pub fn max_value_len(mut self, len: usize) -> Self {
+        self.max_value_len = len;
+        self
+    }
+}
+impl SpanLimits {
+    /// Returns `true` if a span having `n` tags can have one more tag.
+    pub(crate) fn allows_tag(&self, n: usize) -> bool {
+        n < self.max_tags
+    }
+
+    /// Returns `true` if a span having `n` logs can have one more log.
+    pub(crate) fn allows_log(&self, n: usize) -> bool {
+        n < self.max_logs
+    }
+}
+impl Default for SpanLimits {
+    fn default() -> Self {
+        SpanLimits::new()
+    }
+}
+#[cfg(not(rustracing_noop))]
+impl SpanLimits {
+    /// Applies the limits to the tags given when a span is started.
+    pub(crate) fn limit_tags(&self, tags: &mut Vec<Tag>, dropped: &mut DroppedCounts) {
+        if tags.len() > self.max_tags {
+            dropped.tags += (tags.len() - self.max_tags) as u32;
+            tags.truncate(self.max_tags);
+        }
+        for tag in tags {
+            self.truncate_value(tag.name_and_value_mut().1);
+        }
+    }
+
+    /// Returns `true` if a span having `n` baggage items can have one more item.
+    pub(crate) fn allows_baggage_item(&self, n: usize) -> bool {
+        n < self.max_baggage_items
+    }
+
+    pub(crate) fn collapses_repeated_logs(&self) -> bool {
+        self.collapse_repeated_logs
+    }
+
+    /// Returns `true` if the logs of spans are rate-limited.
+    pub(crate) fn limits_log_rate(&self) -> bool {
+        self.max_logs_per_second != u32::MAX
+    }
+
+    /// Returns `true` if a log recorded at `time` is within the rate limit.
+    pub(crate) fn allows_log_at(&self, window: &mut LogWindow, time: SystemTime) -> bool {
+        let elapsed = time.duration_since(window.start).unwrap_or_default();
+        if window.count == 0 || elapsed >= Duration::from_secs(1) {
+            window.start = time;
+            window.count = 0;
+        }
+        if window.count < self.max_logs_per_second {
+            window.count += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Applies the limits to the fields of `log`.
+    pub(crate) fn limit_log(&self, log: &mut Log, dropped: &mut DroppedCounts) {
+        dropped.log_fields += log.truncate_fields(self.max_log_fields) as u32;
+        if self.max_value_len != usize::MAX {
+            for field in log.fields_mut() {
+                self.truncate_value(field.name_and_value_mut().1);
+            }
+        }
+    }
+
+    pub(crate) fn truncate_value(&self, value: &mut TagValue) {
+        if self.max_value_len == usize::MAX {
+            return;
+        }
+        match value {
+            TagValue::String(v) if v.len() > self.max_value_len => {
+                let len = char_boundary(v, self.max_value_len);
+                match v {
+                    Cow::Borrowed(s) => {
+                        let s: &'static str = s;
+                        *v = Cow::Borrowed(&s[..len]);
+                    }
+                    Cow::Owned(s) => s.truncate(len),
+                }
+            }
+            TagValue::Array(values) => values.iter_mut().for_each(|v| self.truncate_value(v)),
+            TagValue::Map(entries) => entries.values_mut().for_each(|v| self.truncate_value(v)),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn truncate_str(&self, value: &mut Arc<str>) {
+        if value.len() > self.max_value_len {
+            *value = Arc::from(&value[..char_boundary(value, self.max_value_len)]);
+        }
+    }
+}
+
+/// Returns the largest char boundary of `s` not exceeding `len`.
+#[cfg(not(rustracing_noop))]
+fn char_boundary(s: &str, mut len: usize) -> usize {
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
+
+/// The numbers of the items dropped by a span because of `SpanLimits`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DroppedCounts {
+    pub tags: u32,
+    pub logs: u32,
+    pub log_fields: u32,
+    pub baggage_items: u32,
+}
+
+/// The one-second window in which the logs of a span are rate-limited.
+#[cfg(not(rustracing_noop))]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LogWindow {
+    start: SystemTime,
+    count: u32,
+}
+#[cfg(not(rustracing_noop))]
+impl Default for LogWindow {
+    fn default() -> Self {
+        LogWindow {
+            start: UNIX_EPOCH,
+            count: 0,
+        }
+    }
+}
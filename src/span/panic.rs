@@ -0,0 +1,94 @@
+use super::Span;
+use crate::tag::StdTag;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Once;
+use std::task::{Context, Poll};
+
+thread_local! {
+    // The location of the last panic on this thread, recorded by the panic hook.
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Installs the panic hook recording the locations of panics.
+///
+/// The hook calls the previously installed one,
+/// so the locations are not recorded if the hook is replaced afterwards.
+pub(crate) fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(location) = info.location() {
+                PANIC_LOCATION.with(|l| *l.borrow_mut() = Some(location.to_string()));
+            }
+            previous(info);
+        }));
+    });
+    PANIC_LOCATION.with(|l| l.borrow_mut().take());
+}
+
+/// Records the panic having the payload `payload` as an error log of `span`.
+pub(crate) fn record_panic<T>(span: &mut Span<T>, payload: &(dyn Any + Send)) {
+    let location = PANIC_LOCATION.with(|l| l.borrow_mut().take());
+    span.log(|log| {
+        if let Some(location) = location {
+            log.field(("panic.location", location));
+        }
+        let message = if let Some(s) = payload.downcast_ref::<&'static str>() {
+            (*s).to_owned()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "Box<dyn Any>".to_owned()
+        };
+        log.error().kind("panic").message(message);
+    });
+    span.set_tag(StdTag::error);
+}
+
+/// Future returned by `Span::catch_panic_future`.
+#[must_use = "futures do nothing unless polled"]
+pub struct CatchPanic<F, T> {
+    future: F,
+    span: Option<Span<T>>,
+}
+impl<F, T> CatchPanic<F, T> {
+    pub(crate) fn new(future: F, span: Span<T>) -> Self {
+        CatchPanic {
+            future,
+            span: Some(span),
+        }
+    }
+}
+impl<F: Future, T> Future for CatchPanic<F, T> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // SAFETY: `future` is never moved out of `self`, and the other fields are not pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => {
+                this.span = None;
+                Poll::Ready(output)
+            }
+            Err(payload) => {
+                if let Some(mut span) = this.span.take() {
+                    record_panic(&mut span, &*payload);
+                }
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+impl<F, T> fmt::Debug for CatchPanic<F, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CatchPanic").finish_non_exhaustive()
+    }
+}
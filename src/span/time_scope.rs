@@ -0,0 +1,78 @@
+use super::Span;
+use crate::clock::Clock;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::time::{Instant, SystemTime};
+
+/// Guard returned by `Span::time_scope`.
+///
+/// When this is dropped, the elapsed time since its creation is logged to the span.
+/// It dereferences to the span, so the span can still be used while the phase runs.
+#[must_use = "the phase ends as soon as the guard is dropped"]
+pub struct TimeScope<'a, T> {
+    span: &'a mut Span<T>,
+    name: Cow<'static, str>,
+    start: Option<Start>,
+}
+impl<'a, T> TimeScope<'a, T> {
+    pub(crate) fn new(span: &'a mut Span<T>, name: Cow<'static, str>) -> Self {
+        let start = span.clock().map(Start::now);
+        TimeScope { span, name, start }
+    }
+}
+impl<T> Deref for TimeScope<'_, T> {
+    type Target = Span<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.span
+    }
+}
+impl<T> DerefMut for TimeScope<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.span
+    }
+}
+impl<T> Drop for TimeScope<'_, T> {
+    fn drop(&mut self) {
+        let (Some(start), Some(clock)) = (self.start, self.span.clock()) else {
+            return;
+        };
+        let elapsed = start.elapsed(clock);
+        let name = std::mem::take(&mut self.name);
+        self.span.log(|log| {
+            log.std().event(name);
+            log.field_f64("elapsed_ms", elapsed.as_secs_f64() * 1000.0);
+        });
+    }
+}
+impl<T> fmt::Debug for TimeScope<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeScope")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Start time of a phase, taken from the monotonic clock if it is available.
+#[derive(Clone, Copy)]
+enum Start {
+    Instant(Instant),
+    Time(SystemTime),
+}
+impl Start {
+    fn now(clock: &dyn Clock) -> Self {
+        if clock.has_instant() {
+            Start::Instant(clock.instant())
+        } else {
+            Start::Time(clock.now())
+        }
+    }
+
+    fn elapsed(self, clock: &dyn Clock) -> std::time::Duration {
+        match self {
+            Start::Instant(start) => clock.instant().saturating_duration_since(start),
+            Start::Time(start) => clock.now().duration_since(start).unwrap_or_default(),
+        }
+    }
+}
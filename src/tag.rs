@@ -1,6 +1,100 @@
 //! Span tag.
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
+use std::ptr;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// The names of the standard span tags and log fields.
+///
+/// These are registered to the interner in advance.
+const STD_KEYS: &[&str] = &[
+    "component",
+    "db.instance",
+    "db.statement",
+    "db.type",
+    "db.user",
+    "error",
+    "http.method",
+    "http.status_code",
+    "http.url",
+    "message_bus.destination",
+    "peer.address",
+    "peer.hostname",
+    "peer.ipv4",
+    "peer.ipv6",
+    "peer.port",
+    "peer.service",
+    "sampling.priority",
+    "span.kind",
+    "db.operation",
+    "db.system",
+    "http.route",
+    "messaging.destination.name",
+    "messaging.operation",
+    "messaging.system",
+    "rpc.method",
+    "rpc.service",
+    "rpc.system",
+    "server.address",
+    "server.port",
+    "event",
+    "message",
+    "error.kind",
+    "error.object",
+    "stack",
+];
+
+/// Interns `key` as a tag key (or a log field name).
+///
+/// All calls with equal keys return the same `&'static str`,
+/// so repeated keys share one allocation and tags having them
+/// are compared by pointer in the dedup and lookup paths of spans.
+///
+/// Interned keys live until the process exits.
+/// So this should not be used for unbounded sets of keys (e.g., keys containing IDs).
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::tag::{intern, Tag};
+///
+/// let key = format!("{}.{}", "http", "route");
+/// let tag = Tag::new(intern(&key), "/users/:id");
+/// assert!(std::ptr::eq(tag.name(), intern("http.route")));
+/// ```
+pub fn intern(key: &str) -> &'static str {
+    static INTERNER: OnceLock<RwLock<HashSet<&'static str>>> = OnceLock::new();
+    let interner = INTERNER.get_or_init(|| RwLock::new(STD_KEYS.iter().copied().collect()));
+
+    if let Some(interned) = interner.read().unwrap_or_else(|e| e.into_inner()).get(key) {
+        return interned;
+    }
+    let mut keys = interner.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(interned) = keys.get(key) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(key.to_owned().into_boxed_str());
+    keys.insert(interned);
+    interned
+}
+
+/// Returns `true` if `a` and `b` are the same key.
+///
+/// Interned keys are equal if and only if they are the same pointer,
+/// so comparing pointers first skips the byte comparison in the common case.
+pub(crate) fn keys_equal(a: &str, b: &str) -> bool {
+    ptr::eq(a, b) || a == b
+}
+
+/// Compares keys in the same manner as `keys_equal`.
+pub(crate) fn cmp_keys(a: &str, b: &str) -> std::cmp::Ordering {
+    if ptr::eq(a, b) {
+        std::cmp::Ordering::Equal
+    } else {
+        a.cmp(b)
+    }
+}
 
 /// Span tag.
 #[derive(Debug, Clone)]
@@ -29,6 +123,62 @@ impl Tag {
         }
     }
 
+    /// Makes a tag having an array value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::tag::{Tag, TagValue};
+    ///
+    /// let tag = Tag::array("http.request.header.accept", ["text/html", "application/json"]);
+    /// assert_eq!(
+    ///     tag.value(),
+    ///     &TagValue::Array(vec!["text/html".into(), "application/json".into()])
+    /// );
+    /// ```
+    pub fn array<N, I, V>(name: N, values: I) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        I: IntoIterator<Item = V>,
+        V: Into<TagValue>,
+    {
+        Tag::new(
+            name,
+            TagValue::Array(values.into_iter().map(Into::into).collect()),
+        )
+    }
+
+    /// Makes a tag having a map value.
+    ///
+    /// If `entries` contains entries with the same key, the last one is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::tag::{Tag, TagValue};
+    ///
+    /// let tag = Tag::map("db.params", [("limit", TagValue::from(10)), ("user", "foo".into())]);
+    /// let TagValue::Map(params) = tag.value() else { unreachable!() };
+    /// assert_eq!(params["limit"], TagValue::Integer(10));
+    /// ```
+    pub fn map<N, I, K, V>(name: N, entries: I) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<Cow<'static, str>>,
+        V: Into<TagValue>,
+    {
+        let map = entries
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        Tag::new(name, TagValue::Map(map))
+    }
+
+    pub(crate) fn name_and_value_mut(&mut self) -> (&str, &mut TagValue) {
+        (&self.name, &mut self.value)
+    }
+
     /// Returns the name of this tag.
     pub fn name(&self) -> &str {
         self.name.as_ref()
@@ -40,6 +190,49 @@ impl Tag {
     }
 }
 
+/// Immutable set of tags shared by many spans.
+///
+/// Attaching a `SharedTags` to a span only clones a reference to the set,
+/// so common tags (e.g., service metadata) can be attached to every span without copying them.
+/// If a tag of a span has the same name as a shared tag, the former takes precedence.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::tag::{SharedTags, Tag};
+///
+/// let tags = SharedTags::new(vec![Tag::new("service", "foo"), Tag::new("region", "eu")]);
+/// assert_eq!(tags.tags().len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedTags(Arc<Vec<Tag>>);
+impl SharedTags {
+    /// Makes a new `SharedTags` instance.
+    ///
+    /// If `tags` contains tags with the same name, the last one is kept.
+    pub fn new<I>(tags: I) -> Self
+    where
+        I: IntoIterator<Item = Tag>,
+    {
+        let mut unique: Vec<Tag> = Vec::new();
+        for tag in tags {
+            unique.retain(|x| !keys_equal(x.name(), tag.name()));
+            unique.push(tag);
+        }
+        SharedTags(Arc::new(unique))
+    }
+
+    /// Returns the tags in this set.
+    pub fn tags(&self) -> &[Tag] {
+        &self.0
+    }
+}
+impl FromIterator<Tag> for SharedTags {
+    fn from_iter<I: IntoIterator<Item = Tag>>(iter: I) -> Self {
+        SharedTags::new(iter)
+    }
+}
+
 /// Span tag value.
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 #[allow(missing_docs)]
@@ -48,6 +241,22 @@ pub enum TagValue {
     Boolean(bool),
     Integer(i64),
     Float(f64),
+
+    /// Array of values, which should have the same type.
+    Array(Vec<TagValue>),
+
+    /// Map having string keys.
+    Map(BTreeMap<Cow<'static, str>, TagValue>),
+}
+impl TagValue {
+    /// Returns the string held by this value, if any.
+    pub fn as_str(&self) -> Option<&str> {
+        if let TagValue::String(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
 }
 impl From<&'static str> for TagValue {
     fn from(f: &'static str) -> Self {
@@ -79,6 +288,11 @@ impl From<f64> for TagValue {
         TagValue::Float(f)
     }
 }
+impl<T: Into<TagValue>> From<Vec<T>> for TagValue {
+    fn from(f: Vec<T>) -> Self {
+        TagValue::Array(f.into_iter().map(Into::into).collect())
+    }
+}
 
 /// [Standard span tags][tags].
 /// [tags]: https://github.com/opentracing/specification/blob/master/semantic_conventions.md#span-tags-table
@@ -111,6 +325,18 @@ impl StdTag {
         Tag::new("db.instance", value.into())
     }
 
+    /// Makes a `"db.operation"` tag.
+    ///
+    /// It indicates the name of the operation being executed.
+    ///
+    /// E.g., `"SELECT"`, `"findAndModify"`
+    pub fn db_operation<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("db.operation", value.into())
+    }
+
     /// Makes a `"db.statement"` tag.
     ///
     /// It indicates a database statement for the given database type.
@@ -125,6 +351,18 @@ impl StdTag {
         Tag::new("db.statement", value.into())
     }
 
+    /// Makes a `"db.system"` tag.
+    ///
+    /// It indicates the database management system product.
+    ///
+    /// E.g., `"postgresql"`, `"redis"`
+    pub fn db_system<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("db.system", value.into())
+    }
+
     /// Makes a `"db.type"` tag.
     ///
     /// It indicates database type.
@@ -169,6 +407,18 @@ impl StdTag {
         Tag::new("http.method", value.into())
     }
 
+    /// Makes a `"http.route"` tag.
+    ///
+    /// It indicates the matched route template of the request.
+    ///
+    /// E.g., `"/users/:id"`
+    pub fn http_route<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("http.route", value.into())
+    }
+
     /// Makes a `"http.status_code"` tag.
     ///
     /// It indicates HTTP response status code for the associated `Span`.
@@ -203,6 +453,43 @@ impl StdTag {
         Tag::new("message_bus.destination", value.into())
     }
 
+    /// Makes a `"messaging.destination.name"` tag.
+    ///
+    /// It indicates the name of the destination to which messages are sent
+    /// or from which they are received.
+    ///
+    /// E.g., `"orders"` (a Kafka topic)
+    pub fn messaging_destination<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("messaging.destination.name", value.into())
+    }
+
+    /// Makes a `"messaging.operation"` tag.
+    ///
+    /// It indicates the kind of the messaging operation.
+    ///
+    /// E.g., `"publish"`, `"receive"`, `"process"`
+    pub fn messaging_operation<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("messaging.operation", value.into())
+    }
+
+    /// Makes a `"messaging.system"` tag.
+    ///
+    /// It indicates the messaging system.
+    ///
+    /// E.g., `"kafka"`, `"rabbitmq"`
+    pub fn messaging_system<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("messaging.system", value.into())
+    }
+
     /// Makes a `"peer.address"` tag.
     ///
     /// It indicates remote "address", suitable for use in a networking client library.
@@ -266,15 +553,77 @@ impl StdTag {
         Tag::new("peer.service", value.into())
     }
 
+    /// Makes a `"rpc.method"` tag.
+    ///
+    /// It indicates the name of the remote method being called.
+    ///
+    /// E.g., `"GetUser"`
+    pub fn rpc_method<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("rpc.method", value.into())
+    }
+
+    /// Makes a `"rpc.service"` tag.
+    ///
+    /// It indicates the full name of the service being called.
+    ///
+    /// E.g., `"myservice.UserService"`
+    pub fn rpc_service<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("rpc.service", value.into())
+    }
+
+    /// Makes a `"rpc.system"` tag.
+    ///
+    /// It indicates the RPC system.
+    ///
+    /// E.g., `"grpc"`, `"jsonrpc"`
+    pub fn rpc_system<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("rpc.system", value.into())
+    }
+
     /// Makes a `"samplingpriority"` tag.
     ///
     /// If greater than `0`, a hint to the `Tracer` to do its best to capture the trace.
     /// If `0`, a hint to the trace to not-capture the trace.
     /// If absent, the `Tracer` should use its default sampling mechanism.
+    ///
+    /// This overrides the decisions of the samplers of the `Tracer`,
+    /// even if it is set after a span has started:
+    /// the span is discarded when it finishes if the value is `0`,
+    /// and sent regardless of the finish sampler otherwise.
+    /// (A span which has not been recorded since its start cannot be recorded afterwards.)
+    /// The value is passed on to the spans started by `Span::child` and `Span::follower`.
     pub fn sampling_priority(value: u32) -> Tag {
         Tag::new("sampling.priority", i64::from(value))
     }
 
+    /// Makes a `"server.address"` tag.
+    ///
+    /// It indicates the domain name or the IP address of the server.
+    ///
+    /// E.g., `"example.com"`, `"10.1.2.80"`
+    pub fn server_address<V>(value: V) -> Tag
+    where
+        V: Into<Cow<'static, str>>,
+    {
+        Tag::new("server.address", value.into())
+    }
+
+    /// Makes a `"server.port"` tag.
+    ///
+    /// It indicates the port number of the server.
+    pub fn server_port(value: u16) -> Tag {
+        Tag::new("server.port", i64::from(value))
+    }
+
     /// Makes a `"span.ind"` tag.
     ///
     /// Either `"client"` or `"server"` for the appropriate roles in an RPC,
@@ -286,3 +635,20 @@ impl StdTag {
         Tag::new("span.kind", value.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_works() {
+        let a = intern(&String::from("foo.bar"));
+        let b = intern("foo.bar");
+        assert!(ptr::eq(a, b));
+        assert!(!ptr::eq(a, intern("foo.baz")));
+        assert!(ptr::eq(intern("error"), intern(&["err", "or"].concat())));
+
+        assert!(keys_equal(a, "foo.bar"));
+        assert!(!keys_equal(a, "foo.baz"));
+    }
+}
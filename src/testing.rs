@@ -0,0 +1,356 @@
+//! Utilities for testing instrumented code.
+use crate::channel::SpanStore;
+use crate::clock::Clock;
+use crate::sampler::AllSampler;
+use crate::span::{FinishedSpan, SpanReference};
+use crate::tag::TagValue;
+use crate::{Tracer, TracerBuilder};
+use std::fmt::{self, Write};
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod arbitrary;
+pub mod carrier;
+
+mod fault;
+mod sampler;
+mod snapshot;
+
+pub use self::fault::FaultInjector;
+pub use self::sampler::SamplingStats;
+pub use self::snapshot::snapshot;
+
+/// The maximum time `TestTracer::wait_for` waits for spans.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tracer which records all the finished spans synchronously.
+///
+/// Every span is sampled, and a span is available from `spans` as soon as it is dropped,
+/// so tests can inspect spans without receiving them from a `SpanReceiver`.
+///
+/// `TestTracer` dereferences to `Tracer`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::testing::TestTracer;
+///
+/// let tracer = TestTracer::new();
+/// {
+///     let parent = tracer.span("parent").start_with_state(());
+///     let _child = parent.child("child", |options| options.start_with_state(()));
+/// }
+///
+/// let spans = tracer.spans();
+/// assert_eq!(spans.len(), 2);
+/// assert_eq!(spans[0].operation_name(), "child");
+/// assert_eq!(tracer.find_by_operation("parent").len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct TestTracer<T = ()> {
+    tracer: Tracer<AllSampler, T>,
+    store: Arc<SpanStore<T>>,
+}
+impl<T> TestTracer<T> {
+    /// Makes a new `TestTracer` instance.
+    pub fn new() -> Self {
+        let (tracer, store) = Tracer::builder(AllSampler).finish_capturing();
+        TestTracer { tracer, store }
+    }
+
+    /// Makes a new `TestTracer` instance which uses `clock` for the timestamps.
+    pub fn with_clock<C: Clock>(clock: C) -> Self {
+        Self::from_builder(Tracer::builder(AllSampler).clock(clock))
+    }
+
+    /// Makes a new `TestTracer` instance from `builder`.
+    ///
+    /// The settings of the span channel in `builder` are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::id::SequentialIdGenerator;
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::testing::TestTracer;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let builder = Tracer::builder(AllSampler).id_generator(SequentialIdGenerator::new(1));
+    /// let tracer = TestTracer::<()>::from_builder(builder);
+    /// assert_eq!(tracer.generate_id(), 1);
+    /// ```
+    pub fn from_builder(builder: TracerBuilder<AllSampler, T>) -> Self {
+        let (tracer, store) = builder.finish_capturing();
+        TestTracer { tracer, store }
+    }
+
+    /// Returns the underlying tracer.
+    pub fn tracer(&self) -> &Tracer<AllSampler, T> {
+        &self.tracer
+    }
+
+    /// Returns the number of the recorded spans.
+    pub fn len(&self) -> usize {
+        self.store.lock().len()
+    }
+
+    /// Returns `true` if no span has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Discards the recorded spans.
+    pub fn clear(&self) {
+        self.store.lock().clear();
+    }
+}
+impl<T: Clone> TestTracer<T> {
+    /// Returns the recorded spans in the order they were finished.
+    pub fn spans(&self) -> Vec<FinishedSpan<T>> {
+        self.store.lock().clone()
+    }
+
+    /// Returns the recorded spans which have the operation name `name`.
+    pub fn find_by_operation(&self, name: &str) -> Vec<FinishedSpan<T>> {
+        self.store
+            .lock()
+            .iter()
+            .filter(|span| span.operation_name() == name)
+            .cloned()
+            .collect()
+    }
+
+    /// Waits until at least `n` spans have been recorded and returns the recorded spans.
+    ///
+    /// This blocks the current thread,
+    /// so the spans should be finished by other threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `n` spans have been recorded within five seconds.
+    pub fn wait_for(&self, n: usize) -> Vec<FinishedSpan<T>> {
+        let spans = self.store.wait_for(n, WAIT_TIMEOUT);
+        assert!(
+            spans.len() >= n,
+            "expected at least {} spans, but only {} spans were recorded within {:?}",
+            n,
+            spans.len(),
+            WAIT_TIMEOUT
+        );
+        spans.clone()
+    }
+}
+impl<T> Default for TestTracer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T> Deref for TestTracer<T> {
+    type Target = Tracer<AllSampler, T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tracer
+    }
+}
+
+/// Asserts that `child` has a `ChildOf` reference to `parent`.
+///
+/// # Panics
+///
+/// Panics with the references of `child` if it is not a child of `parent`.
+#[track_caller]
+pub fn assert_parent_child<T>(parent: &FinishedSpan<T>, child: &FinishedSpan<T>)
+where
+    T: PartialEq + fmt::Debug,
+{
+    let parent_state = parent.context().state();
+    let is_child = child
+        .references()
+        .iter()
+        .any(|r| r.is_child_of() && r.span() == parent_state);
+    assert!(
+        is_child,
+        "span {:?} is not a child of span {:?} ({:?})\n  references: {:?}",
+        child.operation_name(),
+        parent.operation_name(),
+        parent_state,
+        child.references()
+    );
+}
+
+/// Asserts that `span` has the tag `name` whose value is `value`.
+///
+/// The shared tags of `span` are also taken into account.
+///
+/// # Panics
+///
+/// Panics with the tags of `span` if it does not have the tag.
+#[track_caller]
+pub fn assert_has_tag<T, V>(span: &FinishedSpan<T>, name: &str, value: V)
+where
+    V: Into<TagValue>,
+{
+    let value = value.into();
+    let actual = span
+        .all_tags()
+        .find(|t| t.name() == name)
+        .map(|t| t.value());
+    if actual != Some(&value) {
+        let tags = span
+            .all_tags()
+            .map(|t| format!("{}={:?}", t.name(), t.value()))
+            .collect::<Vec<_>>();
+        panic!(
+            "span {:?} does not have the tag {}={:?}\n  tags: [{}]",
+            span.operation_name(),
+            name,
+            value,
+            tags.join(", ")
+        );
+    }
+}
+
+/// Asserts that `spans` form the trees described by `expected`.
+///
+/// The shape is written as one operation name per line, indented by two spaces per depth
+/// below the span it references. Children are listed in the order they appear in `spans`.
+/// Leading and trailing blank lines and the common indentation of `expected` are ignored.
+///
+/// # Panics
+///
+/// Panics with both shapes if they differ.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::testing::{assert_trace_shape, TestTracer};
+///
+/// let tracer = TestTracer::<u32>::new();
+/// {
+///     let root = tracer.span("root").start_with_state(1);
+///     let _a = root.child("a", |options| options.start_with_state(2));
+///     let _b = root.follower("b", |options| options.start_with_state(3));
+/// }
+/// assert_trace_shape(
+///     &tracer.spans(),
+///     "
+///     root
+///       b
+///       a
+///     ",
+/// );
+/// ```
+#[track_caller]
+pub fn assert_trace_shape<T>(spans: &[FinishedSpan<T>], expected: &str)
+where
+    T: PartialEq,
+{
+    let actual = trace_shape(spans);
+    let expected = normalize_shape(expected);
+    assert!(
+        actual == expected,
+        "unexpected trace shape\n--- expected ---\n{}--- actual ---\n{}",
+        expected,
+        actual
+    );
+}
+
+/// Renders the trees formed by `spans` in the format used by `assert_trace_shape`.
+pub fn trace_shape<T: PartialEq>(spans: &[FinishedSpan<T>]) -> String {
+    let parent_of = |span: &FinishedSpan<T>| {
+        span.references().iter().find_map(|r| {
+            spans
+                .iter()
+                .position(|s| s.context().state() == SpanReference::span(r))
+        })
+    };
+    let parents = spans.iter().map(parent_of).collect::<Vec<_>>();
+
+    fn render(
+        shape: &mut String,
+        spans: &[(&str, Option<usize>)],
+        parent: Option<usize>,
+        depth: usize,
+    ) {
+        for (i, (name, _)) in spans.iter().enumerate().filter(|(_, s)| s.1 == parent) {
+            let _ = writeln!(shape, "{:width$}{}", "", name, width = depth * 2);
+            render(shape, spans, Some(i), depth + 1);
+        }
+    }
+    let nodes = spans
+        .iter()
+        .zip(parents)
+        .map(|(span, parent)| (span.operation_name(), parent))
+        .collect::<Vec<_>>();
+    let mut shape = String::new();
+    render(&mut shape, &nodes, None, 0);
+    shape
+}
+
+fn normalize_shape(shape: &str) -> String {
+    let lines = shape
+        .lines()
+        .skip_while(|l| l.trim().is_empty())
+        .collect::<Vec<_>>();
+    let lines = match lines.iter().rposition(|l| !l.trim().is_empty()) {
+        Some(last) => &lines[..=last],
+        None => &[][..],
+    };
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| format!("{}\n", l.get(indent..).unwrap_or("").trim_end()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn wait_for_works() {
+        let tracer = TestTracer::new();
+        let handles = (0..3)
+            .map(|_| {
+                let tracer = tracer.tracer().clone();
+                thread::spawn(move || {
+                    tracer.span("foo").start_with_state(());
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tracer.wait_for(3).len(), 3);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        tracer.clear();
+        assert!(tracer.is_empty());
+    }
+
+    #[test]
+    fn assertions_work() {
+        use crate::tag::Tag;
+
+        let tracer = TestTracer::<u32>::new();
+        {
+            let mut root = tracer.span("root").start_with_state(1);
+            root.set_tag(|| Tag::new("foo", 10));
+            let a = root.child("a", |options| options.start_with_state(2));
+            let _b = a.child("b", |options| options.start_with_state(3));
+        }
+        let spans = tracer.spans();
+        assert_parent_child(&spans[2], &spans[1]);
+        assert_has_tag(&spans[2], "foo", 10);
+        assert_trace_shape(&spans, "root\n  a\n    b\n");
+
+        let result = std::panic::catch_unwind(|| assert_parent_child(&spans[1], &spans[2]));
+        assert!(result.is_err());
+    }
+}
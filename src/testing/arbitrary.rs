@@ -0,0 +1,121 @@
+//! Random generators of the core types for property-based tests.
+//!
+//! Each generator draws a value from the given random number generator,
+//! so tests can check properties (e.g., the round-trip fidelity of carrier codecs)
+//! over many inputs, and reproduce failures with a seeded generator such as `StdRng`.
+//!
+//! # Examples
+//!
+//! ```
+//! use cf_rustracing::testing::arbitrary;
+//! use rand::rngs::StdRng;
+//! use rand::SeedableRng;
+//!
+//! let mut rng = StdRng::seed_from_u64(0);
+//! for _ in 0..100 {
+//!     let tag = arbitrary::tag(&mut rng);
+//!     assert!(!tag.name().is_empty());
+//! }
+//! ```
+use crate::clock::SystemClock;
+use crate::log::{Log, LogBuilder, LogField};
+use crate::span::{BaggageItem, SpanContext};
+use crate::tag::{Tag, TagValue};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// The maximum number of the elements of generated collections.
+const MAX_LEN: usize = 8;
+
+/// Returns a random non-empty string consisting of ASCII alphanumerics, `-`, `_` and `.`.
+pub fn name<R: Rng + ?Sized>(rng: &mut R) -> String {
+    let len = rng.gen_range(1..=16);
+    (0..len)
+        .map(|_| match rng.gen_range(0..16) {
+            0 => '-',
+            1 => '_',
+            2 => '.',
+            _ => char::from(rng.sample(Alphanumeric)),
+        })
+        .collect()
+}
+
+/// Returns a random string which may contain any characters including non-ASCII ones.
+pub fn text<R: Rng + ?Sized>(rng: &mut R) -> String {
+    let len = rng.gen_range(0..=32);
+    (0..len).map(|_| rng.gen::<char>()).collect()
+}
+
+/// Returns a random tag value.
+///
+/// Float values are always finite.
+pub fn tag_value<R: Rng + ?Sized>(rng: &mut R) -> TagValue {
+    match rng.gen_range(0..4) {
+        0 => TagValue::from(text(rng)),
+        1 => TagValue::from(rng.gen::<bool>()),
+        2 => TagValue::from(rng.gen::<i64>()),
+        _ => TagValue::from(rng.gen_range(-1.0e9..1.0e9)),
+    }
+}
+
+/// Returns a random tag.
+pub fn tag<R: Rng + ?Sized>(rng: &mut R) -> Tag {
+    Tag::new(name(rng), tag_value(rng))
+}
+
+/// Returns a random list of tags.
+pub fn tags<R: Rng + ?Sized>(rng: &mut R) -> Vec<Tag> {
+    let len = rng.gen_range(0..=MAX_LEN);
+    (0..len).map(|_| tag(rng)).collect()
+}
+
+/// Returns a random log which has at least one field.
+pub fn log<R: Rng + ?Sized>(rng: &mut R) -> Log {
+    let mut builder = LogBuilder::new();
+    for _ in 0..rng.gen_range(1..=MAX_LEN) {
+        builder.field(LogField::new(name(rng), text(rng)));
+    }
+    builder.finish(&SystemClock).expect("never fails")
+}
+
+/// Returns a random baggage item.
+pub fn baggage_item<R: Rng + ?Sized>(rng: &mut R) -> BaggageItem {
+    BaggageItem::new(&name(rng), &text(rng))
+}
+
+/// Returns a random span context which has `state`.
+pub fn span_context<R: Rng + ?Sized, T>(rng: &mut R, state: T) -> SpanContext<T> {
+    let len = rng.gen_range(0..=MAX_LEN);
+    let items = (0..len).map(|_| baggage_item(rng)).collect();
+    SpanContext::new(state, items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn span_context_keeps_last_baggage_items() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let len = rng.gen_range(0..=MAX_LEN);
+            let items = (0..len)
+                .map(|_| {
+                    // Small name space to make duplicates likely.
+                    let name = rng.gen_range(0..4).to_string();
+                    BaggageItem::new(&name, &text(&mut rng))
+                })
+                .collect::<Vec<_>>();
+            let context = SpanContext::new((), items.clone());
+
+            let actual = context.baggage_items();
+            assert!(actual.windows(2).all(|w| w[0].name() < w[1].name()));
+            for item in actual {
+                let last = items.iter().rev().find(|x| x.name() == item.name());
+                assert_eq!(last.map(|x| x.value()), Some(item.value()));
+            }
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! Fake carriers and round-trip assertions for testing carrier codecs.
+//!
+//! A codec is the implementation of the `carrier` traits by the state type of a span context.
+//! `assert_roundtrip` and its variants inject a context into the fake carriers,
+//! extract it again, and check that the extracted context is equivalent to the original one.
+//! `assert_extraction_never_panics` feeds randomly corrupted carriers to the extractors
+//! of a codec, since they parse untrusted network input.
+use crate::carrier::{
+    ExtractFromBinary, ExtractFromHttpHeader, ExtractFromTextMap, InjectToBinary,
+    InjectToHttpHeader, InjectToTextMap, IterHttpHeaderFields, SetHttpHeaderField, TextMap,
+};
+use crate::span::SpanContext;
+use crate::testing::arbitrary;
+use crate::Result;
+use rand::Rng;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+/// In-memory text map which keeps its entries in insertion order.
+#[derive(Debug, Default, Clone)]
+pub struct FakeTextMap {
+    entries: Vec<(String, String)>,
+}
+impl FakeTextMap {
+    /// Makes a new empty `FakeTextMap` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the entries of this map.
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+}
+impl TextMap for FakeTextMap {
+    fn set(&mut self, key: &str, value: &str) {
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self.entries.push((key.to_owned(), value.to_owned())),
+        }
+    }
+    fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Stand-in of an HTTP header map.
+///
+/// Like real header maps, the names of fields are case-insensitive:
+/// they are stored in lowercase.
+#[derive(Debug, Default, Clone)]
+pub struct FakeHeaderMap {
+    fields: Vec<(String, Vec<u8>)>,
+}
+impl FakeHeaderMap {
+    /// Makes a new empty `FakeHeaderMap` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the value of the field named `name`.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        let name = name.to_ascii_lowercase();
+        self.fields
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_slice())
+    }
+}
+impl SetHttpHeaderField for FakeHeaderMap {
+    fn set_http_header_field(&mut self, name: &str, value: &str) -> Result<()> {
+        let name = name.to_ascii_lowercase();
+        match self.fields.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value.as_bytes().to_owned(),
+            None => self.fields.push((name, value.as_bytes().to_owned())),
+        }
+        Ok(())
+    }
+}
+impl<'a> IterHttpHeaderFields<'a> for FakeHeaderMap {
+    type Fields = Box<dyn Iterator<Item = (&'a str, &'a [u8])> + 'a>;
+
+    fn fields(&'a self) -> Self::Fields {
+        Box::new(self.fields.iter().map(|(n, v)| (n.as_str(), v.as_slice())))
+    }
+}
+
+/// In-memory binary buffer.
+///
+/// Bytes written to the buffer are read back in the same order.
+#[derive(Debug, Default, Clone)]
+pub struct FakeBinary {
+    buf: Vec<u8>,
+    position: usize,
+}
+impl FakeBinary {
+    /// Makes a new empty `FakeBinary` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the bytes written to this buffer.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+}
+impl Write for FakeBinary {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl Read for FakeBinary {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = (&self.buf[self.position..]).read(buf)?;
+        self.position += size;
+        Ok(size)
+    }
+}
+
+/// Asserts that `context` is preserved by injecting it into and extracting it from
+/// all the fake carriers.
+///
+/// The deadline of `context` is compared only if it is set.
+///
+/// # Panics
+///
+/// Panics if a codec fails or the extracted context differs from `context`.
+#[track_caller]
+pub fn assert_roundtrip<T>(context: &SpanContext<T>)
+where
+    T: InjectToTextMap<FakeTextMap> + ExtractFromTextMap<FakeTextMap>,
+    T: InjectToHttpHeader<FakeHeaderMap> + for<'a> ExtractFromHttpHeader<'a, FakeHeaderMap>,
+    T: InjectToBinary<FakeBinary> + ExtractFromBinary<FakeBinary>,
+    T: PartialEq + fmt::Debug,
+{
+    assert_text_map_roundtrip(context);
+    assert_http_header_roundtrip(context);
+    assert_binary_roundtrip(context);
+}
+
+/// Asserts that `context` is preserved by a `FakeTextMap`.
+#[track_caller]
+pub fn assert_text_map_roundtrip<T>(context: &SpanContext<T>)
+where
+    T: InjectToTextMap<FakeTextMap> + ExtractFromTextMap<FakeTextMap>,
+    T: PartialEq + fmt::Debug,
+{
+    let mut carrier = FakeTextMap::new();
+    let extracted = context
+        .inject_to_text_map(&mut carrier)
+        .and_then(|()| SpanContext::extract_from_text_map(&carrier));
+    assert_extracted("text map", context, extracted, &carrier);
+}
+
+/// Asserts that `context` is preserved by a `FakeHeaderMap`.
+#[track_caller]
+pub fn assert_http_header_roundtrip<T>(context: &SpanContext<T>)
+where
+    T: InjectToHttpHeader<FakeHeaderMap> + for<'a> ExtractFromHttpHeader<'a, FakeHeaderMap>,
+    T: PartialEq + fmt::Debug,
+{
+    let mut carrier = FakeHeaderMap::new();
+    let extracted = context
+        .inject_to_http_header(&mut carrier)
+        .and_then(|()| SpanContext::extract_from_http_header(&carrier));
+    assert_extracted("HTTP header", context, extracted, &carrier);
+}
+
+/// Asserts that `context` is preserved by a `FakeBinary`.
+#[track_caller]
+pub fn assert_binary_roundtrip<T>(context: &SpanContext<T>)
+where
+    T: InjectToBinary<FakeBinary> + ExtractFromBinary<FakeBinary>,
+    T: PartialEq + fmt::Debug,
+{
+    let mut carrier = FakeBinary::new();
+    let extracted = context
+        .inject_to_binary(&mut carrier)
+        .and_then(|()| SpanContext::extract_from_binary(&mut carrier));
+    assert_extracted("binary", context, extracted, &carrier);
+}
+
+/// Asserts that the extractors of a codec never panic on corrupted carriers.
+///
+/// The carriers are made by injecting `context` and then corrupted randomly
+/// (e.g., values are truncated, replaced by random bytes or made very long,
+/// and fields are removed, duplicated or added) `iterations` times for each format.
+/// Extraction may fail, but must not panic.
+///
+/// # Panics
+///
+/// Panics with the carrier if an extractor panics.
+#[track_caller]
+pub fn assert_extraction_never_panics<T, R>(
+    rng: &mut R,
+    context: &SpanContext<T>,
+    iterations: usize,
+) where
+    T: InjectToTextMap<FakeTextMap> + ExtractFromTextMap<FakeTextMap>,
+    T: InjectToHttpHeader<FakeHeaderMap> + for<'a> ExtractFromHttpHeader<'a, FakeHeaderMap>,
+    T: InjectToBinary<FakeBinary> + ExtractFromBinary<FakeBinary>,
+    R: Rng + ?Sized,
+{
+    let mut text_map = FakeTextMap::new();
+    let mut header = FakeHeaderMap::new();
+    let mut binary = FakeBinary::new();
+    let _ = context.inject_to_text_map(&mut text_map);
+    let _ = context.inject_to_http_header(&mut header);
+    let _ = context.inject_to_binary(&mut binary);
+
+    for _ in 0..iterations {
+        let mut carrier = text_map.clone();
+        corrupt_fields(rng, &mut carrier.entries, |rng| arbitrary::text(rng));
+        assert_no_panic("text map", &carrier, || {
+            let _ = SpanContext::<T>::extract_from_text_map(&carrier);
+        });
+
+        let mut carrier = header.clone();
+        corrupt_fields(rng, &mut carrier.fields, random_bytes);
+        assert_no_panic("HTTP header", &carrier, || {
+            let _ = SpanContext::<T>::extract_from_http_header(&carrier);
+        });
+
+        let mut carrier = binary.clone();
+        corrupt_bytes(rng, &mut carrier.buf);
+        assert_no_panic("binary", &carrier.clone(), || {
+            let _ = SpanContext::<T>::extract_from_binary(&mut carrier);
+        });
+    }
+}
+
+/// Value of a carrier field.
+trait FieldValue: Clone {
+    fn len(&self) -> usize;
+    fn truncate(&mut self, len: usize);
+    fn pad(&mut self, byte: u8, count: usize);
+}
+impl FieldValue for String {
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn truncate(&mut self, mut len: usize) {
+        while !self.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.truncate(len);
+    }
+    fn pad(&mut self, byte: u8, count: usize) {
+        self.extend(std::iter::repeat(char::from(byte)).take(count));
+    }
+}
+impl FieldValue for Vec<u8> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+    fn truncate(&mut self, len: usize) {
+        self.truncate(len);
+    }
+    fn pad(&mut self, byte: u8, count: usize) {
+        self.extend(std::iter::repeat(byte).take(count));
+    }
+}
+
+/// Applies a random corruption to the fields of a carrier.
+fn corrupt_fields<R, V, F>(rng: &mut R, fields: &mut Vec<(String, V)>, random_value: F)
+where
+    R: Rng + ?Sized,
+    V: FieldValue,
+    F: Fn(&mut R) -> V,
+{
+    let i = rng.gen_range(0..fields.len().max(1));
+    match (rng.gen_range(0..6), fields.get_mut(i)) {
+        (0, Some((_, value))) => {
+            let len = rng.gen_range(0..=value.len());
+            value.truncate(len);
+        }
+        (1, Some((_, value))) => *value = random_value(rng),
+        (2, Some((_, value))) => {
+            let byte = rng.gen_range(b'0'..=b'z');
+            value.pad(byte, rng.gen_range(1..=4096));
+        }
+        (3, Some(_)) => {
+            fields.remove(i);
+        }
+        (4, Some(field)) => {
+            let mut field = field.clone();
+            field.1 = random_value(rng);
+            fields.push(field);
+        }
+        _ => {
+            let name = if rng.gen() {
+                arbitrary::name(rng)
+            } else {
+                arbitrary::text(rng)
+            };
+            fields.push((name, random_value(rng)));
+        }
+    }
+}
+
+/// Applies a random corruption to a binary carrier.
+fn corrupt_bytes<R: Rng + ?Sized>(rng: &mut R, bytes: &mut Vec<u8>) {
+    match rng.gen_range(0..4) {
+        0 => bytes.truncate(rng.gen_range(0..=bytes.len())),
+        1 if !bytes.is_empty() => {
+            for _ in 0..rng.gen_range(1..=4) {
+                let i = rng.gen_range(0..bytes.len());
+                bytes[i] = rng.gen();
+            }
+        }
+        2 => {
+            let byte = if rng.gen() { 0xff } else { rng.gen() };
+            bytes.extend(std::iter::repeat(byte).take(rng.gen_range(1..=4096)));
+        }
+        _ => *bytes = random_bytes(rng),
+    }
+}
+
+fn random_bytes<R: Rng + ?Sized>(rng: &mut R) -> Vec<u8> {
+    let len = rng.gen_range(0..=64);
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+#[track_caller]
+fn assert_no_panic<C: fmt::Debug, F: FnOnce()>(format: &str, carrier: &C, f: F) {
+    if let Err(e) = panic::catch_unwind(AssertUnwindSafe(f)) {
+        let message = e
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| e.downcast_ref::<String>().map(|s| s.as_str()))
+            .unwrap_or("<non-string panic payload>");
+        panic!(
+            "{} extractor panicked: {}\n  carrier: {:?}",
+            format, message, carrier
+        );
+    }
+}
+
+#[track_caller]
+fn assert_extracted<T, C>(
+    format: &str,
+    expected: &SpanContext<T>,
+    extracted: Result<Option<SpanContext<T>>>,
+    carrier: &C,
+) where
+    T: PartialEq + fmt::Debug,
+    C: fmt::Debug,
+{
+    let actual = match extracted {
+        Ok(Some(actual)) => actual,
+        Ok(None) => panic!(
+            "no {} context was extracted\n  carrier: {:?}",
+            format, carrier
+        ),
+        Err(e) => panic!("{} codec failed: {}\n  carrier: {:?}", format, e, carrier),
+    };
+    let items = |c: &SpanContext<T>| {
+        c.baggage_items()
+            .iter()
+            .map(|x| (x.name().to_owned(), x.value().to_owned()))
+            .collect::<Vec<_>>()
+    };
+    assert!(
+        actual.state() == expected.state()
+            && items(&actual) == items(expected)
+            && (expected.deadline().is_none() || actual.deadline() == expected.deadline()),
+        "{} round trip changed the context\n  expected: {:?}\n  actual:   {:?}\n  carrier:  {:?}",
+        format,
+        expected,
+        actual,
+        carrier
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::BaggageItem;
+    use crate::{Error, ErrorKind};
+    use std::str;
+
+    const TRACE_ID: &str = "x-trace-id";
+    const BAGGAGE_PREFIX: &str = "x-baggage-";
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct State(u64);
+    impl<C: TextMap> InjectToTextMap<C> for State {
+        fn inject_to_text_map(context: &SpanContext<Self>, carrier: &mut C) -> Result<()> {
+            carrier.set_fmt(TRACE_ID, format_args!("{:x}", context.state().0));
+            for item in context.baggage_items() {
+                carrier.set(&format!("{}{}", BAGGAGE_PREFIX, item.name()), item.value());
+            }
+            Ok(())
+        }
+    }
+    impl ExtractFromTextMap<FakeTextMap> for State {
+        fn extract_from_text_map(carrier: &FakeTextMap) -> Result<Option<SpanContext<Self>>> {
+            let fields = carrier
+                .entries()
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_bytes()));
+            extract(fields)
+        }
+    }
+    impl InjectToHttpHeader<FakeHeaderMap> for State {
+        fn inject_to_http_header(
+            context: &SpanContext<Self>,
+            carrier: &mut FakeHeaderMap,
+        ) -> Result<()> {
+            track!(carrier
+                .set_http_header_field_fmt(TRACE_ID, format_args!("{:x}", context.state().0)))?;
+            for item in context.baggage_items() {
+                let name = format!("{}{}", BAGGAGE_PREFIX, item.name());
+                track!(carrier.set_http_header_field(&name, item.value()))?;
+            }
+            Ok(())
+        }
+    }
+    impl<'a> ExtractFromHttpHeader<'a, FakeHeaderMap> for State {
+        fn extract_from_http_header(
+            carrier: &'a FakeHeaderMap,
+        ) -> Result<Option<SpanContext<Self>>> {
+            extract(carrier.fields())
+        }
+    }
+    impl InjectToBinary<FakeBinary> for State {
+        fn inject_to_binary(context: &SpanContext<Self>, carrier: &mut FakeBinary) -> Result<()> {
+            track!(carrier
+                .write_all(&context.state().0.to_be_bytes())
+                .map_err(|_| Error::from(ErrorKind::CarrierIo)))
+        }
+    }
+    impl ExtractFromBinary<FakeBinary> for State {
+        fn extract_from_binary(carrier: &mut FakeBinary) -> Result<Option<SpanContext<Self>>> {
+            let mut buf = [0; 8];
+            track!(carrier
+                .read_exact(&mut buf)
+                .map_err(|_| Error::from(ErrorKind::CarrierParse)))?;
+            Ok(Some(SpanContext::new(
+                State(u64::from_be_bytes(buf)),
+                Vec::new(),
+            )))
+        }
+    }
+
+    fn extract<'a, I>(fields: I) -> Result<Option<SpanContext<State>>>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8])>,
+    {
+        let mut state = None;
+        let mut items = Vec::new();
+        for (name, value) in fields {
+            let value =
+                track!(str::from_utf8(value).map_err(|_| Error::from(ErrorKind::CarrierParse)))?;
+            if name == TRACE_ID {
+                let id = track!(u64::from_str_radix(value, 16)
+                    .map_err(|_| Error::from(ErrorKind::CarrierParse)))?;
+                state = Some(State(id));
+            } else if let Some(name) = name.strip_prefix(BAGGAGE_PREFIX) {
+                items.push(BaggageItem::new(name, value));
+            }
+        }
+        Ok(state.map(|state| SpanContext::new(state, items)))
+    }
+
+    #[test]
+    fn extraction_never_panics() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let context = SpanContext::new(State(0xabc), vec![BaggageItem::new("foo", "bar")]);
+        assert_extraction_never_panics(&mut StdRng::seed_from_u64(0), &context, 1000);
+    }
+
+    #[test]
+    fn roundtrip_works() {
+        let context = SpanContext::new(State(0xabc), vec![BaggageItem::new("foo", "bar")]);
+        assert_text_map_roundtrip(&context);
+        assert_http_header_roundtrip(&context);
+
+        // The binary codec above drops baggage items.
+        assert_binary_roundtrip(&SpanContext::new(State(1), Vec::new()));
+        let result = std::panic::catch_unwind(|| assert_binary_roundtrip(&context));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::channel::Faults;
+use crate::sampler::Sampler;
+use crate::TracerBuilder;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Handle to inject failures into the span channel of a tracer.
+///
+/// This makes it possible to verify how an application behaves when
+/// the tracing pipeline fails (e.g., that dropped spans are accounted for,
+/// or that shutdown does not hang) without actually overloading the channel.
+///
+/// The failures are injected into the channels made by `TracerBuilder::finish`.
+/// They have no effect on `TestTracer`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::testing::FaultInjector;
+/// use cf_rustracing::Tracer;
+///
+/// let faults = FaultInjector::new();
+/// let (tracer, mut span_rx) = faults.attach(Tracer::builder(AllSampler)).finish();
+///
+/// faults.close_receiver(true);
+/// tracer.span("foo").start_with_state(());
+/// assert_eq!(tracer.dropped_spans(), 1);
+///
+/// faults.close_receiver(false);
+/// tracer.span("bar").start_with_state(());
+/// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "bar");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector(Arc<Faults>);
+impl FaultInjector {
+    /// Makes a new `FaultInjector` instance which injects no failures.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the tracer built by `builder` subject to the failures injected by this.
+    pub fn attach<S: Sampler<T>, T>(&self, builder: TracerBuilder<S, T>) -> TracerBuilder<S, T> {
+        builder.faults(Arc::clone(&self.0))
+    }
+
+    /// Simulates that the `SpanReceiver` has been dropped.
+    ///
+    /// While this is enabled, finished spans are discarded and counted as dropped spans.
+    pub fn close_receiver(&self, closed: bool) {
+        self.0.receiver_closed.store(closed, Ordering::Relaxed);
+    }
+
+    /// Simulates that the span channel is full.
+    ///
+    /// While this is enabled, finished spans are discarded and counted as dropped spans.
+    pub fn fill_channel(&self, full: bool) {
+        self.0.channel_full.store(full, Ordering::Relaxed);
+    }
+
+    /// Simulates a consumer which does not keep up with the finished spans.
+    ///
+    /// While this is enabled, the `SpanReceiver` receives no spans,
+    /// so the spans finished in the meantime accumulate in the channel
+    /// (and are discarded once a bounded channel is full).
+    /// A `SpanReceiver` waiting for spans is woken up when this is disabled.
+    pub fn pause_receiver(&self, paused: bool) {
+        self.0.set_receiver_paused(paused);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::AllSampler;
+    use crate::Tracer;
+
+    #[tokio::test]
+    async fn paused_receiver_works() {
+        let faults = FaultInjector::new();
+        let (tracer, mut span_rx) = faults
+            .attach(Tracer::builder(AllSampler).channel_capacity(2))
+            .finish();
+
+        faults.fill_channel(true);
+        tracer.span("a").start_with_state(());
+        faults.fill_channel(false);
+        assert_eq!(tracer.dropped_spans(), 1);
+
+        faults.pause_receiver(true);
+        for name in ["b", "c", "d"] {
+            tracer.span(name).start_with_state(());
+        }
+        assert!(span_rx.try_recv().is_err());
+        assert_eq!(tracer.dropped_spans(), 2);
+
+        let handle = tokio::spawn(async move {
+            let mut names = Vec::new();
+            while let Some(span) = span_rx.recv().await {
+                names.push(span.operation_name().to_owned());
+            }
+            names
+        });
+        tokio::task::yield_now().await;
+        faults.pause_receiver(false);
+        drop(tracer);
+        assert_eq!(handle.await.unwrap(), ["b", "c"]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Statistical test harness for samplers.
+use crate::id::RandomIdGenerator;
+use crate::sampler::Sampler;
+use crate::span::{CandidateSpan, ParentKinds};
+use crate::tag::Tag;
+
+/// The z-score of the 99.9% confidence level used by `SamplingStats::assert_rate`.
+const Z_999: f64 = 3.29;
+
+/// Observed sampling decisions of a sampler.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::sampler::ProbabilisticSampler;
+/// use cf_rustracing::testing::SamplingStats;
+///
+/// let sampler = ProbabilisticSampler::new(0.25).unwrap();
+/// let stats = SamplingStats::measure::<_, ()>(&sampler, 10_000);
+/// stats.assert_rate(0.25);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplingStats {
+    /// The number of the candidate spans.
+    pub candidates: u64,
+
+    /// The number of the sampled spans.
+    pub sampled: u64,
+}
+impl SamplingStats {
+    /// Asks `sampler` whether to sample `n` synthetic root spans without tags.
+    pub fn measure<S, T>(sampler: &S, n: u64) -> Self
+    where
+        S: Sampler<T> + ?Sized,
+    {
+        Self::measure_with_tags::<S, T>(sampler, n, &[])
+    }
+
+    /// Asks `sampler` whether to sample `n` synthetic root spans which have `tags`.
+    pub fn measure_with_tags<S, T>(sampler: &S, n: u64, tags: &[Tag]) -> Self
+    where
+        S: Sampler<T> + ?Sized,
+    {
+        let span = CandidateSpan::<T>::new(
+            "",
+            tags,
+            &[],
+            &[],
+            ParentKinds::default(),
+            false,
+            &RandomIdGenerator,
+        );
+        let sampled = (0..n).filter(|_| sampler.is_sampled(&span)).count() as u64;
+        SamplingStats {
+            candidates: n,
+            sampled,
+        }
+    }
+
+    /// Returns the observed sampling rate.
+    pub fn rate(&self) -> f64 {
+        if self.candidates == 0 {
+            0.0
+        } else {
+            self.sampled as f64 / self.candidates as f64
+        }
+    }
+
+    /// Returns the Wilson score interval of the true sampling rate for the z-score `z`
+    /// (e.g., `1.96` for the 95% confidence level).
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        if self.candidates == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.candidates as f64;
+        let p = self.rate();
+        let z2 = z * z;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = z / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+
+    /// Asserts that `expected` lies within the 99.9% confidence interval of the sampling rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the observed rate and the interval if it does not.
+    #[track_caller]
+    pub fn assert_rate(&self, expected: f64) {
+        let (lower, upper) = self.confidence_interval(Z_999);
+        assert!(
+            lower <= expected && expected <= upper,
+            "expected sampling rate {} is outside the 99.9% confidence interval [{:.4}, {:.4}] \
+             (observed {:.4} = {}/{})",
+            expected,
+            lower,
+            upper,
+            self.rate(),
+            self.sampled,
+            self.candidates
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{AllSampler, NullSampler};
+
+    #[test]
+    fn confidence_interval_works() {
+        let stats = SamplingStats {
+            candidates: 1000,
+            sampled: 500,
+        };
+        let (lower, upper) = stats.confidence_interval(1.96);
+        assert!((lower - 0.469).abs() < 0.001);
+        assert!((upper - 0.531).abs() < 0.001);
+
+        SamplingStats::measure::<_, ()>(&AllSampler, 100).assert_rate(1.0);
+        SamplingStats::measure::<_, ()>(&NullSampler, 100).assert_rate(0.0);
+    }
+}
@@ -0,0 +1,189 @@
+//! Normalized textual snapshots of finished spans for golden-file tests.
+use crate::span::{FinishedSpan, SpanReference};
+use crate::tag::TagValue;
+use std::fmt::{self, Write};
+use std::time::SystemTime;
+
+/// Renders `spans` into a canonical text intended for snapshot (golden-file) tests.
+///
+/// The output is stable across runs:
+///
+/// - The states of the span contexts (e.g., trace and span ids) are replaced with labels
+///   (`#0`, `#1`, ...) numbered in the order they first appear, and references to them
+///   are rendered as the labels.
+/// - Timestamps are rendered as offsets from the earliest start time of `spans`.
+///   To make them deterministic, use a `MockClock` (see `TestTracer::with_clock`).
+/// - Tags (including shared tags) and baggage items are sorted by name.
+///
+/// Spans are rendered in the order of `spans`.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::clock::MockClock;
+/// use cf_rustracing::tag::Tag;
+/// use cf_rustracing::testing::{snapshot, TestTracer};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = MockClock::new(UNIX_EPOCH);
+/// let tracer = TestTracer::<u64>::with_clock(clock.clone());
+/// {
+///     let root = tracer.span("root").start_with_state(0xabc);
+///     clock.advance(Duration::from_millis(5));
+///     let mut child = root.child("child", |options| options.start_with_state(0xdef));
+///     child.set_tag(|| Tag::new("peer", "db"));
+///     clock.advance(Duration::from_millis(5));
+/// }
+/// assert_eq!(
+///     snapshot(&tracer.spans()),
+///     r#"span "child" #0
+///   start: +5ms
+///   duration: 5ms
+///   child_of: #1
+///   tags:
+///     peer = "db"
+/// span "root" #1
+///   start: +0ns
+///   duration: 10ms
+/// "#
+/// );
+/// ```
+pub fn snapshot<T: PartialEq>(spans: &[FinishedSpan<T>]) -> String {
+    let origin = spans
+        .iter()
+        .map(|s| s.start_time())
+        .min()
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut labels = Labels(Vec::new());
+    let mut out = String::new();
+    for span in spans {
+        let _ = write_span(&mut out, span, origin, &mut labels);
+    }
+    out
+}
+
+struct Labels<'a, T>(Vec<&'a T>);
+impl<'a, T: PartialEq> Labels<'a, T> {
+    fn get(&mut self, state: &'a T) -> usize {
+        match self.0.iter().position(|x| *x == state) {
+            Some(i) => i,
+            None => {
+                self.0.push(state);
+                self.0.len() - 1
+            }
+        }
+    }
+}
+
+fn write_span<'a, T: PartialEq>(
+    out: &mut String,
+    span: &'a FinishedSpan<T>,
+    origin: SystemTime,
+    labels: &mut Labels<'a, T>,
+) -> fmt::Result {
+    let offset = |time: SystemTime| time.duration_since(origin).unwrap_or_default();
+    writeln!(
+        out,
+        "span {:?} #{}",
+        span.operation_name(),
+        labels.get(span.context().state())
+    )?;
+    writeln!(out, "  start: +{:?}", offset(span.start_time()))?;
+    writeln!(out, "  duration: {:?}", span.duration())?;
+    for reference in span.references() {
+        let kind = match reference {
+            SpanReference::ChildOf(_) => "child_of",
+            SpanReference::FollowsFrom(_) => "follows_from",
+        };
+        writeln!(out, "  {}: #{}", kind, labels.get(reference.span()))?;
+    }
+
+    let mut tags = span.all_tags().collect::<Vec<_>>();
+    if !tags.is_empty() {
+        tags.sort_by(|a, b| a.name().cmp(b.name()));
+        writeln!(out, "  tags:")?;
+        for tag in tags {
+            writeln!(out, "    {} = {}", tag.name(), DisplayValue(tag.value()))?;
+        }
+    }
+    if !span.logs().is_empty() {
+        writeln!(out, "  logs:")?;
+        for log in span.logs() {
+            write!(out, "    +{:?}:", offset(log.time()))?;
+            for field in log.fields() {
+                write!(out, " {}={}", field.name(), DisplayValue(field.value()))?;
+            }
+            writeln!(out)?;
+        }
+    }
+    let mut items = span.context().baggage_items().iter().collect::<Vec<_>>();
+    if !items.is_empty() {
+        items.sort_by(|a, b| a.name().cmp(b.name()));
+        writeln!(out, "  baggage:")?;
+        for item in items {
+            writeln!(out, "    {} = {:?}", item.name(), item.value())?;
+        }
+    }
+    Ok(())
+}
+
+struct DisplayValue<'a>(&'a TagValue);
+impl fmt::Display for DisplayValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            TagValue::String(v) => write!(f, "{:?}", v),
+            TagValue::Boolean(v) => write!(f, "{}", v),
+            TagValue::Integer(v) => write!(f, "{}", v),
+            TagValue::Float(v) => write!(f, "{:?}", v),
+            TagValue::Array(values) => {
+                f.write_str("[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", DisplayValue(v))?;
+                }
+                f.write_str("]")
+            }
+            TagValue::Map(entries) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{:?}: {}", k, DisplayValue(v))?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::testing::TestTracer;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn snapshots_are_stable() {
+        let render = |id: u64| {
+            let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(id));
+            let tracer = TestTracer::<u64>::with_clock(clock.clone());
+            {
+                let mut span = tracer.span("foo").start_with_state(id);
+                clock.advance(Duration::from_millis(3));
+                span.log(|log| {
+                    log.std().event("bar");
+                });
+            }
+            snapshot(&tracer.spans())
+        };
+        assert_eq!(render(1), render(2));
+        assert_eq!(
+            render(1),
+            "span \"foo\" #0\n  start: +0ns\n  duration: 3ms\n  logs:\n    +3ms: event=\"bar\"\n"
+        );
+    }
+}
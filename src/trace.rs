@@ -0,0 +1,681 @@
+//! Assembly of finished spans into traces.
+//!
+//! Spans are received one by one, in the order they are finished
+//! (i.e., children typically arrive before their parents).
+//! `TraceAssembler` groups them by trace and yields whole `Trace`s
+//! to exporters and analyzers.
+//! If spans may be received more than once, they can be filtered by `SpanDeduplicator`
+//! beforehand, and whole traces can be kept or dropped by `TailSampler`.
+use crate::clock::{Clock, SystemClock};
+use crate::span::FinishedSpan;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub use self::clamp::CLAMPED_TAG;
+pub use self::critical_path::{CriticalPath, PathSegment};
+pub use self::dedup::SpanDeduplicator;
+pub use self::skew::ClockSkew;
+pub use self::tail::TailSampler;
+
+mod clamp;
+mod critical_path;
+mod dedup;
+mod skew;
+mod tail;
+
+/// Common accessors of span context states.
+///
+/// Context states are specific to tracing backends.
+/// By implementing this trait, a state type gives generic code
+/// (e.g., `TraceAssembler` and exporters) access to the identifiers and the sampled flag.
+pub trait TraceIdentity {
+    /// Identifier of a trace.
+    type TraceId: Eq + Hash + Clone + fmt::Debug;
+
+    /// Identifier of a span in a trace.
+    type SpanId: Eq + Hash + Clone + fmt::Debug;
+
+    /// Returns the identifier of the trace.
+    fn trace_id(&self) -> Self::TraceId;
+
+    /// Returns the identifier of the span.
+    fn span_id(&self) -> Self::SpanId;
+
+    /// Returns `true` if the trace is sampled.
+    ///
+    /// The default implementation returns `true` for states without a sampled flag.
+    fn is_sampled(&self) -> bool {
+        true
+    }
+}
+
+/// Spans of a trace linked to each other.
+///
+/// Spans are indexed in the order they were received.
+#[derive(Debug)]
+pub struct Trace<T: TraceIdentity> {
+    trace_id: T::TraceId,
+    spans: Vec<FinishedSpan<T>>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    complete: bool,
+}
+impl<T: TraceIdentity> Trace<T> {
+    /// Makes a new `Trace` instance by linking `spans` which belong to the trace `trace_id`.
+    ///
+    /// The parent of a span is the first span it references in `spans`.
+    pub fn new(trace_id: T::TraceId, spans: Vec<FinishedSpan<T>>) -> Self {
+        let indices = spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| (span.context().state().span_id(), i))
+            .collect::<HashMap<_, _>>();
+        let parents = spans
+            .iter()
+            .map(|span| {
+                span.references()
+                    .iter()
+                    .find_map(|r| indices.get(&r.span().span_id()).copied())
+            })
+            .collect::<Vec<_>>();
+        let mut children = vec![Vec::new(); spans.len()];
+        for (i, parent) in parents.iter().enumerate() {
+            if let Some(parent) = *parent {
+                children[parent].push(i);
+            }
+        }
+        let complete = is_complete(&spans);
+        Trace {
+            trace_id,
+            spans,
+            parents,
+            children,
+            complete,
+        }
+    }
+
+    /// Returns the identifier of this trace.
+    pub fn trace_id(&self) -> &T::TraceId {
+        &self.trace_id
+    }
+
+    /// Returns the spans of this trace.
+    pub fn spans(&self) -> &[FinishedSpan<T>] {
+        &self.spans
+    }
+
+    /// Takes the spans out of this trace.
+    pub fn into_spans(self) -> Vec<FinishedSpan<T>> {
+        self.spans
+    }
+
+    /// Returns the index of the parent of the `i`-th span.
+    ///
+    /// This is `None` for the roots of this trace,
+    /// including spans whose parents are missing (e.g., remote parents).
+    pub fn parent(&self, i: usize) -> Option<usize> {
+        self.parents[i]
+    }
+
+    /// Returns the indices of the children of the `i`-th span in the order they were received.
+    pub fn children(&self, i: usize) -> &[usize] {
+        &self.children[i]
+    }
+
+    /// Returns an iterator over the indices of the root spans.
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.spans.len()).filter(|&i| self.parents[i].is_none())
+    }
+
+    /// Returns an iterator over the indices of the orphan spans.
+    ///
+    /// Orphans are the roots having references, whose parents are not in this trace
+    /// (e.g., remote parents or lost spans).
+    pub fn orphans(&self) -> impl Iterator<Item = usize> + '_ {
+        self.roots()
+            .filter(|&i| !self.spans[i].references().is_empty())
+    }
+
+    /// Returns the critical path of the `root`-th span.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// use cf_rustracing::clock::MockClock;
+    /// use cf_rustracing::testing::TestTracer;
+    /// use cf_rustracing::trace::{Trace, TraceIdentity};
+    /// use std::time::Duration;
+    ///
+    /// #[derive(Debug, Clone)]
+    /// struct State(u64);
+    /// impl TraceIdentity for State {
+    ///     type TraceId = ();
+    ///     type SpanId = u64;
+    ///
+    ///     fn trace_id(&self) {}
+    ///     fn span_id(&self) -> u64 {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// let clock = MockClock::default();
+    /// let tracer = TestTracer::with_clock(clock.clone());
+    /// {
+    ///     let root = tracer.span("root").start_with_state(State(0));
+    ///     let fast = root.child("fast", |options| options.start_with_state(State(1)));
+    ///     let slow = root.child("slow", |options| options.start_with_state(State(2)));
+    ///     clock.advance(Duration::from_millis(10));
+    ///     drop(fast);
+    ///     clock.advance(Duration::from_millis(30));
+    ///     drop(slow);
+    ///     clock.advance(Duration::from_millis(5));
+    /// }
+    ///
+    /// let trace = Trace::new((), tracer.spans());
+    /// let path = trace.critical_path(2);
+    /// assert_eq!(path.duration(), Duration::from_millis(45));
+    /// assert!(path.contains(1)); // slow
+    /// assert!(!path.contains(0)); // fast
+    /// # }
+    /// ```
+    pub fn critical_path(&self, root: usize) -> CriticalPath {
+        CriticalPath::new(self, root)
+    }
+
+    /// Returns `true` if this trace has a single root without references
+    /// and all the references of the other spans are resolved.
+    ///
+    /// Traces yielded by `TraceAssembler` because of the timeout may be incomplete.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+fn is_complete<T: TraceIdentity>(spans: &[FinishedSpan<T>]) -> bool {
+    let ids = spans
+        .iter()
+        .map(|span| span.context().state().span_id())
+        .collect::<HashSet<_>>();
+    let mut roots = 0;
+    for span in spans {
+        if span.references().is_empty() {
+            roots += 1;
+        } else if !span
+            .references()
+            .iter()
+            .all(|r| ids.contains(&r.span().span_id()))
+        {
+            return false;
+        }
+    }
+    roots == 1
+}
+
+/// Assembler of traces from finished spans.
+///
+/// A trace is yielded as soon as it becomes complete (see `Trace::is_complete`).
+/// Since some traces never become complete (e.g., traces continuing in other processes
+/// or having lost spans), a trace is also yielded by `expired` once `timeout` has elapsed
+/// since its last span was received.
+///
+/// Note that spans finished after their trace has been yielded (e.g., children outliving
+/// their parents) are assembled into another, incomplete trace.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// use cf_rustracing::testing::TestTracer;
+/// use cf_rustracing::trace::{TraceAssembler, TraceIdentity};
+/// use std::time::Duration;
+///
+/// #[derive(Debug, Clone)]
+/// struct State {
+///     trace_id: u64,
+///     span_id: u64,
+/// }
+/// impl TraceIdentity for State {
+///     type TraceId = u64;
+///     type SpanId = u64;
+///
+///     fn trace_id(&self) -> u64 {
+///         self.trace_id
+///     }
+///     fn span_id(&self) -> u64 {
+///         self.span_id
+///     }
+/// }
+///
+/// let tracer = TestTracer::new();
+/// {
+///     let root = tracer.span("root").start_with_state(State { trace_id: 1, span_id: 1 });
+///     root.child("child", |options| options.start_with_state(State { trace_id: 1, span_id: 2 }));
+/// }
+///
+/// let mut assembler = TraceAssembler::new(Duration::from_secs(10));
+/// let mut spans = tracer.spans().into_iter();
+/// assert!(assembler.push(spans.next().unwrap()).is_none());
+///
+/// let trace = assembler.push(spans.next().unwrap()).unwrap();
+/// assert!(trace.is_complete());
+/// assert_eq!(trace.roots().collect::<Vec<_>>(), [1]);
+/// assert_eq!(trace.children(1), [0]);
+/// # }
+/// ```
+pub struct TraceAssembler<T: TraceIdentity> {
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+    clamp_to_parents: bool,
+    pending: HashMap<T::TraceId, PendingTrace<T>>,
+}
+impl<T: TraceIdentity> TraceAssembler<T> {
+    /// Makes a new `TraceAssembler` instance which gives up waiting for
+    /// the remaining spans of a trace after `timeout` of inactivity.
+    pub fn new(timeout: Duration) -> Self {
+        TraceAssembler {
+            timeout,
+            clock: Arc::new(SystemClock),
+            clamp_to_parents: false,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Sets the clock used to measure the timeout.
+    ///
+    /// The default value is `SystemClock`.
+    pub fn clock<C: Clock>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Makes the assembler apply `Trace::clamp_to_parents` to the traces it yields.
+    ///
+    /// The default value is `false`.
+    pub fn clamp_to_parents(mut self, enabled: bool) -> Self {
+        self.clamp_to_parents = enabled;
+        self
+    }
+
+    /// Adds `span` to its trace.
+    ///
+    /// If the trace has become complete, it will be returned.
+    pub fn push(&mut self, span: FinishedSpan<T>) -> Option<Trace<T>> {
+        let trace_id = span.context().state().trace_id();
+        let now = self.clock.instant();
+        let pending = self
+            .pending
+            .entry(trace_id.clone())
+            .or_insert_with(|| PendingTrace {
+                spans: Vec::new(),
+                updated_at: now,
+            });
+        pending.spans.push(span);
+        pending.updated_at = now;
+        if is_complete(&pending.spans) {
+            let pending = self.pending.remove(&trace_id).expect("never fails");
+            Some(self.make_trace(trace_id, pending.spans))
+        } else {
+            None
+        }
+    }
+
+    /// Removes the traces which have received no spans for the timeout and returns them.
+    pub fn expired(&mut self) -> Vec<Trace<T>> {
+        let now = self.clock.instant();
+        let timeout = self.timeout;
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now.saturating_duration_since(p.updated_at) >= timeout)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        expired
+            .into_iter()
+            .filter_map(|id| {
+                let pending = self.pending.remove(&id)?;
+                Some(self.make_trace(id, pending.spans))
+            })
+            .collect()
+    }
+
+    /// Removes all the pending traces and returns them.
+    pub fn flush(&mut self) -> Vec<Trace<T>> {
+        let pending = self.pending.drain().collect::<Vec<_>>();
+        pending
+            .into_iter()
+            .map(|(id, pending)| self.make_trace(id, pending.spans))
+            .collect()
+    }
+
+    /// Returns the number of the traces waiting for their remaining spans.
+    pub fn pending_traces(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn make_trace(&self, trace_id: T::TraceId, spans: Vec<FinishedSpan<T>>) -> Trace<T> {
+        let mut trace = Trace::new(trace_id, spans);
+        if self.clamp_to_parents {
+            trace.clamp_to_parents();
+        }
+        trace
+    }
+}
+impl<T: TraceIdentity> fmt::Debug for TraceAssembler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceAssembler")
+            .field("timeout", &self.timeout)
+            .field("pending_traces", &self.pending.len())
+            .finish_non_exhaustive()
+    }
+}
+
+struct PendingTrace<T> {
+    spans: Vec<FinishedSpan<T>>,
+    updated_at: Instant,
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::span::SpanContext;
+    use crate::testing::TestTracer;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct State(u64, u64);
+    impl TraceIdentity for State {
+        type TraceId = u64;
+        type SpanId = u64;
+
+        fn trace_id(&self) -> u64 {
+            self.0
+        }
+        fn span_id(&self) -> u64 {
+            self.1
+        }
+    }
+
+    #[test]
+    fn tail_sampler_keeps_whole_traces() {
+        let clock = MockClock::default();
+        let tracer = TestTracer::with_clock(clock.clone());
+        {
+            let root = tracer.span("root").start_with_state(State(1, 1));
+            root.child("error", |options| {
+                options
+                    .tag(crate::tag::StdTag::error())
+                    .start_with_state(State(1, 2))
+            });
+            tracer.span("ok").start_with_state(State(2, 1));
+        }
+        let has_error = |spans: &[FinishedSpan<State>]| {
+            spans
+                .iter()
+                .any(|s| s.tags().iter().any(|t| t.name() == "error"))
+        };
+        let mut sampler = TailSampler::new(Duration::from_secs(5), has_error).clock(clock.clone());
+        for span in tracer.spans() {
+            assert!(sampler.push(span).is_none());
+        }
+        assert_eq!(sampler.pending_traces(), 2);
+        assert!(sampler.expired().is_empty());
+
+        clock.advance(Duration::from_secs(5));
+        let kept = sampler.expired();
+        assert_eq!(kept.len(), 2);
+        assert_eq!((sampler.kept_traces(), sampler.dropped_traces()), (1, 1));
+
+        // Late spans follow the decisions of their traces.
+        tracer.span("late").start_with_state(State(1, 3));
+        tracer.span("late").start_with_state(State(2, 2));
+        let mut late = tracer.spans().into_iter().skip(3);
+        assert!(sampler.push(late.next().unwrap()).is_some());
+        assert!(sampler.push(late.next().unwrap()).is_none());
+
+        // Decisions are forgotten after the window.
+        clock.advance(Duration::from_secs(5));
+        tracer.span("later").start_with_state(State(2, 3));
+        let span = tracer.spans().pop().unwrap();
+        assert!(sampler.push(span).is_none());
+        assert!(sampler.flush().is_empty());
+        assert_eq!(sampler.dropped_traces(), 2);
+    }
+
+    #[test]
+    fn incomplete_traces_expire() {
+        let clock = MockClock::default();
+        let tracer = TestTracer::with_clock(clock.clone());
+        {
+            let remote = SpanContext::new(State(1, 100), Vec::new());
+            let local = tracer
+                .span("local")
+                .child_of(&remote)
+                .start_with_state(State(1, 1));
+            local.child("a", |options| options.start_with_state(State(1, 2)));
+            tracer.span("other").start_with_state(State(2, 1));
+        }
+
+        let mut assembler = TraceAssembler::new(Duration::from_secs(5)).clock(clock.clone());
+        let mut complete = Vec::new();
+        for span in tracer.spans() {
+            complete.extend(assembler.push(span));
+        }
+        assert_eq!(complete.len(), 1);
+        assert_eq!(*complete[0].trace_id(), 2);
+        assert_eq!(assembler.pending_traces(), 1);
+        assert!(assembler.expired().is_empty());
+
+        clock.advance(Duration::from_secs(5));
+        let expired = assembler.expired();
+        assert_eq!(expired.len(), 1);
+        let trace = &expired[0];
+        assert!(!trace.is_complete());
+        assert_eq!(trace.spans()[1].operation_name(), "local");
+        assert_eq!(trace.roots().collect::<Vec<_>>(), [1]);
+        assert_eq!(trace.parent(0), Some(1));
+        assert_eq!(assembler.pending_traces(), 0);
+    }
+
+    #[test]
+    fn duplicates_are_forgotten_after_window() {
+        let clock = MockClock::default();
+        let tracer = TestTracer::new();
+        tracer.span("a").start_with_state(State(1, 1));
+        tracer.span("b").start_with_state(State(1, 2));
+        let spans = tracer.spans();
+
+        let mut dedup = SpanDeduplicator::new(Duration::from_secs(10)).clock(clock.clone());
+        assert!(!dedup.is_duplicate(&spans[0]));
+        clock.advance(Duration::from_secs(5));
+        assert!(!dedup.is_duplicate(&spans[1]));
+        assert!(dedup.is_duplicate(&spans[0]));
+        assert_eq!(dedup.len(), 2);
+
+        clock.advance(Duration::from_secs(5));
+        assert!(!dedup.is_duplicate(&spans[0]));
+        assert!(dedup.is_duplicate(&spans[1]));
+        assert_eq!(dedup.duplicates(), 2);
+    }
+
+    #[test]
+    fn children_are_clamped_to_parents() {
+        use crate::tag::TagValue;
+
+        let ms = Duration::from_millis;
+        let clock = MockClock::default();
+        let tracer = TestTracer::with_clock(clock.clone());
+        {
+            clock.advance(ms(10));
+            let root = tracer.span("root").start_with_state(State(1, 0));
+            let early = tracer
+                .span("early")
+                .child_of(&root)
+                .start_time(UNIX_EPOCH)
+                .start_with_state(State(1, 1));
+            let late = root.child("late", |options| options.start_with_state(State(1, 2)));
+            let follower =
+                root.follower("follower", |options| options.start_with_state(State(1, 3)));
+            clock.advance(ms(10));
+            drop(early);
+            drop(root);
+            clock.advance(ms(5));
+            drop(late);
+            drop(follower);
+        }
+
+        let mut trace = Trace::new(1, tracer.spans());
+        assert_eq!(trace.clamp_to_parents(), 2);
+        let spans = trace.spans();
+        let millis = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let clamped = |i: usize| {
+            spans[i]
+                .tags()
+                .iter()
+                .find(|t| t.name() == CLAMPED_TAG)
+                .map(|t| t.value().clone())
+        };
+
+        // early: 0..20 -> 10..20
+        assert_eq!(spans[0].operation_name(), "early");
+        assert_eq!(millis(spans[0].start_time()), 10);
+        assert_eq!(spans[0].duration(), ms(10));
+        assert_eq!(clamped(0), Some(TagValue::from(10_000i64)));
+
+        // root: 10..20
+        assert_eq!(clamped(1), None);
+
+        // late: 10..25 -> 10..20
+        assert_eq!(millis(spans[2].finish_time()), 20);
+        assert_eq!(clamped(2), Some(TagValue::from(5_000i64)));
+
+        // follower: 10..25 (not clamped)
+        assert_eq!(millis(spans[3].finish_time()), 25);
+        assert_eq!(clamped(3), None);
+    }
+
+    #[test]
+    fn orphans_and_clock_skews_are_reported() {
+        let ms = Duration::from_millis;
+        let clock = MockClock::default();
+        let tracer = TestTracer::with_clock(clock.clone());
+        {
+            clock.advance(ms(10));
+            let root = tracer.span("root").start_with_state(State(1, 0));
+            let early = tracer
+                .span("early")
+                .child_of(&root)
+                .start_time(UNIX_EPOCH)
+                .start_with_state(State(1, 1));
+            let late = root.child("late", |options| options.start_with_state(State(1, 2)));
+            let follower =
+                root.follower("follower", |options| options.start_with_state(State(1, 3)));
+            let remote = SpanContext::new(State(1, 100), Vec::new());
+            tracer
+                .span("orphan")
+                .child_of(&remote)
+                .start_with_state(State(1, 4));
+            clock.advance(ms(10));
+            drop(early);
+            drop(root);
+            clock.advance(ms(5));
+            drop(late);
+            drop(follower);
+        }
+
+        let trace = Trace::new(1, tracer.spans());
+        let names = |i: usize| trace.spans()[i].operation_name();
+        assert_eq!(trace.orphans().map(names).collect::<Vec<_>>(), ["orphan"]);
+
+        let skews = trace
+            .clock_skews()
+            .into_iter()
+            .map(|s| {
+                (
+                    names(s.child()),
+                    names(s.parent()),
+                    s.early_start(),
+                    s.late_finish(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skews,
+            [
+                ("early", "root", ms(10), ms(0)),
+                ("late", "root", ms(0), ms(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn critical_path_works() {
+        let ms = Duration::from_millis;
+        let clock = MockClock::default();
+        let tracer = TestTracer::with_clock(clock.clone());
+        {
+            // root: 0..100, a: 10..40, b: 20..70 (b1: 30..60), c: 80..90
+            let root = tracer.span("root").start_with_state(State(1, 0));
+            clock.advance(ms(10));
+            let a = root.child("a", |options| options.start_with_state(State(1, 1)));
+            clock.advance(ms(10));
+            let b = root.child("b", |options| options.start_with_state(State(1, 2)));
+            clock.advance(ms(10));
+            let b1 = b.child("b1", |options| options.start_with_state(State(1, 3)));
+            clock.advance(ms(10));
+            drop(a);
+            clock.advance(ms(20));
+            drop(b1);
+            clock.advance(ms(10));
+            drop(b);
+            clock.advance(ms(10));
+            let c = root.child("c", |options| options.start_with_state(State(1, 4)));
+            clock.advance(ms(10));
+            drop(c);
+            clock.advance(ms(10));
+        }
+        let trace = Trace::new(1, tracer.spans());
+        let names = |i: usize| trace.spans()[i].operation_name();
+        let root = trace.roots().next().unwrap();
+
+        let path = trace.critical_path(root);
+        let segments = path
+            .segments()
+            .iter()
+            .map(|s| {
+                let offset = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap().as_millis();
+                (names(s.span()), offset(s.start()), offset(s.end()))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            [
+                ("root", 0, 10),
+                ("a", 10, 20),
+                ("b", 20, 30),
+                ("b1", 30, 60),
+                ("b", 60, 70),
+                ("root", 70, 80),
+                ("c", 80, 90),
+                ("root", 90, 100),
+            ]
+        );
+        assert_eq!(path.duration(), ms(100));
+
+        let contributions = path
+            .contributions()
+            .into_iter()
+            .map(|(i, d)| (names(i), d.as_millis()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            contributions,
+            [("b1", 30), ("root", 30), ("b", 20), ("a", 10), ("c", 10)]
+        );
+    }
+}
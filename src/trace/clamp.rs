@@ -0,0 +1,64 @@
+use super::{Trace, TraceIdentity};
+use crate::tag::Tag;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// The name of the tag recording how far the timestamps of a span were moved
+/// by `Trace::clamp_to_parents` (in microseconds).
+pub const CLAMPED_TAG: &str = "rustracing.clamped_us";
+
+impl<T: TraceIdentity> Trace<T> {
+    /// Clamps the timestamps of child spans to the intervals of their parents.
+    ///
+    /// Coarse clocks and scheduling jitter can make a child appear to start before
+    /// or finish after its parent, which confuses trace viewers.
+    /// This moves the start and finish times of such children into their parents' intervals
+    /// (parents are adjusted first) and records the total adjustment in the `CLAMPED_TAG` tag.
+    ///
+    /// Only the children linked to their parents by `ChildOf` references are clamped.
+    /// This returns the number of the adjusted spans.
+    pub fn clamp_to_parents(&mut self) -> usize {
+        let mut adjusted = 0;
+        let mut queue = self.roots().collect::<VecDeque<_>>();
+        while let Some(parent) = queue.pop_front() {
+            let window = (
+                self.spans[parent].start_time(),
+                self.spans[parent].finish_time(),
+            );
+            let parent_id = self.spans[parent].context().state().span_id();
+            for i in 0..self.children[parent].len() {
+                let child = self.children[parent][i];
+                queue.push_back(child);
+
+                let is_child_of = self.spans[child]
+                    .references()
+                    .iter()
+                    .any(|r| r.is_child_of() && r.span().span_id() == parent_id);
+                if is_child_of && self.clamp(child, window) {
+                    adjusted += 1;
+                }
+            }
+        }
+        adjusted
+    }
+
+    fn clamp(&mut self, i: usize, (min, max): (SystemTime, SystemTime)) -> bool {
+        let span = &mut self.spans[i];
+        let start = span.start_time().clamp(min, max);
+        let finish = span.finish_time().clamp(start, max);
+        let moved = distance(start, span.start_time()) + distance(finish, span.finish_time());
+        if moved.is_zero() {
+            return false;
+        }
+        let duration = finish.duration_since(start).unwrap_or_default();
+        span.set_times(start, duration);
+        span.push_tag(Tag::new(CLAMPED_TAG, moved.as_micros() as i64));
+        true
+    }
+}
+
+fn distance(a: SystemTime, b: SystemTime) -> Duration {
+    a.duration_since(b)
+        .or_else(|_| b.duration_since(a))
+        .unwrap_or_default()
+}
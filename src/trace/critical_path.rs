@@ -0,0 +1,122 @@
+use super::{Trace, TraceIdentity};
+use std::time::{Duration, SystemTime};
+
+/// Critical path of a trace.
+///
+/// The critical path is the chain of the spans which determine the end-to-end latency
+/// of a root span: it is built by walking back from the finish time of the root,
+/// descending into the child which finished last at every step.
+/// Shortening a span which is not on the critical path does not reduce the latency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalPath {
+    segments: Vec<PathSegment>,
+}
+impl CriticalPath {
+    pub(crate) fn new<T: TraceIdentity>(trace: &Trace<T>, root: usize) -> Self {
+        let mut segments = Vec::new();
+        let root_finish = trace.spans()[root].finish_time();
+        visit(trace, root, root_finish, &mut segments);
+        segments.reverse();
+        CriticalPath { segments }
+    }
+
+    /// Returns the segments of this path in chronological order.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Returns the total duration of this path.
+    pub fn duration(&self) -> Duration {
+        self.segments.iter().map(|s| s.duration()).sum()
+    }
+
+    /// Returns the time each span spends on this path, in descending order of the time.
+    ///
+    /// The spans are identified by their indices in the trace.
+    pub fn contributions(&self) -> Vec<(usize, Duration)> {
+        let mut contributions: Vec<(usize, Duration)> = Vec::new();
+        for segment in &self.segments {
+            match contributions.iter_mut().find(|(i, _)| *i == segment.span) {
+                Some((_, d)) => *d += segment.duration(),
+                None => contributions.push((segment.span, segment.duration())),
+            }
+        }
+        contributions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        contributions
+    }
+
+    /// Returns `true` if the span is on this path.
+    pub fn contains(&self, span: usize) -> bool {
+        self.segments.iter().any(|s| s.span == span)
+    }
+}
+
+/// Interval during which a span is on a critical path.
+///
+/// During the interval, the span itself (rather than any of its children)
+/// is what the latency is waiting for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathSegment {
+    span: usize,
+    start: SystemTime,
+    end: SystemTime,
+}
+impl PathSegment {
+    /// Returns the index of the span in the trace.
+    pub fn span(&self) -> usize {
+        self.span
+    }
+
+    /// Returns the start time of this segment.
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+
+    /// Returns the end time of this segment.
+    pub fn end(&self) -> SystemTime {
+        self.end
+    }
+
+    /// Returns the duration of this segment.
+    pub fn duration(&self) -> Duration {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+/// Adds the segments of `span` before `end` to `segments` in reverse chronological order.
+fn visit<T: TraceIdentity>(
+    trace: &Trace<T>,
+    span: usize,
+    end: SystemTime,
+    segments: &mut Vec<PathSegment>,
+) {
+    let start = trace.spans()[span].start_time();
+    let mut cursor = end.min(trace.spans()[span].finish_time());
+
+    let mut children = trace.children(span).to_vec();
+    children.sort_by_key(|&i| std::cmp::Reverse(trace.spans()[i].finish_time()));
+    for child in children {
+        let child_start = trace.spans()[child].start_time().max(start);
+        let child_end = trace.spans()[child].finish_time().min(cursor);
+        if child_start >= child_end {
+            // The child does not overlap the remaining part of the span.
+            continue;
+        }
+        if child_end < cursor {
+            segments.push(PathSegment {
+                span,
+                start: child_end,
+                end: cursor,
+            });
+        }
+        visit(trace, child, child_end, segments);
+        cursor = child_start;
+    }
+    if start < cursor {
+        segments.push(PathSegment {
+            span,
+            start,
+            end: cursor,
+        });
+    }
+}
@@ -0,0 +1,134 @@
+use super::TraceIdentity;
+use crate::clock::{Clock, SystemClock};
+use crate::span::FinishedSpan;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type SpanKey<T> = (<T as TraceIdentity>::TraceId, <T as TraceIdentity>::SpanId);
+
+/// Filter discarding the spans which have already been seen.
+///
+/// Spans are identified by their trace and span identifiers,
+/// and are remembered for `window` after they were first seen.
+/// This is useful for pipelines receiving spans through multiple redundant paths
+/// (e.g., retried exports or mirrored streams), which would otherwise count spans twice.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// use cf_rustracing::testing::TestTracer;
+/// use cf_rustracing::trace::{SpanDeduplicator, TraceIdentity};
+/// use std::time::Duration;
+///
+/// #[derive(Debug, Clone)]
+/// struct State(u64);
+/// impl TraceIdentity for State {
+///     type TraceId = ();
+///     type SpanId = u64;
+///
+///     fn trace_id(&self) {}
+///     fn span_id(&self) -> u64 {
+///         self.0
+///     }
+/// }
+///
+/// let tracer = TestTracer::new();
+/// tracer.span("foo").start_with_state(State(1));
+/// let span = tracer.spans().remove(0);
+///
+/// let mut dedup = SpanDeduplicator::new(Duration::from_secs(60));
+/// assert!(dedup.filter(span.clone()).is_some());
+/// assert!(dedup.filter(span).is_none());
+/// assert_eq!(dedup.duplicates(), 1);
+/// # }
+/// ```
+pub struct SpanDeduplicator<T: TraceIdentity> {
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    seen: HashMap<SpanKey<T>, Instant>,
+    order: VecDeque<(SpanKey<T>, Instant)>,
+    duplicates: u64,
+}
+impl<T: TraceIdentity> SpanDeduplicator<T> {
+    /// Makes a new `SpanDeduplicator` instance which remembers spans for `window`.
+    pub fn new(window: Duration) -> Self {
+        SpanDeduplicator {
+            window,
+            clock: Arc::new(SystemClock),
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            duplicates: 0,
+        }
+    }
+
+    /// Sets the clock used to measure the window.
+    ///
+    /// The default value is `SystemClock`.
+    pub fn clock<C: Clock>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns `span` if it has not been seen within the window, or `None` otherwise.
+    pub fn filter(&mut self, span: FinishedSpan<T>) -> Option<FinishedSpan<T>> {
+        if self.is_duplicate(&span) {
+            None
+        } else {
+            Some(span)
+        }
+    }
+
+    /// Returns `true` if `span` has been seen within the window, and marks it as seen.
+    pub fn is_duplicate(&mut self, span: &FinishedSpan<T>) -> bool {
+        let now = self.clock.instant();
+        self.expire(now);
+
+        let state = span.context().state();
+        let key = (state.trace_id(), state.span_id());
+        if self.seen.contains_key(&key) {
+            self.duplicates += 1;
+            return true;
+        }
+        self.seen.insert(key.clone(), now);
+        self.order.push_back((key, now));
+        false
+    }
+
+    /// Returns the number of the duplicate spans detected so far.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Returns the number of the spans being remembered.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns `true` if no span is being remembered.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((_, seen_at)) = self.order.front() {
+            if now.saturating_duration_since(*seen_at) < self.window {
+                break;
+            }
+            let (key, _) = self.order.pop_front().expect("never fails");
+            self.seen.remove(&key);
+        }
+    }
+}
+impl<T: TraceIdentity> fmt::Debug for SpanDeduplicator<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanDeduplicator")
+            .field("window", &self.window)
+            .field("len", &self.seen.len())
+            .field("duplicates", &self.duplicates)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,77 @@
+use super::{Trace, TraceIdentity};
+use std::time::{Duration, SystemTime};
+
+/// Child span whose timestamps fall outside the interval of its parent.
+///
+/// Such spans are usually caused by skewed or coarse clocks
+/// (e.g., when the child and the parent were recorded on different hosts).
+/// See `Trace::clock_skews`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockSkew {
+    child: usize,
+    parent: usize,
+    early_start: Duration,
+    late_finish: Duration,
+}
+impl ClockSkew {
+    /// Returns the index of the child span in the trace.
+    pub fn child(&self) -> usize {
+        self.child
+    }
+
+    /// Returns the index of the parent span in the trace.
+    pub fn parent(&self) -> usize {
+        self.parent
+    }
+
+    /// Returns how long the child started before its parent.
+    pub fn early_start(&self) -> Duration {
+        self.early_start
+    }
+
+    /// Returns how long the child finished after its parent.
+    pub fn late_finish(&self) -> Duration {
+        self.late_finish
+    }
+}
+
+impl<T: TraceIdentity> Trace<T> {
+    /// Returns the child spans whose timestamps fall outside the intervals of their parents.
+    ///
+    /// Unlike `clamp_to_parents`, this only reports the anomalies and leaves the spans as they are.
+    /// Only the children linked to their parents by `ChildOf` references are checked,
+    /// since `FollowsFrom` children may legitimately outlive their parents.
+    pub fn clock_skews(&self) -> Vec<ClockSkew> {
+        let mut skews = Vec::new();
+        for (child, span) in self.spans.iter().enumerate() {
+            let Some(parent) = self.parents[child] else {
+                continue;
+            };
+            let parent_span = &self.spans[parent];
+            let parent_id = parent_span.context().state().span_id();
+            let is_child_of = span
+                .references()
+                .iter()
+                .any(|r| r.is_child_of() && r.span().span_id() == parent_id);
+            if !is_child_of {
+                continue;
+            }
+            let early_start = excess(parent_span.start_time(), span.start_time());
+            let late_finish = excess(span.finish_time(), parent_span.finish_time());
+            if !early_start.is_zero() || !late_finish.is_zero() {
+                skews.push(ClockSkew {
+                    child,
+                    parent,
+                    early_start,
+                    late_finish,
+                });
+            }
+        }
+        skews
+    }
+}
+
+/// Returns how long `a` is later than `b`, or zero if it is not.
+fn excess(a: SystemTime, b: SystemTime) -> Duration {
+    a.duration_since(b).unwrap_or_default()
+}
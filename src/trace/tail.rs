@@ -0,0 +1,214 @@
+use super::TraceIdentity;
+use crate::clock::{Clock, SystemClock};
+use crate::span::FinishedSpan;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type Policy<T> = Box<dyn FnMut(&[FinishedSpan<T>]) -> bool + Send>;
+
+/// Tail-based sampling stage deciding whether to keep whole traces after they have finished.
+///
+/// Finished spans are buffered per trace for `window` after the first span of the trace
+/// was received. Then `policy` is given all the buffered spans of the trace and decides
+/// whether the trace is forwarded or dropped.
+/// The decision is remembered for another `window`, so spans of the trace received later
+/// are forwarded or dropped immediately.
+///
+/// Unlike samplers, which decide when traces start, policies can look at the outcome of traces,
+/// for example, to keep the traces which contain errors or are slow.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// use cf_rustracing::clock::MockClock;
+/// use cf_rustracing::testing::TestTracer;
+/// use cf_rustracing::trace::{TailSampler, TraceIdentity};
+/// use std::time::Duration;
+///
+/// #[derive(Debug, Clone)]
+/// struct State(u64);
+/// impl TraceIdentity for State {
+///     type TraceId = u64;
+///     type SpanId = ();
+///
+///     fn trace_id(&self) -> u64 {
+///         self.0
+///     }
+///     fn span_id(&self) {}
+/// }
+///
+/// let clock = MockClock::default();
+/// let tracer = TestTracer::with_clock(clock.clone());
+/// tracer.span("fast").start_with_state(State(1));
+/// {
+///     let _span = tracer.span("slow").start_with_state(State(2));
+///     clock.advance(Duration::from_secs(1));
+/// }
+///
+/// let mut sampler = TailSampler::new(Duration::from_secs(10), |spans: &[_]| {
+///     spans.iter().any(|span| {
+///         span.tags().iter().any(|tag| tag.name() == "error")
+///             || span.duration() > Duration::from_millis(500)
+///     })
+/// })
+/// .clock(clock.clone());
+/// for span in tracer.spans() {
+///     assert!(sampler.push(span).is_none());
+/// }
+///
+/// clock.advance(Duration::from_secs(10));
+/// let kept = sampler.expired();
+/// assert_eq!(kept.len(), 1);
+/// assert_eq!(kept[0].operation_name(), "slow");
+/// assert_eq!(sampler.dropped_traces(), 1);
+/// # }
+/// ```
+pub struct TailSampler<T: TraceIdentity> {
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    policy: Policy<T>,
+    pending: HashMap<T::TraceId, (Vec<FinishedSpan<T>>, Instant)>,
+    decided: HashMap<T::TraceId, bool>,
+    decided_order: VecDeque<(T::TraceId, Instant)>,
+    kept_traces: u64,
+    dropped_traces: u64,
+}
+impl<T: TraceIdentity> TailSampler<T> {
+    /// Makes a new `TailSampler` instance which buffers the spans of each trace for `window`
+    /// and keeps the traces for which `policy` returns `true`.
+    pub fn new<F>(window: Duration, policy: F) -> Self
+    where
+        F: FnMut(&[FinishedSpan<T>]) -> bool + Send + 'static,
+    {
+        TailSampler {
+            window,
+            clock: Arc::new(SystemClock),
+            policy: Box::new(policy),
+            pending: HashMap::new(),
+            decided: HashMap::new(),
+            decided_order: VecDeque::new(),
+            kept_traces: 0,
+            dropped_traces: 0,
+        }
+    }
+
+    /// Sets the clock used to measure the window.
+    ///
+    /// The default value is `SystemClock`.
+    pub fn clock<C: Clock>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Adds `span` to the buffer of its trace.
+    ///
+    /// If the trace has already been decided to be kept, `span` is returned immediately.
+    pub fn push(&mut self, span: FinishedSpan<T>) -> Option<FinishedSpan<T>> {
+        let now = self.clock.instant();
+        self.forget(now);
+
+        let trace_id = span.context().state().trace_id();
+        match self.decided.get(&trace_id) {
+            Some(true) => Some(span),
+            Some(false) => None,
+            None => {
+                self.pending
+                    .entry(trace_id)
+                    .or_insert_with(|| (Vec::new(), now))
+                    .0
+                    .push(span);
+                None
+            }
+        }
+    }
+
+    /// Decides the traces whose windows have elapsed and returns the spans of the kept ones.
+    pub fn expired(&mut self) -> Vec<FinishedSpan<T>> {
+        let now = self.clock.instant();
+        self.forget(now);
+
+        let window = self.window;
+        let expired = self
+            .pending
+            .iter()
+            .filter(|(_, (_, received_at))| now.saturating_duration_since(*received_at) >= window)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        let mut kept = Vec::new();
+        for trace_id in expired {
+            if let Some((spans, _)) = self.pending.remove(&trace_id) {
+                kept.extend(self.decide(trace_id, spans, now));
+            }
+        }
+        kept
+    }
+
+    /// Decides all the buffered traces regardless of their windows
+    /// and returns the spans of the kept ones.
+    pub fn flush(&mut self) -> Vec<FinishedSpan<T>> {
+        let now = self.clock.instant();
+        let pending = self.pending.drain().collect::<Vec<_>>();
+        let mut kept = Vec::new();
+        for (trace_id, (spans, _)) in pending {
+            kept.extend(self.decide(trace_id, spans, now));
+        }
+        kept
+    }
+
+    /// Returns the number of the traces being buffered.
+    pub fn pending_traces(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of the traces kept so far.
+    pub fn kept_traces(&self) -> u64 {
+        self.kept_traces
+    }
+
+    /// Returns the number of the traces dropped so far.
+    pub fn dropped_traces(&self) -> u64 {
+        self.dropped_traces
+    }
+
+    fn decide(
+        &mut self,
+        trace_id: T::TraceId,
+        spans: Vec<FinishedSpan<T>>,
+        now: Instant,
+    ) -> Vec<FinishedSpan<T>> {
+        let keep = (self.policy)(&spans);
+        self.decided.insert(trace_id.clone(), keep);
+        self.decided_order.push_back((trace_id, now));
+        if keep {
+            self.kept_traces += 1;
+            spans
+        } else {
+            self.dropped_traces += 1;
+            Vec::new()
+        }
+    }
+
+    fn forget(&mut self, now: Instant) {
+        while let Some((_, decided_at)) = self.decided_order.front() {
+            if now.saturating_duration_since(*decided_at) < self.window {
+                break;
+            }
+            let (trace_id, _) = self.decided_order.pop_front().expect("never fails");
+            self.decided.remove(&trace_id);
+        }
+    }
+}
+impl<T: TraceIdentity> fmt::Debug for TailSampler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TailSampler")
+            .field("window", &self.window)
+            .field("pending_traces", &self.pending.len())
+            .field("kept_traces", &self.kept_traces)
+            .field("dropped_traces", &self.dropped_traces)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,8 +1,22 @@
-use crate::sampler::Sampler;
-use crate::span::{SpanReceiver, SpanSender, StartSpanOptions};
+use crate::channel::{
+    self, ChannelOptions, Faults, FinishObservers, SenderOptions, SpanProcessors, SpanStore,
+};
+use crate::clock::{Clock, SystemClock};
+use crate::id::{IdGenerator, RandomIdGenerator};
+use crate::metrics::TracerMetrics;
+use crate::redact::Redactor;
+use crate::reporter::{SharedReporter, SpanReporter};
+use crate::sampler::{FinishSampler, NullSampler, Sampler, SharedFinishSampler};
+use crate::scope::{self, SpanScope};
+use crate::span::{
+    BaggageRestrictions, FinishedSpan, OverflowPolicy, Span, SpanHandle, SpanLimits, SpanReceiver,
+    SpanSender, StartSpanOptions,
+};
+use crate::tag::{SharedTags, Tag, TagValue};
+use crate::tracker::SpanTracker;
 use std::borrow::Cow;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
 
 /// Tracer.
 ///
@@ -30,26 +44,185 @@ pub struct Tracer<S, T> {
 impl<S: Sampler<T>, T> Tracer<S, T> {
     /// Makes a new `Tracer` instance.
     pub fn new(sampler: S) -> (Self, SpanReceiver<T>) {
-        let (span_tx, span_rx) = mpsc::unbounded_channel();
+        Self::builder(sampler).finish()
+    }
 
-        (
-            Tracer {
-                sampler: Arc::new(sampler),
-                span_tx,
-            },
-            span_rx,
-        )
+    /// Makes a new `Tracer` instance whose span channel can buffer up to `capacity` finished spans.
+    ///
+    /// This is equivalent to `Tracer::builder(sampler).channel_capacity(capacity).finish()`.
+    pub fn with_channel_capacity(sampler: S, capacity: usize) -> (Self, SpanReceiver<T>) {
+        Self::builder(sampler).channel_capacity(capacity).finish()
+    }
+
+    /// Makes a new `Tracer` instance which hands finished spans to `reporter`
+    /// instead of sending them to a channel.
+    ///
+    /// This is equivalent to `Tracer::builder(sampler).finish_with_reporter(reporter)`.
+    pub fn with_reporter<R>(sampler: S, reporter: R) -> Self
+    where
+        R: SpanReporter<T> + Send + Sync + 'static,
+    {
+        Self::builder(sampler).finish_with_reporter(reporter)
+    }
+
+    /// Makes a new `TracerBuilder` instance.
+    pub fn builder(sampler: S) -> TracerBuilder<S, T> {
+        TracerBuilder::new(sampler)
+    }
+
+    /// Makes a new `Tracer` instance whose spans are never started (see `Tracer::noop`).
+    pub(crate) fn disabled(sampler: S) -> Self {
+        let builder = TracerBuilder::new(sampler);
+        Tracer {
+            sampler: Arc::new(builder.sampler),
+            span_tx: channel::disabled_channel(builder.sender_options),
+        }
     }
 
     /// Returns `StartSpanOptions` for starting a span which has the name `operation_name`.
-    pub fn span<N>(&self, operation_name: N) -> StartSpanOptions<S, T>
+    pub fn span<N>(&self, operation_name: N) -> StartSpanOptions<'_, S, T>
     where
         N: Into<Cow<'static, str>>,
     {
         StartSpanOptions::new(operation_name, &self.span_tx, &self.sampler)
     }
 }
+impl<T> Tracer<NullSampler, T> {
+    /// Makes a new `Tracer` instance whose spans are never started and which has no receiver.
+    ///
+    /// This is handy for libraries whose tracing is optional:
+    /// they can hold this tracer while tracing is disabled instead of an `Option<Tracer<_, _>>`.
+    /// Unlike spans rejected by `NullSampler`, even spans having a positive `sampling.priority` tag
+    /// are not started.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::tag::StdTag;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let tracer = Tracer::<_, ()>::noop();
+    /// let span = tracer
+    ///     .span("foo")
+    ///     .tag(StdTag::sampling_priority(1))
+    ///     .start_with_state(());
+    /// assert!(span.context().is_none());
+    /// ```
+    pub fn noop() -> Self {
+        Self::disabled(NullSampler)
+    }
+}
 impl<S, T> Tracer<S, T> {
+    /// Returns the number of the finished spans discarded because
+    /// the span channel was full or the receiver had been dropped.
+    ///
+    /// Which spans are discarded depends on `TracerBuilder::overflow_policy`.
+    pub fn dropped_spans(&self) -> u64 {
+        self.span_tx.dropped_spans()
+    }
+
+    /// Returns the snapshot of the counters of this tracer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::ProbabilisticSampler;
+    /// use cf_rustracing::tag::Tag;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let sampler = ProbabilisticSampler::new(0.0).unwrap();
+    /// let (tracer, _span_rx) = Tracer::with_channel_capacity(sampler, 1);
+    /// tracer.span("rejected").start_with_state(());
+    /// for _ in 0..2 {
+    ///     tracer
+    ///         .span("forced")
+    ///         .tag(Tag::new("sampling.priority", 1))
+    ///         .start_with_state(());
+    /// }
+    ///
+    /// let metrics = tracer.metrics();
+    /// assert_eq!(metrics.spans_started, 2);
+    /// assert_eq!(metrics.spans_finished, 2);
+    /// assert_eq!(metrics.spans_sampled_out, 1);
+    /// assert_eq!(metrics.spans_dropped, 1);
+    /// ```
+    pub fn metrics(&self) -> TracerMetrics {
+        self.span_tx.metrics()
+    }
+
+    /// Returns a scope in which `span` is active.
+    ///
+    /// See `SpanScope` for an example.
+    pub fn scope(&self, span: Span<T>) -> SpanScope<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        SpanScope::new(span)
+    }
+
+    /// Returns the handle of the span active in the current scope.
+    ///
+    /// This is `None` outside of the scopes made by `scope`.
+    pub fn active_span(&self) -> Option<SpanHandle<T>>
+    where
+        T: Clone + 'static,
+    {
+        scope::active_span()
+    }
+
+    /// Waits until all the spans finished before this call have been delivered.
+    ///
+    /// Spans are delivered when they are received from the `SpanReceiver`
+    /// (batched spans are sent to the channel immediately),
+    /// or when `SpanReporter::flush` of the reporter returns.
+    /// This also completes if the receiver is dropped, in which case the spans are lost.
+    ///
+    /// Note that the receiver has to keep receiving spans for this to complete.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler).batch_size(10).finish();
+    /// let exporter = tokio::spawn(async move {
+    ///     let mut count = 0;
+    ///     while span_rx.recv().await.is_some() {
+    ///         count += 1;
+    ///     }
+    ///     count
+    /// });
+    ///
+    /// tracer.span("foo").start_with_state(());
+    /// tracer.flush().await;
+    /// tracer.shutdown().await;
+    /// tracer.span("bar").start_with_state(()); // Discarded.
+    ///
+    /// assert_eq!(exporter.await.unwrap(), 1);
+    /// assert_eq!(tracer.dropped_spans(), 1);
+    /// # }
+    /// ```
+    pub async fn flush(&self) {
+        self.span_tx.flush().await
+    }
+
+    /// Shuts down the tracer gracefully.
+    ///
+    /// The spans finished after this call are discarded (and counted by `dropped_spans`),
+    /// and this waits until the spans finished before have been delivered as `flush` does.
+    /// Then `SpanReceiver::recv` returns `None`, even if some spans are still alive.
+    pub async fn shutdown(&self) {
+        self.span_tx.shutdown().await
+    }
+
+    /// Returns a new identifier from the identifier generator of this tracer.
+    pub fn generate_id(&self) -> u64 {
+        self.span_tx.id_generator().generate()
+    }
+
     /// Clone with the given `sampler`.
     pub fn clone_with_sampler<U: Sampler<T>>(&self, sampler: U) -> Tracer<U, T> {
         Tracer {
@@ -66,3 +239,525 @@ impl<S, T> Clone for Tracer<S, T> {
         }
     }
 }
+
+/// `Tracer` builder.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::Tracer;
+/// use cf_rustracing::sampler::AllSampler;
+/// use std::time::Duration;
+///
+/// # #[tokio::main]
+/// # async fn main(){
+/// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+///     .batch_size(64)
+///     .batch_delay(Duration::from_millis(5))
+///     .finish();
+/// {
+///    let _span = tracer.span("foo").start_with_state(());
+/// }
+/// let span = span_rx.recv().await.unwrap();
+/// assert_eq!(span.operation_name(), "foo");
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TracerBuilder<S, T> {
+    sampler: S,
+    channel_options: ChannelOptions,
+    sender_options: SenderOptions<T>,
+}
+impl<S: Sampler<T>, T> TracerBuilder<S, T> {
+    /// Makes a new `TracerBuilder` instance.
+    pub fn new(sampler: S) -> Self {
+        TracerBuilder {
+            sampler,
+            channel_options: ChannelOptions::default(),
+            sender_options: SenderOptions {
+                clock: Arc::new(SystemClock),
+                id_generator: Arc::new(RandomIdGenerator),
+                tracker: None,
+                finish_sampler: None,
+                finish_observers: FinishObservers::default(),
+                processors: SpanProcessors::default(),
+                default_tags: None,
+                limits: SpanLimits::new(),
+                baggage_restrictions: None,
+                inherited_tags: Vec::new(),
+            },
+        }
+    }
+
+    /// Sets the capacity of the span channel.
+    ///
+    /// If the channel is full, finished spans are discarded according to `overflow_policy`
+    /// (the newly finished ones by default).
+    /// If batching is enabled, the capacity is counted in batches.
+    ///
+    /// By default, the channel is unbounded.
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_options.capacity = Some(capacity);
+        self
+    }
+
+    /// Sets the policy applied when finished spans are sent to the full span channel.
+    ///
+    /// This takes effect only if `channel_capacity` is set.
+    /// The discarded spans are counted by `Tracer::dropped_spans`.
+    /// Note that `OverflowPolicy::BlockWithTimeout` blocks the threads finishing spans,
+    /// including the worker threads of async runtimes,
+    /// and that lock-free queues always discard the newest spans.
+    ///
+    /// The default value is `OverflowPolicy::DropNewest`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::Tracer;
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::OverflowPolicy;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+    ///     .channel_capacity(1)
+    ///     .overflow_policy(OverflowPolicy::DropOldest)
+    ///     .finish();
+    /// tracer.span("first").start_with_state(());
+    /// tracer.span("second").start_with_state(());
+    ///
+    /// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "second");
+    /// assert_eq!(tracer.dropped_spans(), 1);
+    /// ```
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.channel_options.overflow_policy = policy;
+        self
+    }
+
+    /// Sets the number of the internal queues of the span channel.
+    ///
+    /// Each thread sends its finished spans to one of the queues and
+    /// the `SpanReceiver` merges them.
+    /// Increasing the number reduces the contention between threads finishing spans concurrently.
+    ///
+    /// The default value is `1`.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.channel_options.shards = shards;
+        self
+    }
+
+    /// Sets the maximum number of finished spans delivered to the receiver at once.
+    ///
+    /// Finished spans are buffered and sent to the `SpanReceiver` in batches
+    /// to reduce the number of wakeups of the receiver.
+    /// A batch is delivered when it reaches `size` spans,
+    /// when it has been buffered for the duration set by `batch_delay`,
+    /// or when the receiver becomes idle.
+    ///
+    /// The default value is `1` (i.e., no batching).
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.channel_options.batch_size = size;
+        self
+    }
+
+    /// Sets the maximum delay of a batch of finished spans.
+    ///
+    /// Note that the delay is checked only when a span is finished.
+    ///
+    /// The default value is `10ms`.
+    pub fn batch_delay(mut self, delay: Duration) -> Self {
+        self.channel_options.batch_delay = delay;
+        self
+    }
+
+    /// Makes the span channel use lock-free ring buffers instead of tokio channels.
+    ///
+    /// Finishing a span then pushes it to a ring with a single CAS
+    /// and never wakes up the receiver.
+    /// Instead, the `SpanReceiver` checks the rings every `poll_interval` while they are empty,
+    /// which requires the tokio runtime polling it to have the timer enabled.
+    ///
+    /// The rings are bounded: their capacity is set by `channel_capacity`
+    /// (8192 by default) and spans finished while they are full are discarded.
+    ///
+    /// The `finish_contention` benchmark compares the throughput of the rings with the one of
+    /// tokio channels (run `cargo bench --bench finish_contention --features lock-free-queue`).
+    #[cfg(feature = "lock-free-queue")]
+    pub fn lock_free_queue(mut self, poll_interval: Duration) -> Self {
+        self.channel_options.ring_poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// Sets the clock used for the timestamps of spans and logs.
+    ///
+    /// The default value is `SystemClock`.
+    pub fn clock<C: Clock>(mut self, clock: C) -> Self {
+        self.sender_options.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets the generator of the identifiers used for the states of new spans.
+    ///
+    /// The default value is `RandomIdGenerator`.
+    pub fn id_generator<G: IdGenerator>(mut self, id_generator: G) -> Self {
+        self.sender_options.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Sets the tracker of the spans which are alive longer than expected.
+    ///
+    /// By default, spans are not tracked.
+    pub fn span_tracker(mut self, tracker: SpanTracker) -> Self {
+        self.sender_options.tracker = Some(tracker);
+        self
+    }
+
+    /// Sets the sampler deciding at finish time whether to keep the spans rejected by the sampler.
+    ///
+    /// If this is set, the spans rejected by the sampler of the tracer are recorded speculatively
+    /// (i.e., `Span::is_sampled` returns `true` and their states are made by `T::from`),
+    /// and `finish_sampler` decides whether to send them when they finish,
+    /// with their final tags and durations.
+    /// The spans started by `Span::child` and `Span::follower` of speculatively recorded spans
+    /// are also recorded speculatively.
+    /// Note that the contexts of speculatively recorded spans are propagated as they are.
+    ///
+    /// By default, the spans rejected by the sampler are not recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::{ErrorSampler, NullSampler};
+    /// use cf_rustracing::tag::StdTag;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::builder(NullSampler)
+    ///     .finish_sampler(ErrorSampler)
+    ///     .finish();
+    /// tracer.span("ok").start_with_state(());
+    /// let mut span = tracer.span("failed").start_with_state(());
+    /// span.set_tag(StdTag::error);
+    /// drop(span);
+    ///
+    /// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "failed");
+    /// assert!(span_rx.try_recv().is_err());
+    /// ```
+    pub fn finish_sampler<F>(mut self, finish_sampler: F) -> Self
+    where
+        F: FinishSampler<T> + Send + Sync + 'static,
+    {
+        self.sender_options.finish_sampler = Some(SharedFinishSampler(Arc::new(finish_sampler)));
+        self
+    }
+
+    /// Adds `observer`, which is called with every span finished by the tracer,
+    /// whether it is sampled or not.
+    ///
+    /// This is intended for cheap metric recording (e.g., feeding latency histograms
+    /// keyed by operation names), so that metrics are derived from all the spans
+    /// even if only some of them are reported.
+    /// Observers are called synchronously on the threads finishing spans,
+    /// before the finish sampler and the span processors are applied.
+    ///
+    /// If this is set, the spans rejected by the sampler are recorded speculatively
+    /// as with `finish_sampler`, and discarded after being observed
+    /// unless the finish sampler keeps them.
+    /// Note that this makes unsampled spans as costly as sampled ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::NullSampler;
+    /// use cf_rustracing::span::FinishedSpan;
+    /// use cf_rustracing::Tracer;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let requests = Arc::new(AtomicU64::new(0));
+    /// let (tracer, mut span_rx) = Tracer::builder(NullSampler)
+    ///     .on_span_finished({
+    ///         let requests = Arc::clone(&requests);
+    ///         move |span: &FinishedSpan<()>| {
+    ///             if span.operation_name() == "request" {
+    ///                 requests.fetch_add(1, Ordering::Relaxed);
+    ///             }
+    ///         }
+    ///     })
+    ///     .finish();
+    /// tracer.span("request").start_with_state(());
+    /// tracer.span("request").start_with_state(());
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// assert_eq!(requests.load(Ordering::Relaxed), 2);
+    /// assert!(span_rx.try_recv().is_err());
+    /// ```
+    pub fn on_span_finished<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&FinishedSpan<T>) + Send + Sync + 'static,
+    {
+        self.sender_options.finish_observers.push(observer);
+        self
+    }
+
+    /// Appends `processor` to the chain of the processors applied to finished spans.
+    ///
+    /// Before a finished span is sent to the receiver (or the reporter),
+    /// the processors are applied to it in the order they were added.
+    /// If a processor returns `None`, the span is discarded silently
+    /// (i.e., it is not counted by `Tracer::dropped_spans`).
+    /// This allows dropping noisy spans, renaming them or adding process-wide tags in one place.
+    ///
+    /// Since spans are finished concurrently, processors are shared by threads
+    /// and should not block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::tag::Tag;
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+    ///     .span_processor(|span| (span.operation_name() != "health").then_some(span))
+    ///     .span_processor(|mut span| {
+    ///         span.set_tag(Tag::new("hostname", "foo"));
+    ///         Some(span)
+    ///     })
+    ///     .finish();
+    /// tracer.span("health").start_with_state(());
+    /// tracer.span("request").start_with_state(());
+    ///
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.operation_name(), "request");
+    /// assert_eq!(span.tags()[0].name(), "hostname");
+    /// assert!(span_rx.try_recv().is_err());
+    /// ```
+    pub fn span_processor<F>(mut self, processor: F) -> Self
+    where
+        F: Fn(FinishedSpan<T>) -> Option<FinishedSpan<T>> + Send + Sync + 'static,
+    {
+        self.sender_options.processors.push(processor);
+        self
+    }
+
+    /// Discards the finished spans shorter than `duration`, unless they failed.
+    ///
+    /// Spans failed if their status is `SpanStatus::Error` or they are tagged with `error=true`.
+    /// This is applied to each span individually as a span processor (see `span_processor`),
+    /// so the children of a discarded span are still reported if they are slow enough.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::clock::MockClock;
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::span::SpanStatus;
+    /// use cf_rustracing::Tracer;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let clock = MockClock::new(UNIX_EPOCH);
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+    ///     .clock(clock.clone())
+    ///     .report_only_slower_than(Duration::from_millis(10))
+    ///     .finish();
+    /// tracer.span("fast").start_with_state(());
+    /// let mut span = tracer.span("failed").start_with_state(());
+    /// span.set_status(|| SpanStatus::error("timeout"));
+    /// drop(span);
+    /// let span = tracer.span("slow").start_with_state(());
+    /// clock.advance(Duration::from_millis(10));
+    /// drop(span);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "failed");
+    /// assert_eq!(span_rx.try_recv().unwrap().operation_name(), "slow");
+    /// assert!(span_rx.try_recv().is_err());
+    /// # }
+    /// ```
+    pub fn report_only_slower_than(self, duration: Duration) -> Self {
+        self.span_processor(move |span| {
+            let failed = span.status().is_error()
+                || span
+                    .all_tags()
+                    .any(|t| t.name() == "error" && *t.value() == TagValue::Boolean(true));
+            (failed || span.duration() >= duration).then_some(span)
+        })
+    }
+
+    /// Attaches `tags` to every span started by the tracer.
+    ///
+    /// This is meant for process-level attributes such as the service version or the host name.
+    /// The tags are shared by the spans instead of being copied to each of them.
+    /// Tags set on a span take precedence over the default tags having the same names,
+    /// and a set attached with `StartSpanOptions::shared_tags` replaces the default tags.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::tag::{Tag, TagValue};
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+    ///     .default_tags(vec![
+    ///         Tag::new("service.version", "1.2.3"),
+    ///         Tag::new("deployment.environment", "prod"),
+    ///     ])
+    ///     .finish();
+    /// tracer
+    ///     .span("foo")
+    ///     .tag(Tag::new("deployment.environment", "canary"))
+    ///     .start_with_state(());
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// let mut tags = span
+    ///     .all_tags()
+    ///     .map(|t| (t.name(), t.value().clone()))
+    ///     .collect::<Vec<_>>();
+    /// tags.sort_by(|a, b| a.0.cmp(b.0));
+    /// assert_eq!(
+    ///     tags,
+    ///     [
+    ///         ("deployment.environment", TagValue::from("canary")),
+    ///         ("service.version", TagValue::from("1.2.3")),
+    ///     ]
+    /// );
+    /// # }
+    /// ```
+    pub fn default_tags<I>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = Tag>,
+    {
+        self.sender_options.default_tags = Some(SharedTags::new(tags));
+        self
+    }
+
+    /// Makes the tags having the given names inherited by the descendants of spans.
+    ///
+    /// When a span is started with a reference to a context (e.g., by `Span::child`),
+    /// the tags with the names set to the span of the context are copied to the new span,
+    /// unless it has its own tags with the same names.
+    /// If a span has multiple references, the tags are inherited from the first one.
+    ///
+    /// By default, no tags are inherited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::tag::{Tag, TagValue};
+    /// use cf_rustracing::Tracer;
+    ///
+    /// let (tracer, mut span_rx) = Tracer::builder(AllSampler)
+    ///     .inherited_tags(["tenant.id"])
+    ///     .finish();
+    /// let mut root = tracer.span("root").start_with_state(());
+    /// root.set_tag(|| Tag::new("tenant.id", "acme"));
+    /// let child = root.child("child", |options| options.start_with_state(()));
+    /// let grandchild = child.child("grandchild", |options| options.start_with_state(()));
+    /// drop(grandchild);
+    ///
+    /// # #[cfg(not(rustracing_noop))]
+    /// # {
+    /// let span = span_rx.try_recv().unwrap();
+    /// assert_eq!(span.operation_name(), "grandchild");
+    /// assert_eq!(span.tags()[0].value(), &TagValue::from("acme"));
+    /// # }
+    /// ```
+    pub fn inherited_tags<I, N>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: Into<Cow<'static, str>>,
+    {
+        self.sender_options.inherited_tags = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the limits on the data recorded by each span.
+    ///
+    /// By default, spans are not limited.
+    pub fn span_limits(mut self, limits: SpanLimits) -> Self {
+        self.sender_options.limits = limits;
+        self
+    }
+
+    /// Sets the restrictions on the baggage items of spans.
+    ///
+    /// By default, baggage items are not restricted (except by `span_limits`).
+    pub fn baggage_restrictions(mut self, restrictions: BaggageRestrictions) -> Self {
+        self.sender_options.baggage_restrictions = Some(restrictions);
+        self
+    }
+
+    /// Applies `redactor` to every finished span before it is sent.
+    ///
+    /// This is a `span_processor` which removes sensitive data from spans;
+    /// it is applied after the processors added before it.
+    pub fn redactor(self, redactor: Redactor) -> Self {
+        self.span_processor(move |mut span| {
+            redactor.redact(&mut span);
+            Some(span)
+        })
+    }
+
+    /// Makes the span channel subject to the failures injected by `faults`.
+    pub(crate) fn faults(mut self, faults: Arc<Faults>) -> Self {
+        self.channel_options.faults = Some(faults);
+        self
+    }
+
+    /// Builds a `Tracer` and its `SpanReceiver`.
+    pub fn finish(self) -> (Tracer<S, T>, SpanReceiver<T>) {
+        let (span_tx, span_rx) = channel::channel(self.channel_options, self.sender_options);
+        let tracer = Tracer {
+            sampler: Arc::new(self.sampler),
+            span_tx,
+        };
+        (tracer, span_rx)
+    }
+
+    /// Builds a `Tracer` which hands finished spans to `reporter` synchronously
+    /// instead of sending them to a channel.
+    ///
+    /// The settings of the span channel (e.g., `channel_capacity` and `batch_size`) are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cf_rustracing::sampler::AllSampler;
+    /// use cf_rustracing::Tracer;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let spans = Arc::new(Mutex::new(Vec::new()));
+    /// let tracer = Tracer::builder(AllSampler).finish_with_reporter(Arc::clone(&spans));
+    /// tracer.span("foo").start_with_state(());
+    ///
+    /// let spans = spans.lock().unwrap();
+    /// assert_eq!(spans.len(), 1);
+    /// assert_eq!(spans[0].operation_name(), "foo");
+    /// ```
+    pub fn finish_with_reporter<R>(self, reporter: R) -> Tracer<S, T>
+    where
+        R: SpanReporter<T> + Send + Sync + 'static,
+    {
+        let span_tx =
+            channel::reporting_channel(SharedReporter(Arc::new(reporter)), self.sender_options);
+        Tracer {
+            sampler: Arc::new(self.sampler),
+            span_tx,
+        }
+    }
+
+    /// Builds a `Tracer` which stores finished spans in the returned `SpanStore`
+    /// instead of sending them to a channel.
+    pub(crate) fn finish_capturing(self) -> (Tracer<S, T>, Arc<SpanStore<T>>) {
+        let (span_tx, store) = channel::capturing_channel(self.sender_options);
+        let tracer = Tracer {
+            sampler: Arc::new(self.sampler),
+            span_tx,
+        };
+        (tracer, store)
+    }
+}
@@ -0,0 +1,270 @@
+//! Detection of leaked spans.
+//!
+//! A span handle which is never dropped (e.g., because it was moved into a task which
+//! never completes) is never reported, and the children it has already reported
+//! end up in traces without their parent.
+//! `SpanTracker` keeps track of the live spans of a tracer to make such leaks visible.
+use crate::clock::{Clock, SystemClock};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Tracker of the spans which are alive longer than a threshold.
+///
+/// Attach it to a tracer with `TracerBuilder::span_tracker`.
+/// Since every recorded span is registered with the tracker while it is alive,
+/// tracking adds a lock to starting and finishing recorded spans:
+/// it is intended for debugging rather than for production hot paths.
+///
+/// Live spans are checked when a span is started (at most once every threshold)
+/// and when `check` is called, and every span alive longer than the threshold
+/// is reported to the callback once.
+///
+/// # Examples
+///
+/// ```
+/// use cf_rustracing::clock::MockClock;
+/// use cf_rustracing::sampler::AllSampler;
+/// use cf_rustracing::tracker::SpanTracker;
+/// use cf_rustracing::Tracer;
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// let leaked = Arc::new(Mutex::new(Vec::new()));
+/// let tracker = SpanTracker::new(Duration::from_secs(60), {
+///     let leaked = Arc::clone(&leaked);
+///     move |span| leaked.lock().unwrap().push(span.operation_name().to_owned())
+/// });
+///
+/// let clock = MockClock::default();
+/// let (tracer, _span_rx) = Tracer::<_, ()>::builder(AllSampler)
+///     .clock(clock.clone())
+///     .span_tracker(tracker.clone())
+///     .finish();
+/// let span = tracer.span("forgotten").start_with_state(());
+/// std::mem::forget(span);
+///
+/// clock.advance(Duration::from_secs(61));
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// assert_eq!(tracker.check(), 1);
+/// assert_eq!(*leaked.lock().unwrap(), ["forgotten"]);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SpanTracker(Arc<TrackerInner>);
+impl SpanTracker {
+    /// Makes a new `SpanTracker` instance which calls `on_leak` for the spans
+    /// alive longer than `threshold`.
+    pub fn new<F>(threshold: Duration, on_leak: F) -> Self
+    where
+        F: Fn(&LeakedSpan) + Send + Sync + 'static,
+    {
+        SpanTracker(Arc::new(TrackerInner {
+            threshold,
+            on_leak: Box::new(on_leak),
+            clock: OnceLock::new(),
+            state: Mutex::new(TrackerState {
+                next_key: 0,
+                live: HashMap::new(),
+                last_check: None,
+            }),
+        }))
+    }
+
+    /// Returns the number of the live spans.
+    pub fn live_spans(&self) -> usize {
+        self.lock().live.len()
+    }
+
+    /// Reports the spans which have been alive longer than the threshold
+    /// and have not been reported yet.
+    ///
+    /// This returns the number of the reported spans.
+    pub fn check(&self) -> usize {
+        let now = self.now();
+        let leaked = self.collect_leaked(&mut self.lock(), now);
+        self.report(&leaked);
+        leaked.len()
+    }
+
+    /// Sets the clock used to measure the ages of spans.
+    ///
+    /// The clock of the first tracer the tracker is attached to is used.
+    pub(crate) fn attach_clock(&self, clock: Arc<dyn Clock>) {
+        let _ = self.0.clock.set(clock);
+    }
+
+    /// Registers a span started now and returns the key identifying it.
+    pub(crate) fn register(&self, operation_name: Cow<'static, str>, parent: Option<u64>) -> u64 {
+        let now = self.now();
+        let mut state = self.lock();
+        let key = state.next_key;
+        state.next_key += 1;
+        state.live.insert(
+            key,
+            LiveSpan {
+                operation_name,
+                started_at: now,
+                parent,
+                finished_children: 0,
+                reported: false,
+            },
+        );
+
+        let last_check = *state.last_check.get_or_insert(now);
+        if now.saturating_duration_since(last_check) >= self.0.threshold {
+            let leaked = self.collect_leaked(&mut state, now);
+            drop(state);
+            self.report(&leaked);
+        }
+        key
+    }
+
+    /// Unregisters the span identified by `key` which has just finished.
+    pub(crate) fn unregister(&self, key: u64) {
+        let mut state = self.lock();
+        let parent = state.live.remove(&key).and_then(|span| span.parent);
+        if let Some(parent) = parent.and_then(|parent| state.live.get_mut(&parent)) {
+            parent.finished_children += 1;
+        }
+    }
+
+    fn collect_leaked(&self, state: &mut TrackerState, now: Instant) -> Vec<LeakedSpan> {
+        state.last_check = Some(now);
+        let threshold = self.0.threshold;
+        state
+            .live
+            .values_mut()
+            .filter(|span| !span.reported)
+            .filter(|span| now.saturating_duration_since(span.started_at) > threshold)
+            .map(|span| {
+                span.reported = true;
+                LeakedSpan {
+                    operation_name: span.operation_name.clone(),
+                    age: now.saturating_duration_since(span.started_at),
+                    finished_children: span.finished_children,
+                }
+            })
+            .collect()
+    }
+
+    fn report(&self, leaked: &[LeakedSpan]) {
+        for span in leaked {
+            (self.0.on_leak)(span);
+        }
+    }
+
+    fn now(&self) -> Instant {
+        match self.0.clock.get() {
+            Some(clock) => clock.instant(),
+            None => SystemClock.instant(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TrackerState> {
+        self.0.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+impl fmt::Debug for SpanTracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpanTracker")
+            .field("threshold", &self.0.threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Span which has been alive longer than the threshold of a `SpanTracker`.
+#[derive(Debug, Clone)]
+pub struct LeakedSpan {
+    operation_name: Cow<'static, str>,
+    age: Duration,
+    finished_children: usize,
+}
+impl LeakedSpan {
+    /// Returns the operation name of the span.
+    pub fn operation_name(&self) -> &str {
+        self.operation_name.as_ref()
+    }
+
+    /// Returns the time elapsed since the span was started.
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+
+    /// Returns the number of the children of the span which have already finished.
+    ///
+    /// If this is not zero, the children have been reported without their parent.
+    /// Only the children started by `Span::child` are counted.
+    pub fn finished_children(&self) -> usize {
+        self.finished_children
+    }
+}
+
+struct TrackerInner {
+    threshold: Duration,
+    on_leak: Box<dyn Fn(&LeakedSpan) + Send + Sync>,
+    clock: OnceLock<Arc<dyn Clock>>,
+    state: Mutex<TrackerState>,
+}
+
+struct TrackerState {
+    next_key: u64,
+    live: HashMap<u64, LiveSpan>,
+    last_check: Option<Instant>,
+}
+
+struct LiveSpan {
+    operation_name: Cow<'static, str>,
+    started_at: Instant,
+    parent: Option<u64>,
+    finished_children: usize,
+    reported: bool,
+}
+
+#[cfg(all(test, not(rustracing_noop)))]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::sampler::AllSampler;
+    use crate::testing::TestTracer;
+    use crate::Tracer;
+
+    #[test]
+    fn leaked_parents_are_reported() {
+        let leaked = Arc::new(Mutex::new(Vec::new()));
+        let tracker = SpanTracker::new(Duration::from_secs(10), {
+            let leaked = Arc::clone(&leaked);
+            move |span: &LeakedSpan| leaked.lock().unwrap().push(span.clone())
+        });
+        let clock = MockClock::default();
+        let tracer = TestTracer::<()>::from_builder(
+            Tracer::builder(AllSampler)
+                .clock(clock.clone())
+                .span_tracker(tracker.clone()),
+        );
+
+        let parent = tracer.span("parent").start_with_state(());
+        parent.child("child", |options| options.start_with_state(()));
+        assert_eq!(tracker.live_spans(), 1);
+
+        clock.advance(Duration::from_secs(11));
+        tracer.span("trigger").start_with_state(());
+        {
+            let leaked = leaked.lock().unwrap();
+            assert_eq!(leaked.len(), 1);
+            assert_eq!(leaked[0].operation_name(), "parent");
+            assert_eq!(leaked[0].age(), Duration::from_secs(11));
+            assert_eq!(leaked[0].finished_children(), 1);
+        }
+
+        // Spans are reported only once.
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(tracker.check(), 0);
+
+        drop(parent);
+        assert_eq!(tracker.live_spans(), 0);
+    }
+}
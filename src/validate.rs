@@ -0,0 +1,236 @@
+//! Structural validation of traces.
+//!
+//! `validate_trace` checks a set of finished spans (typically the spans of a trace)
+//! for structural problems. It can be used in integration tests as well as
+//! in a debug build of a span reporting pipeline.
+use crate::span::{FinishedSpan, SpanReference};
+use std::fmt;
+
+/// Structural problem of a trace.
+///
+/// Spans are identified by their indices in the slice passed to `validate_trace`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceProblem {
+    /// The span references a span which is not in the set.
+    MissingParent {
+        /// The index of the referencing span.
+        span: usize,
+    },
+
+    /// The spans reference each other in a cycle.
+    Cycle {
+        /// The indices of the spans in the cycle, starting from the smallest one.
+        spans: Vec<usize>,
+    },
+
+    /// The span finished after the span it is a child of.
+    ChildFinishedAfterParent {
+        /// The index of the child span.
+        child: usize,
+
+        /// The index of the parent span.
+        parent: usize,
+    },
+
+    /// The spans have the same state (i.e., the same identifiers).
+    DuplicateId {
+        /// The indices of the spans.
+        spans: Vec<usize>,
+    },
+}
+impl TraceProblem {
+    /// Returns a human-readable description of this problem using the names of `spans`.
+    pub fn describe<T>(&self, spans: &[FinishedSpan<T>]) -> String {
+        Described(self, spans).to_string()
+    }
+}
+
+struct Described<'a, T>(&'a TraceProblem, &'a [FinishedSpan<T>]);
+impl<T> fmt::Display for Described<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |i: usize| {
+            self.1
+                .get(i)
+                .map_or("<unknown>", |span| span.operation_name())
+        };
+        let names = |spans: &[usize]| {
+            spans
+                .iter()
+                .map(|&i| format!("{:?}[{}]", name(i), i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self.0 {
+            TraceProblem::MissingParent { span } => {
+                write!(f, "the parent of {:?}[{}] is missing", name(*span), span)
+            }
+            TraceProblem::Cycle { spans } => write!(f, "reference cycle: {}", names(spans)),
+            TraceProblem::ChildFinishedAfterParent { child, parent } => write!(
+                f,
+                "{:?}[{}] finished after its parent {:?}[{}]",
+                name(*child),
+                child,
+                name(*parent),
+                parent
+            ),
+            TraceProblem::DuplicateId { spans } => {
+                write!(f, "spans with the same id: {}", names(spans))
+            }
+        }
+    }
+}
+
+/// Checks `spans` for structural problems.
+///
+/// The references of spans are resolved by comparing their states.
+/// Note that `MissingParent` is also reported for spans whose parents are
+/// in other processes or have not been received yet.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(not(rustracing_noop))]
+/// # {
+/// use cf_rustracing::testing::TestTracer;
+/// use cf_rustracing::validate::{validate_trace, TraceProblem};
+///
+/// let tracer = TestTracer::<u32>::new();
+/// let child = {
+///     let parent = tracer.span("parent").start_with_state(1);
+///     parent.child("child", |options| options.start_with_state(2))
+/// };
+/// drop(child);
+///
+/// let spans = tracer.spans();
+/// assert_eq!(
+///     validate_trace(&spans),
+///     [TraceProblem::ChildFinishedAfterParent { child: 1, parent: 0 }]
+/// );
+/// # }
+/// ```
+pub fn validate_trace<T: PartialEq>(spans: &[FinishedSpan<T>]) -> Vec<TraceProblem> {
+    let mut problems = Vec::new();
+    let find = |state: &T| spans.iter().position(|s| s.context().state() == state);
+
+    for (i, span) in spans.iter().enumerate() {
+        let duplicates = spans
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.context().state() == span.context().state())
+            .map(|(j, _)| j)
+            .collect::<Vec<_>>();
+        if duplicates.len() > 1 && duplicates[0] == i {
+            problems.push(TraceProblem::DuplicateId { spans: duplicates });
+        }
+    }
+
+    let mut parents = vec![Vec::new(); spans.len()];
+    for (i, span) in spans.iter().enumerate() {
+        for reference in span.references() {
+            match find(reference.span()) {
+                None => problems.push(TraceProblem::MissingParent { span: i }),
+                Some(parent) => {
+                    parents[i].push(parent);
+                    let is_child_of = matches!(reference, SpanReference::ChildOf(_));
+                    if is_child_of && span.finish_time() > spans[parent].finish_time() {
+                        problems.push(TraceProblem::ChildFinishedAfterParent { child: i, parent });
+                    }
+                }
+            }
+        }
+    }
+
+    for cycle in find_cycles(&parents) {
+        problems.push(TraceProblem::Cycle { spans: cycle });
+    }
+    problems
+}
+
+/// Finds the cycles of the graph whose edges are `edges[node]`.
+fn find_cycles(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Color {
+        White,
+        Gray,
+        Black,
+    }
+
+    fn visit(
+        node: usize,
+        edges: &[Vec<usize>],
+        colors: &mut [Color],
+        path: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        colors[node] = Color::Gray;
+        path.push(node);
+        for &next in &edges[node] {
+            match colors[next] {
+                Color::White => visit(next, edges, colors, path, cycles),
+                Color::Gray => {
+                    let start = path.iter().position(|&n| n == next).expect("never fails");
+                    let mut cycle = path[start..].to_vec();
+                    let min = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+                    cycle.rotate_left(min);
+                    if !cycles.contains(&cycle) {
+                        cycles.push(cycle);
+                    }
+                }
+                Color::Black => {}
+            }
+        }
+        path.pop();
+        colors[node] = Color::Black;
+    }
+
+    let mut colors = vec![Color::White; edges.len()];
+    let mut cycles = Vec::new();
+    for node in 0..edges.len() {
+        if colors[node] == Color::White {
+            visit(node, edges, &mut colors, &mut Vec::new(), &mut cycles);
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_cycles_works() {
+        assert!(find_cycles(&[vec![], vec![0], vec![1]]).is_empty());
+        assert_eq!(find_cycles(&[vec![2], vec![0], vec![1]]), [vec![0, 2, 1]]);
+        assert_eq!(find_cycles(&[vec![0]]), [vec![0]]);
+    }
+
+    #[test]
+    #[cfg(not(rustracing_noop))]
+    fn validate_trace_works() {
+        use crate::testing::TestTracer;
+
+        let tracer = TestTracer::<u32>::new();
+        {
+            let root = tracer.span("root").start_with_state(1);
+            let _a = root.child("a", |options| options.start_with_state(2));
+            let _b = root.child("b", |options| options.start_with_state(2));
+            let _orphan = tracer
+                .span("orphan")
+                .child_of(&crate::span::SpanContext::new(9, Vec::new()))
+                .start_with_state(3);
+        }
+        let spans = tracer.spans();
+        let problems = validate_trace(&spans);
+        assert_eq!(
+            problems,
+            [
+                TraceProblem::DuplicateId { spans: vec![1, 2] },
+                TraceProblem::MissingParent { span: 0 },
+            ]
+        );
+        assert_eq!(
+            problems[1].describe(&spans),
+            "the parent of \"orphan\"[0] is missing"
+        );
+    }
+}